@@ -19,6 +19,29 @@ impl QuicVersion {
             QuicVersion::Others(_) => unimplemented!(),
         }
     }
+
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity
+    pub fn retry_integrity_key(&self) -> Option<[u8; 0x10]> {
+        match self {
+            QuicVersion::Rfc9000 => Some([
+                0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68,
+                0xc8, 0x4e,
+            ]),
+            // the tag of other versions is not known
+            QuicVersion::VersionNegotiation | QuicVersion::Others(_) => None,
+        }
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity
+    pub fn retry_integrity_nonce(&self) -> Option<[u8; 0x0c]> {
+        match self {
+            QuicVersion::Rfc9000 => Some([
+                0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+            ]),
+            // the tag of other versions is not known
+            QuicVersion::VersionNegotiation | QuicVersion::Others(_) => None,
+        }
+    }
 }
 
 impl Into<QuicVersion> for u32 {
@@ -30,3 +53,13 @@ impl Into<QuicVersion> for u32 {
         }
     }
 }
+
+impl From<QuicVersion> for u32 {
+    fn from(version: QuicVersion) -> Self {
+        match version {
+            QuicVersion::VersionNegotiation => 0x00000000,
+            QuicVersion::Rfc9000 => 0x00000001,
+            QuicVersion::Others(x) => x,
+        }
+    }
+}
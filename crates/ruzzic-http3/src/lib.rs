@@ -48,6 +48,9 @@ pub struct Http3App;
 impl AppLayer for Http3App {
     type Message = Http3AppMessage;
     type Error = RuzzicHttp3Error;
+
+    /// https://www.rfc-editor.org/rfc/rfc9114.html#section-3.1
+    const ALPN_PROTOCOLS: &'static [&'static [u8]] = &[b"h3"];
 }
 
 #[derive(Debug)]
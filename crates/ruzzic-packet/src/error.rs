@@ -1,5 +1,6 @@
 use crate::packet::{
-    long_header::LongHeaderPacketTransformError, PacketReadError, PacketTransformError,
+    long_header::LongHeaderPacketTransformError, short_header::ShortHeaderPacketReadError,
+    PacketReadError, PacketTransformError,
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -10,4 +11,6 @@ pub enum Error {
     PacketTransformError(#[from] PacketTransformError),
     #[error("error when long packet transform to finer")]
     LongHeaderPacketTransformError(#[from] LongHeaderPacketTransformError),
+    #[error("error when read short header packet")]
+    ShortHeaderPacketReadError(#[from] ShortHeaderPacketReadError),
 }
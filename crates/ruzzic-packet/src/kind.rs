@@ -1,6 +1,16 @@
-use crate::packet::long_header::{initial::InitialPacket, LongHeaderPacket};
+use crate::packet::{
+    long_header::{
+        handshake::HandshakePacket, initial::InitialPacket, retry::RetryPacket,
+        zero_rtt::ZeroRttPacket, LongHeaderPacket,
+    },
+    short_header::OneRttPacket,
+};
 
 pub enum PacketKind<'a> {
     LongHeader(LongHeaderPacket<'a>),
     Initial(InitialPacket<'a>),
+    ZeroRtt(ZeroRttPacket<'a>),
+    Handshake(HandshakePacket<'a>),
+    Retry(RetryPacket<'a>),
+    OneRtt(OneRttPacket<'a>),
 }
@@ -2,6 +2,8 @@ use ruzzic_common::QuicVersion;
 
 // unspecified long header packet
 pub mod long_header;
+// 1-RTT packet
+pub mod short_header;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Packet<'a> {
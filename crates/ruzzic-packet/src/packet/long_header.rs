@@ -4,6 +4,12 @@ use crate::packet::{self, Packet, PacketTransformError};
 
 // initial packet
 pub mod initial;
+// 0-RTT packet
+pub mod zero_rtt;
+// handshake packet
+pub mod handshake;
+// retry packet
+pub mod retry;
 // version negotiation packet
 pub mod version_negotiation;
 
@@ -39,6 +45,11 @@ impl<'a> LongHeaderPacket<'a> {
         self.type_specific_payload
     }
 
+    /// Long Packet Type in the first byte.
+    pub(crate) fn long_packet_type(&self) -> u8 {
+        (self.first_byte & 0b0011_0000) >> 4
+    }
+
     pub(crate) fn packet_number_length(&self) -> usize {
         (self.first_byte & 0b0000_0011) as usize + 1
     }
@@ -106,6 +117,9 @@ pub enum LongHeaderPacketTransformError {
 #[derive(Debug, PartialEq)]
 pub enum KindOfPacket {
     Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
     VersionNegotiation,
 }
 
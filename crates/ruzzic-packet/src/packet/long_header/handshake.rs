@@ -0,0 +1,142 @@
+use ruzzic_common::QuicVersion;
+
+use crate::packet::long_header::{
+    self,
+    initial::{packet_number, payload, remain_length},
+    version_negotiation::is_version_negotiation,
+    LongHeaderPacket, LongHeaderPacketTransformError,
+};
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-handshake-packet
+#[derive(Debug, PartialEq)]
+pub struct HandshakePacket<'a> {
+    first_byte: u8,
+    version: QuicVersion,
+    destination_connection_id: &'a [u8],
+    source_connection_id: &'a [u8],
+    packet_number: u32,
+    payload: &'a [u8],
+}
+
+impl<'a> HandshakePacket<'a> {
+    pub fn first_byte(&self) -> u8 {
+        self.first_byte
+    }
+
+    pub fn version(&self) -> QuicVersion {
+        self.version.clone()
+    }
+
+    pub fn destination_connection_id(&self) -> &[u8] {
+        self.destination_connection_id
+    }
+
+    pub fn source_connection_id(&self) -> &[u8] {
+        self.source_connection_id
+    }
+
+    pub fn packet_number(&self) -> u32 {
+        self.packet_number
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        self.payload
+    }
+}
+
+impl<'a> TryFrom<&'a LongHeaderPacket<'a>> for HandshakePacket<'a> {
+    type Error = LongHeaderPacketTransformError;
+
+    fn try_from(packet: &'a LongHeaderPacket<'a>) -> Result<Self, Self::Error> {
+        if is_handshake(packet) {
+            let type_specific_payload = packet.type_specific_payload();
+            let position = 0;
+
+            let remain_length::RemainLength {
+                value: remain_length,
+                position_after: position,
+            } = remain_length::remain_length(type_specific_payload, position)?;
+
+            let packet_number_length = packet.packet_number_length();
+            let packet_number::PacketNumber {
+                value: packet_number,
+                position_after: position,
+            } = packet_number::packet_number(
+                type_specific_payload,
+                position,
+                packet_number_length,
+            )?;
+
+            let payload::Payload {
+                value: payload,
+                position_after: _,
+            } = payload::payload(
+                type_specific_payload,
+                position,
+                remain_length
+                    .checked_sub(packet_number_length)
+                    .ok_or(Self::Error::UnexpectedEnd(position))?,
+            )?;
+
+            Ok(Self {
+                first_byte: packet.first_byte(),
+                version: packet.version(),
+                destination_connection_id: packet.destination_connection_id(),
+                source_connection_id: packet.source_connection_id(),
+                packet_number,
+                payload,
+            })
+        } else {
+            Err(Self::Error::NotThisKind(
+                long_header::KindOfPacket::Handshake,
+            ))
+        }
+    }
+}
+
+pub fn is_handshake(long_header_packet: &LongHeaderPacket) -> bool {
+    !is_version_negotiation(long_header_packet) && long_header_packet.long_packet_type() == 2
+}
+
+#[cfg(test)]
+mod tests {
+    use ruzzic_common::QuicVersion;
+
+    use crate::packet::long_header::{
+        KindOfPacket, LongHeaderPacket, LongHeaderPacketTransformError,
+    };
+
+    use super::HandshakePacket;
+
+    #[test]
+    fn simple() -> Result<(), LongHeaderPacketTransformError> {
+        let input: &LongHeaderPacket =
+            &LongHeaderPacket::new(0b1110_0000, QuicVersion::Rfc9000, &[1], &[], &[2, 3, 4]);
+        let handshake_packet: HandshakePacket = input.try_into()?;
+        assert_eq!(
+            handshake_packet,
+            HandshakePacket {
+                first_byte: 0b1110_0000,
+                version: QuicVersion::Rfc9000,
+                destination_connection_id: &[1],
+                source_connection_id: &[],
+                packet_number: 3,
+                payload: &[4],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_handshake() {
+        let input: &LongHeaderPacket =
+            &LongHeaderPacket::new(0b1100_0000, QuicVersion::Rfc9000, &[], &[], &[0, 2, 0, 0]);
+        let result: Result<HandshakePacket, _> = input.try_into();
+        assert_eq!(
+            result,
+            Err(LongHeaderPacketTransformError::NotThisKind(
+                KindOfPacket::Handshake
+            ))
+        );
+    }
+}
@@ -2,9 +2,10 @@ use ruzzic_common::QuicVersion;
 
 use crate::packet::long_header::{self, LongHeaderPacket, LongHeaderPacketTransformError};
 
-mod packet_number;
-mod payload;
-mod remain_length;
+// shared with other long header packets which have the Length field
+pub(super) mod packet_number;
+pub(super) mod payload;
+pub(super) mod remain_length;
 mod token;
 
 #[derive(Debug, PartialEq)]
@@ -0,0 +1,161 @@
+use ruzzic_common::QuicVersion;
+
+use crate::packet::long_header::{
+    self, version_negotiation::is_version_negotiation, LongHeaderPacket,
+    LongHeaderPacketTransformError,
+};
+
+const RETRY_INTEGRITY_TAG_LENGTH: usize = 16;
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-retry-packet
+#[derive(Debug, PartialEq)]
+pub struct RetryPacket<'a> {
+    first_byte: u8,
+    version: QuicVersion,
+    destination_connection_id: &'a [u8],
+    source_connection_id: &'a [u8],
+    retry_token: &'a [u8],
+    retry_integrity_tag: &'a [u8],
+}
+
+impl<'a> RetryPacket<'a> {
+    pub fn first_byte(&self) -> u8 {
+        self.first_byte
+    }
+
+    pub fn version(&self) -> QuicVersion {
+        self.version.clone()
+    }
+
+    pub fn destination_connection_id(&self) -> &[u8] {
+        self.destination_connection_id
+    }
+
+    pub fn source_connection_id(&self) -> &[u8] {
+        self.source_connection_id
+    }
+
+    pub fn retry_token(&self) -> &[u8] {
+        self.retry_token
+    }
+
+    pub fn retry_integrity_tag(&self) -> &[u8] {
+        self.retry_integrity_tag
+    }
+
+    /// Input of the Retry Integrity Tag computation.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity
+    pub fn retry_pseudo_packet(&self, original_destination_connection_id: &[u8]) -> Vec<u8> {
+        [
+            &[original_destination_connection_id.len() as u8][..],
+            original_destination_connection_id,
+            &[self.first_byte],
+            &u32::from(self.version()).to_be_bytes(),
+            &[self.destination_connection_id.len() as u8],
+            self.destination_connection_id,
+            &[self.source_connection_id.len() as u8],
+            self.source_connection_id,
+            self.retry_token,
+        ]
+        .concat()
+    }
+}
+
+impl<'a> TryFrom<&'a LongHeaderPacket<'a>> for RetryPacket<'a> {
+    type Error = LongHeaderPacketTransformError;
+
+    fn try_from(packet: &'a LongHeaderPacket<'a>) -> Result<Self, Self::Error> {
+        if is_retry(packet) {
+            let type_specific_payload = packet.type_specific_payload();
+
+            // the Retry Token takes up the rest except for the Retry Integrity Tag
+            let retry_token_length = type_specific_payload
+                .len()
+                .checked_sub(RETRY_INTEGRITY_TAG_LENGTH)
+                .ok_or(Self::Error::UnexpectedEnd(0))?;
+            let (retry_token, retry_integrity_tag) =
+                type_specific_payload.split_at(retry_token_length);
+
+            Ok(Self {
+                first_byte: packet.first_byte(),
+                version: packet.version(),
+                destination_connection_id: packet.destination_connection_id(),
+                source_connection_id: packet.source_connection_id(),
+                retry_token,
+                retry_integrity_tag,
+            })
+        } else {
+            Err(Self::Error::NotThisKind(long_header::KindOfPacket::Retry))
+        }
+    }
+}
+
+pub fn is_retry(long_header_packet: &LongHeaderPacket) -> bool {
+    !is_version_negotiation(long_header_packet) && long_header_packet.long_packet_type() == 3
+}
+
+#[cfg(test)]
+mod tests {
+    use ruzzic_common::QuicVersion;
+
+    use crate::packet::{
+        long_header::{KindOfPacket, LongHeaderPacket, LongHeaderPacketTransformError},
+        Packet,
+    };
+
+    use super::RetryPacket;
+
+    // https://www.rfc-editor.org/rfc/rfc9001.html#name-retry
+    #[test]
+    fn rfc9001_retry_packet() {
+        let input: &[u8] = &[
+            0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50, 0x2a, 0x42, 0x62,
+            0xb5, 0x74, 0x6f, 0x6b, 0x65, 0x6e, 0x04, 0xa2, 0x65, 0xba, 0x2e, 0xff, 0x4d, 0x82,
+            0x90, 0x58, 0xfb, 0x3f, 0x0f, 0x24, 0x96, 0xba,
+        ];
+        let packet: Packet = input.try_into().unwrap();
+        let long_header_packet: LongHeaderPacket = (&packet).try_into().unwrap();
+        let retry_packet: RetryPacket = (&long_header_packet).try_into().unwrap();
+        assert_eq!(
+            retry_packet,
+            RetryPacket {
+                first_byte: 0xff,
+                version: QuicVersion::Rfc9000,
+                destination_connection_id: &[],
+                source_connection_id: &[0xf0, 0x67, 0xa5, 0x50, 0x2a, 0x42, 0x62, 0xb5],
+                retry_token: b"token",
+                retry_integrity_tag: &input[20..],
+            }
+        );
+
+        let original_destination_connection_id = &[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+        assert_eq!(
+            retry_packet.retry_pseudo_packet(original_destination_connection_id),
+            [&[8][..], original_destination_connection_id, &input[..20]].concat()
+        );
+    }
+
+    #[test]
+    fn too_short() {
+        let input: &LongHeaderPacket =
+            &LongHeaderPacket::new(0b1111_0000, QuicVersion::Rfc9000, &[], &[], &[0; 15]);
+        let result: Result<RetryPacket, _> = input.try_into();
+        assert_eq!(
+            result,
+            Err(LongHeaderPacketTransformError::UnexpectedEnd(0))
+        );
+    }
+
+    #[test]
+    fn not_retry() {
+        let input: &LongHeaderPacket =
+            &LongHeaderPacket::new(0b1100_0000, QuicVersion::Rfc9000, &[], &[], &[0, 2, 0, 0]);
+        let result: Result<RetryPacket, _> = input.try_into();
+        assert_eq!(
+            result,
+            Err(LongHeaderPacketTransformError::NotThisKind(
+                KindOfPacket::Retry
+            ))
+        );
+    }
+}
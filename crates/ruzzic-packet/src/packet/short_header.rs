@@ -1,6 +1,184 @@
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-1-rtt-packet
+#[derive(Debug, PartialEq)]
 pub struct OneRttPacket<'a> {
     first_byte: u8,
     destination_connection_id: &'a [u8],
     packet_number: u32,
     packet_payload: &'a [u8],
 }
+
+impl<'a> OneRttPacket<'a> {
+    pub fn first_byte(&self) -> u8 {
+        self.first_byte
+    }
+
+    pub fn destination_connection_id(&self) -> &[u8] {
+        self.destination_connection_id
+    }
+
+    pub fn packet_number(&self) -> u32 {
+        self.packet_number
+    }
+
+    pub fn packet_payload(&self) -> &[u8] {
+        self.packet_payload
+    }
+
+    /// Spin bit for passive latency measurement.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-latency-spin-bit
+    pub fn spin_bit(&self) -> bool {
+        self.first_byte & 0b0010_0000 != 0
+    }
+
+    /// Key Phase bit which tells which keys protect the packet.
+    /// This is protected by header protection.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-1-rtt-packet
+    pub fn key_phase(&self) -> bool {
+        self.first_byte & 0b0000_0100 != 0
+    }
+
+    pub(crate) fn packet_number_length(&self) -> usize {
+        packet_number_length(self.first_byte)
+    }
+
+    /// Serialize the packet. The packet number is truncated to the length in the first byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let packet_number_length = self.packet_number_length();
+        [
+            &[self.first_byte][..],
+            self.destination_connection_id,
+            &self.packet_number.to_be_bytes()[4 - packet_number_length..],
+            self.packet_payload,
+        ]
+        .concat()
+    }
+
+    pub fn new(
+        spin_bit: bool,
+        key_phase: bool,
+        destination_connection_id: &'a [u8],
+        packet_number: u32,
+        packet_number_length: usize,
+        packet_payload: &'a [u8],
+    ) -> Self {
+        let first_byte = 0b0100_0000
+            | (spin_bit as u8) << 5
+            | (key_phase as u8) << 2
+            | (packet_number_length - 1) as u8;
+        Self {
+            first_byte,
+            destination_connection_id,
+            packet_number,
+            packet_payload,
+        }
+    }
+}
+
+/// A short header does not have the length of the destination connection id,
+/// so the length of connection ids issued by ourselves is needed to read it.
+impl<'a> TryFrom<(&'a [u8], usize)> for OneRttPacket<'a> {
+    type Error = ShortHeaderPacketReadError;
+
+    fn try_from((buf, connection_id_length): (&'a [u8], usize)) -> Result<Self, Self::Error> {
+        let position = 0;
+
+        let first_byte = *buf
+            .get(position)
+            .ok_or(Self::Error::UnexpectedEnd(position))?;
+        if !is_short(first_byte) {
+            return Err(Self::Error::NotShortHeader);
+        }
+        let position = position + 1;
+
+        let destination_connection_id = buf
+            .get(position..position + connection_id_length)
+            .ok_or(Self::Error::UnexpectedEnd(position))?;
+        let position = position + connection_id_length;
+
+        let packet_number_length = packet_number_length(first_byte);
+        let packet_number = {
+            let buf = buf
+                .get(position..position + packet_number_length)
+                .ok_or(Self::Error::UnexpectedEnd(position))?;
+            let mut packet_number = 0u32;
+            for b in buf {
+                packet_number = (packet_number << 8) + *b as u32;
+            }
+            packet_number
+        };
+        let position = position + packet_number_length;
+
+        // a short header packet is always the last packet in a datagram
+        let packet_payload = &buf[position..];
+
+        Ok(Self {
+            first_byte,
+            destination_connection_id,
+            packet_number,
+            packet_payload,
+        })
+    }
+}
+
+pub fn is_short(first_byte: u8) -> bool {
+    first_byte & 0b1000_0000 == 0
+}
+
+fn packet_number_length(first_byte: u8) -> usize {
+    (first_byte & 0b0000_0011) as usize + 1
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ShortHeaderPacketReadError {
+    #[error("not a short header packet")]
+    NotShortHeader,
+    #[error("unexpected end")]
+    UnexpectedEnd(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OneRttPacket, ShortHeaderPacketReadError};
+
+    #[test]
+    fn simple() -> Result<(), ShortHeaderPacketReadError> {
+        let input: &[u8] = &[
+            0b0110_0101, // first byte: spin bit, key phase and 2 bytes packet number
+            1,
+            2, // destination connection id
+            0,
+            3, // packet number
+            1, // payload
+        ];
+
+        let packet: OneRttPacket = (input, 2).try_into()?;
+        assert_eq!(
+            packet,
+            OneRttPacket {
+                first_byte: 0b0110_0101,
+                destination_connection_id: &[1, 2],
+                packet_number: 3,
+                packet_payload: &[1],
+            }
+        );
+        assert!(packet.spin_bit());
+        assert!(packet.key_phase());
+        assert_eq!(packet.to_bytes(), input);
+        assert_eq!(OneRttPacket::new(true, true, &[1, 2], 3, 2, &[1]), packet);
+        Ok(())
+    }
+
+    #[test]
+    fn not_short_header() {
+        let input: &[u8] = &[0b1100_0000, 0, 0, 0, 1];
+        let result: Result<OneRttPacket, _> = (input, 0).try_into();
+        assert_eq!(result, Err(ShortHeaderPacketReadError::NotShortHeader));
+    }
+
+    #[test]
+    fn too_short_connection_id() {
+        let input: &[u8] = &[0b0100_0000, 1];
+        let result: Result<OneRttPacket, _> = (input, 8).try_into();
+        assert_eq!(result, Err(ShortHeaderPacketReadError::UnexpectedEnd(1)));
+    }
+}
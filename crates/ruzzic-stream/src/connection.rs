@@ -1,15 +1,39 @@
-use std::{cell::Ref, ops::Deref, sync::Arc};
+use std::{
+    collections::VecDeque,
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use rand::{prelude::StdRng, Fill, SeedableRng};
-use ruzzic_common::{read_bytes_to::FromReadBytesWith, EndpointType};
+use ruzzic_common::{
+    read_bytes_to::{FromReadBytesWith, ReadBytesToWith},
+    EndpointType,
+};
+use ruzzic_tls::{
+    session::{ClientConfig, EarlyData, Secrets, ServerConfig, Session},
+    TlsError,
+};
 
 use crate::{
+    crypto::{cipher_suite, EncryptionLevel, KeySet, KeySets, KeyUpdateError},
     endpoint_state::EndpointState,
-    packet::{self, Packet, PacketNumber, PacketPayload},
+    frame::{ack, connection_close, crypto, Frame, FrameError, FrameType, Frames},
+    packet::{self, Packet, PacketNumber, PacketNumberSpace, PacketPayload},
+    stream::{RecvState, SendState, StreamDirection, StreamError, StreamID, Streams},
+    transport_parameters::{TransportParameterError, TransportParameters},
     Token, Version,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+use self::{
+    packet_space::PacketSpace,
+    recovery::{Recovery, SentPacket, Timeout},
+};
+
+mod packet_space;
+mod recovery;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConnectionID(pub(crate) Vec<u8>);
 
 impl FromReadBytesWith<()> for ConnectionID {
@@ -30,9 +54,13 @@ impl ConnectionID {
         self.0.len()
     }
 
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
     /// Connection IDs MUST NOT contain any information that can be used by an external observer(RFC 9000)
     /// So this is generated by cryptographically secure PRNG
-    fn random() -> Self {
+    pub(crate) fn random() -> Self {
         // from_entrypy() function may cause some over-head.
         // https://docs.rs/rand/0.8.5/rand/trait.SeedableRng.html#method.from_entropy
         let mut rng = StdRng::from_entropy();
@@ -46,7 +74,8 @@ impl ConnectionID {
     }
 }
 
-const CONNECTION_ID_LENGTH: usize = 20;
+/// Length of connection ids issued by ourselves.
+pub(crate) const CONNECTION_ID_LENGTH: usize = 20;
 impl Fill for ConnectionID {
     fn try_fill<R: rand::Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), rand::Error> {
         for i in 0..CONNECTION_ID_LENGTH {
@@ -56,27 +85,387 @@ impl Fill for ConnectionID {
     }
 }
 
+/// What became of the stream data a client sent in 0-RTT packets.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyDataStatus {
+    NotSent,
+    /// Sent, and the server has not told whether it is accepted yet.
+    Pending,
+    Accepted,
+    /// The data has to be sent again in 1-RTT packets.
+    Rejected,
+}
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-connection-termination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Handshake,
+    Established,
+    /// Closed by this endpoint, which replies CONNECTION_CLOSE to packets for a while.
+    Closing,
+    /// Closed by the peer, and nothing is sent any more.
+    Draining,
+    Closed,
+}
+
+/// Why a connection is closed.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-error-handling
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ConnectionError {
+    /// The peer violated the protocol.
+    #[error("transport error {code:#x}: {reason}")]
+    TransportError {
+        code: u64,
+        /// Type of the frame which caused the error.
+        frame_type: Option<u64>,
+        reason: String,
+    },
+    #[error("closed by the application with {code:#x}: {reason}")]
+    ApplicationClosed { code: u64, reason: String },
+    #[error("closed by the peer with the transport error {code:#x}: {reason}")]
+    PeerTransportError { code: u64, reason: String },
+    #[error("closed by the peer with the application error {code:#x}: {reason}")]
+    PeerApplicationClosed { code: u64, reason: String },
+    #[error("no packets from the peer within the idle timeout")]
+    IdleTimeout,
+}
+
+impl From<FrameError> for ConnectionError {
+    fn from(e: FrameError) -> Self {
+        ConnectionError::TransportError {
+            code: e.transport_error_code(),
+            frame_type: e.frame_type(),
+            reason: e.to_string(),
+        }
+    }
+}
+
+/// TLS and the transport parameters fail on CRYPTO frames.
+impl From<TlsError> for ConnectionError {
+    fn from(e: TlsError) -> Self {
+        ConnectionError::TransportError {
+            code: e.transport_error_code(),
+            frame_type: Some(CRYPTO_FRAME_TYPE),
+            reason: e.to_string(),
+        }
+    }
+}
+
+impl From<TransportParameterError> for ConnectionError {
+    fn from(e: TransportParameterError) -> Self {
+        ConnectionError::TransportError {
+            code: e.transport_error_code(),
+            frame_type: Some(CRYPTO_FRAME_TYPE),
+            reason: e.to_string(),
+        }
+    }
+}
+
+impl From<KeyUpdateError> for ConnectionError {
+    fn from(e: KeyUpdateError) -> Self {
+        ConnectionError::TransportError {
+            code: e.transport_error_code(),
+            frame_type: None,
+            reason: e.to_string(),
+        }
+    }
+}
+
+/// What a connection tells the application.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The handshake is complete.
+    Connected,
+    StreamData {
+        stream_id: StreamID,
+        offset: u64,
+        data: Vec<u8>,
+        is_fin: bool,
+    },
+    /// The peer abandoned sending on the stream.
+    StreamReset {
+        stream_id: StreamID,
+        error_code: u64,
+    },
+    /// The peer asked to stop sending on the stream, which is reset.
+    StreamStopped {
+        stream_id: StreamID,
+        error_code: u64,
+    },
+    /// The connection enters the closing, draining or closed state.
+    Closed(ConnectionError),
+}
+
+const CRYPTO_FRAME_TYPE: u64 = 0x06;
+const PROTOCOL_VIOLATION: u64 = 0x0a;
+const CRYPTO_BUFFER_EXCEEDED: u64 = 0x0d;
+/// Reported in Initial and Handshake packets instead of an application error.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-10.2.3
+const APPLICATION_ERROR: u64 = 0x0c;
+
+/// Datagrams are kept within the smallest maximum datagram size, since the path MTU is not discovered.
+/// Datagrams which carry Initial packets are expanded to this size.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-14.1
+const MAX_DATAGRAM_SIZE: usize = 1200;
+/// The order of packets coalesced in a datagram.
+const LEVELS: [EncryptionLevel; 4] = [
+    EncryptionLevel::Initial,
+    EncryptionLevel::ZeroRTT,
+    EncryptionLevel::Handshake,
+    EncryptionLevel::OneRTT,
+];
+
+/// A connection with no I/O in it.
+/// Datagrams received are given to `handle_datagram`, and ones to send are taken from `poll_transmit`
+/// until it returns None. `handle_timeout` must be called at the deadline `poll_timeout` tells.
 pub struct Connection {
+    endpoint_type: EndpointType,
     version: Version,
     destination_connection_id: ConnectionID,
     source_connection_id: ConnectionID,
+    /// Destination Connection ID of the first Initial packet sent by the client.
+    /// Initial secrets are derived from this.
+    original_destination_connection_id: ConnectionID,
     token: Token,
+    key_sets: KeySets,
+    /// Indexed by packet number spaces, used to decode truncated packet numbers.
+    largest_received_packet_numbers: [Option<PacketNumber>; 3],
+    /// Source Connection ID of the Retry packet a client received.
+    retry_source_connection_id: Option<ConnectionID>,
+    local_transport_parameters: TransportParameters,
+    peer_transport_parameters: Option<TransportParameters>,
+    /// Limits of the server remembered with the ticket a client sends 0-RTT with,
+    /// until the parameters of this connection arrive.
+    remembered_transport_parameters: Option<TransportParameters>,
+    early_data: EarlyDataStatus,
+    /// STREAM frames sent in 0-RTT packets, sent again in 1-RTT packets if 0-RTT is rejected.
+    early_frames: Vec<Frame>,
+    streams: Streams,
+    state: ConnectionState,
+    /// None if packets are built and read by the caller without the handshake.
+    tls: Option<Session>,
+    /// Indexed by packet number spaces.
+    spaces: [PacketSpace; 3],
+    recovery: Recovery,
+    events: VecDeque<Event>,
+    /// Why the connection is closed, which is sent in CONNECTION_CLOSE frames in the closing state.
+    error: Option<ConnectionError>,
+    /// Whether a packet arrived in the closing state, which is answered with CONNECTION_CLOSE.
+    close_pending: bool,
+    /// When the closing or the draining state ends.
+    close_deadline: Option<Instant>,
+    idle_deadline: Option<Instant>,
+    /// When the 1-RTT keys of the previous key phase are discarded.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-6.5
+    key_discard_deadline: Option<Instant>,
+    /// The idle timer restarts on the first ack-eliciting packet sent after one is received.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-10.1
+    ack_eliciting_sent: bool,
+    /// Whether a client has received an Initial packet, after which Retry packets are ignored.
+    initial_received: bool,
+    /// A server sends at most three times what it receives until the address of the client is validated.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-8.1
+    address_validated: bool,
+    bytes_received: u64,
+    bytes_sent: u64,
 }
 
 impl Connection {
-    pub fn new_with_packet(version: Version, packet: Packet) -> Self {
-        let destination_connection_id = *packet.destination_connection_id().clone();
-        let source_connection_id = *packet.source_connection_id().unwrap();
+    fn new(
+        endpoint_type: EndpointType,
+        version: Version,
+        destination_connection_id: ConnectionID,
+        source_connection_id: ConnectionID,
+        original_destination_connection_id: ConnectionID,
+        local_transport_parameters: TransportParameters,
+    ) -> Self {
+        let mut key_sets = KeySets::default();
+        key_sets.install(
+            EncryptionLevel::Initial,
+            KeySet::new_initial(
+                version.into(),
+                &original_destination_connection_id,
+                &endpoint_type,
+            ),
+        );
+        let new_space = || {
+            PacketSpace::new(match endpoint_type {
+                EndpointType::Client => EndpointState::new_client(None),
+                EndpointType::Server => EndpointState::new_server(None),
+            })
+        };
         Connection {
+            // the address of a server is validated by the client connecting to it
+            address_validated: endpoint_type == EndpointType::Client,
+            endpoint_type,
             version,
             destination_connection_id,
-            source_connection_id: source_connection_id,
+            source_connection_id,
+            original_destination_connection_id,
             token: Token::empty(),
+            key_sets,
+            largest_received_packet_numbers: [None; 3],
+            retry_source_connection_id: None,
+            local_transport_parameters,
+            peer_transport_parameters: None,
+            remembered_transport_parameters: None,
+            early_data: EarlyDataStatus::NotSent,
+            early_frames: Vec::new(),
+            streams: Streams::new(endpoint_type),
+            state: ConnectionState::Handshake,
+            tls: None,
+            spaces: [new_space(), new_space(), new_space()],
+            recovery: Recovery::new(endpoint_type),
+            events: VecDeque::new(),
+            error: None,
+            close_pending: false,
+            close_deadline: None,
+            idle_deadline: None,
+            key_discard_deadline: None,
+            ack_eliciting_sent: false,
+            initial_received: false,
+            bytes_received: 0,
+            bytes_sent: 0,
         }
     }
 
-    pub(crate) fn next_packet_payload(&self) -> PacketPayload {
-        PacketPayload::from_vec(Vec::new())
+    /// Create a server side connection from the first Initial packet sent by a client.
+    /// None if the packet is not an Initial packet.
+    pub fn new_with_packet(version: Version, packet: &Packet) -> Option<Self> {
+        if packet.encryption_level() != Some(EncryptionLevel::Initial) {
+            return None;
+        }
+        let original_destination_connection_id = *packet.destination_connection_id();
+        // a server replies to the source connection id of the client
+        let destination_connection_id = *packet.source_connection_id()?;
+        let source_connection_id = original_destination_connection_id.clone();
+        let local_transport_parameters = TransportParameters {
+            original_destination_connection_id: Some(original_destination_connection_id.clone()),
+            initial_source_connection_id: Some(source_connection_id.clone()),
+            ..Default::default()
+        };
+        Some(Self::new(
+            EndpointType::Server,
+            version,
+            destination_connection_id,
+            source_connection_id,
+            original_destination_connection_id,
+            local_transport_parameters,
+        ))
+    }
+
+    pub(crate) fn new_client(
+        version: Version,
+        destination_connection_id: ConnectionID,
+        source_connection_id: ConnectionID,
+    ) -> Self {
+        let local_transport_parameters = TransportParameters {
+            initial_source_connection_id: Some(source_connection_id.clone()),
+            ..Default::default()
+        };
+        Self::new(
+            EndpointType::Client,
+            version,
+            destination_connection_id.clone(),
+            source_connection_id,
+            destination_connection_id,
+            local_transport_parameters,
+        )
+    }
+
+    /// Start the handshake of a client.
+    /// The first datagram to send is taken from `poll_transmit`.
+    pub fn connect(
+        version: Version,
+        config: &ClientConfig,
+        server_name: &str,
+        transport_parameters: TransportParameters,
+        now: Instant,
+    ) -> Self {
+        let mut connection =
+            Self::new_client(version, ConnectionID::random(), ConnectionID::random());
+        connection.set_local_transport_parameters(transport_parameters);
+        let tls = Session::new_client(
+            config,
+            server_name,
+            connection.local_transport_parameters.encode(),
+        );
+        connection.start(tls, now);
+        connection
+    }
+
+    /// Accept a client with the first Initial packet it sent.
+    /// The datagram of the packet must be given to `handle_datagram` next.
+    /// None if the packet is not an Initial packet.
+    pub fn accept(
+        packet: &Packet,
+        config: Arc<ServerConfig>,
+        transport_parameters: TransportParameters,
+        now: Instant,
+    ) -> Option<Self> {
+        Self::accept_with_id(
+            packet,
+            ConnectionID::random(),
+            config,
+            transport_parameters,
+            now,
+        )
+    }
+
+    /// The server uses its own connection id instead of the one the client chose.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-7.2
+    pub(crate) fn accept_with_id(
+        packet: &Packet,
+        source_connection_id: ConnectionID,
+        config: Arc<ServerConfig>,
+        transport_parameters: TransportParameters,
+        now: Instant,
+    ) -> Option<Self> {
+        let version = packet.version()?;
+        let mut connection = Self::new_with_packet(version, packet)?;
+        connection.source_connection_id = source_connection_id;
+        connection.set_local_transport_parameters(transport_parameters);
+        let mut tls = Session::new_server(config, connection.local_transport_parameters.encode());
+        tls.set_early_data_context(connection.early_data_context());
+        connection.start(tls, now);
+        Some(connection)
+    }
+
+    fn start(&mut self, tls: Session, now: Instant) {
+        self.tls = Some(tls);
+        self.restart_idle_timer(now);
+        // a client has the ClientHello to send
+        if let Err(error) = self.process_tls() {
+            self.close_with_error(now, error);
+        }
+    }
+
+    /// A client continues with the connection id and the token given by a Retry packet.
+    /// Initial keys are derived again from the new Destination Connection ID.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-retry-packet
+    pub(crate) fn on_retry(&mut self, packet: &Packet) -> Result<(), std::io::Error> {
+        let retry_token = packet.retry_token().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "not a retry packet")
+        })?;
+        if !packet.verify_retry_integrity_tag(&self.original_destination_connection_id) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "retry integrity tag is invalid",
+            ));
+        }
+        self.token = retry_token.clone();
+        self.destination_connection_id = *packet.source_connection_id().unwrap();
+        self.retry_source_connection_id = Some(self.destination_connection_id.clone());
+        self.key_sets.install(
+            EncryptionLevel::Initial,
+            KeySet::new_initial(
+                self.version.into(),
+                &self.destination_connection_id,
+                &EndpointType::Client,
+            ),
+        );
+        Ok(())
     }
 
     pub(crate) fn version(&self) -> &Version {
@@ -95,10 +484,1147 @@ impl Connection {
         &self.token
     }
 
-    pub(crate) fn client_id(&self, endpoint_state: &EndpointState) -> &ConnectionID {
-        match endpoint_state.type_is() {
-            EndpointType::Server => &self.destination_connection_id,
-            EndpointType::Client => &self.source_connection_id,
+    pub(crate) fn original_destination_connection_id(&self) -> &ConnectionID {
+        &self.original_destination_connection_id
+    }
+
+    pub(crate) fn largest_received_packet_number(
+        &self,
+        space: PacketNumberSpace,
+    ) -> Option<PacketNumber> {
+        self.largest_received_packet_numbers[space as usize]
+    }
+
+    /// Must be called after a packet is successfully decrypted.
+    pub(crate) fn on_packet_received(
+        &mut self,
+        space: PacketNumberSpace,
+        packet_number: PacketNumber,
+    ) {
+        let largest = &mut self.largest_received_packet_numbers[space as usize];
+        *largest = Some(largest.map_or(packet_number, |largest| largest.max(packet_number)));
+    }
+
+    pub(crate) fn key_sets(&self) -> &KeySets {
+        &self.key_sets
+    }
+
+    /// Install keys exported by TLS when the handshake reaches a new encryption level.
+    pub(crate) fn install_key_set(&mut self, level: EncryptionLevel, key_set: KeySet) {
+        self.key_sets.install(level, key_set);
+    }
+
+    pub(crate) fn discard_key_set(&mut self, level: EncryptionLevel) {
+        self.key_sets.discard(level);
+    }
+
+    /// Parameters to be sent in the quic_transport_parameters extension.
+    pub(crate) fn local_transport_parameters(&self) -> &TransportParameters {
+        &self.local_transport_parameters
+    }
+
+    /// Configure the limits announced to the peer.
+    /// Connection IDs are filled from the connection unless they are given.
+    pub(crate) fn set_local_transport_parameters(&mut self, mut parameters: TransportParameters) {
+        parameters
+            .initial_source_connection_id
+            .get_or_insert_with(|| self.source_connection_id.clone());
+        if self.endpoint_type == EndpointType::Server {
+            parameters
+                .original_destination_connection_id
+                .get_or_insert_with(|| self.original_destination_connection_id.clone());
+        }
+        self.streams.set_local_limits(&parameters);
+        self.local_transport_parameters = parameters;
+    }
+
+    /// The remembered limits are used in 0-RTT before the parameters of the peer arrive.
+    pub(crate) fn peer_transport_parameters(&self) -> Option<&TransportParameters> {
+        self.peer_transport_parameters
+            .as_ref()
+            .or(self.remembered_transport_parameters.as_ref())
+    }
+
+    /// Must be called with the quic_transport_parameters extension received from the peer.
+    /// The connection must be closed when this returns an error.
+    pub(crate) fn on_peer_transport_parameters(
+        &mut self,
+        buf: &[u8],
+    ) -> Result<(), TransportParameterError> {
+        let peer = match self.endpoint_type {
+            EndpointType::Client => EndpointType::Server,
+            EndpointType::Server => EndpointType::Client,
+        };
+        let parameters = TransportParameters::decode(buf, &peer)?;
+        // the peer's Source Connection ID is the Destination Connection ID of ours
+        parameters.authenticate_connection_ids(
+            &peer,
+            &self.destination_connection_id,
+            &self.original_destination_connection_id,
+            self.retry_source_connection_id.as_ref(),
+        )?;
+        // https://www.rfc-editor.org/rfc/rfc9000.html#section-7.4.1
+        if let Some(remembered) = self.remembered_transport_parameters.take() {
+            parameters.check_remembered(&remembered)?;
+        }
+        self.streams.set_peer_limits(&parameters);
+        self.recovery
+            .set_max_ack_delay(Duration::from_millis(parameters.max_ack_delay));
+        self.peer_transport_parameters = Some(parameters);
+        Ok(())
+    }
+
+    /// A server gives this to TLS, so that 0-RTT is rejected
+    /// if the limits a client remembers have changed since the ticket is issued.
+    pub(crate) fn early_data_context(&self) -> Vec<u8> {
+        self.local_transport_parameters.remembered().encode()
+    }
+
+    /// A client starts 0-RTT with the keys of the client early traffic secret,
+    /// within the limits remembered with the ticket.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6.1
+    pub(crate) fn start_early_data(
+        &mut self,
+        key_set: KeySet,
+        remembered_transport_parameters: &[u8],
+    ) -> Result<(), TransportParameterError> {
+        let parameters =
+            TransportParameters::decode(remembered_transport_parameters, &EndpointType::Server)?;
+        self.streams.set_peer_limits(&parameters);
+        self.remembered_transport_parameters = Some(parameters.remembered());
+        self.key_sets.install(EncryptionLevel::ZeroRTT, key_set);
+        Ok(())
+    }
+
+    pub fn early_data_status(&self) -> EarlyDataStatus {
+        self.early_data
+    }
+
+    /// Whether stream data fits in the limits remembered for 0-RTT.
+    fn is_within_early_limits(&self, stream_id: &StreamID, offset: u64, length: u64) -> bool {
+        if self.key_sets.get(EncryptionLevel::ZeroRTT).is_none() {
+            return false;
+        }
+        let Some(limits) = self.remembered_transport_parameters.as_ref() else {
+            return false;
+        };
+        let is_unidirectional = stream_id.0 & 0x02 != 0;
+        let (max_streams, max_stream_data) = if is_unidirectional {
+            (
+                limits.initial_max_streams_uni,
+                limits.initial_max_stream_data_uni,
+            )
+        } else {
+            (
+                limits.initial_max_streams_bidi,
+                limits.initial_max_stream_data_bidi_remote,
+            )
+        };
+        let sent = self
+            .early_frames
+            .iter()
+            .map(|frame| match frame {
+                Frame::Stream(body) => body.data_length(),
+                _ => 0,
+            })
+            .sum::<u64>();
+        stream_id.0 >> 2 < max_streams
+            && offset + length <= max_stream_data
+            && sent + length <= limits.initial_max_data
+    }
+
+    /// Must be called when TLS tells whether the server accepted 0-RTT,
+    /// before the transport parameters of the server.
+    /// Returns the frames to send again in 1-RTT packets if 0-RTT is rejected.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6.2
+    pub(crate) fn on_early_data_result(&mut self, accepted: bool) -> Vec<Frame> {
+        let frames = std::mem::take(&mut self.early_frames);
+        if accepted {
+            if self.early_data == EarlyDataStatus::Pending {
+                self.early_data = EarlyDataStatus::Accepted;
+            }
+            return Vec::new();
+        }
+        if self.early_data == EarlyDataStatus::Pending {
+            self.early_data = EarlyDataStatus::Rejected;
+        }
+        self.remembered_transport_parameters = None;
+        self.key_sets.discard(EncryptionLevel::ZeroRTT);
+        self.recovery.discard_zero_rtt();
+        frames
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Closed, and all the events are taken.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.state == ConnectionState::Closed && self.events.is_empty()
+    }
+
+    /// Stream data taken from here gives the peer credit to send more.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        let event = self.events.pop_front()?;
+        if let Event::StreamData {
+            stream_id, data, ..
+        } = &event
+        {
+            self.streams.on_consumed(stream_id, data.len() as u64);
         }
+        Some(event)
     }
+
+    /// The protocol negotiated with ALPN, which tells the application to hand the connection to.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.tls.as_ref()?.alpn_protocol()
+    }
+
+    /// Update the 1-RTT keys, once the handshake is confirmed and the previous update is acknowledged.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-initiating-a-key-update
+    pub fn initiate_key_update(&mut self, now: Instant) -> Result<(), KeyUpdateError> {
+        // the Handshake keys are discarded when the handshake is confirmed
+        if self.key_sets.get(EncryptionLevel::Handshake).is_some() {
+            return Err(KeyUpdateError::UpdateNotConfirmed);
+        }
+        self.key_sets
+            .one_rtt_mut()
+            .ok_or(KeyUpdateError::UpdateNotConfirmed)?
+            .initiate_update()?;
+        self.on_key_update(now);
+        Ok(())
+    }
+
+    /// Open a stream within the limit of the peer.
+    /// A client can open streams before the handshake completes if it has the limits remembered for 0-RTT.
+    pub fn open_stream(&mut self, direction: StreamDirection) -> Result<StreamID, StreamError> {
+        self.check_open()?;
+        self.streams.open(direction)
+    }
+
+    /// Data is sent in 1-RTT packets, or in 0-RTT packets before the handshake completes.
+    pub fn write_stream(&mut self, stream_id: &StreamID, data: &[u8]) -> Result<(), StreamError> {
+        self.check_open()?;
+        self.streams.write(stream_id, data)
+    }
+
+    /// Send the FIN bit after the data written.
+    pub fn finish_stream(&mut self, stream_id: &StreamID) -> Result<(), StreamError> {
+        self.check_open()?;
+        self.streams.finish(stream_id)
+    }
+
+    /// Abandon sending on the stream.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-resetting-streams
+    pub fn reset_stream(
+        &mut self,
+        stream_id: &StreamID,
+        error_code: u64,
+    ) -> Result<(), StreamError> {
+        self.check_open()?;
+        let frame = self.streams.reset(stream_id, error_code)?;
+        self.queue_frames(frame);
+        Ok(())
+    }
+
+    /// Ask the peer to stop sending on the stream, and discard the data received.
+    pub fn stop_sending(
+        &mut self,
+        stream_id: &StreamID,
+        error_code: u64,
+    ) -> Result<(), StreamError> {
+        self.check_open()?;
+        let frame = self.streams.stop_sending(stream_id, error_code)?;
+        self.queue_frames(frame);
+        Ok(())
+    }
+
+    pub fn stream_send_state(&self, stream_id: &StreamID) -> Option<SendState> {
+        self.streams.send_state(stream_id)
+    }
+
+    pub fn stream_recv_state(&self, stream_id: &StreamID) -> Option<RecvState> {
+        self.streams.recv_state(stream_id)
+    }
+
+    fn check_open(&self) -> Result<(), StreamError> {
+        if !self.is_open() {
+            return Err(StreamError::ConnectionClosed);
+        }
+        Ok(())
+    }
+
+    /// Frames to send in 1-RTT packets.
+    fn queue_frames(&mut self, frames: impl IntoIterator<Item = Frame>) {
+        self.spaces[PacketNumberSpace::ApplicationData as usize]
+            .pending_frames
+            .extend(frames);
+    }
+
+    /// Close the connection with an application error code.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-immediate-close
+    pub fn close(&mut self, now: Instant, error_code: u64, reason: &str) {
+        self.close_with_error(
+            now,
+            ConnectionError::ApplicationClosed {
+                code: error_code,
+                reason: reason.to_string(),
+            },
+        );
+    }
+
+    /// Enter the closing state, in which CONNECTION_CLOSE is sent.
+    fn close_with_error(&mut self, now: Instant, error: ConnectionError) {
+        if !self.is_open() {
+            return;
+        }
+        self.state = ConnectionState::Closing;
+        self.close_pending = true;
+        self.close_deadline = Some(now + self.pto() * 3);
+        self.events.push_back(Event::Closed(error.clone()));
+        self.error = Some(error);
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(
+            self.state,
+            ConnectionState::Handshake | ConnectionState::Established
+        )
+    }
+
+    /// When `handle_timeout` must be called.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        match self.state {
+            ConnectionState::Handshake | ConnectionState::Established => [
+                self.idle_deadline,
+                self.recovery.loss_detection_timer(),
+                self.key_discard_deadline,
+            ]
+            .into_iter()
+            .flatten()
+            .min(),
+            ConnectionState::Closing | ConnectionState::Draining => self.close_deadline,
+            ConnectionState::Closed => None,
+        }
+    }
+
+    pub fn handle_timeout(&mut self, now: Instant) {
+        match self.state {
+            ConnectionState::Handshake | ConnectionState::Established => {
+                // the connection is closed silently
+                if self.idle_deadline.is_some_and(|deadline| deadline <= now) {
+                    self.state = ConnectionState::Closed;
+                    self.error = Some(ConnectionError::IdleTimeout);
+                    self.events
+                        .push_back(Event::Closed(ConnectionError::IdleTimeout));
+                    return;
+                }
+                if self
+                    .recovery
+                    .loss_detection_timer()
+                    .is_some_and(|timer| timer <= now)
+                {
+                    self.on_loss_detection_timeout(now);
+                }
+                if self
+                    .key_discard_deadline
+                    .is_some_and(|deadline| deadline <= now)
+                {
+                    self.key_discard_deadline = None;
+                    if let Some(keys) = self.key_sets.one_rtt_mut() {
+                        keys.discard_previous_keys();
+                    }
+                }
+            }
+            ConnectionState::Closing | ConnectionState::Draining => {
+                if self.close_deadline.is_some_and(|deadline| deadline <= now) {
+                    self.state = ConnectionState::Closed;
+                }
+            }
+            ConnectionState::Closed => {}
+        }
+    }
+
+    /// Packets are lost, or ack-eliciting packets are sent as probes.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-on-timeout
+    fn on_loss_detection_timeout(&mut self, now: Instant) {
+        match self.recovery.on_timeout(now) {
+            Some(Timeout::Lost(space, packets)) => self.on_packets_lost(space, packets),
+            Some(Timeout::Probe(space)) => {
+                let packet_space = &mut self.spaces[space as usize];
+                // the handshake can not go on without the CRYPTO data in flight
+                for frame in self.recovery.frames_in_flight(space) {
+                    if let Frame::Crypto(body) = frame {
+                        packet_space.on_crypto_lost(body.offset(), body.crypto_data().len() as u64);
+                    }
+                }
+                packet_space.probes = 2;
+            }
+            None => {}
+        }
+    }
+
+    /// Keys of the previous key phase are kept for three times the PTO.
+    fn on_key_update(&mut self, now: Instant) {
+        self.key_discard_deadline = Some(now + self.pto() * 3);
+    }
+
+    /// The probe timeout without the backoff.
+    fn pto(&self) -> Duration {
+        self.recovery.pto()
+    }
+
+    /// The round-trip time receive windows are tuned with.
+    fn rtt(&self) -> Duration {
+        self.recovery.rtt().smoothed_rtt()
+    }
+
+    /// The smaller max_idle_timeout of the two endpoints, and at least three times the PTO.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-10.1
+    fn idle_timeout(&self) -> Option<Duration> {
+        let local = self.local_transport_parameters().max_idle_timeout;
+        // remembered parameters do not have max_idle_timeout
+        let peer = self
+            .peer_transport_parameters()
+            .map_or(0, |parameters| parameters.max_idle_timeout);
+        let timeout = match (local, peer) {
+            (0, 0) => return None,
+            (0, timeout) | (timeout, 0) => timeout,
+            (local, peer) => local.min(peer),
+        };
+        Some(Duration::from_millis(timeout).max(self.pto() * 3))
+    }
+
+    fn restart_idle_timer(&mut self, now: Instant) {
+        self.idle_deadline = self.idle_timeout().map(|timeout| now + timeout);
+    }
+
+    /// Process the packets coalesced in a datagram from the peer.
+    pub fn handle_datagram(&mut self, now: Instant, datagram: &[u8]) {
+        match self.state {
+            ConnectionState::Handshake | ConnectionState::Established => {}
+            ConnectionState::Closing => {
+                self.close_pending = true;
+                return;
+            }
+            ConnectionState::Draining | ConnectionState::Closed => return,
+        }
+        self.bytes_received += datagram.len() as u64;
+        let mut rest = datagram;
+        while !rest.is_empty() && self.is_open() {
+            // the rest can not be read without the length
+            let Ok(length) = packet::first_packet_length(rest) else {
+                return;
+            };
+            let (packet, next) = rest.split_at(length);
+            rest = next;
+            if let Err(error) = self.handle_packet(now, packet) {
+                self.close_with_error(now, error);
+            }
+        }
+    }
+
+    /// Packets which can not be processed are dropped.
+    fn handle_packet(&mut self, now: Instant, buf: &[u8]) -> Result<(), ConnectionError> {
+        let Ok(packet) =
+            Cursor::new(buf).read_bytes_to_with::<Packet>(self.source_connection_id.len())
+        else {
+            return Ok(());
+        };
+        // there is only one version to negotiate, and long header packets of other versions are dropped
+        if packet
+            .version()
+            .is_some_and(|version| version != self.version)
+            || !self.is_local_id(&packet)
+        {
+            return Ok(());
+        }
+        if packet.retry_token().is_some() {
+            self.handle_retry(&packet);
+            return Ok(());
+        }
+        // a Version Negotiation packet is not expected with a single version
+        let Some(level) = packet.encryption_level() else {
+            return Ok(());
+        };
+        let is_server = self.endpoint_type == EndpointType::Server;
+        // https://www.rfc-editor.org/rfc/rfc9001.html#section-5.7
+        if (level == EncryptionLevel::ZeroRTT && !is_server)
+            || (level == EncryptionLevel::OneRTT
+                && is_server
+                && self.state != ConnectionState::Established)
+        {
+            return Ok(());
+        }
+        let packet = match packet.decrypt(self) {
+            Ok(packet) => packet,
+            Err(_) => {
+                if let (EncryptionLevel::OneRTT, Some(keys)) = (level, self.key_sets.one_rtt_mut())
+                {
+                    keys.on_authentication_failure()?;
+                }
+                return Ok(());
+            }
+        };
+        let space = PacketNumberSpace::from(level);
+        let packet_number = packet.packet_number();
+        if self.spaces[space as usize].is_duplicate(packet_number) {
+            return Ok(());
+        }
+        if let (Some(key_phase), Some(keys)) = (packet.key_phase(), self.key_sets.one_rtt_mut()) {
+            let current_phase = keys.key_phase();
+            keys.on_packet_decrypted(key_phase, packet_number.to_u64())?;
+            if keys.key_phase() != current_phase {
+                self.on_key_update(now);
+            }
+        }
+        self.on_packet_received(space, packet_number);
+        match level {
+            // a client replies to the Source Connection ID the server chose
+            EncryptionLevel::Initial if !is_server && !self.initial_received => {
+                self.initial_received = true;
+                self.destination_connection_id = *packet.source_connection_id().unwrap();
+            }
+            // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.9.1
+            EncryptionLevel::Handshake if is_server => {
+                self.address_validated = true;
+                self.discard_space(EncryptionLevel::Initial);
+            }
+            _ => {}
+        }
+
+        let frames = Frames::decode(packet.payload(), level)?;
+        for frame in frames.frames() {
+            self.handle_frame(now, level, frame)?;
+            if !self.is_open() {
+                return Ok(());
+            }
+        }
+        let is_ack_eliciting = frames.frames().iter().any(Frame::is_ack_eliciting);
+        self.spaces[space as usize].on_packet_received(packet_number, is_ack_eliciting, now);
+        self.ack_eliciting_sent = false;
+        self.restart_idle_timer(now);
+        Ok(())
+    }
+
+    /// A client keeps using the Destination Connection ID it chose
+    /// until it receives a packet from the server.
+    fn is_local_id(&self, packet: &Packet) -> bool {
+        let destination_connection_id = packet.destination_connection_id();
+        *destination_connection_id == self.source_connection_id
+            || (self.endpoint_type == EndpointType::Server
+                && packet.version().is_some()
+                && *destination_connection_id == self.original_destination_connection_id)
+    }
+
+    /// A client accepts only one Retry packet, before any Initial packet from the server.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-17.2.5.2
+    fn handle_retry(&mut self, packet: &Packet) {
+        if self.endpoint_type == EndpointType::Server
+            || self.initial_received
+            || self.retry_source_connection_id.is_some()
+            || packet.retry_token().is_some_and(|token| token.0.is_empty())
+        {
+            return;
+        }
+        if self.on_retry(packet).is_ok() {
+            self.spaces[PacketNumberSpace::Initial as usize].resend_crypto();
+        }
+    }
+
+    fn handle_frame(
+        &mut self,
+        now: Instant,
+        level: EncryptionLevel,
+        frame: &Frame,
+    ) -> Result<(), ConnectionError> {
+        match frame {
+            Frame::Ack(body) => self.on_ack_frame(now, level, body),
+            Frame::Crypto(body) => self.on_crypto_frame(level, body),
+            Frame::Stream(body) => {
+                let result = self.streams.on_stream_frame(body);
+                self.on_stream_result(frame, result)
+            }
+            Frame::ResetStream(body) => {
+                let result = self.streams.on_reset_stream(body);
+                self.on_stream_result(frame, result)
+            }
+            Frame::MaxData(body) => {
+                self.streams.on_max_data(body);
+                Ok(())
+            }
+            Frame::MaxStreamData(body) => {
+                let result = self.streams.on_max_stream_data(body);
+                self.on_stream_result(frame, result)
+            }
+            Frame::MaxStreams(body) => {
+                self.streams.on_max_streams(body);
+                Ok(())
+            }
+            Frame::DataBlocked(body) => {
+                self.streams.on_data_blocked(body);
+                Ok(())
+            }
+            Frame::StreamDataBlocked(body) => {
+                let result = self.streams.on_stream_data_blocked(body);
+                self.on_stream_result(frame, result)
+            }
+            Frame::StreamsBlocked(body) => {
+                self.streams.on_streams_blocked(body);
+                Ok(())
+            }
+            Frame::StopSending(body) => {
+                let reset = self.streams.on_stop_sending(body);
+                let reset = self.on_stream_result(frame, reset)?;
+                if reset.is_some() {
+                    self.events.push_back(Event::StreamStopped {
+                        stream_id: body.stream_id().clone(),
+                        error_code: body.error_code(),
+                    });
+                }
+                self.queue_frames(reset);
+                Ok(())
+            }
+            Frame::ConnectionClose(body) => {
+                self.on_connection_close(now, body);
+                Ok(())
+            }
+            Frame::HandshakeDone | Frame::NewToken(_)
+                if self.endpoint_type == EndpointType::Server =>
+            {
+                Err(ConnectionError::TransportError {
+                    code: PROTOCOL_VIOLATION,
+                    frame_type: Some(frame.frame_type()),
+                    reason: "only a server sends the frame".to_string(),
+                })
+            }
+            Frame::HandshakeDone => {
+                self.on_handshake_confirmed();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deliver what the stream frame made readable, or close the connection with the error.
+    fn on_stream_result<T>(
+        &mut self,
+        frame: &Frame,
+        result: Result<T, StreamError>,
+    ) -> Result<T, ConnectionError> {
+        let value = result.map_err(|e| ConnectionError::TransportError {
+            code: e.transport_error_code().unwrap_or(PROTOCOL_VIOLATION),
+            frame_type: Some(frame.frame_type()),
+            reason: e.to_string(),
+        })?;
+        while let Some((stream_id, chunk)) = self.streams.read() {
+            let event = match chunk {
+                Ok(chunk) => Event::StreamData {
+                    stream_id,
+                    offset: chunk.offset,
+                    data: chunk.data,
+                    is_fin: chunk.is_fin,
+                },
+                Err(StreamError::Reset(error_code)) => Event::StreamReset {
+                    stream_id,
+                    error_code,
+                },
+                Err(_) => continue,
+            };
+            self.events.push_back(event);
+        }
+        Ok(value)
+    }
+
+    fn on_ack_frame(
+        &mut self,
+        now: Instant,
+        level: EncryptionLevel,
+        body: &ack::Body,
+    ) -> Result<(), ConnectionError> {
+        let largest_acknowledged = body.largest_acknowledged();
+        let space = PacketNumberSpace::from(level);
+        let sending = &mut self.spaces[space as usize].sending;
+        if largest_acknowledged >= *sending.next_packet_number() {
+            return Err(ConnectionError::TransportError {
+                code: PROTOCOL_VIOLATION,
+                frame_type: Some(body.frame_type()),
+                reason: "a packet not sent is acknowledged".to_string(),
+            });
+        }
+        sending.on_packet_acknowledged(largest_acknowledged);
+        if let (EncryptionLevel::OneRTT, Some(keys)) = (level, self.key_sets.one_rtt_mut()) {
+            keys.on_packet_acknowledged(largest_acknowledged.to_u64());
+        }
+        // the delay is only meaningful in 1-RTT packets, since the others are acknowledged immediately
+        // https://www.rfc-editor.org/rfc/rfc9000.html#section-13.2.5
+        let ack_delay = match level {
+            EncryptionLevel::OneRTT => {
+                let exponent = self
+                    .peer_transport_parameters
+                    .as_ref()
+                    .map_or(3, |parameters| parameters.ack_delay_exponent);
+                Duration::from_micros(
+                    body.ack_delay()
+                        .checked_shl(exponent as u32)
+                        .unwrap_or(u64::MAX),
+                )
+            }
+            _ => Duration::ZERO,
+        };
+        let (acked, lost) = self
+            .recovery
+            .on_ack_received(space, &body.ranges(), ack_delay, now);
+        for frame in acked.iter().flat_map(|packet| &packet.frames) {
+            match frame {
+                Frame::Stream(body) => self.streams.on_frame_acked(body),
+                Frame::ResetStream(body) => self.streams.on_reset_acked(body.stream_id()),
+                _ => {}
+            }
+        }
+        self.on_packets_lost(space, lost);
+        Ok(())
+    }
+
+    /// The frames of lost packets are sent again as they are, or with the current state.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-retransmission-of-informati
+    fn on_packets_lost(&mut self, space: PacketNumberSpace, packets: Vec<SentPacket>) {
+        for frame in packets.into_iter().flat_map(|packet| packet.frames) {
+            match &frame {
+                Frame::Stream(body) => self.streams.on_frame_lost(body),
+                Frame::Crypto(body) => self.spaces[space as usize]
+                    .on_crypto_lost(body.offset(), body.crypto_data().len() as u64),
+                Frame::MaxData(_)
+                | Frame::MaxStreamData(_)
+                | Frame::MaxStreams(_)
+                | Frame::DataBlocked(_)
+                | Frame::StreamDataBlocked(_)
+                | Frame::StreamsBlocked(_) => self.streams.on_flow_control_frame_lost(&frame),
+                // a new ACK frame is sent, and PING has done its job
+                Frame::Ack(_) | Frame::Padding | Frame::Ping | Frame::ConnectionClose(_) => {}
+                _ => self.spaces[space as usize].pending_frames.push_back(frame),
+            }
+        }
+    }
+
+    fn on_crypto_frame(
+        &mut self,
+        level: EncryptionLevel,
+        body: &crypto::Body,
+    ) -> Result<(), ConnectionError> {
+        let data = self.spaces[PacketNumberSpace::from(level) as usize]
+            .on_crypto_frame(body.offset(), body.crypto_data())
+            .ok_or_else(|| ConnectionError::TransportError {
+                code: CRYPTO_BUFFER_EXCEEDED,
+                frame_type: Some(CRYPTO_FRAME_TYPE),
+                reason: "too much CRYPTO data out of order".to_string(),
+            })?;
+        let Some(tls) = self.tls.as_mut() else {
+            return Ok(());
+        };
+        if !data.is_empty() {
+            tls.read_handshake(level.into(), &data)?;
+        }
+        self.process_tls()
+    }
+
+    /// Take what TLS has produced: handshake data, secrets, the transport parameters of the peer
+    /// and whether 0-RTT is accepted.
+    fn process_tls(&mut self) -> Result<(), ConnectionError> {
+        let Some(tls) = self.tls.as_mut() else {
+            return Ok(());
+        };
+        while let Some((level, data)) = tls.write_handshake() {
+            let space = PacketNumberSpace::from(EncryptionLevel::from(level));
+            self.spaces[space as usize].push_crypto(&data);
+        }
+        let early_data = tls.early_data();
+        let peer_transport_parameters = match self.peer_transport_parameters {
+            None => tls.peer_transport_parameters().map(<[u8]>::to_vec),
+            Some(_) => None,
+        };
+        let secrets = std::iter::from_fn(|| tls.next_secrets()).collect::<Vec<_>>();
+        let is_handshake_complete = tls.is_handshake_complete();
+
+        // the result of 0-RTT comes before the transport parameters of the server
+        if self.endpoint_type == EndpointType::Client
+            && self.key_sets.get(EncryptionLevel::ZeroRTT).is_some()
+            && matches!(early_data, EarlyData::Accepted | EarlyData::Rejected)
+        {
+            let frames = self.on_early_data_result(early_data == EarlyData::Accepted);
+            self.spaces[PacketNumberSpace::ApplicationData as usize]
+                .pending_frames
+                .extend(frames);
+        }
+        if let Some(parameters) = peer_transport_parameters {
+            self.on_peer_transport_parameters(&parameters)?;
+        }
+        for secrets in secrets {
+            self.install_secrets(secrets);
+        }
+        if is_handshake_complete && self.state == ConnectionState::Handshake {
+            self.state = ConnectionState::Established;
+            self.events.push_back(Event::Connected);
+            // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.2
+            if self.endpoint_type == EndpointType::Server {
+                self.spaces[PacketNumberSpace::ApplicationData as usize]
+                    .pending_frames
+                    .push_back(Frame::HandshakeDone);
+                self.on_handshake_confirmed();
+            }
+        }
+        Ok(())
+    }
+
+    fn install_secrets(&mut self, secrets: Secrets) {
+        let cipher_suite = cipher_suite::from_id(secrets.cipher_suite.to_u16())
+            .expect("TLS negotiates only the cipher suites QUIC supports");
+        let level = EncryptionLevel::from(secrets.level);
+        if level == EncryptionLevel::ZeroRTT {
+            let key_set = KeySet::from_early_secret(cipher_suite, &secrets.client);
+            match self.endpoint_type {
+                EndpointType::Client => {
+                    let remembered = self
+                        .tls
+                        .as_ref()
+                        .and_then(|tls| tls.remembered_transport_parameters())
+                        .map(<[u8]>::to_vec)
+                        .unwrap_or_default();
+                    // without the limits, no data is sent in 0-RTT
+                    if let Err(e) = self.start_early_data(key_set, &remembered) {
+                        log::debug!("0-RTT is not used: {e}");
+                    }
+                }
+                EndpointType::Server => self.install_key_set(level, key_set),
+            }
+            return;
+        }
+        let key_set = KeySet::from_secrets(
+            cipher_suite,
+            &secrets.client,
+            &secrets.server,
+            &self.endpoint_type,
+        );
+        self.install_key_set(level, key_set);
+        // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.9.3
+        if level == EncryptionLevel::OneRTT && self.endpoint_type == EndpointType::Client {
+            self.discard_key_set(EncryptionLevel::ZeroRTT);
+        }
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-discarding-handshake-keys
+    fn on_handshake_confirmed(&mut self) {
+        self.discard_space(EncryptionLevel::Handshake);
+        self.recovery.on_handshake_confirmed();
+    }
+
+    fn discard_space(&mut self, level: EncryptionLevel) {
+        self.discard_key_set(level);
+        let space = PacketNumberSpace::from(level);
+        self.spaces[space as usize].discard();
+        self.recovery.discard_space(space);
+    }
+
+    /// Enter the draining state.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-immediate-close
+    fn on_connection_close(&mut self, now: Instant, body: &connection_close::Body) {
+        let code = body.error_code();
+        let reason = body.reason_phrase().to_string();
+        let error = if body.this_frame_type() == 0x1d {
+            ConnectionError::PeerApplicationClosed { code, reason }
+        } else {
+            ConnectionError::PeerTransportError { code, reason }
+        };
+        self.state = ConnectionState::Draining;
+        self.close_deadline = Some(now + self.pto() * 3);
+        self.events.push_back(Event::Closed(error.clone()));
+        self.error = Some(error);
+    }
+
+    /// The next datagram to send, with packets of each encryption level coalesced.
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<Vec<u8>> {
+        match self.state {
+            ConnectionState::Handshake | ConnectionState::Established => {}
+            ConnectionState::Closing if self.close_pending => {
+                self.close_pending = false;
+                return Some(self.close_datagram(now));
+            }
+            _ => return None,
+        }
+        let budget = if self.address_validated {
+            MAX_DATAGRAM_SIZE
+        } else {
+            let allowance = (self.bytes_received * 3).saturating_sub(self.bytes_sent);
+            MAX_DATAGRAM_SIZE.min(allowance as usize)
+        };
+        let mut remaining = budget;
+        let mut packets = Vec::new();
+        for level in LEVELS {
+            let overhead = self.packet_overhead(level);
+            if self.key_sets.get(level).is_none() || remaining <= overhead {
+                continue;
+            }
+            let frames = self.frames_to_send(level, remaining - overhead, now);
+            if frames.is_empty() {
+                continue;
+            }
+            remaining -= overhead + frames.iter().map(Frame::raw_length).sum::<usize>();
+            packets.push((level, frames));
+        }
+        let has_initial = packets
+            .iter()
+            .any(|(level, _)| *level == EncryptionLevel::Initial);
+        if packets.is_empty() || (has_initial && budget < MAX_DATAGRAM_SIZE) {
+            return None;
+        }
+        if packets
+            .iter()
+            .any(|(level, _)| *level == EncryptionLevel::OneRTT)
+        {
+            let packet_number = self.spaces[PacketNumberSpace::ApplicationData as usize]
+                .sending
+                .next_packet_number()
+                .to_u64();
+            let keys = self
+                .key_sets
+                .one_rtt_mut()
+                .expect("1-RTT packets are sent with the keys");
+            let current_phase = keys.key_phase();
+            match keys.sealing_keys(packet_number) {
+                Ok((key_phase, _)) if key_phase != current_phase => self.on_key_update(now),
+                Ok(_) => {}
+                Err(e) => {
+                    self.close_with_error(now, e.into());
+                    return self.poll_transmit(now);
+                }
+            }
+        }
+        let is_ack_eliciting = packets
+            .iter()
+            .any(|(_, frames)| frames.iter().any(Frame::is_ack_eliciting));
+        if is_ack_eliciting && !self.ack_eliciting_sent {
+            self.ack_eliciting_sent = true;
+            self.restart_idle_timer(now);
+        }
+        let sent_handshake = packets
+            .iter()
+            .any(|(level, _)| *level == EncryptionLevel::Handshake);
+        let datagram = self.assemble(now, packets, has_initial);
+        // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.9.1
+        if sent_handshake && self.endpoint_type == EndpointType::Client {
+            self.discard_space(EncryptionLevel::Initial);
+        }
+        Some(datagram)
+    }
+
+    /// Frames to send in a packet of the level, which fit in `max_length` bytes.
+    fn frames_to_send(
+        &mut self,
+        level: EncryptionLevel,
+        max_length: usize,
+        now: Instant,
+    ) -> Vec<Frame> {
+        if level == EncryptionLevel::ZeroRTT {
+            return self.early_frames_to_send(max_length);
+        }
+        if level == EncryptionLevel::OneRTT {
+            let frames = self.streams.flow_control_frames(now, self.rtt());
+            self.queue_frames(frames);
+        }
+        let mut frames = Vec::new();
+        let mut remaining = max_length;
+        let ack_delay_exponent = self.local_transport_parameters.ack_delay_exponent;
+        let space = &mut self.spaces[PacketNumberSpace::from(level) as usize];
+        if space.is_ack_pending() {
+            if let Some(ack) = space.ack_frame(ack_delay_exponent, now) {
+                remaining = remaining.saturating_sub(ack.raw_length());
+                frames.push(ack);
+            }
+        }
+        while let Some(frame) = space.next_crypto_frame(remaining) {
+            remaining -= frame.raw_length();
+            frames.push(frame);
+        }
+        while let Some(frame) = space.pending_frames.front() {
+            if frame.raw_length() > remaining {
+                break;
+            }
+            remaining -= frame.raw_length();
+            frames.extend(space.pending_frames.pop_front());
+        }
+        if level == EncryptionLevel::OneRTT {
+            while let Some(frame) = self.streams.next_frame(remaining) {
+                remaining -= frame.raw_length();
+                frames.push(frame);
+            }
+        }
+        let space = &self.spaces[PacketNumberSpace::from(level) as usize];
+        if space.probes > 0 && remaining > 0 && !frames.iter().any(Frame::is_ack_eliciting) {
+            frames.push(Frame::Ping);
+        }
+        frames
+    }
+
+    /// STREAM frames within the limits remembered for 0-RTT.
+    /// The rest of the data waits for 1-RTT.
+    fn early_frames_to_send(&mut self, max_length: usize) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        // a server only receives 0-RTT packets
+        if self.endpoint_type == EndpointType::Server {
+            return frames;
+        }
+        let mut remaining = max_length;
+        while let Some(frame) = self.streams.next_frame(remaining) {
+            let Frame::Stream(body) = &frame else {
+                unreachable!("streams send only STREAM frames")
+            };
+            if !self.is_within_early_limits(body.stream_id(), body.offset(), body.data_length()) {
+                self.queue_frames([frame]);
+                break;
+            }
+            remaining -= frame.raw_length();
+            self.early_frames.push(frame.clone());
+            self.early_data = EarlyDataStatus::Pending;
+            frames.push(frame);
+        }
+        frames
+    }
+
+    /// CONNECTION_CLOSE in every encryption level the peer may have keys of.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-10.2.3
+    fn close_datagram(&mut self, now: Instant) -> Vec<u8> {
+        let error = self.error.as_ref().expect("closed with an error");
+        let packets = [
+            EncryptionLevel::Initial,
+            EncryptionLevel::Handshake,
+            EncryptionLevel::OneRTT,
+        ]
+        .into_iter()
+        .filter(|level| self.key_sets.get(*level).is_some())
+        .map(|level| (level, vec![close_frame(error, level)]))
+        .collect::<Vec<_>>();
+        let pad = self.endpoint_type == EndpointType::Client
+            && packets
+                .iter()
+                .any(|(level, _)| *level == EncryptionLevel::Initial);
+        self.assemble(now, packets, pad)
+    }
+
+    /// Protect and coalesce packets of the frames, and remember them until they are acknowledged or lost.
+    /// The last packet is padded if the datagram has to be expanded.
+    fn assemble(
+        &mut self,
+        now: Instant,
+        packets: Vec<(EncryptionLevel, Vec<Frame>)>,
+        pad: bool,
+    ) -> Vec<u8> {
+        let mut datagram = Vec::new();
+        let count = packets.len();
+        for (i, (level, frames)) in packets.into_iter().enumerate() {
+            let space = PacketNumberSpace::from(level);
+            let sending = &self.spaces[space as usize].sending;
+            let packet_number = sending.next_packet_number().to_u64();
+            let is_ack_eliciting = frames.iter().any(Frame::is_ack_eliciting);
+            let mut payload = Frames::new(frames.clone()).to_bytes();
+            // 4 bytes from the packet number are sampled for the header protection
+            // https://www.rfc-editor.org/rfc/rfc9001.html#section-5.4.2
+            let minimum = 4usize.saturating_sub(sending.packet_number_length());
+            if payload.len() < minimum {
+                payload.resize(minimum, 0);
+            }
+            let mut packet = self.build_packet(level, payload.clone());
+            // the Length field is at least 2 bytes, so the padding does not change the header
+            let length = datagram.len() + packet.raw().len();
+            if pad && i + 1 == count && length < MAX_DATAGRAM_SIZE {
+                payload.resize(payload.len() + MAX_DATAGRAM_SIZE - length, 0);
+                packet = self.build_packet(level, payload);
+            }
+            datagram.extend_from_slice(packet.raw());
+            let packet_space = &mut self.spaces[space as usize];
+            packet_space.sending.on_packet_sent();
+            if is_ack_eliciting {
+                packet_space.probes = packet_space.probes.saturating_sub(1);
+            }
+            self.recovery.on_packet_sent(
+                space,
+                packet_number,
+                SentPacket {
+                    time_sent: now,
+                    is_ack_eliciting,
+                    is_zero_rtt: level == EncryptionLevel::ZeroRTT,
+                    frames,
+                },
+            );
+        }
+        if !self.address_validated {
+            self.bytes_sent += datagram.len() as u64;
+        }
+        datagram
+    }
+
+    fn build_packet(&self, level: EncryptionLevel, payload: Vec<u8>) -> Packet {
+        let sending = &self.spaces[PacketNumberSpace::from(level) as usize].sending;
+        let payload = PacketPayload::from_vec(payload);
+        match level {
+            EncryptionLevel::Initial => Packet::new_initial(self, sending, payload),
+            EncryptionLevel::ZeroRTT => Packet::new_zero_rtt(self, sending, payload),
+            EncryptionLevel::Handshake => Packet::new_handshake(self, sending, payload),
+            EncryptionLevel::OneRTT => Packet::new_one_rtt(self, sending, false, payload),
+        }
+    }
+
+    /// Bytes of a packet other than the frames.
+    /// The Length field is counted as 2 bytes, which is enough for a datagram.
+    fn packet_overhead(&self, level: EncryptionLevel) -> usize {
+        let packet_number_length = self.spaces[PacketNumberSpace::from(level) as usize]
+            .sending
+            .packet_number_length();
+        let tag_length = self
+            .key_sets
+            .get(level)
+            .map_or(16, |key_set| key_set.local().tag_length());
+        let header_length = match level {
+            EncryptionLevel::OneRTT => 1 + self.destination_connection_id.len(),
+            _ => {
+                let token_length = match level {
+                    EncryptionLevel::Initial => self.token.raw_length(),
+                    _ => 0,
+                };
+                1 + 4
+                    + 1
+                    + self.destination_connection_id.len()
+                    + 1
+                    + self.source_connection_id.len()
+                    + token_length
+                    + 2
+            }
+        };
+        header_length + packet_number_length + tag_length
+    }
+}
+
+/// An application error is hidden in Initial and Handshake packets,
+/// since they are not protected enough to carry it.
+fn close_frame(error: &ConnectionError, level: EncryptionLevel) -> Frame {
+    let body = match error {
+        ConnectionError::TransportError {
+            code,
+            frame_type,
+            reason,
+        } => connection_close::Body::new(
+            *code,
+            Some(FrameType::from_u64(frame_type.unwrap_or(0))),
+            reason.clone(),
+        ),
+        ConnectionError::ApplicationClosed { code, reason } if level == EncryptionLevel::OneRTT => {
+            connection_close::Body::new(*code, None, reason.clone())
+        }
+        _ => {
+            connection_close::Body::new(APPLICATION_ERROR, Some(FrameType::Padding), String::new())
+        }
+    };
+    Frame::ConnectionClose(body)
 }
+
+#[cfg(test)]
+pub(crate) mod tests;
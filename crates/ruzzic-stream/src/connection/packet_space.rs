@@ -0,0 +1,307 @@
+//! What a connection keeps for each packet number space.
+//! https://www.rfc-editor.org/rfc/rfc9000.html#name-packet-numbers
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    endpoint_state::EndpointState,
+    frame::{ack, crypto, Frame},
+    packet::PacketNumber,
+};
+
+/// Ranges of received packet numbers reported in ACK frames.
+/// Older ranges are forgotten, since the peer stops sending them again at some point.
+const MAX_ACK_RANGES: usize = 32;
+
+/// How far out of order CRYPTO data is buffered.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-7.5
+pub(crate) const MAX_CRYPTO_BUFFER: u64 = 64 * 1024;
+
+pub(crate) struct PacketSpace {
+    /// Packet numbers to send with.
+    pub(crate) sending: EndpointState,
+    /// Received packet numbers as inclusive ranges in ascending order.
+    received: Vec<(u64, u64)>,
+    largest_received_at: Option<Instant>,
+    /// Whether an ack-eliciting packet is received since the last ACK frame.
+    ack_pending: bool,
+    /// The whole CRYPTO stream to send, and how much of it is sent.
+    crypto_send: Vec<u8>,
+    crypto_sent: u64,
+    /// Ranges of CRYPTO data lost, sent again before new data, by offset.
+    crypto_lost: BTreeMap<u64, u64>,
+    /// CRYPTO data received out of order, by offset.
+    crypto_received: BTreeMap<u64, Vec<u8>>,
+    /// Offset up to which CRYPTO data is delivered to TLS.
+    crypto_delivered: u64,
+    /// Frames other than ACK and CRYPTO waiting to be sent.
+    pub(crate) pending_frames: VecDeque<Frame>,
+    /// Ack-eliciting packets to send when the probe timeout expires.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.4
+    pub(crate) probes: usize,
+}
+
+impl PacketSpace {
+    pub(crate) fn new(sending: EndpointState) -> Self {
+        Self {
+            sending,
+            received: Vec::new(),
+            largest_received_at: None,
+            ack_pending: false,
+            crypto_send: Vec::new(),
+            crypto_sent: 0,
+            crypto_lost: BTreeMap::new(),
+            crypto_received: BTreeMap::new(),
+            crypto_delivered: 0,
+            pending_frames: VecDeque::new(),
+            probes: 0,
+        }
+    }
+
+    /// Forget what is received and what is to be sent, when the keys of the space are discarded.
+    /// Packet numbers are kept.
+    pub(crate) fn discard(&mut self) {
+        let sending = std::mem::replace(&mut self.sending, EndpointState::new_client(None));
+        *self = Self::new(sending);
+    }
+
+    pub(crate) fn is_duplicate(&self, packet_number: PacketNumber) -> bool {
+        let packet_number = packet_number.to_u64();
+        self.received
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&packet_number))
+    }
+
+    /// Must be called after the payload of a packet is processed.
+    pub(crate) fn on_packet_received(
+        &mut self,
+        packet_number: PacketNumber,
+        is_ack_eliciting: bool,
+        now: Instant,
+    ) {
+        let packet_number = packet_number.to_u64();
+        if self
+            .received
+            .last()
+            .is_none_or(|(_, end)| packet_number > *end)
+        {
+            self.largest_received_at = Some(now);
+        }
+        let index = self
+            .received
+            .partition_point(|(start, _)| *start < packet_number);
+        self.received.insert(index, (packet_number, packet_number));
+        // merge with the neighbors
+        if index + 1 < self.received.len() && self.received[index + 1].0 == packet_number + 1 {
+            let (_, end) = self.received.remove(index + 1);
+            self.received[index].1 = end;
+        }
+        if index > 0 && self.received[index - 1].1 + 1 == packet_number {
+            let (_, end) = self.received.remove(index);
+            self.received[index - 1].1 = end;
+        }
+        if self.received.len() > MAX_ACK_RANGES {
+            self.received.remove(0);
+        }
+        self.ack_pending |= is_ack_eliciting;
+    }
+
+    pub(crate) fn is_ack_pending(&self) -> bool {
+        self.ack_pending
+    }
+
+    /// ACK frame of all the packets received, with the time since the largest one is received.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-ack-frames
+    pub(crate) fn ack_frame(&mut self, ack_delay_exponent: u64, now: Instant) -> Option<Frame> {
+        let (&(smallest, largest), rest) = self.received.split_last()?;
+        let ack_delay = self
+            .largest_received_at
+            .map_or(Duration::ZERO, |received_at| now - received_at);
+        let mut ack_ranges = Vec::new();
+        let mut previous_smallest = smallest;
+        for &(start, end) in rest.iter().rev() {
+            ack_ranges.push(ack::AckRange::new(previous_smallest - end - 2, end - start));
+            previous_smallest = start;
+        }
+        self.ack_pending = false;
+        Some(Frame::Ack(ack::Body::new(
+            PacketNumber(largest),
+            ack_delay.as_micros() as u64 >> ack_delay_exponent,
+            largest - smallest,
+            ack_ranges,
+            None,
+        )))
+    }
+
+    /// Queue handshake data TLS writes in this space.
+    pub(crate) fn push_crypto(&mut self, data: &[u8]) {
+        self.crypto_send.extend_from_slice(data);
+    }
+
+    pub(crate) fn has_crypto_to_send(&self) -> bool {
+        !self.crypto_lost.is_empty() || self.crypto_sent < self.crypto_send.len() as u64
+    }
+
+    /// CRYPTO frame of the data lost or not sent yet, which fits in `max_length` bytes.
+    pub(crate) fn next_crypto_frame(&mut self, max_length: usize) -> Option<Frame> {
+        if !self.has_crypto_to_send() {
+            return None;
+        }
+        // offset and length take at most 8 bytes each, and the frame type 1 byte
+        let max_length = max_length.checked_sub(17).filter(|length| *length > 0)?;
+        let (start, end) = match self.crypto_lost.pop_first() {
+            Some((start, end)) => {
+                let split = end.min(start + max_length as u64);
+                if split < end {
+                    self.crypto_lost.insert(split, end);
+                }
+                (start, split)
+            }
+            None => {
+                let start = self.crypto_sent;
+                self.crypto_sent = (self.crypto_send.len() as u64).min(start + max_length as u64);
+                (start, self.crypto_sent)
+            }
+        };
+        Some(Frame::Crypto(crypto::Body::new(
+            start,
+            self.crypto_send[start as usize..end as usize].to_vec(),
+        )))
+    }
+
+    /// Must be called when a packet with a CRYPTO frame is lost.
+    pub(crate) fn on_crypto_lost(&mut self, offset: u64, length: u64) {
+        if length == 0 || offset >= self.crypto_sent {
+            return;
+        }
+        let end = (offset + length).min(self.crypto_sent);
+        let entry = self.crypto_lost.entry(offset).or_default();
+        *entry = (*entry).max(end);
+    }
+
+    /// Send the CRYPTO stream from the beginning, as a client does after a Retry packet.
+    pub(crate) fn resend_crypto(&mut self) {
+        self.crypto_sent = 0;
+        self.crypto_lost.clear();
+    }
+
+    /// Buffer CRYPTO data and return what is contiguous with the data delivered so far.
+    /// None if the data is too far ahead to buffer.
+    pub(crate) fn on_crypto_frame(&mut self, offset: u64, data: &[u8]) -> Option<Vec<u8>> {
+        let end = offset + data.len() as u64;
+        if end > self.crypto_delivered + MAX_CRYPTO_BUFFER {
+            return None;
+        }
+        if end > self.crypto_delivered {
+            let skip = self.crypto_delivered.saturating_sub(offset);
+            let offset = offset + skip;
+            let data = &data[skip as usize..];
+            let buffered = self.crypto_received.entry(offset).or_default();
+            if data.len() > buffered.len() {
+                *buffered = data.to_vec();
+            }
+        }
+        let mut contiguous = Vec::new();
+        while let Some(entry) = self.crypto_received.first_entry() {
+            if *entry.key() > self.crypto_delivered {
+                break;
+            }
+            let (offset, data) = entry.remove_entry();
+            let skip = (self.crypto_delivered - offset) as usize;
+            if skip < data.len() {
+                contiguous.extend_from_slice(&data[skip..]);
+                self.crypto_delivered += (data.len() - skip) as u64;
+            }
+        }
+        Some(contiguous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space() -> PacketSpace {
+        PacketSpace::new(EndpointState::new_client(None))
+    }
+
+    #[test]
+    fn ack_ranges() {
+        let now = Instant::now();
+        let mut space = space();
+        assert!(space.ack_frame(3, now).is_none());
+        for packet_number in [0, 1, 2, 5, 7, 6, 9] {
+            space.on_packet_received(PacketNumber(packet_number), true, now);
+        }
+        assert!(space.is_duplicate(PacketNumber(6)));
+        assert!(!space.is_duplicate(PacketNumber(8)));
+        assert_eq!(space.received, vec![(0, 2), (5, 7), (9, 9)]);
+        let expected = Frame::Ack(ack::Body::new(
+            PacketNumber(9),
+            0,
+            0,
+            vec![ack::AckRange::new(0, 2), ack::AckRange::new(1, 2)],
+            None,
+        ));
+        assert_eq!(space.ack_frame(3, now), Some(expected));
+        assert!(!space.is_ack_pending());
+
+        // an ACK frame is not acknowledged
+        space.on_packet_received(PacketNumber(8), false, now);
+        assert!(!space.is_ack_pending());
+        assert_eq!(space.received, vec![(0, 2), (5, 9)]);
+    }
+
+    #[test]
+    fn crypto_reassembly() {
+        let mut space = space();
+        assert_eq!(space.on_crypto_frame(3, b"def"), Some(Vec::new()));
+        assert_eq!(space.on_crypto_frame(0, b"ab"), Some(b"ab".to_vec()));
+        // overlaps what is delivered and what is buffered
+        assert_eq!(space.on_crypto_frame(1, b"bcd"), Some(b"cdef".to_vec()));
+        assert_eq!(space.on_crypto_frame(0, b"abcdef"), Some(Vec::new()));
+        // the limit moves with what is delivered
+        assert_eq!(
+            space.on_crypto_frame(MAX_CRYPTO_BUFFER + 5, b"g"),
+            Some(Vec::new())
+        );
+        assert_eq!(space.on_crypto_frame(MAX_CRYPTO_BUFFER + 6, b"h"), None);
+    }
+
+    #[test]
+    fn crypto_frames() {
+        let mut space = space();
+        space.push_crypto(&[1; 100]);
+        let Some(Frame::Crypto(first)) = space.next_crypto_frame(67) else {
+            panic!("no CRYPTO frame")
+        };
+        assert_eq!((first.offset(), first.crypto_data().len()), (0, 50));
+        let Some(Frame::Crypto(second)) = space.next_crypto_frame(1200) else {
+            panic!("no CRYPTO frame")
+        };
+        assert_eq!((second.offset(), second.crypto_data().len()), (50, 50));
+        assert!(space.next_crypto_frame(1200).is_none());
+
+        // lost data is sent again first
+        space.push_crypto(&[2; 10]);
+        space.on_crypto_lost(40, 30);
+        let Some(Frame::Crypto(lost)) = space.next_crypto_frame(37) else {
+            panic!("no CRYPTO frame")
+        };
+        assert_eq!((lost.offset(), lost.crypto_data().len()), (40, 20));
+        let Some(Frame::Crypto(lost)) = space.next_crypto_frame(1200) else {
+            panic!("no CRYPTO frame")
+        };
+        assert_eq!((lost.offset(), lost.crypto_data().len()), (60, 10));
+        let Some(Frame::Crypto(new)) = space.next_crypto_frame(1200) else {
+            panic!("no CRYPTO frame")
+        };
+        assert_eq!((new.offset(), new.crypto_data()), (100, &[2; 10][..]));
+        assert!(space.next_crypto_frame(1200).is_none());
+        space.resend_crypto();
+        assert!(space.has_crypto_to_send());
+    }
+}
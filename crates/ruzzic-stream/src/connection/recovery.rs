@@ -0,0 +1,498 @@
+//! Loss detection and the probe timeout.
+//! https://www.rfc-editor.org/rfc/rfc9002.html
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use ruzzic_common::EndpointType;
+
+use crate::{frame::Frame, packet::PacketNumberSpace};
+
+/// RTT assumed before it is measured.
+/// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.2
+const INITIAL_RTT: Duration = Duration::from_millis(333);
+/// A packet is lost when this many packets sent after it are acknowledged.
+/// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.1.1
+const PACKET_THRESHOLD: u64 = 3;
+/// Timer granularity.
+/// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.1.2
+const GRANULARITY: Duration = Duration::from_millis(1);
+
+const SPACES: [PacketNumberSpace; 3] = [
+    PacketNumberSpace::Initial,
+    PacketNumberSpace::Handshake,
+    PacketNumberSpace::ApplicationData,
+];
+
+#[derive(Debug, Clone)]
+pub(crate) struct SentPacket {
+    pub(crate) time_sent: Instant,
+    pub(crate) is_ack_eliciting: bool,
+    /// 0-RTT packets are forgotten if the server rejects 0-RTT.
+    pub(crate) is_zero_rtt: bool,
+    /// The frames are sent again if the packet is lost, not the packet itself.
+    pub(crate) frames: Vec<Frame>,
+}
+
+/// https://www.rfc-editor.org/rfc/rfc9002.html#name-estimating-the-round-trip-t
+#[derive(Debug)]
+pub(crate) struct RttEstimator {
+    latest_rtt: Duration,
+    smoothed_rtt: Duration,
+    rttvar: Duration,
+    min_rtt: Duration,
+    has_sample: bool,
+}
+
+impl RttEstimator {
+    fn new() -> Self {
+        Self {
+            latest_rtt: Duration::ZERO,
+            smoothed_rtt: INITIAL_RTT,
+            rttvar: INITIAL_RTT / 2,
+            min_rtt: Duration::ZERO,
+            has_sample: false,
+        }
+    }
+
+    pub(crate) fn smoothed_rtt(&self) -> Duration {
+        self.smoothed_rtt
+    }
+
+    /// The acknowledgment delay is subtracted unless the sample gets smaller than min_rtt with it.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-5.3
+    fn update(&mut self, latest_rtt: Duration, ack_delay: Duration) {
+        self.latest_rtt = latest_rtt;
+        if !self.has_sample {
+            self.has_sample = true;
+            self.min_rtt = latest_rtt;
+            self.smoothed_rtt = latest_rtt;
+            self.rttvar = latest_rtt / 2;
+            return;
+        }
+        self.min_rtt = self.min_rtt.min(latest_rtt);
+        let adjusted_rtt = if latest_rtt >= self.min_rtt + ack_delay {
+            latest_rtt - ack_delay
+        } else {
+            latest_rtt
+        };
+        self.rttvar = (self.rttvar * 3 + self.smoothed_rtt.abs_diff(adjusted_rtt)) / 4;
+        self.smoothed_rtt = (self.smoothed_rtt * 7 + adjusted_rtt) / 8;
+    }
+
+    /// The time a packet is lost after a later one is acknowledged.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.1.2
+    fn loss_delay(&self) -> Duration {
+        (self.latest_rtt.max(self.smoothed_rtt) * 9 / 8).max(GRANULARITY)
+    }
+}
+
+#[derive(Debug, Default)]
+struct SpaceRecovery {
+    /// Packets not acknowledged nor lost, by packet number.
+    sent: BTreeMap<u64, SentPacket>,
+    largest_acked: Option<u64>,
+    /// When the earliest packet not lost yet gets lost by the time threshold.
+    loss_time: Option<Instant>,
+    time_of_last_ack_eliciting_packet: Option<Instant>,
+    is_discarded: bool,
+}
+
+impl SpaceRecovery {
+    fn has_ack_eliciting_in_flight(&self) -> bool {
+        self.sent.values().any(|packet| packet.is_ack_eliciting)
+    }
+}
+
+/// What to do when the loss detection timer expires.
+#[derive(Debug)]
+pub(crate) enum Timeout {
+    /// The frames of the packets are sent again.
+    Lost(PacketNumberSpace, Vec<SentPacket>),
+    /// Send ack-eliciting packets in the space, with new data if there is any or PING.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.4
+    Probe(PacketNumberSpace),
+}
+
+/// Sent packets of each packet number space, and the timers to tell when they are lost.
+pub(crate) struct Recovery {
+    rtt: RttEstimator,
+    spaces: [SpaceRecovery; 3],
+    /// The times the PTO has expired since an acknowledgment, by which the PTO doubles.
+    pto_count: u32,
+    /// max_ack_delay of the peer.
+    max_ack_delay: Duration,
+    is_handshake_confirmed: bool,
+    /// A client keeps probing until the server can not be blocked by the anti-amplification limit.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.2.1
+    is_peer_address_validated: bool,
+    /// Anti-deadlock probes are timed from this, when nothing is in flight.
+    last_ack_eliciting_sent: Option<Instant>,
+}
+
+impl Recovery {
+    pub(crate) fn new(endpoint_type: EndpointType) -> Self {
+        Self {
+            rtt: RttEstimator::new(),
+            spaces: Default::default(),
+            pto_count: 0,
+            max_ack_delay: Duration::ZERO,
+            is_handshake_confirmed: false,
+            is_peer_address_validated: endpoint_type == EndpointType::Server,
+            last_ack_eliciting_sent: None,
+        }
+    }
+
+    pub(crate) fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    pub(crate) fn set_max_ack_delay(&mut self, max_ack_delay: Duration) {
+        self.max_ack_delay = max_ack_delay;
+    }
+
+    /// The peer's max_ack_delay is only counted after this.
+    pub(crate) fn on_handshake_confirmed(&mut self) {
+        self.is_handshake_confirmed = true;
+        self.is_peer_address_validated = true;
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-on-sending-a-packet
+    pub(crate) fn on_packet_sent(
+        &mut self,
+        space: PacketNumberSpace,
+        packet_number: u64,
+        packet: SentPacket,
+    ) {
+        let recovery = &mut self.spaces[space as usize];
+        if packet.is_ack_eliciting {
+            recovery.time_of_last_ack_eliciting_packet = Some(packet.time_sent);
+            self.last_ack_eliciting_sent = Some(packet.time_sent);
+        }
+        recovery.sent.insert(packet_number, packet);
+    }
+
+    /// Returns the packets newly acknowledged and the ones lost, in order of packet numbers.
+    /// `ranges` are inclusive ranges of packet numbers, and `ack_delay` is ignored until
+    /// the handshake is confirmed.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-on-receiving-an-acknowledgm
+    pub(crate) fn on_ack_received(
+        &mut self,
+        space: PacketNumberSpace,
+        ranges: &[(u64, u64)],
+        ack_delay: Duration,
+        now: Instant,
+    ) -> (Vec<SentPacket>, Vec<SentPacket>) {
+        let Some(largest_acknowledged) = ranges.iter().map(|(_, end)| *end).max() else {
+            return (Vec::new(), Vec::new());
+        };
+        let recovery = &mut self.spaces[space as usize];
+        recovery.largest_acked = recovery.largest_acked.max(Some(largest_acknowledged));
+        let mut acked = BTreeMap::new();
+        for (start, end) in ranges {
+            let packet_numbers = recovery
+                .sent
+                .range(start..=end)
+                .map(|(packet_number, _)| *packet_number)
+                .collect::<Vec<_>>();
+            for packet_number in packet_numbers {
+                acked.extend(recovery.sent.remove_entry(&packet_number));
+            }
+        }
+        if acked.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        // the RTT is sampled when the largest packet is newly acknowledged
+        if let Some(largest) = acked.get(&largest_acknowledged) {
+            if acked.values().any(|packet| packet.is_ack_eliciting) {
+                let ack_delay = if self.is_handshake_confirmed {
+                    ack_delay.min(self.max_ack_delay)
+                } else {
+                    Duration::ZERO
+                };
+                self.rtt
+                    .update(now.saturating_duration_since(largest.time_sent), ack_delay);
+            }
+        }
+        let lost = self.detect_lost_packets(space, now);
+        // the server can send probes freely once it has the Handshake packets of the client
+        if space == PacketNumberSpace::Handshake {
+            self.is_peer_address_validated = true;
+        }
+        if self.is_peer_address_validated {
+            self.pto_count = 0;
+        }
+        (acked.into_values().collect(), lost)
+    }
+
+    /// Packets sent long enough before, or enough packets before the largest acknowledged are lost.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-detecting-lost-packets
+    fn detect_lost_packets(&mut self, space: PacketNumberSpace, now: Instant) -> Vec<SentPacket> {
+        let loss_delay = self.rtt.loss_delay();
+        let recovery = &mut self.spaces[space as usize];
+        recovery.loss_time = None;
+        let Some(largest_acked) = recovery.largest_acked else {
+            return Vec::new();
+        };
+        let lost_send_time = now.checked_sub(loss_delay);
+        let mut lost = Vec::new();
+        for (packet_number, packet) in recovery.sent.range(..=largest_acked) {
+            if lost_send_time.is_some_and(|time| packet.time_sent <= time)
+                || largest_acked >= packet_number + PACKET_THRESHOLD
+            {
+                lost.push(*packet_number);
+            } else {
+                let loss_time = packet.time_sent + loss_delay;
+                recovery.loss_time =
+                    Some(recovery.loss_time.map_or(loss_time, |t| t.min(loss_time)));
+            }
+        }
+        lost.into_iter()
+            .filter_map(|packet_number| recovery.sent.remove(&packet_number))
+            .collect()
+    }
+
+    /// Frames of the packets not acknowledged nor lost yet.
+    pub(crate) fn frames_in_flight(
+        &self,
+        space: PacketNumberSpace,
+    ) -> impl Iterator<Item = &Frame> {
+        self.spaces[space as usize]
+            .sent
+            .values()
+            .flat_map(|packet| &packet.frames)
+    }
+
+    /// Packets in the space are forgotten without being lost, when the keys are discarded.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-on-discarding-keys
+    pub(crate) fn discard_space(&mut self, space: PacketNumberSpace) {
+        self.spaces[space as usize] = SpaceRecovery {
+            is_discarded: true,
+            ..Default::default()
+        };
+        self.pto_count = 0;
+    }
+
+    /// 0-RTT packets are not retransmitted as such, when the server rejects 0-RTT.
+    pub(crate) fn discard_zero_rtt(&mut self) {
+        self.spaces[PacketNumberSpace::ApplicationData as usize]
+            .sent
+            .retain(|_, packet| !packet.is_zero_rtt);
+    }
+
+    /// The probe timeout without the backoff.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.1
+    pub(crate) fn pto(&self) -> Duration {
+        self.rtt.smoothed_rtt + (self.rtt.rttvar * 4).max(GRANULARITY) + self.max_ack_delay
+    }
+
+    fn pto_time_and_space(&self) -> Option<(Instant, PacketNumberSpace)> {
+        let backoff = 2u32.saturating_pow(self.pto_count);
+        let duration = (self.rtt.smoothed_rtt + (self.rtt.rttvar * 4).max(GRANULARITY)) * backoff;
+        // a client probes to unblock the server, with nothing in flight
+        if !self.has_ack_eliciting_in_flight() {
+            let space = if self.spaces[PacketNumberSpace::Initial as usize].is_discarded {
+                PacketNumberSpace::Handshake
+            } else {
+                PacketNumberSpace::Initial
+            };
+            return Some((self.last_ack_eliciting_sent? + duration, space));
+        }
+        let mut earliest: Option<(Instant, PacketNumberSpace)> = None;
+        for space in SPACES {
+            let recovery = &self.spaces[space as usize];
+            if !recovery.has_ack_eliciting_in_flight() {
+                continue;
+            }
+            let mut duration = duration;
+            if space == PacketNumberSpace::ApplicationData {
+                // https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.1-8
+                if !self.is_handshake_confirmed {
+                    break;
+                }
+                duration += self.max_ack_delay * backoff;
+            }
+            let Some(sent) = recovery.time_of_last_ack_eliciting_packet else {
+                continue;
+            };
+            if earliest.is_none_or(|(time, _)| sent + duration < time) {
+                earliest = Some((sent + duration, space));
+            }
+        }
+        earliest
+    }
+
+    fn has_ack_eliciting_in_flight(&self) -> bool {
+        self.spaces
+            .iter()
+            .any(SpaceRecovery::has_ack_eliciting_in_flight)
+    }
+
+    fn earliest_loss_time(&self) -> Option<(Instant, PacketNumberSpace)> {
+        SPACES
+            .into_iter()
+            .filter_map(|space| Some((self.spaces[space as usize].loss_time?, space)))
+            .min_by_key(|(time, _)| *time)
+    }
+
+    /// When `on_timeout` must be called.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-setting-the-loss-detection
+    pub(crate) fn loss_detection_timer(&self) -> Option<Instant> {
+        if let Some((time, _)) = self.earliest_loss_time() {
+            return Some(time);
+        }
+        if !self.has_ack_eliciting_in_flight() && self.is_peer_address_validated {
+            return None;
+        }
+        self.pto_time_and_space().map(|(time, _)| time)
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#name-on-timeout
+    pub(crate) fn on_timeout(&mut self, now: Instant) -> Option<Timeout> {
+        if let Some((_, space)) = self.earliest_loss_time() {
+            let lost = self.detect_lost_packets(space, now);
+            return Some(Timeout::Lost(space, lost));
+        }
+        let (_, space) = self.pto_time_and_space()?;
+        self.pto_count += 1;
+        Some(Timeout::Probe(space))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(time_sent: Instant) -> SentPacket {
+        SentPacket {
+            time_sent,
+            is_ack_eliciting: true,
+            is_zero_rtt: false,
+            frames: vec![Frame::Ping],
+        }
+    }
+
+    fn sent_times(packets: &[SentPacket], start: Instant) -> Vec<Duration> {
+        packets
+            .iter()
+            .map(|packet| packet.time_sent - start)
+            .collect()
+    }
+
+    #[test]
+    fn rtt_estimation() {
+        let mut rtt = RttEstimator::new();
+        assert_eq!(rtt.smoothed_rtt(), INITIAL_RTT);
+        rtt.update(Duration::from_millis(100), Duration::from_millis(10));
+        assert_eq!(rtt.smoothed_rtt(), Duration::from_millis(100));
+        assert_eq!(rtt.rttvar, Duration::from_millis(50));
+        // the acknowledgment delay is subtracted
+        rtt.update(Duration::from_millis(140), Duration::from_millis(20));
+        assert_eq!(rtt.smoothed_rtt(), Duration::from_millis(102500) / 1000);
+        assert_eq!(rtt.rttvar, Duration::from_millis(42500) / 1000);
+        // not below min_rtt
+        rtt.update(Duration::from_millis(90), Duration::from_millis(20));
+        assert_eq!(rtt.min_rtt, Duration::from_millis(90));
+        assert_eq!(rtt.latest_rtt, Duration::from_millis(90));
+    }
+
+    #[test]
+    fn packet_threshold() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut recovery = Recovery::new(EndpointType::Server);
+        for packet_number in 0..5 {
+            recovery.on_packet_sent(
+                PacketNumberSpace::Initial,
+                packet_number,
+                packet(start + ms(packet_number)),
+            );
+        }
+        // the RTT is 20ms, and the time threshold 22.5ms
+        let (acked, lost) = recovery.on_ack_received(
+            PacketNumberSpace::Initial,
+            &[(4, 4)],
+            Duration::ZERO,
+            start + ms(24),
+        );
+        assert_eq!(sent_times(&acked, start), [ms(4)]);
+        // three packets after them are acknowledged
+        assert_eq!(sent_times(&lost, start), [ms(0), ms(1)]);
+        // the others are lost after the time threshold
+        let loss_time = recovery.loss_detection_timer().unwrap();
+        assert_eq!(loss_time, start + ms(2) + Duration::from_micros(22500));
+        let Some(Timeout::Lost(PacketNumberSpace::Initial, lost)) = recovery.on_timeout(loss_time)
+        else {
+            panic!("packets are not lost")
+        };
+        assert_eq!(sent_times(&lost, start), [ms(2)]);
+        let loss_time = recovery.loss_detection_timer().unwrap();
+        assert_eq!(loss_time, start + ms(3) + Duration::from_micros(22500));
+        recovery.on_timeout(loss_time);
+        assert_eq!(recovery.loss_detection_timer(), None);
+    }
+
+    #[test]
+    fn probe_timeout() {
+        let start = Instant::now();
+        let mut recovery = Recovery::new(EndpointType::Server);
+        recovery.set_max_ack_delay(Duration::from_millis(25));
+        recovery.on_packet_sent(PacketNumberSpace::Handshake, 0, packet(start));
+        recovery.on_packet_sent(PacketNumberSpace::ApplicationData, 0, packet(start));
+        // before the RTT is measured: 333ms + 4 * 166.5ms, and the application data space waits
+        // for the handshake to be confirmed
+        let pto = INITIAL_RTT * 3;
+        assert_eq!(recovery.loss_detection_timer(), Some(start + pto));
+        assert!(matches!(
+            recovery.on_timeout(start + pto),
+            Some(Timeout::Probe(PacketNumberSpace::Handshake))
+        ));
+        // backoff
+        assert_eq!(recovery.loss_detection_timer(), Some(start + pto * 2));
+
+        recovery.on_handshake_confirmed();
+        recovery.discard_space(PacketNumberSpace::Handshake);
+        assert_eq!(
+            recovery.loss_detection_timer(),
+            Some(start + pto + Duration::from_millis(25))
+        );
+        assert!(matches!(
+            recovery.on_timeout(start + pto),
+            Some(Timeout::Probe(PacketNumberSpace::ApplicationData))
+        ));
+        let (acked, _) = recovery.on_ack_received(
+            PacketNumberSpace::ApplicationData,
+            &[(0, 0)],
+            Duration::ZERO,
+            start + pto,
+        );
+        assert_eq!(acked.len(), 1);
+        assert_eq!(recovery.pto_count, 0);
+        assert_eq!(recovery.loss_detection_timer(), None);
+    }
+
+    #[test]
+    fn anti_deadlock() {
+        let start = Instant::now();
+        let mut recovery = Recovery::new(EndpointType::Client);
+        recovery.on_packet_sent(PacketNumberSpace::Initial, 0, packet(start));
+        let now = start + Duration::from_millis(100);
+        recovery.on_ack_received(PacketNumberSpace::Initial, &[(0, 0)], Duration::ZERO, now);
+        // the client keeps the timer for the server which may be blocked
+        let pto = Duration::from_millis(100) + Duration::from_millis(200);
+        assert_eq!(recovery.loss_detection_timer(), Some(start + pto));
+        assert!(matches!(
+            recovery.on_timeout(start + pto),
+            Some(Timeout::Probe(PacketNumberSpace::Initial))
+        ));
+        recovery.discard_space(PacketNumberSpace::Initial);
+        assert!(matches!(
+            recovery.on_timeout(start + pto),
+            Some(Timeout::Probe(PacketNumberSpace::Handshake))
+        ));
+        recovery.on_handshake_confirmed();
+        assert_eq!(recovery.loss_detection_timer(), None);
+    }
+}
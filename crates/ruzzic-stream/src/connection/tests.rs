@@ -0,0 +1,728 @@
+use std::{
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ruzzic_common::{read_bytes_to::ReadBytesToWith, QuicVersion};
+use ruzzic_tls::{
+    resumption::{ReplayWindow, Ticketer},
+    session::{ClientConfig, ServerConfig},
+    sign::Ed25519SigningKey,
+    verify::AcceptAnyServerCert,
+};
+
+use super::{Connection, ConnectionError, ConnectionState, EarlyDataStatus, Event};
+use crate::frame::{stream, Frame};
+use crate::{
+    crypto::{EncryptionLevel, KeyUpdateError},
+    packet::Packet,
+    stream::{RecvState, SendState, StreamDirection, StreamError, StreamID},
+    transport_parameters::TransportParameters,
+};
+
+pub(crate) const ALPN: &[u8] = b"test";
+
+pub(crate) fn server_config() -> ServerConfig {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+    let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    let signing_key = Ed25519SigningKey::from_pkcs8_der(&key_pair.serialize_der()).unwrap();
+    let mut config = ServerConfig::new(vec![cert.der().to_vec()], Arc::new(signing_key));
+    config.alpn_protocols = vec![ALPN.to_vec()];
+    config
+}
+
+pub(crate) fn client_config() -> ClientConfig {
+    let mut config = ClientConfig::new(Arc::new(AcceptAnyServerCert));
+    config.alpn_protocols = vec![ALPN.to_vec()];
+    config
+}
+
+pub(crate) fn transport_parameters() -> TransportParameters {
+    TransportParameters {
+        max_idle_timeout: 30_000,
+        initial_max_data: 1 << 20,
+        initial_max_stream_data_bidi_local: 1 << 16,
+        initial_max_stream_data_bidi_remote: 1 << 16,
+        initial_max_stream_data_uni: 1 << 16,
+        initial_max_streams_bidi: 10,
+        initial_max_streams_uni: 3,
+        ..Default::default()
+    }
+}
+
+fn connect(config: &ClientConfig, now: Instant) -> Connection {
+    Connection::connect(
+        QuicVersion::Rfc9000.into(),
+        config,
+        "localhost",
+        transport_parameters(),
+        now,
+    )
+}
+
+/// The server is created from the first datagram of the client.
+fn accept(config: Arc<ServerConfig>, datagram: &[u8], now: Instant) -> Connection {
+    let packet = Cursor::new(datagram)
+        .read_bytes_to_with::<Packet>(0)
+        .unwrap();
+    let mut server = Connection::accept(&packet, config, transport_parameters(), now).unwrap();
+    server.handle_datagram(now, datagram);
+    server
+}
+
+/// Deliver datagrams both ways until neither side has anything to send.
+fn exchange(client: &mut Connection, server: &mut Connection, now: Instant) {
+    loop {
+        let mut sent = false;
+        while let Some(datagram) = client.poll_transmit(now) {
+            server.handle_datagram(now, &datagram);
+            sent = true;
+        }
+        while let Some(datagram) = server.poll_transmit(now) {
+            client.handle_datagram(now, &datagram);
+            sent = true;
+        }
+        if !sent {
+            break;
+        }
+    }
+}
+
+/// Deliver datagrams from the client and the replies of the server, with the latency in each direction.
+/// Returns whether anything is sent.
+fn round_trip(
+    client: &mut Connection,
+    server: &mut Connection,
+    now: &mut Instant,
+    latency: Duration,
+) -> bool {
+    let datagrams = std::iter::from_fn(|| client.poll_transmit(*now)).collect::<Vec<_>>();
+    *now += latency;
+    for datagram in &datagrams {
+        server.handle_datagram(*now, datagram);
+    }
+    let replies = std::iter::from_fn(|| server.poll_transmit(*now)).collect::<Vec<_>>();
+    *now += latency;
+    for datagram in &replies {
+        client.handle_datagram(*now, datagram);
+    }
+    !datagrams.is_empty() || !replies.is_empty()
+}
+
+fn handshake(
+    client_config: &ClientConfig,
+    server_config: Arc<ServerConfig>,
+) -> (Connection, Connection) {
+    let now = Instant::now();
+    let mut client = connect(client_config, now);
+    let datagram = client.poll_transmit(now).unwrap();
+    let mut server = accept(server_config, &datagram, now);
+    exchange(&mut client, &mut server, now);
+    (client, server)
+}
+
+fn events(connection: &mut Connection) -> Vec<Event> {
+    std::iter::from_fn(|| connection.poll_event()).collect()
+}
+
+#[test]
+fn handshake_completes() {
+    let now = Instant::now();
+    let mut client = connect(&client_config(), now);
+    assert_eq!(client.state(), ConnectionState::Handshake);
+    let datagram = client.poll_transmit(now).unwrap();
+    // https://www.rfc-editor.org/rfc/rfc9000.html#section-14.1
+    assert_eq!(datagram.len(), 1200);
+
+    let mut server = accept(Arc::new(server_config()), &datagram, now);
+    // the server can not send more than three times what it has received
+    let mut sent = 0;
+    while let Some(datagram) = server.poll_transmit(now) {
+        sent += datagram.len();
+        client.handle_datagram(now, &datagram);
+    }
+    assert!(sent <= 3 * 1200);
+
+    exchange(&mut client, &mut server, now);
+    assert_eq!(client.state(), ConnectionState::Established);
+    assert_eq!(server.state(), ConnectionState::Established);
+    assert!(matches!(events(&mut client)[..], [Event::Connected]));
+    assert!(matches!(events(&mut server)[..], [Event::Connected]));
+    assert_eq!(client.alpn_protocol(), Some(ALPN));
+    assert_eq!(server.alpn_protocol(), Some(ALPN));
+
+    // HANDSHAKE_DONE confirms the handshake at the client
+    for connection in [&client, &server] {
+        assert!(connection.key_sets.get(EncryptionLevel::Initial).is_none());
+        assert!(connection
+            .key_sets
+            .get(EncryptionLevel::Handshake)
+            .is_none());
+        assert!(connection.key_sets.get(EncryptionLevel::OneRTT).is_some());
+    }
+}
+
+#[test]
+fn accept_only_initial_packets() {
+    let now = Instant::now();
+    let packet = Cursor::new([0x40, 1, 2, 3, 4, 5])
+        .read_bytes_to_with::<Packet>(0)
+        .unwrap();
+    let server = Connection::accept(
+        &packet,
+        Arc::new(server_config()),
+        transport_parameters(),
+        now,
+    );
+    assert!(server.is_none());
+}
+
+#[test]
+fn other_versions_are_dropped() {
+    let now = Instant::now();
+    let mut client = connect(&client_config(), now);
+    let datagram = client.poll_transmit(now).unwrap();
+    let mut server = accept(Arc::new(server_config()), &datagram, now);
+    let reply = server.poll_transmit(now).unwrap();
+    let mut other_version = reply.clone();
+    other_version[1..5].copy_from_slice(&[0x1a, 0x2a, 0x3a, 0x4a]);
+    client.handle_datagram(now, &other_version);
+    assert!(!client.initial_received);
+    client.handle_datagram(now, &reply);
+    assert!(client.initial_received);
+}
+
+#[test]
+fn stream_data() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    let data = vec![7; 2500];
+    let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+    assert_eq!(stream_id, StreamID::new(0));
+    client.write_stream(&stream_id, &data).unwrap();
+    client.finish_stream(&stream_id).unwrap();
+    exchange(&mut client, &mut server, now);
+
+    let mut received = Vec::new();
+    let mut fin = false;
+    for event in events(&mut server) {
+        if let Event::StreamData {
+            stream_id,
+            offset,
+            data,
+            is_fin,
+        } = event
+        {
+            assert_eq!(stream_id, StreamID::new(0));
+            assert_eq!(offset, received.len() as u64);
+            received.extend(data);
+            fin |= is_fin;
+        }
+    }
+    assert_eq!(received, data);
+    assert!(fin);
+}
+
+#[test]
+fn application_close() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    events(&mut client);
+    events(&mut server);
+    let now = Instant::now();
+    client.close(now, 42, "bye");
+    assert_eq!(client.state(), ConnectionState::Closing);
+    assert_eq!(
+        client.open_stream(StreamDirection::Bidirectional),
+        Err(StreamError::ConnectionClosed)
+    );
+    let datagram = client.poll_transmit(now).unwrap();
+    assert!(client.poll_transmit(now).is_none());
+
+    server.handle_datagram(now, &datagram);
+    assert_eq!(server.state(), ConnectionState::Draining);
+    let error = ConnectionError::PeerApplicationClosed {
+        code: 42,
+        reason: "bye".to_string(),
+    };
+    assert_eq!(events(&mut server), vec![Event::Closed(error)]);
+    // nothing is sent while draining
+    assert!(server.poll_transmit(now).is_none());
+
+    // the closing endpoint responds to packets with CONNECTION_CLOSE again
+    client.handle_datagram(now, &datagram);
+    assert!(client.poll_transmit(now).is_some());
+
+    for connection in [&mut client, &mut server] {
+        let deadline = connection.poll_timeout().unwrap();
+        connection.handle_timeout(deadline);
+        assert_eq!(connection.state(), ConnectionState::Closed);
+        assert_eq!(connection.poll_timeout(), None);
+    }
+}
+
+#[test]
+fn idle_timeout() {
+    let (mut client, _) = handshake(&client_config(), Arc::new(server_config()));
+    events(&mut client);
+    let deadline = client.poll_timeout().unwrap();
+    client.handle_timeout(deadline - Duration::from_millis(1));
+    assert_eq!(client.state(), ConnectionState::Established);
+
+    client.handle_timeout(deadline);
+    assert_eq!(client.state(), ConnectionState::Closed);
+    assert_eq!(
+        events(&mut client),
+        vec![Event::Closed(ConnectionError::IdleTimeout)]
+    );
+    assert!(client.poll_transmit(deadline).is_none());
+}
+
+#[test]
+fn no_application_protocol() {
+    let mut client_config = client_config();
+    client_config.alpn_protocols = vec![b"other".to_vec()];
+    let (mut client, mut server) = handshake(&client_config, Arc::new(server_config()));
+
+    // no_application_protocol alert
+    // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.8
+    assert_eq!(server.state(), ConnectionState::Closing);
+    assert_eq!(client.state(), ConnectionState::Draining);
+    let client_events = events(&mut client);
+    assert!(matches!(
+        &client_events[..],
+        [Event::Closed(ConnectionError::PeerTransportError {
+            code: 0x178,
+            ..
+        })]
+    ));
+    assert!(events(&mut server)
+        .iter()
+        .all(|event| !matches!(event, Event::Connected)));
+}
+
+#[test]
+fn zero_rtt() {
+    let mut client_config = client_config();
+    client_config.early_data = true;
+    let mut server_config = server_config();
+    server_config.ticketer = Some(Arc::new(Ticketer::new(Duration::from_secs(3600))));
+    server_config.early_data = Some(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    let server_config = Arc::new(server_config);
+    // the ticket comes with the first connection
+    handshake(&client_config, server_config.clone());
+
+    let now = Instant::now();
+    let mut client = connect(&client_config, now);
+    let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+    client.write_stream(&stream_id, b"early").unwrap();
+    client.finish_stream(&stream_id).unwrap();
+    let datagram = client.poll_transmit(now).unwrap();
+    assert_eq!(datagram.len(), 1200);
+    let mut server = accept(server_config, &datagram, now);
+    assert!(matches!(
+        &events(&mut server)[..],
+        [Event::StreamData { data, is_fin: true, .. }] if data == b"early"
+    ));
+
+    exchange(&mut client, &mut server, now);
+    assert_eq!(client.state(), ConnectionState::Established);
+    assert_eq!(client.early_data_status(), EarlyDataStatus::Accepted);
+    assert!(client.key_sets.get(EncryptionLevel::ZeroRTT).is_none());
+    assert!(matches!(events(&mut server)[..], [Event::Connected]));
+}
+
+#[test]
+fn early_data_limits() {
+    let now = Instant::now();
+    let mut client_config = client_config();
+    client_config.early_data = true;
+    let mut server_config = server_config();
+    server_config.ticketer = Some(Arc::new(Ticketer::new(Duration::from_secs(3600))));
+    server_config.early_data = Some(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    let limits = TransportParameters {
+        initial_max_data: 10,
+        initial_max_stream_data_bidi_remote: 8,
+        initial_max_streams_bidi: 2,
+        ..transport_parameters()
+    };
+    let mut client = connect(&client_config, now);
+    let datagram = client.poll_transmit(now).unwrap();
+    let packet = Cursor::new(&datagram[..])
+        .read_bytes_to_with::<Packet>(0)
+        .unwrap();
+    let mut server = Connection::accept(&packet, Arc::new(server_config), limits, now).unwrap();
+    server.handle_datagram(now, &datagram);
+    exchange(&mut client, &mut server, now);
+
+    // the limits remembered with the ticket
+    let mut client = connect(&client_config, now);
+    assert_eq!(client.early_data_status(), EarlyDataStatus::NotSent);
+    assert!(client.is_within_early_limits(&StreamID::new(0), 0, 8));
+    assert!(!client.is_within_early_limits(&StreamID::new(0), 5, 4));
+    assert!(!client.is_within_early_limits(&StreamID::new(8), 0, 1));
+    for _ in 0..2 {
+        let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+        client.write_stream(&stream_id, &[1; 8]).unwrap();
+    }
+    let frames = client.early_frames_to_send(1200);
+    let sent = frames
+        .iter()
+        .map(|frame| match frame {
+            Frame::Stream(body) => body.data_length(),
+            _ => 0,
+        })
+        .sum::<u64>();
+    assert_eq!(sent, 10);
+    assert_eq!(client.early_data_status(), EarlyDataStatus::Pending);
+    assert!(!client.is_within_early_limits(&StreamID::new(4), 2, 1));
+
+    // the data is sent again in 1-RTT packets
+    assert_eq!(client.on_early_data_result(false), frames);
+    assert_eq!(client.early_data_status(), EarlyDataStatus::Rejected);
+    assert!(client.key_sets.get(EncryptionLevel::ZeroRTT).is_none());
+}
+
+#[test]
+fn streams_before_handshake() {
+    let now = Instant::now();
+    let mut client = connect(&client_config(), now);
+    // the limits of the server are not known yet
+    assert_eq!(
+        client.open_stream(StreamDirection::Bidirectional),
+        Err(StreamError::StreamLimit)
+    );
+}
+
+#[test]
+fn stream_in_both_directions() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    events(&mut client);
+    events(&mut server);
+
+    let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+    client.write_stream(&stream_id, b"request").unwrap();
+    client.finish_stream(&stream_id).unwrap();
+    exchange(&mut client, &mut server, now);
+    assert!(matches!(
+        &events(&mut server)[..],
+        [Event::StreamData { data, is_fin: true, .. }] if data == b"request"
+    ));
+    assert_eq!(
+        server.stream_recv_state(&stream_id),
+        Some(RecvState::DataRead)
+    );
+
+    server.write_stream(&stream_id, b"response").unwrap();
+    server.finish_stream(&stream_id).unwrap();
+    exchange(&mut client, &mut server, now);
+    assert!(matches!(
+        &events(&mut client)[..],
+        [Event::StreamData { data, is_fin: true, .. }] if data == b"response"
+    ));
+    // both parts are done, and the stream is forgotten
+    assert_eq!(client.stream_send_state(&stream_id), None);
+    assert_eq!(server.stream_send_state(&stream_id), None);
+
+    // a unidirectional stream of the server
+    let stream_id = server.open_stream(StreamDirection::Unidirectional).unwrap();
+    assert_eq!(stream_id, StreamID::new(3));
+    server.write_stream(&stream_id, b"push").unwrap();
+    exchange(&mut client, &mut server, now);
+    assert!(matches!(
+        &events(&mut client)[..],
+        [Event::StreamData { stream_id, is_fin: false, .. }] if *stream_id == StreamID::new(3)
+    ));
+    assert_eq!(
+        client.write_stream(&stream_id, b"reply"),
+        Err(StreamError::UnknownStream)
+    );
+}
+
+#[test]
+fn stop_sending_and_reset() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    events(&mut client);
+    events(&mut server);
+
+    let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+    client.write_stream(&stream_id, b"abc").unwrap();
+    exchange(&mut client, &mut server, now);
+    events(&mut server);
+
+    // the server stops the data of the client and resets its own data
+    server.stop_sending(&stream_id, 0x10).unwrap();
+    server.write_stream(&stream_id, b"partial").unwrap();
+    server.reset_stream(&stream_id, 0x11).unwrap();
+    while let Some(datagram) = server.poll_transmit(now) {
+        client.handle_datagram(now, &datagram);
+    }
+    let client_events = events(&mut client);
+    assert!(client_events.contains(&Event::StreamStopped {
+        stream_id: stream_id.clone(),
+        error_code: 0x10,
+    }));
+    assert!(client_events.contains(&Event::StreamReset {
+        stream_id: stream_id.clone(),
+        error_code: 0x11,
+    }));
+    assert_eq!(
+        client.write_stream(&stream_id, b"more"),
+        Err(StreamError::Stopped(0x10))
+    );
+    assert_eq!(
+        client.stream_send_state(&stream_id),
+        Some(SendState::ResetSent)
+    );
+    // the client answered STOP_SENDING with RESET_STREAM
+    exchange(&mut client, &mut server, now);
+    assert!(events(&mut server).contains(&Event::StreamReset {
+        stream_id: stream_id.clone(),
+        error_code: 0x10,
+    }));
+    assert_eq!(client.stream_send_state(&stream_id), None);
+}
+
+#[test]
+fn stream_state_error() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    events(&mut client);
+    events(&mut server);
+
+    // a stream the client only sends on
+    server.queue_frames([Frame::Stream(stream::Body::new(
+        StreamID::new(2),
+        Some(0),
+        b"x".to_vec(),
+        false,
+    ))]);
+    exchange(&mut client, &mut server, now);
+    assert_eq!(client.state(), ConnectionState::Closing);
+    assert!(matches!(
+        &events(&mut server)[..],
+        [Event::Closed(ConnectionError::PeerTransportError {
+            code: 0x05,
+            ..
+        })]
+    ));
+}
+
+#[test]
+fn flow_control() {
+    let latency = Duration::from_millis(10);
+    let mut now = Instant::now();
+    let mut client = connect(&client_config(), now);
+    let datagram = client.poll_transmit(now).unwrap();
+    now += latency;
+    let mut server = accept(Arc::new(server_config()), &datagram, now);
+    while round_trip(&mut client, &mut server, &mut now, latency) {}
+    events(&mut client);
+    events(&mut server);
+    let data = (0..400_000).map(|i| i as u8).collect::<Vec<_>>();
+    let stream_id = client.open_stream(StreamDirection::Unidirectional).unwrap();
+    client.write_stream(&stream_id, &data).unwrap();
+    client.finish_stream(&stream_id).unwrap();
+
+    let mut received = Vec::new();
+    let mut largest_round = 0;
+    while received.len() < data.len() {
+        let datagrams = std::iter::from_fn(|| client.poll_transmit(now)).collect::<Vec<_>>();
+        assert!(!datagrams.is_empty());
+        now += latency;
+        for datagram in &datagrams {
+            server.handle_datagram(now, datagram);
+        }
+        let before = received.len();
+        // the peer gets more credit as the data is taken
+        for event in events(&mut server) {
+            if let Event::StreamData { data, .. } = event {
+                received.extend(data);
+            }
+        }
+        let replies = std::iter::from_fn(|| server.poll_transmit(now)).collect::<Vec<_>>();
+        now += latency;
+        for datagram in &replies {
+            client.handle_datagram(now, datagram);
+        }
+        if before == 0 {
+            // initial_max_stream_data_uni
+            assert_eq!(received.len(), 1 << 16);
+        }
+        largest_round = largest_round.max(received.len() - before);
+    }
+    assert_eq!(received, data);
+    // the window doubles as the data is taken within two round trips
+    assert!(
+        largest_round > 1 << 16,
+        "{largest_round} bytes in a round trip"
+    );
+}
+
+#[test]
+fn flow_control_error() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    events(&mut client);
+    events(&mut server);
+
+    // beyond initial_max_stream_data_bidi_remote of the client
+    server.queue_frames([Frame::Stream(stream::Body::new(
+        StreamID::new(1),
+        Some(1 << 16),
+        b"x".to_vec(),
+        false,
+    ))]);
+    exchange(&mut client, &mut server, now);
+    assert_eq!(client.state(), ConnectionState::Closing);
+    assert!(matches!(
+        &events(&mut server)[..],
+        [Event::Closed(ConnectionError::PeerTransportError {
+            code: 0x03,
+            ..
+        })]
+    ));
+}
+
+#[test]
+fn key_update() {
+    let now = Instant::now();
+    let mut client = connect(&client_config(), now);
+    assert_eq!(
+        client.initiate_key_update(now),
+        Err(KeyUpdateError::UpdateNotConfirmed)
+    );
+    let datagram = client.poll_transmit(now).unwrap();
+    let mut server = accept(Arc::new(server_config()), &datagram, now);
+    exchange(&mut client, &mut server, now);
+    events(&mut client);
+    events(&mut server);
+
+    client.initiate_key_update(now).unwrap();
+    // the update is not acknowledged yet
+    assert_eq!(
+        client.initiate_key_update(now),
+        Err(KeyUpdateError::UpdateNotConfirmed)
+    );
+    let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+    client.write_stream(&stream_id, b"hello").unwrap();
+    exchange(&mut client, &mut server, now);
+    assert!(matches!(
+        &events(&mut server)[..],
+        [Event::StreamData { data, .. }] if data == b"hello"
+    ));
+    for connection in [&client, &server] {
+        assert!(connection.key_sets.one_rtt().unwrap().key_phase());
+    }
+
+    // the server updates the keys in turn after a packet in the phase is acknowledged
+    assert_eq!(
+        server.initiate_key_update(now),
+        Err(KeyUpdateError::UpdateNotConfirmed)
+    );
+    server.write_stream(&stream_id, b"world").unwrap();
+    exchange(&mut client, &mut server, now);
+    server.initiate_key_update(now).unwrap();
+    server.finish_stream(&stream_id).unwrap();
+    exchange(&mut client, &mut server, now);
+    assert!(matches!(
+        &events(&mut client)[..],
+        [Event::StreamData { data, .. }, Event::StreamData { is_fin: true, .. }] if data == b"world"
+    ));
+    for connection in [&client, &server] {
+        assert!(!connection.key_sets.one_rtt().unwrap().key_phase());
+    }
+}
+
+#[test]
+fn previous_keys_are_discarded() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    client
+        .key_sets
+        .one_rtt_mut()
+        .unwrap()
+        .initiate_update()
+        .unwrap();
+    let stream_id = client.open_stream(StreamDirection::Bidirectional).unwrap();
+    client.write_stream(&stream_id, b"hello").unwrap();
+    let datagram = client.poll_transmit(now).unwrap();
+    server.handle_datagram(now, &datagram);
+
+    // the server follows the key update of the client
+    let keys = server.key_sets.one_rtt().unwrap();
+    assert!(keys.key_phase());
+    let previous = keys.opening_keys(false, 0).clone();
+    let deadline = server.key_discard_deadline.unwrap();
+    assert_eq!(deadline, now + server.pto() * 3);
+    assert!(server.poll_timeout().unwrap() <= deadline);
+
+    server.handle_timeout(deadline);
+    assert_eq!(server.key_discard_deadline, None);
+    let keys = server.key_sets.one_rtt().unwrap();
+    assert_ne!(keys.opening_keys(false, 0), &previous);
+}
+
+#[test]
+fn lost_initial_is_sent_again() {
+    let now = Instant::now();
+    let mut client = connect(&client_config(), now);
+    client.poll_transmit(now).unwrap();
+    assert!(client.poll_transmit(now).is_none());
+
+    // the probe timeout before the RTT is measured
+    let timeout = client.poll_timeout().unwrap();
+    assert_eq!(timeout, now + Duration::from_millis(999));
+    client.handle_timeout(timeout);
+    let datagram = client.poll_transmit(timeout).unwrap();
+    assert_eq!(datagram.len(), 1200);
+    let mut server = accept(Arc::new(server_config()), &datagram, timeout);
+    exchange(&mut client, &mut server, timeout);
+    assert_eq!(client.state(), ConnectionState::Established);
+    assert_eq!(server.state(), ConnectionState::Established);
+}
+
+#[test]
+fn padding_across_length_boundary() {
+    let now = Instant::now();
+    // the padded last packet goes across a Length of 63 and 64 bytes
+    for padding in 1000..1060 {
+        let mut client = connect(&client_config(), now);
+        let first = [vec![Frame::Ping], vec![Frame::Padding; padding]].concat();
+        let packets = vec![
+            (EncryptionLevel::Initial, first),
+            (EncryptionLevel::Initial, vec![Frame::Ping]),
+        ];
+        assert_eq!(client.assemble(now, packets, true).len(), 1200);
+    }
+}
+
+#[test]
+fn lost_stream_data_is_sent_again() {
+    let (mut client, mut server) = handshake(&client_config(), Arc::new(server_config()));
+    let now = Instant::now();
+    events(&mut client);
+    events(&mut server);
+
+    let stream_id = client.open_stream(StreamDirection::Unidirectional).unwrap();
+    client.write_stream(&stream_id, b"lost").unwrap();
+    client.finish_stream(&stream_id).unwrap();
+    client.poll_transmit(now).unwrap();
+    assert!(client.poll_transmit(now).is_none());
+
+    // PING is sent as the probe, and the packet before it is lost when it is acknowledged
+    let timeout = client.poll_timeout().unwrap();
+    assert!(timeout < now + Duration::from_secs(1));
+    client.handle_timeout(timeout);
+    exchange(&mut client, &mut server, timeout);
+    assert!(matches!(
+        &events(&mut server)[..],
+        [Event::StreamData { data, is_fin: true, .. }] if data == b"lost"
+    ));
+    assert_eq!(client.stream_send_state(&stream_id), None);
+}
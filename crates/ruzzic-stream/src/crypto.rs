@@ -0,0 +1,452 @@
+use ruzzic_common::{EndpointType, QuicVersion};
+use ruzzic_tls::session::Level;
+
+use crate::connection::ConnectionID;
+
+use self::cipher_suite::{CipherSuite, TLS_AES_128_GCM_SHA256};
+
+pub mod cipher_suite;
+mod key_update;
+
+pub use self::key_update::{KeyUpdateError, OneRttKeys};
+
+/// https://www.rfc-editor.org/rfc/rfc9001.html#name-encryption-levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionLevel {
+    Initial,
+    ZeroRTT,
+    Handshake,
+    OneRTT,
+}
+
+impl From<Level> for EncryptionLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Initial => EncryptionLevel::Initial,
+            Level::ZeroRtt => EncryptionLevel::ZeroRTT,
+            Level::Handshake => EncryptionLevel::Handshake,
+            Level::OneRtt => EncryptionLevel::OneRTT,
+        }
+    }
+}
+
+impl From<EncryptionLevel> for Level {
+    fn from(level: EncryptionLevel) -> Self {
+        match level {
+            EncryptionLevel::Initial => Level::Initial,
+            EncryptionLevel::ZeroRTT => Level::ZeroRtt,
+            EncryptionLevel::Handshake => Level::Handshake,
+            EncryptionLevel::OneRTT => Level::OneRtt,
+        }
+    }
+}
+
+/// Packet protection keys for one direction.
+#[derive(Debug, Clone)]
+pub struct DirectionalKeys {
+    cipher_suite: &'static dyn CipherSuite,
+    secret: Vec<u8>,
+    key: Vec<u8>,
+    iv: Vec<u8>,
+    hp: Vec<u8>,
+}
+
+impl PartialEq for DirectionalKeys {
+    fn eq(&self, other: &Self) -> bool {
+        self.cipher_suite.id() == other.cipher_suite.id()
+            && self.key == other.key
+            && self.iv == other.iv
+            && self.hp == other.hp
+    }
+}
+
+impl DirectionalKeys {
+    /// Derive packet protection keys from a traffic secret exported by TLS.
+    pub fn from_secret(cipher_suite: &'static dyn CipherSuite, secret: &[u8]) -> Self {
+        let key = hkdf_expand_label(
+            cipher_suite,
+            secret,
+            "quic key".as_bytes(),
+            &[],
+            cipher_suite.key_length() as u16,
+        );
+        let iv = hkdf_expand_label(
+            cipher_suite,
+            secret,
+            "quic iv".as_bytes(),
+            &[],
+            cipher_suite.iv_length() as u16,
+        );
+        let hp = hkdf_expand_label(
+            cipher_suite,
+            secret,
+            "quic hp".as_bytes(),
+            &[],
+            cipher_suite.key_length() as u16,
+        );
+        Self {
+            cipher_suite,
+            secret: secret.to_vec(),
+            key,
+            iv,
+            hp,
+        }
+    }
+
+    /// Keys of the next key phase.
+    /// The header protection key is not updated.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-key-update
+    pub fn next_generation(&self) -> Self {
+        let cipher_suite = self.cipher_suite;
+        let secret = hkdf_expand_label(
+            cipher_suite,
+            &self.secret,
+            "quic ku".as_bytes(),
+            &[],
+            cipher_suite.hash_length() as u16,
+        );
+        let key = hkdf_expand_label(
+            cipher_suite,
+            &secret,
+            "quic key".as_bytes(),
+            &[],
+            cipher_suite.key_length() as u16,
+        );
+        let iv = hkdf_expand_label(
+            cipher_suite,
+            &secret,
+            "quic iv".as_bytes(),
+            &[],
+            cipher_suite.iv_length() as u16,
+        );
+        Self {
+            cipher_suite,
+            secret,
+            key,
+            iv,
+            hp: self.hp.clone(),
+        }
+    }
+
+    /// Initial packets are always protected with AEAD_AES_128_GCM.
+    fn new_initial(initial_salt: &[u8], connection_id: &ConnectionID, label: &[u8]) -> Self {
+        let cipher_suite = &TLS_AES_128_GCM_SHA256;
+        log::debug!("initial_salt: {initial_salt:x?}");
+        log::debug!("connection_id: {connection_id:x?}",);
+        let initial_secret = cipher_suite.hkdf_extract(initial_salt, &connection_id.to_vec());
+        log::debug!("initial_secret: {initial_secret:x?}");
+
+        let endpoint_initial_secret = hkdf_expand_label(
+            cipher_suite,
+            &initial_secret,
+            label,
+            &[],
+            cipher_suite.hash_length() as u16,
+        );
+
+        Self::from_secret(cipher_suite, &endpoint_initial_secret)
+    }
+
+    pub fn cipher_suite(&self) -> &'static dyn CipherSuite {
+        self.cipher_suite
+    }
+
+    pub(crate) fn tag_length(&self) -> usize {
+        self.cipher_suite.tag_length()
+    }
+
+    pub(crate) fn sample_length(&self) -> usize {
+        self.cipher_suite.sample_length()
+    }
+
+    fn nonce(&self, packet_number: &[u8]) -> Vec<u8> {
+        let packet_number = [
+            &vec![0; self.iv.len() - packet_number.len()][..],
+            packet_number,
+        ]
+        .concat();
+        packet_number
+            .iter()
+            .enumerate()
+            .map(|(i, b)| b ^ self.iv[i])
+            .collect::<Vec<u8>>()
+    }
+
+    pub(crate) fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        packet_number: &[u8],
+        packet_header: &[u8],
+    ) -> Vec<u8> {
+        let nonce = self.nonce(packet_number);
+        self.cipher_suite
+            .seal(&self.key, &nonce, packet_header, payload)
+    }
+
+    pub(crate) fn decrypt_payload(
+        &self,
+        payload: &[u8],
+        packet_number: &[u8],
+        packet_header: &[u8],
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let nonce = self.nonce(packet_number);
+        self.cipher_suite
+            .open(&self.key, &nonce, packet_header, payload)
+    }
+
+    pub(crate) fn header_protection_mask(&self, sample: &[u8]) -> Vec<u8> {
+        self.cipher_suite.header_protection_mask(&self.hp, sample)
+    }
+}
+
+/// Keys of one encryption level.
+/// `local` protects packets sent by this endpoint and `remote` removes protection from packets sent by the peer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySet {
+    local: DirectionalKeys,
+    remote: DirectionalKeys,
+}
+
+impl KeySet {
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-initial-secrets
+    pub fn new_initial(
+        version: QuicVersion,
+        original_destination_connection_id: &ConnectionID,
+        endpoint_type: &EndpointType,
+    ) -> Self {
+        let initial_salt = version.initial_salt();
+        let client = DirectionalKeys::new_initial(
+            &initial_salt,
+            original_destination_connection_id,
+            "client in".as_bytes(),
+        );
+        let server = DirectionalKeys::new_initial(
+            &initial_salt,
+            original_destination_connection_id,
+            "server in".as_bytes(),
+        );
+        Self::from_client_and_server(client, server, endpoint_type)
+    }
+
+    /// Build a key set from the client and server traffic secrets of the Handshake or 1-RTT level.
+    pub fn from_secrets(
+        cipher_suite: &'static dyn CipherSuite,
+        client_secret: &[u8],
+        server_secret: &[u8],
+        endpoint_type: &EndpointType,
+    ) -> Self {
+        Self::from_client_and_server(
+            DirectionalKeys::from_secret(cipher_suite, client_secret),
+            DirectionalKeys::from_secret(cipher_suite, server_secret),
+            endpoint_type,
+        )
+    }
+
+    /// 0-RTT packets are only sent by the client, so both directions share the client early traffic secret.
+    pub fn from_early_secret(
+        cipher_suite: &'static dyn CipherSuite,
+        client_early_traffic_secret: &[u8],
+    ) -> Self {
+        let keys = DirectionalKeys::from_secret(cipher_suite, client_early_traffic_secret);
+        Self {
+            local: keys.clone(),
+            remote: keys,
+        }
+    }
+
+    fn from_client_and_server(
+        client: DirectionalKeys,
+        server: DirectionalKeys,
+        endpoint_type: &EndpointType,
+    ) -> Self {
+        match endpoint_type {
+            EndpointType::Client => Self {
+                local: client,
+                remote: server,
+            },
+            EndpointType::Server => Self {
+                local: server,
+                remote: client,
+            },
+        }
+    }
+
+    pub fn next_generation(&self) -> Self {
+        Self {
+            local: self.local.next_generation(),
+            remote: self.remote.next_generation(),
+        }
+    }
+
+    pub fn local(&self) -> &DirectionalKeys {
+        &self.local
+    }
+
+    pub fn remote(&self) -> &DirectionalKeys {
+        &self.remote
+    }
+}
+
+/// Key sets of all encryption levels held by a connection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeySets {
+    initial: Option<KeySet>,
+    zero_rtt: Option<KeySet>,
+    handshake: Option<KeySet>,
+    one_rtt: Option<OneRttKeys>,
+}
+
+impl KeySets {
+    pub fn get(&self, level: EncryptionLevel) -> Option<&KeySet> {
+        match level {
+            EncryptionLevel::Initial => self.initial.as_ref(),
+            EncryptionLevel::ZeroRTT => self.zero_rtt.as_ref(),
+            EncryptionLevel::Handshake => self.handshake.as_ref(),
+            EncryptionLevel::OneRTT => self.one_rtt.as_ref().map(|keys| keys.current()),
+        }
+    }
+
+    pub fn install(&mut self, level: EncryptionLevel, key_set: KeySet) {
+        match level {
+            EncryptionLevel::Initial => self.initial = Some(key_set),
+            EncryptionLevel::ZeroRTT => self.zero_rtt = Some(key_set),
+            EncryptionLevel::Handshake => self.handshake = Some(key_set),
+            EncryptionLevel::OneRTT => self.one_rtt = Some(OneRttKeys::new(key_set)),
+        }
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-discarding-unused-keys
+    pub fn discard(&mut self, level: EncryptionLevel) {
+        match level {
+            EncryptionLevel::Initial => self.initial = None,
+            EncryptionLevel::ZeroRTT => self.zero_rtt = None,
+            EncryptionLevel::Handshake => self.handshake = None,
+            EncryptionLevel::OneRTT => self.one_rtt = None,
+        }
+    }
+
+    /// 1-RTT keys which go through key updates.
+    pub fn one_rtt(&self) -> Option<&OneRttKeys> {
+        self.one_rtt.as_ref()
+    }
+
+    pub fn one_rtt_mut(&mut self) -> Option<&mut OneRttKeys> {
+        self.one_rtt.as_mut()
+    }
+}
+
+pub(crate) fn hkdf_expand_label(
+    cipher_suite: &dyn CipherSuite,
+    secret: &[u8],
+    label: &[u8],
+    context: &[u8],
+    length: u16,
+) -> Vec<u8> {
+    let hkdf_label = ruzzic_tls::key_schedule::hkdf_label(label, context, length);
+    cipher_suite.hkdf_expand(secret, &hkdf_label, length)
+}
+
+/// Retry packets are not protected, but carry a tag computed over the Retry Pseudo-Packet.
+/// `retry_packet` is the Retry packet without the Retry Integrity Tag.
+/// None if the key of the version is not known.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity
+pub fn retry_integrity_tag(
+    version: &QuicVersion,
+    original_destination_connection_id: &ConnectionID,
+    retry_packet: &[u8],
+) -> Option<Vec<u8>> {
+    let original_destination_connection_id = original_destination_connection_id.to_vec();
+    let retry_pseudo_packet = [
+        &[original_destination_connection_id.len() as u8][..],
+        &original_destination_connection_id,
+        retry_packet,
+    ]
+    .concat();
+    Some(TLS_AES_128_GCM_SHA256.seal(
+        &version.retry_integrity_key()?,
+        &version.retry_integrity_nonce()?,
+        &retry_pseudo_packet,
+        &[],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://www.rfc-editor.org/rfc/rfc9001.html#name-keys
+    #[test]
+    fn initial_keys() {
+        let connection_id = ConnectionID(hex::decode("8394c8f03e515708").unwrap());
+        let key_set =
+            KeySet::new_initial(QuicVersion::Rfc9000, &connection_id, &EndpointType::Client);
+
+        let client = key_set.local();
+        assert_eq!(
+            client.key,
+            hex::decode("1f369613dd76d5467730efcbe3b1a22d").unwrap()
+        );
+        assert_eq!(client.iv, hex::decode("fa044b2f42a3fd3b46fb255c").unwrap());
+        assert_eq!(
+            client.hp,
+            hex::decode("9f50449e04a0e810283a1e9933adedd2").unwrap()
+        );
+
+        let server = key_set.remote();
+        assert_eq!(
+            server.key,
+            hex::decode("cf3a5331653c364c88f0f379b6067e37").unwrap()
+        );
+        assert_eq!(server.iv, hex::decode("0ac1493ca1905853b0bba03e").unwrap());
+        assert_eq!(
+            server.hp,
+            hex::decode("c206b8d9b9f0f37644430b490eeaa314").unwrap()
+        );
+    }
+
+    #[test]
+    fn key_sets() {
+        let mut key_sets = KeySets::default();
+        assert!(key_sets.get(EncryptionLevel::Handshake).is_none());
+
+        let key_set = KeySet::from_secrets(
+            &TLS_AES_128_GCM_SHA256,
+            &[0x01; 32],
+            &[0x02; 32],
+            &EndpointType::Server,
+        );
+        key_sets.install(EncryptionLevel::Handshake, key_set.clone());
+        assert_eq!(key_sets.get(EncryptionLevel::Handshake), Some(&key_set));
+        assert_eq!(
+            key_set.local(),
+            &DirectionalKeys::from_secret(&TLS_AES_128_GCM_SHA256, &[0x02; 32])
+        );
+
+        key_sets.discard(EncryptionLevel::Handshake);
+        assert!(key_sets.get(EncryptionLevel::Handshake).is_none());
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc9001.html#name-retry
+    #[test]
+    fn retry_integrity_tags() {
+        let original_destination_connection_id =
+            ConnectionID(hex::decode("8394c8f03e515708").unwrap());
+        let retry_packet = hex::decode("ff000000010008f067a5502a4262b5746f6b656e").unwrap();
+        assert_eq!(
+            retry_integrity_tag(
+                &QuicVersion::Rfc9000,
+                &original_destination_connection_id,
+                &retry_packet
+            ),
+            Some(hex::decode("04a265ba2eff4d829058fb3f0f2496ba").unwrap())
+        );
+        assert_eq!(
+            retry_integrity_tag(
+                &QuicVersion::Others(0x1a2a3a4a),
+                &original_destination_connection_id,
+                &retry_packet
+            ),
+            None
+        );
+    }
+}
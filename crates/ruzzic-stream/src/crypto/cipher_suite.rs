@@ -0,0 +1,358 @@
+use std::fmt::Debug;
+
+use aes_gcm::{
+    aead::{self, Aead, NewAead},
+    aes::{Aes128, Aes256, Block, BlockEncrypt, NewBlockCipher},
+    Aes128Gcm, Aes256Gcm,
+};
+use chacha20::{
+    cipher::{NewCipher, StreamCipher, StreamCipherSeek},
+    ChaCha20,
+};
+use chacha20poly1305::ChaCha20Poly1305;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use sha2::{Sha256, Sha384};
+
+/// Algorithms of a TLS 1.3 cipher suite used for QUIC packet protection.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#name-packet-protection
+pub trait CipherSuite: Debug + Send + Sync {
+    /// Identifier of the cipher suite in TLS (e.g. 0x1301 for TLS_AES_128_GCM_SHA256).
+    fn id(&self) -> u16;
+
+    fn key_length(&self) -> usize;
+
+    fn iv_length(&self) -> usize {
+        12
+    }
+
+    fn tag_length(&self) -> usize {
+        16
+    }
+
+    fn hash_length(&self) -> usize;
+
+    fn hkdf_extract(&self, salt: &[u8], ikm: &[u8]) -> Vec<u8>;
+
+    fn hkdf_expand(&self, prk: &[u8], info: &[u8], length: u16) -> Vec<u8>;
+
+    fn seal(&self, key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8>;
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        msg: &[u8],
+    ) -> Result<Vec<u8>, std::io::Error>;
+
+    /// Both AES and ChaCha20 based header protection take a 16 bytes sample.
+    fn sample_length(&self) -> usize {
+        16
+    }
+
+    fn header_protection_mask(&self, hp: &[u8], sample: &[u8]) -> Vec<u8>;
+
+    /// Number of packets which can be protected with a single key.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-confidentiality-limit
+    fn confidentiality_limit(&self) -> u64;
+
+    /// Number of packets which can fail authentication with a single key.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-integrity-limit
+    fn integrity_limit(&self) -> u64;
+}
+
+pub static TLS_AES_128_GCM_SHA256: Aes128GcmSha256 = Aes128GcmSha256;
+pub static TLS_AES_256_GCM_SHA384: Aes256GcmSha384 = Aes256GcmSha384;
+pub static TLS_CHACHA20_POLY1305_SHA256: Chacha20Poly1305Sha256 = Chacha20Poly1305Sha256;
+
+/// Look up a cipher suite by its identifier in TLS.
+pub fn from_id(id: u16) -> Option<&'static dyn CipherSuite> {
+    match id {
+        0x1301 => Some(&TLS_AES_128_GCM_SHA256),
+        0x1302 => Some(&TLS_AES_256_GCM_SHA384),
+        0x1303 => Some(&TLS_CHACHA20_POLY1305_SHA256),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct Aes128GcmSha256;
+
+impl CipherSuite for Aes128GcmSha256 {
+    fn id(&self) -> u16 {
+        0x1301
+    }
+
+    fn key_length(&self) -> usize {
+        16
+    }
+
+    fn hash_length(&self) -> usize {
+        32
+    }
+
+    fn hkdf_extract(&self, salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+        hkdf_extract::<Hmac<Sha256>>(salt, ikm)
+    }
+
+    fn hkdf_expand(&self, prk: &[u8], info: &[u8], length: u16) -> Vec<u8> {
+        hkdf_expand::<Hmac<Sha256>>(prk, info, length)
+    }
+
+    fn seal(&self, key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+        seal(Aes128Gcm::new(key.into()), nonce, aad, msg)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        msg: &[u8],
+    ) -> Result<Vec<u8>, std::io::Error> {
+        open(Aes128Gcm::new(key.into()), nonce, aad, msg)
+    }
+
+    fn header_protection_mask(&self, hp: &[u8], sample: &[u8]) -> Vec<u8> {
+        let mut mask: Block = *<&Block>::from(sample);
+        Aes128::new_from_slice(hp).unwrap().encrypt_block(&mut mask);
+        mask.to_vec()
+    }
+
+    fn confidentiality_limit(&self) -> u64 {
+        1 << 23
+    }
+
+    fn integrity_limit(&self) -> u64 {
+        1 << 52
+    }
+}
+
+#[derive(Debug)]
+pub struct Aes256GcmSha384;
+
+impl CipherSuite for Aes256GcmSha384 {
+    fn id(&self) -> u16 {
+        0x1302
+    }
+
+    fn key_length(&self) -> usize {
+        32
+    }
+
+    fn hash_length(&self) -> usize {
+        48
+    }
+
+    fn hkdf_extract(&self, salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+        hkdf_extract::<Hmac<Sha384>>(salt, ikm)
+    }
+
+    fn hkdf_expand(&self, prk: &[u8], info: &[u8], length: u16) -> Vec<u8> {
+        hkdf_expand::<Hmac<Sha384>>(prk, info, length)
+    }
+
+    fn seal(&self, key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+        seal(Aes256Gcm::new(key.into()), nonce, aad, msg)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        msg: &[u8],
+    ) -> Result<Vec<u8>, std::io::Error> {
+        open(Aes256Gcm::new(key.into()), nonce, aad, msg)
+    }
+
+    fn header_protection_mask(&self, hp: &[u8], sample: &[u8]) -> Vec<u8> {
+        let mut mask: Block = *<&Block>::from(sample);
+        Aes256::new_from_slice(hp).unwrap().encrypt_block(&mut mask);
+        mask.to_vec()
+    }
+
+    fn confidentiality_limit(&self) -> u64 {
+        1 << 23
+    }
+
+    fn integrity_limit(&self) -> u64 {
+        1 << 52
+    }
+}
+
+#[derive(Debug)]
+pub struct Chacha20Poly1305Sha256;
+
+impl CipherSuite for Chacha20Poly1305Sha256 {
+    fn id(&self) -> u16 {
+        0x1303
+    }
+
+    fn key_length(&self) -> usize {
+        32
+    }
+
+    fn hash_length(&self) -> usize {
+        32
+    }
+
+    fn hkdf_extract(&self, salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+        hkdf_extract::<Hmac<Sha256>>(salt, ikm)
+    }
+
+    fn hkdf_expand(&self, prk: &[u8], info: &[u8], length: u16) -> Vec<u8> {
+        hkdf_expand::<Hmac<Sha256>>(prk, info, length)
+    }
+
+    fn seal(&self, key: &[u8], nonce: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+        seal(ChaCha20Poly1305::new(key.into()), nonce, aad, msg)
+    }
+
+    fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        msg: &[u8],
+    ) -> Result<Vec<u8>, std::io::Error> {
+        open(ChaCha20Poly1305::new(key.into()), nonce, aad, msg)
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-chacha20-based-header-prote
+    fn header_protection_mask(&self, hp: &[u8], sample: &[u8]) -> Vec<u8> {
+        let counter = u32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]);
+        let nonce = &sample[4..16];
+        let mut chacha = ChaCha20::new(hp.into(), nonce.into());
+        // one ChaCha20 block is 64 bytes
+        chacha.seek(counter as u64 * 64);
+        let mut mask = vec![0u8; 5];
+        chacha.apply_keystream(&mut mask);
+        mask
+    }
+
+    fn confidentiality_limit(&self) -> u64 {
+        // the number of possible packets (2^62) is smaller than the limit
+        u64::MAX
+    }
+
+    fn integrity_limit(&self) -> u64 {
+        1 << 36
+    }
+}
+
+fn seal(aead: impl Aead, nonce: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+    // AEAD only fails to encrypt when the payload is larger than its limit,
+    // which never happens for a payload that fits in a UDP datagram.
+    aead.encrypt(nonce.into(), aead::Payload { msg, aad })
+        .expect("payload of a QUIC packet never exceeds the AEAD limit")
+}
+
+fn open(aead: impl Aead, nonce: &[u8], aad: &[u8], msg: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    aead.decrypt(nonce.into(), aead::Payload { msg, aad })
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Crypto Error. Details are not displayed to prevent attacks using error information.",
+            )
+        })
+}
+
+fn hkdf_extract<M: Mac + KeyInit>(salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(salt).expect("HMAC can take a key of any size");
+    mac.update(ikm);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hkdf_expand<M: Mac + KeyInit>(prk: &[u8], info: &[u8], length: u16) -> Vec<u8> {
+    let mut okm = Vec::new();
+    let mut t_result = Vec::new();
+    let mut i = 1u8;
+    while okm.len() < length as usize {
+        let mut t = <M as Mac>::new_from_slice(prk).expect("HMAC can take a key of any size");
+        t.update(&t_result);
+        t.update(info);
+        t.update(&[i]);
+        t_result = t.finalize().into_bytes().to_vec();
+        okm.extend_from_slice(&t_result);
+        i += 1;
+    }
+    okm[..length as usize].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{hkdf_expand_label, DirectionalKeys};
+
+    // https://www.rfc-editor.org/rfc/rfc9001.html#name-chacha20-poly1305-short-hea
+    #[test]
+    fn chacha20_poly1305_short_header_packet() {
+        let secret =
+            hex::decode("9ac312a7f877468ebe69422748ad00a15443f18203a07d6060f688f30f21632b")
+                .unwrap();
+        let suite = &TLS_CHACHA20_POLY1305_SHA256;
+        let keys = DirectionalKeys::from_secret(suite, &secret);
+        assert_eq!(
+            keys.key,
+            hex::decode("c6d98ff3441c3fe1b2182094f69caa2ed4b716b65488960a7a984979fb23e1c8")
+                .unwrap()
+        );
+        assert_eq!(keys.iv, hex::decode("e0459b3474bdd0e44a41c144").unwrap());
+        assert_eq!(
+            keys.hp,
+            hex::decode("25a282b9e82f06f21f488917a4fc8f1b73573685608597d0efcb076b0ab7a7a4")
+                .unwrap()
+        );
+        assert_eq!(
+            hkdf_expand_label(suite, &secret, "quic ku".as_bytes(), &[], 32),
+            hex::decode("1223504755036d556342ee9361d253421a826c9ecdf3c7148684b36b714881f9")
+                .unwrap()
+        );
+
+        let next_keys = keys.next_generation();
+        assert_eq!(
+            next_keys.secret,
+            hex::decode("1223504755036d556342ee9361d253421a826c9ecdf3c7148684b36b714881f9")
+                .unwrap()
+        );
+        assert_eq!(next_keys.hp, keys.hp);
+
+        // packet number 654360564 is encoded in 3 bytes
+        let header = hex::decode("4200bff4").unwrap();
+        let payload = keys.encrypt_payload(&[0x01], &654360564u32.to_be_bytes(), &header);
+        assert_eq!(
+            payload,
+            hex::decode("655e5cd55c41f69080575d7999c25a5bfb").unwrap()
+        );
+
+        let mask = keys.header_protection_mask(&payload[1..17]);
+        assert_eq!(mask, hex::decode("aefefe7d03").unwrap());
+    }
+
+    #[test]
+    fn aes_256_gcm_round_trip() {
+        let suite = &TLS_AES_256_GCM_SHA384;
+        let keys = DirectionalKeys::from_secret(suite, &[0x01; 48]);
+        assert_eq!(keys.key.len(), 32);
+        assert_eq!(keys.hp.len(), 32);
+
+        let header = [0x40, 0x01];
+        let payload = keys.encrypt_payload(&[0x01, 0x00, 0x00], &[0x01], &header);
+        assert_eq!(payload.len(), 3 + suite.tag_length());
+        assert_eq!(
+            keys.decrypt_payload(&payload, &[0x01], &header).unwrap(),
+            vec![0x01, 0x00, 0x00]
+        );
+        assert!(keys.decrypt_payload(&payload, &[0x02], &header).is_err());
+        assert_eq!(keys.header_protection_mask(&payload[..16]).len(), 16);
+    }
+
+    #[test]
+    fn cipher_suites_from_id() {
+        assert_eq!(from_id(0x1301).unwrap().key_length(), 16);
+        assert_eq!(from_id(0x1302).unwrap().hash_length(), 48);
+        assert_eq!(from_id(0x1303).unwrap().key_length(), 32);
+        assert!(from_id(0x1304).is_none());
+    }
+}
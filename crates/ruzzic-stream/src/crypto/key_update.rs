@@ -0,0 +1,278 @@
+use super::{DirectionalKeys, KeySet};
+
+/// 1-RTT keys with the Key Phase bit.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#name-key-update
+#[derive(Debug, Clone, PartialEq)]
+pub struct OneRttKeys {
+    key_phase: bool,
+    current: KeySet,
+    next: KeySet,
+    /// Kept for a while to remove protection from delayed packets of the previous key phase.
+    previous: Option<KeySet>,
+    /// The smallest packet number received in the current key phase.
+    first_received_packet_number: Option<u64>,
+    /// The smallest packet number sent in the current key phase.
+    first_sent_packet_number: Option<u64>,
+    /// Whether a packet sent in the current key phase has been acknowledged.
+    confirmed: bool,
+    protected_packets: u64,
+    authentication_failures: u64,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum KeyUpdateError {
+    #[error("the previous key update is not confirmed yet")]
+    UpdateNotConfirmed,
+    #[error("the confidentiality limit of the AEAD is reached")]
+    ConfidentialityLimitReached,
+    #[error("the integrity limit of the AEAD is reached")]
+    IntegrityLimitReached,
+}
+
+impl KeyUpdateError {
+    /// Transport error code to close the connection with.
+    pub fn transport_error_code(&self) -> u64 {
+        match self {
+            // KEY_UPDATE_ERROR
+            KeyUpdateError::UpdateNotConfirmed => 0x0e,
+            // AEAD_LIMIT_REACHED
+            KeyUpdateError::ConfidentialityLimitReached | KeyUpdateError::IntegrityLimitReached => {
+                0x0f
+            }
+        }
+    }
+}
+
+impl OneRttKeys {
+    pub(crate) fn new(key_set: KeySet) -> Self {
+        Self {
+            key_phase: false,
+            next: key_set.next_generation(),
+            current: key_set,
+            previous: None,
+            first_received_packet_number: None,
+            first_sent_packet_number: None,
+            // the handshake is confirmed before the first key update
+            confirmed: true,
+            protected_packets: 0,
+            authentication_failures: 0,
+        }
+    }
+
+    pub fn key_phase(&self) -> bool {
+        self.key_phase
+    }
+
+    pub fn current(&self) -> &KeySet {
+        &self.current
+    }
+
+    /// Keys to protect a packet with the packet number and the Key Phase bit to be set in it.
+    /// A key update is initiated well before the confidentiality limit is reached.
+    pub fn sealing_keys(
+        &mut self,
+        packet_number: u64,
+    ) -> Result<(bool, &DirectionalKeys), KeyUpdateError> {
+        let limit = self.current.local().cipher_suite().confidentiality_limit();
+        if self.protected_packets >= limit / 4 * 3 && self.confirmed {
+            self.initiate_update()?;
+        }
+        if self.protected_packets >= limit {
+            return Err(KeyUpdateError::ConfidentialityLimitReached);
+        }
+        self.protected_packets += 1;
+        self.first_sent_packet_number.get_or_insert(packet_number);
+        Ok((self.key_phase, self.current.local()))
+    }
+
+    /// Keys to remove protection from a packet which has the Key Phase bit.
+    /// The packet number is the one recovered after header protection is removed.
+    pub fn opening_keys(&self, key_phase: bool, packet_number: u64) -> &DirectionalKeys {
+        if key_phase == self.key_phase {
+            return self.current.remote();
+        }
+        match (&self.previous, self.first_received_packet_number) {
+            // packets of the previous phase have smaller packet numbers than the current one
+            (Some(previous), Some(first)) if packet_number < first => previous.remote(),
+            (Some(previous), None) => previous.remote(),
+            _ => self.next.remote(),
+        }
+    }
+
+    /// Must be called after a packet is successfully decrypted with the keys from `opening_keys`.
+    /// This responds to a key update initiated by the peer.
+    pub fn on_packet_decrypted(
+        &mut self,
+        key_phase: bool,
+        packet_number: u64,
+    ) -> Result<(), KeyUpdateError> {
+        if key_phase == self.key_phase {
+            self.first_received_packet_number = Some(
+                self.first_received_packet_number
+                    .map_or(packet_number, |first| first.min(packet_number)),
+            );
+            return Ok(());
+        }
+        if self.opening_keys(key_phase, packet_number) != self.next.remote() {
+            // delayed packet of the previous key phase
+            return Ok(());
+        }
+        // the peer initiated a key update
+        if !self.confirmed {
+            return Err(KeyUpdateError::UpdateNotConfirmed);
+        }
+        self.rotate();
+        self.first_received_packet_number = Some(packet_number);
+        Ok(())
+    }
+
+    /// Must be called when a packet is acknowledged by the peer.
+    pub fn on_packet_acknowledged(&mut self, packet_number: u64) {
+        if let Some(first) = self.first_sent_packet_number {
+            if packet_number >= first {
+                self.confirmed = true;
+            }
+        }
+    }
+
+    /// Must be called when a packet fails authentication.
+    /// The connection must be closed when this returns an error.
+    pub fn on_authentication_failure(&mut self) -> Result<(), KeyUpdateError> {
+        self.authentication_failures += 1;
+        if self.authentication_failures >= self.current.remote().cipher_suite().integrity_limit() {
+            return Err(KeyUpdateError::IntegrityLimitReached);
+        }
+        Ok(())
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-initiating-a-key-update
+    pub fn initiate_update(&mut self) -> Result<(), KeyUpdateError> {
+        if !self.confirmed {
+            return Err(KeyUpdateError::UpdateNotConfirmed);
+        }
+        self.rotate();
+        Ok(())
+    }
+
+    /// Previous keys should be discarded after three times the PTO from the key update.
+    pub fn discard_previous_keys(&mut self) {
+        self.previous = None;
+    }
+
+    fn rotate(&mut self) {
+        let next = self.next.next_generation();
+        let current = std::mem::replace(&mut self.next, next);
+        self.previous = Some(std::mem::replace(&mut self.current, current));
+        self.key_phase = !self.key_phase;
+        self.first_received_packet_number = None;
+        self.first_sent_packet_number = None;
+        self.confirmed = false;
+        self.protected_packets = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruzzic_common::EndpointType;
+
+    use super::*;
+    use crate::crypto::cipher_suite::{CipherSuite, TLS_AES_128_GCM_SHA256};
+
+    fn key_pair() -> (OneRttKeys, OneRttKeys) {
+        let client = KeySet::from_secrets(
+            &TLS_AES_128_GCM_SHA256,
+            &[0x01; 32],
+            &[0x02; 32],
+            &EndpointType::Client,
+        );
+        let server = KeySet::from_secrets(
+            &TLS_AES_128_GCM_SHA256,
+            &[0x01; 32],
+            &[0x02; 32],
+            &EndpointType::Server,
+        );
+        (OneRttKeys::new(client), OneRttKeys::new(server))
+    }
+
+    #[test]
+    fn key_update() {
+        let (mut client, mut server) = key_pair();
+
+        let (key_phase, keys) = client.sealing_keys(0).unwrap();
+        assert!(!key_phase);
+        assert_eq!(server.opening_keys(key_phase, 0), keys);
+
+        client.initiate_update().unwrap();
+        // the previous update is not acknowledged yet
+        assert_eq!(
+            client.initiate_update(),
+            Err(KeyUpdateError::UpdateNotConfirmed)
+        );
+        let (key_phase, keys) = client.sealing_keys(1).unwrap();
+        let keys = keys.clone();
+        assert!(key_phase);
+        assert_eq!(server.opening_keys(key_phase, 1), &keys);
+        assert_ne!(server.current().remote(), &keys);
+
+        server.on_packet_decrypted(key_phase, 1).unwrap();
+        assert!(server.key_phase());
+        assert_eq!(server.current().remote(), &keys);
+        let (key_phase, keys) = server.sealing_keys(0).unwrap();
+        assert!(key_phase);
+        assert_eq!(client.opening_keys(key_phase, 0), keys);
+
+        // a delayed packet of the previous key phase
+        let previous = client.previous.as_ref().unwrap().local().clone();
+        assert_eq!(server.opening_keys(false, 0), &previous);
+        server.on_packet_decrypted(false, 0).unwrap();
+        assert!(server.key_phase());
+
+        client.on_packet_acknowledged(1);
+        client.initiate_update().unwrap();
+        assert!(!client.key_phase());
+    }
+
+    #[test]
+    fn discard_previous_keys() {
+        let (mut client, mut server) = key_pair();
+        client.initiate_update().unwrap();
+        let (key_phase, _) = client.sealing_keys(5).unwrap();
+        server.on_packet_decrypted(key_phase, 5).unwrap();
+        server.discard_previous_keys();
+
+        // without the previous keys, a packet of the other phase is considered as the next one
+        assert_eq!(server.opening_keys(false, 3), server.next.remote());
+    }
+
+    #[test]
+    fn confidentiality_limit() {
+        let (mut client, _) = key_pair();
+        let limit = TLS_AES_128_GCM_SHA256.confidentiality_limit();
+
+        client.protected_packets = limit / 4 * 3;
+        let (key_phase, _) = client.sealing_keys(0).unwrap();
+        assert!(key_phase);
+
+        // the update can not be initiated before it is confirmed
+        client.protected_packets = limit;
+        assert_eq!(
+            client.sealing_keys(1),
+            Err(KeyUpdateError::ConfidentialityLimitReached)
+        );
+        assert_eq!(
+            KeyUpdateError::ConfidentialityLimitReached.transport_error_code(),
+            0x0f
+        );
+    }
+
+    #[test]
+    fn integrity_limit() {
+        let (mut client, _) = key_pair();
+        client.authentication_failures = TLS_AES_128_GCM_SHA256.integrity_limit() - 2;
+        assert!(client.on_authentication_failure().is_ok());
+        assert_eq!(
+            client.on_authentication_failure(),
+            Err(KeyUpdateError::IntegrityLimitReached)
+        );
+    }
+}
@@ -0,0 +1,539 @@
+//! Connections sharing one UDP socket, told apart by connection ID.
+//! https://www.rfc-editor.org/rfc/rfc9000.html#name-matching-packets-to-connect
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::Cursor,
+    net::SocketAddr,
+    sync::Arc,
+    time::Instant,
+};
+
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use ruzzic_common::{read_bytes_to::ReadBytesToWith, EndpointType};
+use ruzzic_tls::session::{ClientConfig, ServerConfig};
+use sha2::Sha256;
+
+use crate::{
+    connection::{Connection, ConnectionID, Event, CONNECTION_ID_LENGTH},
+    packet::Packet,
+    transport_parameters::TransportParameters,
+    Token, Version,
+};
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-14.1
+const MIN_INITIAL_DATAGRAM_SIZE: usize = 1200;
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-7.2
+const MIN_INITIAL_CONNECTION_ID_LENGTH: usize = 8;
+
+/// A stateless reset looks like a short header packet,
+/// with at least 38 unpredictable bits before the token.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-10.3
+const MIN_STATELESS_RESET_SIZE: usize = 21;
+const MAX_STATELESS_RESET_SIZE: usize = 42;
+const STATELESS_RESET_TOKEN_LENGTH: usize = 16;
+/// A Retry Token ends with this many bytes of the HMAC over what it is bound to.
+const RETRY_TOKEN_TAG_LENGTH: usize = 16;
+
+const SUPPORTED_VERSIONS: [Version; 1] = [Version(0x1)];
+
+/// Identifies a connection of an endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionHandle(u64);
+
+impl ConnectionHandle {
+    fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+/// A datagram to send.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transmit {
+    pub destination: SocketAddr,
+    pub contents: Vec<u8>,
+}
+
+struct ConnectionEntry {
+    connection: Connection,
+    remote: SocketAddr,
+    /// Every connection id routed to the connection.
+    connection_ids: Vec<ConnectionID>,
+}
+
+pub struct Endpoint {
+    endpoint_type: EndpointType,
+    server_config: Option<Arc<ServerConfig>>,
+    transport_parameters: TransportParameters,
+    connections: BTreeMap<ConnectionHandle, ConnectionEntry>,
+    connection_ids: HashMap<ConnectionID, ConnectionHandle>,
+    next_handle: u64,
+    /// Stateless reset tokens are derived from connection ids with the key,
+    /// so they are sent without any state of the connection.
+    reset_key: [u8; 32],
+    /// Whether the address of a client is validated with a Retry packet before a connection is created.
+    retry: bool,
+    /// Retry Tokens are authenticated with the key, so that they are checked without any state.
+    token_key: [u8; 32],
+    /// Datagrams sent on behalf of no connection.
+    transmits: VecDeque<Transmit>,
+    /// Where polling for datagrams and events starts, so that every connection has its turn.
+    transmit_cursor: ConnectionHandle,
+    event_cursor: ConnectionHandle,
+}
+
+impl Endpoint {
+    pub fn new_server(
+        config: Arc<ServerConfig>,
+        transport_parameters: TransportParameters,
+    ) -> Self {
+        Self::new(EndpointType::Server, Some(config), transport_parameters)
+    }
+
+    pub fn new_client(transport_parameters: TransportParameters) -> Self {
+        Self::new(EndpointType::Client, None, transport_parameters)
+    }
+
+    fn new(
+        endpoint_type: EndpointType,
+        server_config: Option<Arc<ServerConfig>>,
+        transport_parameters: TransportParameters,
+    ) -> Self {
+        let mut reset_key = [0; 32];
+        rand::thread_rng().fill_bytes(&mut reset_key);
+        let mut token_key = [0; 32];
+        rand::thread_rng().fill_bytes(&mut token_key);
+        Self {
+            endpoint_type,
+            server_config,
+            transport_parameters,
+            connections: BTreeMap::new(),
+            connection_ids: HashMap::new(),
+            next_handle: 0,
+            reset_key,
+            retry: false,
+            token_key,
+            transmits: VecDeque::new(),
+            transmit_cursor: ConnectionHandle(0),
+            event_cursor: ConnectionHandle(0),
+        }
+    }
+
+    /// A server sends a Retry packet in reply to the first Initial packet of each client,
+    /// and creates the connection once the client proves its address with the Retry Token.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-address-validation-using-re
+    pub fn with_retry(mut self) -> Self {
+        self.retry = true;
+        self
+    }
+
+    /// Start a connection to a server.
+    pub fn connect(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        config: &ClientConfig,
+        server_name: &str,
+    ) -> ConnectionHandle {
+        let connection = Connection::connect(
+            SUPPORTED_VERSIONS[0],
+            config,
+            server_name,
+            self.transport_parameters.clone(),
+            now,
+        );
+        self.insert(connection, remote)
+    }
+
+    pub fn connection(&self, handle: ConnectionHandle) -> Option<&Connection> {
+        self.connections.get(&handle).map(|entry| &entry.connection)
+    }
+
+    pub fn connection_mut(&mut self, handle: ConnectionHandle) -> Option<&mut Connection> {
+        self.connections
+            .get_mut(&handle)
+            .map(|entry| &mut entry.connection)
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Route a datagram to its connection, which is created for a new client at a server.
+    /// None if the datagram belongs to no connection.
+    pub fn handle_datagram(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        datagram: &[u8],
+    ) -> Option<ConnectionHandle> {
+        let header = Header::parse(datagram)?;
+        if let Some(&handle) = self.connection_ids.get(&header.destination_connection_id) {
+            let entry = self.connections.get_mut(&handle)?;
+            entry.connection.handle_datagram(now, datagram);
+            return Some(handle);
+        }
+        match header.version {
+            // https://www.rfc-editor.org/rfc/rfc9000.html#section-6.1
+            Some(version) if version != Version(0) && !SUPPORTED_VERSIONS.contains(&version) => {
+                if self.endpoint_type == EndpointType::Server
+                    && datagram.len() >= MIN_INITIAL_DATAGRAM_SIZE
+                {
+                    self.send_version_negotiation(remote, &header);
+                }
+                None
+            }
+            Some(_) if header.is_initial => self.accept(now, remote, datagram, &header),
+            Some(_) => None,
+            None => {
+                self.send_stateless_reset(remote, datagram, &header.destination_connection_id);
+                None
+            }
+        }
+    }
+
+    fn accept(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        datagram: &[u8],
+        header: &Header,
+    ) -> Option<ConnectionHandle> {
+        let config = self.server_config.clone()?;
+        // a small datagram may be used for amplification attacks
+        if datagram.len() < MIN_INITIAL_DATAGRAM_SIZE
+            || header.destination_connection_id.len() < MIN_INITIAL_CONNECTION_ID_LENGTH
+        {
+            return None;
+        }
+        let packet = Cursor::new(datagram)
+            .read_bytes_to_with::<Packet>(CONNECTION_ID_LENGTH)
+            .ok()?;
+        let mut transport_parameters = self.transport_parameters.clone();
+        if self.retry {
+            let token = packet.initial_token()?;
+            if token.0.is_empty() {
+                self.send_retry(remote, &packet);
+                return None;
+            }
+            // an invalid token is dropped rather than answered with another Retry
+            let original_destination_connection_id =
+                self.validate_retry_token(remote, &header.destination_connection_id, token)?;
+            transport_parameters.original_destination_connection_id =
+                Some(original_destination_connection_id);
+            transport_parameters.retry_source_connection_id =
+                Some(header.destination_connection_id.clone());
+        }
+        let source_connection_id = self.new_connection_id();
+        let transport_parameters = TransportParameters {
+            stateless_reset_token: Some(self.stateless_reset_token(&source_connection_id)),
+            ..transport_parameters
+        };
+        let mut connection = Connection::accept_with_id(
+            &packet,
+            source_connection_id,
+            config,
+            transport_parameters,
+            now,
+        )?;
+        connection.handle_datagram(now, datagram);
+        let handle = self.insert(connection, remote);
+        // packets of the client are sent to the original one until the server replies
+        self.connection_ids
+            .insert(header.destination_connection_id.clone(), handle);
+        self.connections
+            .get_mut(&handle)
+            .expect("just inserted")
+            .connection_ids
+            .push(header.destination_connection_id.clone());
+        Some(handle)
+    }
+
+    fn insert(&mut self, connection: Connection, remote: SocketAddr) -> ConnectionHandle {
+        let handle = ConnectionHandle(self.next_handle);
+        self.next_handle += 1;
+        let connection_id = connection.source_connection_id().clone();
+        self.connection_ids.insert(connection_id.clone(), handle);
+        self.connections.insert(
+            handle,
+            ConnectionEntry {
+                connection,
+                remote,
+                connection_ids: vec![connection_id],
+            },
+        );
+        handle
+    }
+
+    /// A connection id not used by any other connection.
+    fn new_connection_id(&self) -> ConnectionID {
+        loop {
+            let connection_id = ConnectionID::random();
+            if !self.connection_ids.contains_key(&connection_id) {
+                return connection_id;
+            }
+        }
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-calculating-a-stateless-res
+    fn stateless_reset_token(&self, connection_id: &ConnectionID) -> u128 {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.reset_key).expect("any key length");
+        mac.update(connection_id.as_slice());
+        let tag = mac.finalize().into_bytes();
+        u128::from_be_bytes(tag[..STATELESS_RESET_TOKEN_LENGTH].try_into().unwrap())
+    }
+
+    /// The client is asked to come again to a connection id of the server with the token.
+    /// No state is kept until then, the token carries the Original Destination Connection ID.
+    fn send_retry(&mut self, remote: SocketAddr, packet: &Packet) {
+        // the connection only builds the Retry packet, it is not kept
+        let Some(connection) = packet
+            .version()
+            .and_then(|version| Connection::new_with_packet(version, packet))
+        else {
+            return;
+        };
+        let source_connection_id = self.new_connection_id();
+        let original_destination_connection_id = packet.destination_connection_id();
+        let tag = self.retry_token_tag(
+            remote,
+            &source_connection_id,
+            &original_destination_connection_id,
+        );
+        let token = Token(
+            [
+                &[original_destination_connection_id.len() as u8][..],
+                original_destination_connection_id.as_slice(),
+                &tag,
+            ]
+            .concat(),
+        );
+        let retry = Packet::new_retry(&connection, &source_connection_id, token);
+        self.transmits.push_back(Transmit {
+            destination: remote,
+            contents: retry.raw().to_vec(),
+        });
+    }
+
+    /// The Original Destination Connection ID in the token,
+    /// if the token is the one sent to the address in the Retry packet from the connection id.
+    fn validate_retry_token(
+        &self,
+        remote: SocketAddr,
+        retry_source_connection_id: &ConnectionID,
+        token: &Token,
+    ) -> Option<ConnectionID> {
+        let (&length, rest) = token.0.split_first()?;
+        if rest.len() != length as usize + RETRY_TOKEN_TAG_LENGTH {
+            return None;
+        }
+        let (original_destination_connection_id, tag) = rest.split_at(length as usize);
+        let original_destination_connection_id =
+            ConnectionID(original_destination_connection_id.to_vec());
+        let expected = self.retry_token_tag(
+            remote,
+            retry_source_connection_id,
+            &original_destination_connection_id,
+        );
+        (tag == expected).then_some(original_destination_connection_id)
+    }
+
+    fn retry_token_tag(
+        &self,
+        remote: SocketAddr,
+        retry_source_connection_id: &ConnectionID,
+        original_destination_connection_id: &ConnectionID,
+    ) -> [u8; RETRY_TOKEN_TAG_LENGTH] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.token_key).expect("any key length");
+        mac.update(remote.to_string().as_bytes());
+        for connection_id in [
+            retry_source_connection_id,
+            original_destination_connection_id,
+        ] {
+            mac.update(&[connection_id.len() as u8]);
+            mac.update(connection_id.as_slice());
+        }
+        let tag = mac.finalize().into_bytes();
+        tag[..RETRY_TOKEN_TAG_LENGTH].try_into().unwrap()
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-version-negotiation-packet
+    fn send_version_negotiation(&mut self, remote: SocketAddr, header: &Header) {
+        let mut packet = vec![0x80 | rand::thread_rng().gen::<u8>(), 0, 0, 0, 0];
+        // the connection ids of the client are echoed
+        for connection_id in [
+            &header.source_connection_id,
+            &header.destination_connection_id,
+        ] {
+            packet.push(connection_id.len() as u8);
+            packet.extend_from_slice(connection_id.as_slice());
+        }
+        for version in SUPPORTED_VERSIONS {
+            packet.extend_from_slice(&version.to_bytes());
+        }
+        self.transmits.push_back(Transmit {
+            destination: remote,
+            contents: packet,
+        });
+    }
+
+    /// A stateless reset is smaller than the packet which triggers it,
+    /// so that two endpoints do not reset each other endlessly.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-looping
+    fn send_stateless_reset(
+        &mut self,
+        remote: SocketAddr,
+        datagram: &[u8],
+        connection_id: &ConnectionID,
+    ) {
+        if self.endpoint_type != EndpointType::Server || datagram.len() <= MIN_STATELESS_RESET_SIZE
+        {
+            return;
+        }
+        let length = (datagram.len() - 1).min(MAX_STATELESS_RESET_SIZE);
+        let mut packet = vec![0; length - STATELESS_RESET_TOKEN_LENGTH];
+        rand::thread_rng().fill_bytes(&mut packet);
+        // the fixed bit is set and the header form is short
+        packet[0] = 0x40 | (packet[0] & 0x3f);
+        packet.extend_from_slice(&self.stateless_reset_token(connection_id).to_be_bytes());
+        self.transmits.push_back(Transmit {
+            destination: remote,
+            contents: packet,
+        });
+    }
+
+    /// The next datagram to send, of any connection.
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<Transmit> {
+        if let Some(transmit) = self.transmits.pop_front() {
+            return Some(transmit);
+        }
+        let (handle, transmit) = find_from(&mut self.connections, self.transmit_cursor, |entry| {
+            entry
+                .connection
+                .poll_transmit(now)
+                .map(|contents| Transmit {
+                    destination: entry.remote,
+                    contents,
+                })
+        })?;
+        self.transmit_cursor = handle.next();
+        Some(transmit)
+    }
+
+    /// The next event of any connection.
+    pub fn poll_event(&mut self) -> Option<(ConnectionHandle, Event)> {
+        let (handle, event) = find_from(&mut self.connections, self.event_cursor, |entry| {
+            entry.connection.poll_event()
+        })?;
+        self.event_cursor = handle.next();
+        if self.connections[&handle].connection.is_finished() {
+            self.remove(handle);
+        }
+        Some((handle, event))
+    }
+
+    /// The earliest time `handle_timeout` must be called.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        self.connections
+            .values()
+            .filter_map(|entry| entry.connection.poll_timeout())
+            .min()
+    }
+
+    pub fn handle_timeout(&mut self, now: Instant) {
+        for entry in self.connections.values_mut() {
+            if entry
+                .connection
+                .poll_timeout()
+                .is_some_and(|timeout| timeout <= now)
+            {
+                entry.connection.handle_timeout(now);
+            }
+        }
+        self.remove_finished();
+    }
+
+    /// Connections are forgotten once they are closed and their events are taken.
+    fn remove_finished(&mut self) {
+        let finished = self
+            .connections
+            .iter()
+            .filter(|(_, entry)| entry.connection.is_finished())
+            .map(|(handle, _)| *handle)
+            .collect::<Vec<_>>();
+        for handle in finished {
+            self.remove(handle);
+        }
+    }
+
+    fn remove(&mut self, handle: ConnectionHandle) {
+        let entry = self.connections.remove(&handle).expect("exists");
+        for connection_id in entry.connection_ids {
+            self.connection_ids.remove(&connection_id);
+        }
+    }
+}
+
+/// The first result of the connections from the cursor, wrapping around to the ones before it.
+fn find_from<T>(
+    connections: &mut BTreeMap<ConnectionHandle, ConnectionEntry>,
+    cursor: ConnectionHandle,
+    mut f: impl FnMut(&mut ConnectionEntry) -> Option<T>,
+) -> Option<(ConnectionHandle, T)> {
+    let mut find = |(handle, entry): (&ConnectionHandle, &mut ConnectionEntry)| {
+        f(entry).map(|found| (*handle, found))
+    };
+    connections
+        .range_mut(cursor..)
+        .find_map(&mut find)
+        .or_else(|| connections.range_mut(..cursor).find_map(&mut find))
+}
+
+/// What is needed to route a datagram, read without removing protection.
+struct Header {
+    /// None for a short header.
+    version: Option<Version>,
+    is_initial: bool,
+    destination_connection_id: ConnectionID,
+    source_connection_id: ConnectionID,
+}
+
+impl Header {
+    /// Short headers have the connection ids issued by this endpoint, which have a fixed length.
+    /// https://www.rfc-editor.org/rfc/rfc8999.html#section-5
+    fn parse(datagram: &[u8]) -> Option<Self> {
+        let first = *datagram.first()?;
+        if first & 0x80 == 0 {
+            let connection_id = datagram.get(1..1 + CONNECTION_ID_LENGTH)?;
+            return Some(Self {
+                version: None,
+                is_initial: false,
+                destination_connection_id: ConnectionID(connection_id.to_vec()),
+                source_connection_id: ConnectionID(Vec::new()),
+            });
+        }
+        let version = Version(u32::from_be_bytes(datagram.get(1..5)?.try_into().ok()?));
+        let mut rest = &datagram[5..];
+        let mut read_connection_id = || {
+            let (&length, tail) = rest.split_first()?;
+            let length = length as usize;
+            let connection_id = tail.get(..length)?;
+            rest = &tail[length..];
+            Some(ConnectionID(connection_id.to_vec()))
+        };
+        let destination_connection_id = read_connection_id()?;
+        let source_connection_id = read_connection_id()?;
+        Some(Self {
+            version: Some(version),
+            // https://www.rfc-editor.org/rfc/rfc9000.html#name-initial-packet
+            is_initial: (first >> 4) & 0x03 == 0,
+            destination_connection_id,
+            source_connection_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
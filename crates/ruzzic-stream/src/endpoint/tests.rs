@@ -0,0 +1,309 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::*;
+use crate::connection::{
+    tests::{client_config, server_config, transport_parameters},
+    ConnectionState,
+};
+use crate::stream::StreamDirection;
+
+fn address(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+const SERVER: u16 = 4433;
+
+fn server() -> Endpoint {
+    Endpoint::new_server(Arc::new(server_config()), transport_parameters())
+}
+
+/// Deliver datagrams between the endpoints until nothing is sent.
+/// Each client is known to the server by its port.
+fn exchange(server: &mut Endpoint, clients: &mut [(u16, &mut Endpoint)], now: Instant) {
+    loop {
+        let mut sent = false;
+        for (port, client) in clients.iter_mut() {
+            while let Some(transmit) = client.poll_transmit(now) {
+                assert_eq!(transmit.destination, address(SERVER));
+                server.handle_datagram(now, address(*port), &transmit.contents);
+                sent = true;
+            }
+        }
+        while let Some(transmit) = server.poll_transmit(now) {
+            let (_, client) = clients
+                .iter_mut()
+                .find(|(port, _)| address(*port) == transmit.destination)
+                .unwrap();
+            client.handle_datagram(now, address(SERVER), &transmit.contents);
+            sent = true;
+        }
+        if !sent {
+            break;
+        }
+    }
+}
+
+fn connected(endpoint: &mut Endpoint) -> Vec<ConnectionHandle> {
+    std::iter::from_fn(|| endpoint.poll_event())
+        .filter(|(_, event)| matches!(event, Event::Connected))
+        .map(|(handle, _)| handle)
+        .collect()
+}
+
+#[test]
+fn connections_of_clients() {
+    let now = Instant::now();
+    let mut server = server();
+    let mut first = Endpoint::new_client(transport_parameters());
+    let mut second = Endpoint::new_client(transport_parameters());
+    let first_handle = first.connect(now, address(SERVER), &client_config(), "localhost");
+    second.connect(now, address(SERVER), &client_config(), "localhost");
+
+    exchange(&mut server, &mut [(1, &mut first), (2, &mut second)], now);
+    assert_eq!(connected(&mut first), vec![first_handle]);
+    assert_eq!(connected(&mut second).len(), 1);
+    assert_eq!(connected(&mut server).len(), 2);
+    assert_eq!(server.connection_count(), 2);
+
+    // the server chose a connection id of its own
+    let client = first.connection(first_handle).unwrap();
+    assert_eq!(client.state(), ConnectionState::Established);
+    let server_ids = server
+        .connections
+        .values()
+        .map(|entry| entry.connection.source_connection_id().clone())
+        .collect::<Vec<_>>();
+    assert!(server_ids.contains(client.destination_connection_id()));
+    assert_eq!(server_ids[0].len(), CONNECTION_ID_LENGTH);
+
+    let connection = first.connection_mut(first_handle).unwrap();
+    let stream_id = connection
+        .open_stream(StreamDirection::Bidirectional)
+        .unwrap();
+    connection.write_stream(&stream_id, b"hello").unwrap();
+    exchange(&mut server, &mut [(1, &mut first), (2, &mut second)], now);
+    let events = std::iter::from_fn(|| server.poll_event()).collect::<Vec<_>>();
+    assert!(matches!(
+        &events[..],
+        [(_, Event::StreamData { data, .. })] if data == b"hello"
+    ));
+}
+
+#[test]
+fn connections_take_turns() {
+    let now = Instant::now();
+    let mut server = server();
+    let mut first = Endpoint::new_client(transport_parameters());
+    let mut second = Endpoint::new_client(transport_parameters());
+    first.connect(now, address(SERVER), &client_config(), "localhost");
+    second.connect(now, address(SERVER), &client_config(), "localhost");
+    exchange(&mut server, &mut [(1, &mut first), (2, &mut second)], now);
+    let handles = connected(&mut server);
+
+    for &handle in &handles {
+        let connection = server.connection_mut(handle).unwrap();
+        let stream_id = connection
+            .open_stream(StreamDirection::Bidirectional)
+            .unwrap();
+        connection.write_stream(&stream_id, &[1; 5000]).unwrap();
+    }
+    let destinations = std::iter::from_fn(|| server.poll_transmit(now))
+        .take(4)
+        .map(|transmit| transmit.destination)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        destinations,
+        vec![address(1), address(2), address(1), address(2)]
+    );
+}
+
+#[test]
+fn version_negotiation() {
+    let now = Instant::now();
+    let mut server = server();
+    let mut client = Endpoint::new_client(transport_parameters());
+    client.connect(now, address(SERVER), &client_config(), "localhost");
+    let mut datagram = client.poll_transmit(now).unwrap().contents;
+    // a reserved version to force version negotiation
+    datagram[1..5].copy_from_slice(&[0x1a, 0x2a, 0x3a, 0x4a]);
+
+    assert_eq!(server.handle_datagram(now, address(1), &datagram), None);
+    assert_eq!(server.connection_count(), 0);
+    let transmit = server.poll_transmit(now).unwrap();
+    assert_eq!(transmit.destination, address(1));
+    let packet = transmit.contents;
+    assert_eq!(packet[0] & 0x80, 0x80);
+    assert_eq!(packet[1..5], [0, 0, 0, 0]);
+    // the connection ids are swapped
+    let header = Header::parse(&datagram).unwrap();
+    let reply = Header::parse(&packet).unwrap();
+    assert_eq!(reply.destination_connection_id, header.source_connection_id);
+    assert_eq!(reply.source_connection_id, header.destination_connection_id);
+    assert_eq!(packet[packet.len() - 4..], [0, 0, 0, 1]);
+
+    // too small to be answered
+    assert_eq!(
+        server.handle_datagram(now, address(1), &datagram[..1000]),
+        None
+    );
+    assert!(server.poll_transmit(now).is_none());
+}
+
+#[test]
+fn stateless_reset() {
+    let now = Instant::now();
+    let mut server = server();
+    let mut client = Endpoint::new_client(transport_parameters());
+    let handle = client.connect(now, address(SERVER), &client_config(), "localhost");
+    exchange(&mut server, &mut [(1, &mut client)], now);
+
+    // the server forgets the connection
+    let (_, entry) = server.connections.iter().next().unwrap();
+    let connection_id = entry.connection.source_connection_id().clone();
+    let token = server.stateless_reset_token(&connection_id);
+    let peer_parameters = client.connection(handle).unwrap();
+    assert_eq!(
+        peer_parameters
+            .peer_transport_parameters()
+            .unwrap()
+            .stateless_reset_token,
+        Some(token)
+    );
+    server.connections.clear();
+    server.connection_ids.clear();
+
+    let connection = client.connection_mut(handle).unwrap();
+    let stream_id = connection
+        .open_stream(StreamDirection::Bidirectional)
+        .unwrap();
+    connection.write_stream(&stream_id, &[1; 100]).unwrap();
+    let datagram = client.poll_transmit(now).unwrap().contents;
+    assert_eq!(server.handle_datagram(now, address(1), &datagram), None);
+    let reset = server.poll_transmit(now).unwrap().contents;
+    assert!(reset.len() < datagram.len());
+    assert_eq!(reset[0] & 0xc0, 0x40);
+    assert_eq!(reset[reset.len() - 16..], token.to_be_bytes());
+
+    // a stateless reset is not answered with another one
+    let small = &datagram[..MIN_STATELESS_RESET_SIZE];
+    server.handle_datagram(now, address(1), small);
+    assert!(server.poll_transmit(now).is_none());
+}
+
+#[test]
+fn small_initial_is_dropped() {
+    let now = Instant::now();
+    let mut server = server();
+    let mut client = Endpoint::new_client(transport_parameters());
+    client.connect(now, address(SERVER), &client_config(), "localhost");
+    let datagram = client.poll_transmit(now).unwrap().contents;
+    assert_eq!(
+        server.handle_datagram(now, address(1), &datagram[..1199]),
+        None
+    );
+    assert_eq!(server.connection_count(), 0);
+    assert!(server.handle_datagram(now, address(1), &datagram).is_some());
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn malformed_initial_is_dropped() {
+    let now = Instant::now();
+    let mut header = vec![0xc0, 0, 0, 0, 1, 8];
+    header.extend_from_slice(&[0x11; 8]);
+    // no Source Connection ID and no token
+    header.extend_from_slice(&[0, 0]);
+    // an empty packet, a packet longer than the datagram and a length no datagram can hold
+    for length in [&[0x40, 0][..], &[0x80, 0, 0x10, 0], &[0xff; 8]] {
+        let mut datagram = [&header[..], length].concat();
+        datagram.resize(MIN_INITIAL_DATAGRAM_SIZE, 0);
+        for mut server in [server(), server().with_retry()] {
+            assert_eq!(server.handle_datagram(now, address(1), &datagram), None);
+            assert_eq!(server.connection_count(), 0);
+            assert!(server.poll_transmit(now).is_none());
+        }
+    }
+}
+
+#[test]
+fn closed_connections_are_removed() {
+    let now = Instant::now();
+    let mut server = server();
+    let mut client = Endpoint::new_client(transport_parameters());
+    let handle = client.connect(now, address(SERVER), &client_config(), "localhost");
+    exchange(&mut server, &mut [(1, &mut client)], now);
+    connected(&mut server);
+
+    client.connection_mut(handle).unwrap().close(now, 0, "");
+    exchange(&mut server, &mut [(1, &mut client)], now);
+    let timeout = server.poll_timeout().unwrap();
+    assert!(timeout <= now + Duration::from_secs(5));
+    server.handle_timeout(timeout);
+    // the Closed event is still to be taken
+    assert_eq!(server.connection_count(), 1);
+    assert!(matches!(server.poll_event(), Some((_, Event::Closed(_)))));
+    assert_eq!(server.connection_count(), 0);
+    assert!(server.connection_ids.is_empty());
+}
+
+#[test]
+fn retry() {
+    let now = Instant::now();
+    let mut server = server().with_retry();
+    let mut client = Endpoint::new_client(transport_parameters());
+    let handle = client.connect(now, address(SERVER), &client_config(), "localhost");
+    let datagram = client.poll_transmit(now).unwrap().contents;
+    assert_eq!(server.handle_datagram(now, address(1), &datagram), None);
+    assert_eq!(server.connection_count(), 0);
+    let retry = server.poll_transmit(now).unwrap();
+    assert_eq!(retry.destination, address(1));
+    assert_eq!(retry.contents[0] & 0xf0, 0xf0);
+
+    assert_eq!(
+        client.handle_datagram(now, address(SERVER), &retry.contents),
+        Some(handle)
+    );
+    let datagram = client.poll_transmit(now).unwrap().contents;
+    // the token is bound to the address of the client
+    assert_eq!(server.handle_datagram(now, address(2), &datagram), None);
+    assert!(server.poll_transmit(now).is_none());
+    assert!(server.handle_datagram(now, address(1), &datagram).is_some());
+
+    // the client checks the connection ids of the Retry in the transport parameters
+    exchange(&mut server, &mut [(1, &mut client)], now);
+    assert_eq!(connected(&mut client), vec![handle]);
+    assert_eq!(connected(&mut server).len(), 1);
+}
+
+#[test]
+fn retry_of_unknown_version_is_ignored() {
+    let now = Instant::now();
+    let mut client = Endpoint::new_client(transport_parameters());
+    let handle = client.connect(now, address(SERVER), &client_config(), "localhost");
+    client.poll_transmit(now).unwrap();
+    let connection = client.connection(handle).unwrap();
+    let destination_connection_id = connection.destination_connection_id().clone();
+
+    let mut retry = vec![0xf0, 0x1a, 0x2a, 0x3a, 0x4a];
+    let source_connection_id = connection.source_connection_id();
+    retry.push(source_connection_id.len() as u8);
+    retry.extend_from_slice(source_connection_id.as_slice());
+    retry.push(8);
+    retry.extend_from_slice(&[0x55; 8]);
+    // Retry Token and Retry Integrity Tag
+    retry.resize(56, 0xaa);
+    assert_eq!(
+        client.handle_datagram(now, address(SERVER), &retry),
+        Some(handle)
+    );
+    let connection = client.connection(handle).unwrap();
+    assert_eq!(connection.state(), ConnectionState::Handshake);
+    assert_eq!(
+        connection.destination_connection_id(),
+        &destination_connection_id
+    );
+}
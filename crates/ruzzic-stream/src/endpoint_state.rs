@@ -5,6 +5,8 @@ use crate::packet::PacketNumber;
 #[derive(Debug, PartialEq)]
 pub struct EndpointState {
     next_packet_number: PacketNumber,
+    /// The largest packet number acknowledged by the peer, which decides how long packet numbers are in headers.
+    largest_acknowledged_packet_number: Option<PacketNumber>,
     type_is: EndpointType,
 }
 
@@ -12,14 +14,41 @@ impl EndpointState {
     pub(crate) fn new_server(packet_number: Option<PacketNumber>) -> Self {
         Self {
             next_packet_number: packet_number.unwrap_or(PacketNumber::zero()),
+            largest_acknowledged_packet_number: None,
             type_is: EndpointType::Server,
         }
     }
 
+    pub(crate) fn new_client(packet_number: Option<PacketNumber>) -> Self {
+        Self {
+            next_packet_number: packet_number.unwrap_or(PacketNumber::zero()),
+            largest_acknowledged_packet_number: None,
+            type_is: EndpointType::Client,
+        }
+    }
+
     pub(crate) fn next_packet_number(&self) -> &PacketNumber {
         &self.next_packet_number
     }
 
+    /// Must be called when a packet is sent with the next packet number.
+    pub(crate) fn on_packet_sent(&mut self) {
+        self.next_packet_number = PacketNumber(self.next_packet_number.to_u64() + 1);
+    }
+
+    /// Length in bytes of the next packet number in a header.
+    pub(crate) fn packet_number_length(&self) -> usize {
+        self.next_packet_number
+            .encoded_length(self.largest_acknowledged_packet_number)
+    }
+
+    pub(crate) fn on_packet_acknowledged(&mut self, packet_number: PacketNumber) {
+        self.largest_acknowledged_packet_number = Some(
+            self.largest_acknowledged_packet_number
+                .map_or(packet_number, |largest| largest.max(packet_number)),
+        );
+    }
+
     pub(crate) fn type_is(&self) -> &EndpointType {
         &self.type_is
     }
@@ -1,33 +1,35 @@
+use std::io::{Cursor, Read};
+
 use bitvec::prelude::*;
 use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo, ReadBytesToWith};
 
-use crate::read_varint;
+use crate::{crypto::EncryptionLevel, read_varint, size_of_varint, u64_to_varint_exact_size};
 
-mod ack;
-mod connection_close;
-mod crypto;
-mod data_blocked;
-mod max_data;
-mod max_stream_data;
-mod max_streams;
+pub(crate) mod ack;
+pub(crate) mod connection_close;
+pub(crate) mod crypto;
+pub(crate) mod data_blocked;
+pub(crate) mod max_data;
+pub(crate) mod max_stream_data;
+pub(crate) mod max_streams;
 mod new_connection_id;
 mod new_token;
 mod padding;
 mod path_challenge;
 mod path_response;
 mod ping;
-mod reset_stream;
+pub(crate) mod reset_stream;
 mod retire_connection_id;
-mod stop_sending;
-mod stream;
-mod stream_data_blocked;
-mod streams_blocked;
+pub(crate) mod stop_sending;
+pub(crate) mod stream;
+pub(crate) mod stream_data_blocked;
+pub(crate) mod streams_blocked;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Frames(Vec<Frame>);
 
-#[derive(Debug, PartialEq)]
-enum Frame {
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
     Padding,
     Ping,
     Ack(ack::Body),
@@ -51,7 +53,7 @@ enum Frame {
     Extension(u64),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum FrameType {
     Padding,
     Ping,
@@ -73,7 +75,72 @@ pub enum FrameType {
     PathResponse,
     ConnectionClose,
     HandshakeDone,
-    Extension,
+    Extension(u64),
+}
+
+/// Errors on decoding frames in a packet payload.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-frames-and-frame-types
+#[derive(thiserror::Error, Debug)]
+pub enum FrameError {
+    #[error("packet payload has no frames")]
+    NoFrames,
+    #[error("unknown frame type {0:#x}")]
+    UnknownFrameType(u64),
+    #[error("frame type {0:#x} is not encoded in the shortest form")]
+    NonMinimalFrameType(u64),
+    #[error("malformed frame of type {frame_type:?}: {source}")]
+    Malformed {
+        /// `None` if even the frame type can not be read.
+        frame_type: Option<u64>,
+        source: std::io::Error,
+    },
+    #[error("frame type {frame_type:#x} is not allowed in {level:?} packets")]
+    NotAllowed {
+        frame_type: u64,
+        level: EncryptionLevel,
+    },
+}
+
+impl FrameError {
+    /// Transport error code to close the connection with.
+    pub fn transport_error_code(&self) -> u64 {
+        match self {
+            // FRAME_ENCODING_ERROR
+            FrameError::UnknownFrameType(_) | FrameError::Malformed { .. } => 0x07,
+            // PROTOCOL_VIOLATION
+            FrameError::NoFrames
+            | FrameError::NonMinimalFrameType(_)
+            | FrameError::NotAllowed { .. } => 0x0a,
+        }
+    }
+
+    /// Frame type to be set in the CONNECTION_CLOSE frame.
+    pub fn frame_type(&self) -> Option<u64> {
+        match self {
+            FrameError::NoFrames => None,
+            FrameError::UnknownFrameType(frame_type)
+            | FrameError::NonMinimalFrameType(frame_type)
+            | FrameError::NotAllowed { frame_type, .. } => Some(*frame_type),
+            FrameError::Malformed { frame_type, .. } => *frame_type,
+        }
+    }
+}
+
+/// Read a length-prefixed field without allocating the length claimed by the peer up front.
+pub(crate) fn read_exact_length(
+    input: &mut impl Read,
+    length: u64,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut buf = Vec::new();
+    input.take(length).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < length {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+pub(crate) fn invalid_data(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
 }
 
 impl FromReadBytesWith<()> for Frame {
@@ -82,6 +149,12 @@ impl FromReadBytesWith<()> for Frame {
         Self: Sized,
     {
         let frame_type = read_varint(input)?.to_u64();
+        Frame::read_body(input, frame_type)
+    }
+}
+
+impl Frame {
+    fn read_body(input: &mut impl Read, frame_type: u64) -> Result<Self, std::io::Error> {
         Ok(match frame_type {
             0x00 => Frame::Padding,
             0x01 => Frame::Ping,
@@ -90,7 +163,7 @@ impl FromReadBytesWith<()> for Frame {
             0x05 => Frame::StopSending(input.read_bytes_to()?),
             0x06 => Frame::Crypto(input.read_bytes_to()?),
             0x07 => Frame::NewToken(input.read_bytes_to()?),
-            x if (0x08..0x0f).contains(&x) => {
+            x if (0x08..=0x0f).contains(&x) => {
                 let mut flags = bitarr![Msb0, u8; 0; 1];
                 flags.store(x);
                 Frame::Stream(input.read_bytes_to_with(&flags[5..])?)
@@ -110,6 +183,174 @@ impl FromReadBytesWith<()> for Frame {
             _ => Frame::Extension(frame_type),
         })
     }
+
+    /// Whether this frame can be carried in packets of the encryption level.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#frame-types
+    pub fn is_allowed_in(&self, level: EncryptionLevel) -> bool {
+        match self {
+            Frame::Padding | Frame::Ping => true,
+            Frame::ConnectionClose(body) if body.this_frame_type() == 0x1c => true,
+            Frame::Ack(_) | Frame::Crypto(_) => level != EncryptionLevel::ZeroRTT,
+            Frame::NewToken(_) | Frame::PathResponse(_) | Frame::HandshakeDone => {
+                level == EncryptionLevel::OneRTT
+            }
+            _ => matches!(level, EncryptionLevel::ZeroRTT | EncryptionLevel::OneRTT),
+        }
+    }
+
+    /// Packets which contain only ACK, PADDING and CONNECTION_CLOSE frames are not acknowledged immediately.
+    /// https://www.rfc-editor.org/rfc/rfc9002.html#section-2
+    pub fn is_ack_eliciting(&self) -> bool {
+        !matches!(
+            self,
+            Frame::Ack(_) | Frame::Padding | Frame::ConnectionClose(_)
+        )
+    }
+
+    pub(crate) fn frame_type(&self) -> u64 {
+        match self {
+            Frame::Padding => 0x00,
+            Frame::Ping => 0x01,
+            Frame::Ack(b) => b.frame_type(),
+            Frame::ResetStream(_) => 0x04,
+            Frame::StopSending(_) => 0x05,
+            Frame::Crypto(_) => 0x06,
+            Frame::NewToken(_) => 0x07,
+            Frame::Stream(b) => b.frame_type(),
+            Frame::MaxData(_) => 0x10,
+            Frame::MaxStreamData(_) => 0x11,
+            Frame::MaxStreams(b) => b.frame_type(),
+            Frame::DataBlocked(_) => 0x14,
+            Frame::StreamDataBlocked(_) => 0x15,
+            Frame::StreamsBlocked(b) => b.frame_type(),
+            Frame::NewConnectionID(_) => 0x18,
+            Frame::RetireConnectionID(_) => 0x19,
+            Frame::PathChallenge(_) => 0x1a,
+            Frame::PathResponse(_) => 0x1b,
+            Frame::ConnectionClose(b) => b.this_frame_type(),
+            Frame::HandshakeDone => 0x1e,
+            Frame::Extension(x) => *x,
+        }
+    }
+
+    fn body_bytes(&self) -> Vec<u8> {
+        match self {
+            Frame::Padding | Frame::Ping | Frame::HandshakeDone | Frame::Extension(_) => Vec::new(),
+            Frame::Ack(b) => b.to_bytes(),
+            Frame::ResetStream(b) => b.to_bytes(),
+            Frame::StopSending(b) => b.to_bytes(),
+            Frame::Crypto(b) => b.to_bytes(),
+            Frame::NewToken(b) => b.to_bytes(),
+            Frame::Stream(b) => b.to_bytes(),
+            Frame::MaxData(b) => b.to_bytes(),
+            Frame::MaxStreamData(b) => b.to_bytes(),
+            Frame::MaxStreams(b) => b.to_bytes(),
+            Frame::DataBlocked(b) => b.to_bytes(),
+            Frame::StreamDataBlocked(b) => b.to_bytes(),
+            Frame::StreamsBlocked(b) => b.to_bytes(),
+            Frame::NewConnectionID(b) => b.to_bytes(),
+            Frame::RetireConnectionID(b) => b.to_bytes(),
+            Frame::PathChallenge(b) => b.to_bytes(),
+            Frame::PathResponse(b) => b.to_bytes(),
+            Frame::ConnectionClose(b) => b.to_bytes(),
+        }
+    }
+
+    fn body_raw_length(&self) -> usize {
+        match self {
+            Frame::Padding | Frame::Ping | Frame::HandshakeDone | Frame::Extension(_) => 0,
+            Frame::Ack(b) => b.raw_length(),
+            Frame::ResetStream(b) => b.raw_length(),
+            Frame::StopSending(b) => b.raw_length(),
+            Frame::Crypto(b) => b.raw_length(),
+            Frame::NewToken(b) => b.raw_length(),
+            Frame::Stream(b) => b.raw_length(),
+            Frame::MaxData(b) => b.raw_length(),
+            Frame::MaxStreamData(b) => b.raw_length(),
+            Frame::MaxStreams(b) => b.raw_length(),
+            Frame::DataBlocked(b) => b.raw_length(),
+            Frame::StreamDataBlocked(b) => b.raw_length(),
+            Frame::StreamsBlocked(b) => b.raw_length(),
+            Frame::NewConnectionID(b) => b.raw_length(),
+            Frame::RetireConnectionID(b) => b.raw_length(),
+            Frame::PathChallenge(b) => b.raw_length(),
+            Frame::PathResponse(b) => b.raw_length(),
+            Frame::ConnectionClose(b) => b.raw_length(),
+        }
+    }
+
+    /// Frame types are always encoded in the shortest form.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-frames-and-frame-types
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            u64_to_varint_exact_size(self.frame_type()).to_bytes(),
+            self.body_bytes(),
+        ]
+        .concat()
+    }
+
+    /// Encoded length of this frame, to know how many frames fit in a packet.
+    pub(crate) fn raw_length(&self) -> usize {
+        size_of_varint(self.frame_type()) + self.body_raw_length()
+    }
+}
+
+impl Frames {
+    pub(crate) fn new(frames: Vec<Frame>) -> Self {
+        Self(frames)
+    }
+
+    /// Decode a whole packet payload of the encryption level.
+    /// Any malformed or disallowed frame is an error instead of the end of the frames.
+    pub fn decode(payload: &[u8], level: EncryptionLevel) -> Result<Self, FrameError> {
+        if payload.is_empty() {
+            return Err(FrameError::NoFrames);
+        }
+        let frames = Self::decode_frames(payload)?;
+        if let Some(frame) = frames.0.iter().find(|frame| !frame.is_allowed_in(level)) {
+            return Err(FrameError::NotAllowed {
+                frame_type: frame.frame_type(),
+                level,
+            });
+        }
+        Ok(frames)
+    }
+
+    fn decode_frames(payload: &[u8]) -> Result<Self, FrameError> {
+        let mut input = Cursor::new(payload);
+        let mut frames = Vec::new();
+        while (input.position() as usize) < payload.len() {
+            let start = input.position();
+            let frame_type = read_varint(&mut input)
+                .map_err(|source| FrameError::Malformed {
+                    frame_type: None,
+                    source,
+                })?
+                .to_u64();
+            if (input.position() - start) as usize != size_of_varint(frame_type) {
+                return Err(FrameError::NonMinimalFrameType(frame_type));
+            }
+            let frame = Frame::read_body(&mut input, frame_type).map_err(|source| {
+                FrameError::Malformed {
+                    frame_type: Some(frame_type),
+                    source,
+                }
+            })?;
+            if let Frame::Extension(frame_type) = frame {
+                return Err(FrameError::UnknownFrameType(frame_type));
+            }
+            frames.push(frame);
+        }
+        Ok(Frames(frames))
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.0
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|frame| frame.to_bytes()).collect()
+    }
 }
 
 impl FromReadBytesWith<()> for Frames {
@@ -117,17 +358,15 @@ impl FromReadBytesWith<()> for Frames {
     where
         Self: Sized,
     {
-        let mut frames = Vec::new();
-        // TODO: shouldn't judge if it's the end of Error or not.
-        while let Ok(frame) = input.read_bytes_to() {
-            frames.push(frame);
-        }
-        Ok(Frames(frames))
+        let mut payload = Vec::new();
+        input.read_to_end(&mut payload)?;
+        Frames::decode_frames(&payload)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
     }
 }
 
 impl FrameType {
-    fn from_u64(x: u64) -> Self {
+    pub(crate) fn from_u64(x: u64) -> Self {
         match x {
             0x00 => FrameType::Padding,
             0x01 => FrameType::Ping,
@@ -136,7 +375,7 @@ impl FrameType {
             0x05 => FrameType::StopSending,
             0x06 => FrameType::Crypto,
             0x07 => FrameType::NewToken,
-            x if (0x08..0x0f).contains(&x) => FrameType::Stream,
+            x if (0x08..=0x0f).contains(&x) => FrameType::Stream,
             0x10 => FrameType::MaxData,
             0x11 => FrameType::MaxStreamData,
             0x12 | 0x13 => FrameType::MaxStreams,
@@ -149,7 +388,34 @@ impl FrameType {
             0x1b => FrameType::PathResponse,
             0x1c | 0x1d => FrameType::ConnectionClose,
             0x1e => FrameType::HandshakeDone,
-            _ => FrameType::Extension,
+            x => FrameType::Extension(x),
+        }
+    }
+
+    /// Frame types which have flags in them are represented by the smallest value.
+    fn to_u64(&self) -> u64 {
+        match self {
+            FrameType::Padding => 0x00,
+            FrameType::Ping => 0x01,
+            FrameType::Ack => 0x02,
+            FrameType::ResetStream => 0x04,
+            FrameType::StopSending => 0x05,
+            FrameType::Crypto => 0x06,
+            FrameType::NewToken => 0x07,
+            FrameType::Stream => 0x08,
+            FrameType::MaxData => 0x10,
+            FrameType::MaxStreamData => 0x11,
+            FrameType::MaxStreams => 0x12,
+            FrameType::DataBlocked => 0x14,
+            FrameType::StreamDataBlocked => 0x15,
+            FrameType::StreamsBlocked => 0x16,
+            FrameType::NewConnectionID => 0x18,
+            FrameType::RetireConnectionID => 0x19,
+            FrameType::PathChallenge => 0x1a,
+            FrameType::PathResponse => 0x1b,
+            FrameType::ConnectionClose => 0x1c,
+            FrameType::HandshakeDone => 0x1e,
+            FrameType::Extension(x) => *x,
         }
     }
 }
@@ -159,6 +425,164 @@ mod tests {
     use std::io::Cursor;
 
     use super::*;
+    use crate::{
+        connection::ConnectionID,
+        packet::PacketNumber,
+        stream::{StreamDirection, StreamID},
+        Token,
+    };
+    use proptest::{collection::vec, prelude::*};
+
+    const MAX_VARINT: u64 = (1 << 62) - 1;
+    const MAX_STREAMS: u64 = 1 << 60;
+
+    fn varint() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            0..(1u64 << 6),
+            0..(1u64 << 14),
+            0..(1u64 << 30),
+            0..=MAX_VARINT
+        ]
+    }
+
+    fn ack_range() -> impl Strategy<Value = u64> {
+        prop_oneof![0..(1u64 << 6), 0..(1u64 << 14), 0..(1u64 << 30)]
+    }
+
+    fn stream_direction() -> impl Strategy<Value = StreamDirection> {
+        prop_oneof![
+            Just(StreamDirection::Bidirectional),
+            Just(StreamDirection::Unidirectional)
+        ]
+    }
+
+    fn frames_without_body() -> impl Strategy<Value = Frame> {
+        prop_oneof![
+            Just(Frame::Padding),
+            Just(Frame::Ping),
+            Just(Frame::HandshakeDone),
+            (0x1fu64..=MAX_VARINT).prop_map(Frame::Extension),
+        ]
+    }
+
+    fn stream_frames() -> impl Strategy<Value = Frame> {
+        prop_oneof![
+            (varint(), varint(), varint()).prop_map(|(id, code, size)| Frame::ResetStream(
+                reset_stream::Body::new(StreamID(id), code, size)
+            )),
+            (varint(), varint()).prop_map(|(id, code)| Frame::StopSending(
+                stop_sending::Body::new(StreamID(id), code)
+            )),
+            (
+                varint(),
+                proptest::option::of(0..MAX_VARINT - 64),
+                vec(any::<u8>(), 0..64),
+                any::<bool>()
+            )
+                .prop_map(|(id, offset, data, is_fin)| Frame::Stream(
+                    stream::Body::new(StreamID(id), offset, data, is_fin)
+                )),
+            varint().prop_map(|max| Frame::MaxData(max_data::Body::new(max))),
+            (varint(), varint()).prop_map(|(id, max)| Frame::MaxStreamData(
+                max_stream_data::Body::new(StreamID(id), max)
+            )),
+            (stream_direction(), 0..=MAX_STREAMS).prop_map(|(direction, max)| Frame::MaxStreams(
+                max_streams::Body::new(direction, max)
+            )),
+            varint().prop_map(|max| Frame::DataBlocked(data_blocked::Body::new(max))),
+            (varint(), varint()).prop_map(|(id, max)| Frame::StreamDataBlocked(
+                stream_data_blocked::Body::new(StreamID(id), max)
+            )),
+            (stream_direction(), 0..=MAX_STREAMS).prop_map(|(direction, max)| {
+                Frame::StreamsBlocked(streams_blocked::Body::new(direction, max))
+            }),
+        ]
+    }
+
+    fn connection_frames() -> impl Strategy<Value = Frame> {
+        prop_oneof![
+            (
+                varint(),
+                ack_range(),
+                vec((ack_range(), ack_range()), 0..4),
+                ack_range(),
+                proptest::option::of((varint(), varint(), varint()))
+            )
+                .prop_map(|(delay, first, ranges, smallest, ecn)| Frame::Ack(
+                    ack::Body::new(
+                        // large enough for every range to stay above zero
+                        PacketNumber(
+                            ranges
+                                .iter()
+                                .map(|(gap, length)| gap + 2 + length)
+                                .sum::<u64>()
+                                + first
+                                + smallest
+                        ),
+                        delay,
+                        first,
+                        ranges
+                            .into_iter()
+                            .map(|(gap, length)| ack::AckRange::new(gap, length))
+                            .collect(),
+                        ecn.map(|(ect0, ect1, ce)| ack::ECNCounts::new(ect0, ect1, ce)),
+                    )
+                )),
+            (0..MAX_VARINT - 64, vec(any::<u8>(), 0..64))
+                .prop_map(|(offset, data)| Frame::Crypto(crypto::Body::new(offset, data))),
+            vec(any::<u8>(), 1..64)
+                .prop_map(|token| Frame::NewToken(new_token::Body::new(Token(token)))),
+            (
+                varint().prop_flat_map(|sequence| (Just(sequence), 0..=sequence)),
+                vec(any::<u8>(), 1..=20),
+                any::<u128>()
+            )
+                .prop_map(|((sequence, retire), id, token)| Frame::NewConnectionID(
+                    new_connection_id::Body::new(sequence, retire, ConnectionID(id), token)
+                )),
+            varint().prop_map(|sequence| Frame::RetireConnectionID(
+                retire_connection_id::Body::new(sequence)
+            )),
+            any::<[u8; 8]>().prop_map(|data| Frame::PathChallenge(path_challenge::Body::new(data))),
+            any::<[u8; 8]>().prop_map(|data| Frame::PathResponse(path_response::Body::new(data))),
+            (
+                varint(),
+                proptest::option::of(varint().prop_map(FrameType::from_u64)),
+                "\\PC{0,32}"
+            )
+                .prop_map(|(code, frame_type, reason)| Frame::ConnectionClose(
+                    connection_close::Body::new(code, frame_type, reason)
+                )),
+        ]
+    }
+
+    fn frame() -> impl Strategy<Value = Frame> {
+        prop_oneof![frames_without_body(), stream_frames(), connection_frames()]
+    }
+
+    proptest! {
+        #[test]
+        fn frame_round_trip(frame in frame()) {
+            let buf = frame.to_bytes();
+            prop_assert_eq!(buf.len(), frame.raw_length());
+
+            let mut input = Cursor::new(buf);
+            let actual: Frame = input.read_bytes_to().unwrap();
+            prop_assert_eq!(input.position() as usize, frame.raw_length());
+            prop_assert_eq!(actual, frame);
+        }
+
+        #[test]
+        fn frames_round_trip(frames in vec(frame().prop_filter("extension frames have unknown bodies", |f| !matches!(f, Frame::Extension(_))), 0..8)) {
+            let frames = Frames::new(frames);
+            let buf = frames.to_bytes();
+            prop_assert_eq!(buf.len(), frames.frames().iter().map(Frame::raw_length).sum::<usize>());
+
+            let mut input = Cursor::new(buf);
+            let actual: Frames = input.read_bytes_to().unwrap();
+            prop_assert_eq!(actual, frames);
+        }
+    }
 
     #[test]
     fn empty_frames() {
@@ -168,6 +592,93 @@ mod tests {
         assert_eq!(frames, Frames(Vec::new()));
     }
 
+    #[test]
+    fn decode_errors() {
+        let decode = |payload: &[u8], level| Frames::decode(payload, level).unwrap_err();
+
+        let e = decode(&[], EncryptionLevel::Initial);
+        assert!(matches!(e, FrameError::NoFrames));
+        assert_eq!(e.transport_error_code(), 0x0a);
+
+        // a PING frame followed by an unknown frame
+        let e = decode(&[0x01, 0x1f], EncryptionLevel::OneRTT);
+        assert!(matches!(e, FrameError::UnknownFrameType(0x1f)));
+        assert_eq!(e.transport_error_code(), 0x07);
+        assert_eq!(e.frame_type(), Some(0x1f));
+
+        // a MAX_DATA frame without its value
+        let e = decode(&[0x10], EncryptionLevel::OneRTT);
+        assert!(matches!(
+            e,
+            FrameError::Malformed {
+                frame_type: Some(0x10),
+                ..
+            }
+        ));
+        assert_eq!(e.transport_error_code(), 0x07);
+
+        // a NEW_TOKEN frame with an empty token
+        let e = decode(&[0x07, 0x00], EncryptionLevel::OneRTT);
+        assert_eq!(e.transport_error_code(), 0x07);
+
+        // a PING frame type in two bytes
+        let e = decode(&[0x40, 0x01], EncryptionLevel::OneRTT);
+        assert!(matches!(e, FrameError::NonMinimalFrameType(0x01)));
+        assert_eq!(e.transport_error_code(), 0x0a);
+
+        let e = decode(&[0x1e], EncryptionLevel::Handshake);
+        assert!(matches!(
+            e,
+            FrameError::NotAllowed {
+                frame_type: 0x1e,
+                level: EncryptionLevel::Handshake
+            }
+        ));
+        assert_eq!(e.transport_error_code(), 0x0a);
+    }
+
+    #[test]
+    fn allowed_frames() {
+        let ack = Frame::Ack(ack::Body::new(PacketNumber(0), 0, 0, Vec::new(), None));
+        let stream = Frame::Stream(stream::Body::new(StreamID(0), None, Vec::new(), false));
+        let transport_close = Frame::ConnectionClose(connection_close::Body::new(
+            0,
+            Some(FrameType::Ping),
+            String::new(),
+        ));
+        let application_close =
+            Frame::ConnectionClose(connection_close::Body::new(0, None, String::new()));
+        let table = [
+            (&Frame::Padding, [true, true, true, true]),
+            (&ack, [true, false, true, true]),
+            (&stream, [false, true, false, true]),
+            (&Frame::HandshakeDone, [false, false, false, true]),
+            (&transport_close, [true, true, true, true]),
+            (&application_close, [false, true, false, true]),
+        ];
+        let levels = [
+            EncryptionLevel::Initial,
+            EncryptionLevel::ZeroRTT,
+            EncryptionLevel::Handshake,
+            EncryptionLevel::OneRTT,
+        ];
+        for (frame, allowed) in table {
+            for (level, allowed) in levels.into_iter().zip(allowed) {
+                assert_eq!(
+                    frame.is_allowed_in(level),
+                    allowed,
+                    "{frame:?} in {level:?}"
+                );
+            }
+        }
+
+        let frames = Frames::decode(&[0x01, 0x00, 0x00], EncryptionLevel::Initial).unwrap();
+        assert_eq!(
+            frames.frames(),
+            [Frame::Ping, Frame::Padding, Frame::Padding]
+        );
+    }
+
     #[test]
     fn neqo_server_initial_packet_frames() {
         let buf = [
@@ -1,24 +1,27 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{packet::PacketNumber, read_varint, VarInt};
+use super::invalid_data;
+use crate::{packet::PacketNumber, read_varint, size_of_varint, u64_to_varint_exact_size, VarInt};
 use std::io::Read;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     // 通常は相手がAckフレームを作る前に受信した最大のPacketNumberらしい
     largest_acknowledged: PacketNumber,
     ack_delay: VarInt,
+    /// The number of contiguous packets preceding the Largest Acknowledged.
+    first_ack_range: VarInt,
     ack_ranges: Vec<AckRange>,
     ecn_counts: Option<ECNCounts>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AckRange {
     gap: VarInt,
     length: VarInt,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ECNCounts {
     ect0_count: VarInt,
     ect1_count: VarInt,
@@ -33,10 +36,21 @@ impl FromReadBytesWith<u64> for Body {
         let largest_acknowledged = read_varint(input)?;
         let ack_delay = read_varint(input)?;
         let ack_ranges_length = read_varint(input)?;
+        let first_ack_range = read_varint(input)?;
+        // every range must stay above zero
+        // https://www.rfc-editor.org/rfc/rfc9000.html#name-ack-ranges
+        let mut smallest = largest_acknowledged
+            .to_u64()
+            .checked_sub(first_ack_range.to_u64())
+            .ok_or_else(|| invalid_data("the first ACK range goes below zero"))?;
         let mut ack_ranges = Vec::new();
         for _ in 0..ack_ranges_length.to_u64() {
             let gap = read_varint(input)?;
             let length = read_varint(input)?;
+            smallest = smallest
+                .checked_sub(gap.to_u64() + 2)
+                .and_then(|largest| largest.checked_sub(length.to_u64()))
+                .ok_or_else(|| invalid_data("an ACK range goes below zero"))?;
             ack_ranges.push(AckRange { gap, length });
         }
         let ecn_counts = if frame_type == 0x03 {
@@ -52,14 +66,121 @@ impl FromReadBytesWith<u64> for Body {
             None
         };
         Ok(Self {
-            largest_acknowledged: PacketNumber(largest_acknowledged.to_u64() as u32),
+            largest_acknowledged: PacketNumber(largest_acknowledged.to_u64()),
             ack_delay,
+            first_ack_range,
             ack_ranges,
             ecn_counts,
         })
     }
 }
 
+impl Body {
+    pub(crate) fn new(
+        largest_acknowledged: PacketNumber,
+        ack_delay: u64,
+        first_ack_range: u64,
+        ack_ranges: Vec<AckRange>,
+        ecn_counts: Option<ECNCounts>,
+    ) -> Self {
+        Self {
+            largest_acknowledged,
+            ack_delay: u64_to_varint_exact_size(ack_delay),
+            first_ack_range: u64_to_varint_exact_size(first_ack_range),
+            ack_ranges,
+            ecn_counts,
+        }
+    }
+
+    pub(crate) fn largest_acknowledged(&self) -> PacketNumber {
+        self.largest_acknowledged
+    }
+
+    /// In units of 2^ack_delay_exponent microseconds.
+    pub(crate) fn ack_delay(&self) -> u64 {
+        self.ack_delay.to_u64()
+    }
+
+    /// Acknowledged packet numbers as inclusive ranges in descending order.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-ack-ranges
+    pub(crate) fn ranges(&self) -> Vec<(u64, u64)> {
+        let largest = self.largest_acknowledged.to_u64();
+        let mut smallest = largest - self.first_ack_range.to_u64();
+        let mut ranges = vec![(smallest, largest)];
+        for range in &self.ack_ranges {
+            let largest = smallest - range.gap.to_u64() - 2;
+            smallest = largest - range.length.to_u64();
+            ranges.push((smallest, largest));
+        }
+        ranges
+    }
+
+    pub(crate) fn frame_type(&self) -> u64 {
+        if self.ecn_counts.is_some() {
+            0x03
+        } else {
+            0x02
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            u64_to_varint_exact_size(self.largest_acknowledged.to_u64()).to_bytes(),
+            self.ack_delay.to_bytes(),
+            u64_to_varint_exact_size(self.ack_ranges.len() as u64).to_bytes(),
+            self.first_ack_range.to_bytes(),
+            self.ack_ranges
+                .iter()
+                .flat_map(|r| [r.gap.to_bytes(), r.length.to_bytes()].concat())
+                .collect(),
+            self.ecn_counts.as_ref().map_or(Vec::new(), |c| {
+                [
+                    c.ect0_count.to_bytes(),
+                    c.ect1_count.to_bytes(),
+                    c.ecn_ce_count.to_bytes(),
+                ]
+                .concat()
+            }),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        size_of_varint(self.largest_acknowledged.to_u64())
+            + self.ack_delay.byte_size()
+            + size_of_varint(self.ack_ranges.len() as u64)
+            + self.first_ack_range.byte_size()
+            + self
+                .ack_ranges
+                .iter()
+                .map(|r| r.gap.byte_size() + r.length.byte_size())
+                .sum::<usize>()
+            + self.ecn_counts.as_ref().map_or(0, |c| {
+                c.ect0_count.byte_size() + c.ect1_count.byte_size() + c.ecn_ce_count.byte_size()
+            })
+    }
+}
+
+impl AckRange {
+    pub(crate) fn new(gap: u64, length: u64) -> Self {
+        Self {
+            gap: u64_to_varint_exact_size(gap),
+            length: u64_to_varint_exact_size(length),
+        }
+    }
+}
+
+impl ECNCounts {
+    #[cfg(test)]
+    pub(crate) fn new(ect0_count: u64, ect1_count: u64, ecn_ce_count: u64) -> Self {
+        Self {
+            ect0_count: u64_to_varint_exact_size(ect0_count),
+            ect1_count: u64_to_varint_exact_size(ect1_count),
+            ecn_ce_count: u64_to_varint_exact_size(ecn_ce_count),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruzzic_common::read_bytes_to::ReadBytesToWith;
@@ -69,12 +190,13 @@ mod tests {
 
     #[test]
     fn ack_frame_0x02() {
-        let buf = [0, 0, 1, 0, 0];
+        let buf = [2, 0, 1, 0, 0, 0];
         let mut input = Cursor::new(buf);
         let actual: Body = input.read_bytes_to_with(0x02).unwrap();
         let expect = Body {
-            largest_acknowledged: PacketNumber(0x00),
+            largest_acknowledged: PacketNumber(0x02),
             ack_delay: VarInt(0),
+            first_ack_range: VarInt(0),
             ack_ranges: vec![AckRange {
                 gap: VarInt(0),
                 length: VarInt(0),
@@ -86,12 +208,13 @@ mod tests {
 
     #[test]
     fn ack_frame_0x03() {
-        let buf = [0, 0, 1, 0, 0, 0, 0, 0];
+        let buf = [2, 0, 1, 0, 0, 0, 0, 0, 0];
         let mut input = Cursor::new(buf);
         let actual: Body = input.read_bytes_to_with(0x03).unwrap();
         let expect = Body {
-            largest_acknowledged: PacketNumber(0x00),
+            largest_acknowledged: PacketNumber(0x02),
             ack_delay: VarInt(0),
+            first_ack_range: VarInt(0),
             ack_ranges: vec![AckRange {
                 gap: VarInt(0),
                 length: VarInt(0),
@@ -104,4 +227,29 @@ mod tests {
         };
         assert_eq!(actual, expect);
     }
+
+    #[test]
+    fn ranges() {
+        let body = Body::new(
+            PacketNumber(20),
+            0,
+            2,
+            vec![AckRange::new(1, 3), AckRange::new(0, 0)],
+            None,
+        );
+        assert_eq!(body.ranges(), vec![(18, 20), (12, 15), (10, 10)]);
+    }
+
+    #[test]
+    fn ack_range_below_zero() {
+        // the first ACK range is larger than the largest acknowledged
+        let mut input = Cursor::new([0, 0, 0, 1]);
+        let actual: Result<Body, _> = input.read_bytes_to_with(0x02);
+        assert_eq!(actual.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        // the gap goes below zero
+        let mut input = Cursor::new([2, 0, 1, 0, 1, 0]);
+        let actual: Result<Body, _> = input.read_bytes_to_with(0x02);
+        assert_eq!(actual.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
 }
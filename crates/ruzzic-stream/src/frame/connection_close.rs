@@ -1,10 +1,10 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{read_varint, VarInt};
+use crate::{read_varint, size_of_varint, u64_to_varint_exact_size, VarInt};
 
-use super::FrameType;
+use super::{read_exact_length, FrameType};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     error_code: VarInt,
     frame_type: Option<FrameType>,
@@ -28,10 +28,9 @@ impl FromReadBytesWith<u64> for Body {
             unreachable!("Invalid frame type")
         };
         let reason_phrase_length = read_varint(input)?.to_u64();
-        let mut reason_phrase = vec![0; reason_phrase_length as usize];
-        input.read_exact(&mut reason_phrase)?;
+        let reason_phrase = read_exact_length(input, reason_phrase_length)?;
         // > This SHOULD be a UTF-8 encoded string [RFC3629], though the frame does not carry information, such as language tags, that would aid comprehension by any entity other than the one that created the text.
-        let reason_phrase = unsafe { String::from_utf8_unchecked(reason_phrase) };
+        let reason_phrase = String::from_utf8_lossy(&reason_phrase).into_owned();
         Ok(Self {
             error_code,
             frame_type,
@@ -40,6 +39,60 @@ impl FromReadBytesWith<u64> for Body {
     }
 }
 
+impl Body {
+    /// A CONNECTION_CLOSE frame of the type 0x1c reports a QUIC error with the frame type which triggered it,
+    /// and one of the type 0x1d reports an application error.
+    pub(crate) fn new(
+        error_code: u64,
+        frame_type: Option<FrameType>,
+        reason_phrase: String,
+    ) -> Self {
+        Self {
+            error_code: u64_to_varint_exact_size(error_code),
+            frame_type,
+            reason_phrase,
+        }
+    }
+
+    pub(crate) fn error_code(&self) -> u64 {
+        self.error_code.to_u64()
+    }
+
+    pub(crate) fn reason_phrase(&self) -> &str {
+        &self.reason_phrase
+    }
+
+    pub(crate) fn this_frame_type(&self) -> u64 {
+        if self.frame_type.is_some() {
+            0x1c
+        } else {
+            0x1d
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.error_code.to_bytes(),
+            self.frame_type.as_ref().map_or(Vec::new(), |t| {
+                u64_to_varint_exact_size(t.to_u64()).to_bytes()
+            }),
+            u64_to_varint_exact_size(self.reason_phrase.len() as u64).to_bytes(),
+            self.reason_phrase.as_bytes().to_vec(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.error_code.byte_size()
+            + self
+                .frame_type
+                .as_ref()
+                .map_or(0, |t| size_of_varint(t.to_u64()))
+            + size_of_varint(self.reason_phrase.len() as u64)
+            + self.reason_phrase.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruzzic_common::read_bytes_to::ReadBytesToWith;
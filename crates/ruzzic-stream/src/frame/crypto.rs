@@ -1,17 +1,17 @@
-use std::io::Cursor;
+use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
-use ruzzic_tls::handshake::Handshake;
+use super::{invalid_data, read_exact_length};
+use crate::{read_varint, size_of_varint, u64_to_varint_exact_size, VarInt};
 
-use crate::{read_varint, VarInt};
-
-#[derive(Debug, PartialEq)]
+/// CRYPTO frames carry a byte stream of TLS handshake messages,
+/// and a message can be split across frames, so it is kept as bytes until reassembled.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     offset: VarInt,
-    tls_handshake: Handshake,
+    crypto_data: CryptoData,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CryptoData(Vec<u8>);
 
 impl FromReadBytesWith<()> for Body {
@@ -21,15 +21,68 @@ impl FromReadBytesWith<()> for Body {
     {
         let offset = read_varint(input)?;
         let length = read_varint(input)?;
-        let mut input = {
-            let mut buf = vec![0; length.to_u64() as usize];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
+        if offset.to_u64() + length.to_u64() >= 1 << 62 {
+            return Err(invalid_data("the CRYPTO frame exceeds the maximum offset"));
+        }
+        let buf = read_exact_length(input, length.to_u64())?;
 
         Ok(Self {
             offset,
-            tls_handshake: input.read_bytes_to()?,
+            crypto_data: CryptoData(buf),
         })
     }
 }
+
+impl Body {
+    pub(crate) fn new(offset: u64, crypto_data: Vec<u8>) -> Self {
+        Self {
+            offset: u64_to_varint_exact_size(offset),
+            crypto_data: CryptoData(crypto_data),
+        }
+    }
+
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset.to_u64()
+    }
+
+    pub(crate) fn crypto_data(&self) -> &[u8] {
+        &self.crypto_data.0
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.offset.to_bytes(),
+            u64_to_varint_exact_size(self.crypto_data.0.len() as u64).to_bytes(),
+            self.crypto_data.0.clone(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        let length = self.crypto_data.0.len();
+        self.offset.byte_size() + size_of_varint(length as u64) + length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruzzic_common::read_bytes_to::ReadBytesTo;
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn crypto() {
+        let buf = [
+            0x40, 0x01, // Offset
+            2,    // Length
+            1, 2, // Crypto Data
+        ];
+        let mut input = Cursor::new(buf);
+        let actual: Body = input.read_bytes_to().unwrap();
+        assert_eq!(actual.offset(), 1);
+        assert_eq!(actual.crypto_data(), &[1, 2]);
+        assert_eq!(actual.to_bytes(), buf);
+        assert_eq!(actual.raw_length(), buf.len());
+    }
+}
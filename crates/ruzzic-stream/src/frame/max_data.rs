@@ -1,8 +1,8 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{read_varint, VarInt};
+use crate::{read_varint, u64_to_varint_exact_size, VarInt};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     maximum_data: VarInt,
 }
@@ -17,6 +17,26 @@ impl FromReadBytesWith<()> for Body {
     }
 }
 
+impl Body {
+    pub(crate) fn new(maximum_data: u64) -> Self {
+        Self {
+            maximum_data: u64_to_varint_exact_size(maximum_data),
+        }
+    }
+
+    pub(crate) fn maximum_data(&self) -> u64 {
+        self.maximum_data.to_u64()
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.maximum_data.to_bytes()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.maximum_data.byte_size()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruzzic_common::read_bytes_to::ReadBytesTo;
@@ -2,9 +2,10 @@ use std::io::Read;
 
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{read_varint, stream::StreamDirection, VarInt};
+use super::invalid_data;
+use crate::{read_varint, stream::StreamDirection, u64_to_varint_exact_size, VarInt};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     kind: StreamDirection,
     maximum_streams: VarInt,
@@ -23,6 +24,10 @@ impl FromReadBytesWith<u64> for Body {
             unreachable!("Invalid frame type")
         };
         let maximum_streams = read_varint(input)?;
+        // a stream ID can not be encoded beyond this
+        if maximum_streams.to_u64() > 1 << 60 {
+            return Err(invalid_data("the MAX_STREAMS frame exceeds 2^60 streams"));
+        }
         Ok(Self {
             kind,
             maximum_streams,
@@ -30,6 +35,38 @@ impl FromReadBytesWith<u64> for Body {
     }
 }
 
+impl Body {
+    pub(crate) fn new(kind: StreamDirection, maximum_streams: u64) -> Self {
+        Self {
+            kind,
+            maximum_streams: u64_to_varint_exact_size(maximum_streams),
+        }
+    }
+
+    pub(crate) fn kind(&self) -> &StreamDirection {
+        &self.kind
+    }
+
+    pub(crate) fn maximum_streams(&self) -> u64 {
+        self.maximum_streams.to_u64()
+    }
+
+    pub(crate) fn frame_type(&self) -> u64 {
+        match self.kind {
+            StreamDirection::Bidirectional => 0x12,
+            StreamDirection::Unidirectional => 0x13,
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.maximum_streams.to_bytes()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.maximum_streams.byte_size()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{stream::StreamDirection, VarInt};
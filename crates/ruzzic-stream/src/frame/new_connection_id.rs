@@ -1,9 +1,10 @@
 use byteorder::{BigEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use super::invalid_data;
 use crate::{connection::ConnectionID, read_varint, VarInt};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     sequence_number: VarInt,
     retire_prior_to: VarInt,
@@ -18,7 +19,15 @@ impl FromReadBytesWith<()> for Body {
     {
         let sequence_number = read_varint(input)?;
         let retire_prior_to = read_varint(input)?;
+        if retire_prior_to.to_u64() > sequence_number.to_u64() {
+            return Err(invalid_data(
+                "Retire Prior To is greater than the sequence number",
+            ));
+        }
         let length = input.read_u8()?;
+        if !(1..=20).contains(&length) {
+            return Err(invalid_data("the connection ID length is out of 1..=20"));
+        }
         let mut connection_id = vec![0; length as usize];
         input.read_exact(&mut connection_id)?;
         let stateless_reset_token = input.read_u128::<BigEndian>()?;
@@ -31,6 +40,42 @@ impl FromReadBytesWith<()> for Body {
     }
 }
 
+impl Body {
+    #[cfg(test)]
+    pub(crate) fn new(
+        sequence_number: u64,
+        retire_prior_to: u64,
+        connection_id: ConnectionID,
+        stateless_reset_token: u128,
+    ) -> Self {
+        Self {
+            sequence_number: crate::u64_to_varint_exact_size(sequence_number),
+            retire_prior_to: crate::u64_to_varint_exact_size(retire_prior_to),
+            connection_id,
+            stateless_reset_token,
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.sequence_number.to_bytes(),
+            self.retire_prior_to.to_bytes(),
+            vec![self.connection_id.0.len() as u8],
+            self.connection_id.0.clone(),
+            self.stateless_reset_token.to_be_bytes().to_vec(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.sequence_number.byte_size()
+            + self.retire_prior_to.byte_size()
+            + 1
+            + self.connection_id.0.len()
+            + std::mem::size_of::<u128>()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruzzic_common::read_bytes_to::ReadBytesTo;
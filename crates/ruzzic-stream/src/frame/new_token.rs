@@ -1,11 +1,11 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use super::{invalid_data, read_exact_length};
 use crate::{read_varint, Token};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     // The token MUST NOT be empty
-    // TODO: so implment error handling
     token: Token,
 }
 
@@ -15,12 +15,29 @@ impl FromReadBytesWith<()> for Body {
         Self: Sized,
     {
         let length = read_varint(input)?;
-        let mut buf = vec![0; length.to_u64() as usize];
-        input.read_exact(&mut buf)?;
+        if length.to_u64() == 0 {
+            return Err(invalid_data("the NEW_TOKEN frame has an empty token"));
+        }
+        let buf = read_exact_length(input, length.to_u64())?;
         Ok(Self { token: Token(buf) })
     }
 }
 
+impl Body {
+    #[cfg(test)]
+    pub(crate) fn new(token: Token) -> Self {
+        Self { token }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.token.to_bytes()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.token.raw_length()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruzzic_common::read_bytes_to::ReadBytesTo;
@@ -1,10 +1,9 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{read_varint, VarInt};
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
-    data: VarInt,
+    // 8 bytes of arbitrary data, not a variable-length integer
+    data: [u8; 8],
 }
 
 impl FromReadBytesWith<()> for Body {
@@ -12,11 +11,27 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let data = read_varint(input)?;
+        let mut data = [0; 8];
+        input.read_exact(&mut data)?;
         Ok(Self { data })
     }
 }
 
+impl Body {
+    #[cfg(test)]
+    pub(crate) fn new(data: [u8; 8]) -> Self {
+        Self { data }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.data.to_vec()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.data.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -25,11 +40,11 @@ mod tests {
     use std::io::Cursor;
 
     #[test]
-    fn data_blocked() {
-        let buf = [0];
+    fn path_response() {
+        let buf = [0, 1, 2, 3, 4, 5, 6, 7];
         let mut input = Cursor::new(buf);
         let actual: Body = input.read_bytes_to().unwrap();
-        let expected = Body { data: VarInt(0) };
+        let expected = Body { data: buf };
         assert_eq!(actual, expected);
     }
 }
@@ -1,8 +1,11 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{read_varint, stream::StreamID, ApplicationProtocolErrorCode, VarInt};
+use crate::{
+    read_varint, size_of_varint, stream::StreamID, u64_to_varint_exact_size,
+    ApplicationProtocolErrorCode, VarInt,
+};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     stream_id: StreamID,
     error_code: ApplicationProtocolErrorCode,
@@ -25,6 +28,43 @@ impl FromReadBytesWith<()> for Body {
     }
 }
 
+impl Body {
+    pub(crate) fn new(stream_id: StreamID, error_code: u64, final_size: u64) -> Self {
+        Self {
+            stream_id,
+            error_code: ApplicationProtocolErrorCode(error_code),
+            final_size: u64_to_varint_exact_size(final_size),
+        }
+    }
+
+    pub(crate) fn stream_id(&self) -> &StreamID {
+        &self.stream_id
+    }
+
+    pub(crate) fn error_code(&self) -> u64 {
+        self.error_code.0
+    }
+
+    pub(crate) fn final_size(&self) -> u64 {
+        self.final_size.to_u64()
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            u64_to_varint_exact_size(self.stream_id.0).to_bytes(),
+            u64_to_varint_exact_size(self.error_code.0).to_bytes(),
+            self.final_size.to_bytes(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        size_of_varint(self.stream_id.0)
+            + size_of_varint(self.error_code.0)
+            + self.final_size.byte_size()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ruzzic_common::read_bytes_to::ReadBytesTo;
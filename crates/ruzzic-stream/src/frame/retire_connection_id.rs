@@ -2,7 +2,7 @@ use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
 use crate::{read_varint, VarInt};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     sequence_number: VarInt,
 }
@@ -17,6 +17,23 @@ impl FromReadBytesWith<()> for Body {
     }
 }
 
+impl Body {
+    #[cfg(test)]
+    pub(crate) fn new(sequence_number: u64) -> Self {
+        Self {
+            sequence_number: crate::u64_to_varint_exact_size(sequence_number),
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.sequence_number.to_bytes()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        self.sequence_number.byte_size()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
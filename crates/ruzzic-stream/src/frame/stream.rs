@@ -1,13 +1,14 @@
 use bitvec::{prelude::*, slice::BitSlice};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use super::{invalid_data, read_exact_length};
 use crate::{
-    read_varint,
+    read_varint, size_of_varint,
     stream::{StreamData, StreamID},
-    VarInt,
+    u64_to_varint_exact_size, VarInt,
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     stream_id: StreamID,
     offset: Option<VarInt>,
@@ -29,14 +30,18 @@ impl FromReadBytesWith<&BitSlice<Msb0, u8>> for Body {
         } else {
             None
         };
-        let mut buf = Vec::new();
-        if flags[1] {
+        let buf = if flags[1] {
             let length = read_varint(input)?;
-            buf.resize(length.to_u64() as usize, 0);
-            input.read_exact(&mut buf)?;
+            read_exact_length(input, length.to_u64())?
         } else {
-            let _ = input.read_to_end(&mut buf)?;
+            let mut buf = Vec::new();
+            input.read_to_end(&mut buf)?;
+            buf
         };
+        let end = offset.as_ref().map_or(0, |offset| offset.to_u64()) + buf.len() as u64;
+        if end >= 1 << 62 {
+            return Err(invalid_data("the STREAM frame exceeds the maximum offset"));
+        }
         let is_fin = flags[2];
         Ok(Self {
             stream_id: StreamID(stream_id.to_u64()),
@@ -47,6 +52,69 @@ impl FromReadBytesWith<&BitSlice<Msb0, u8>> for Body {
     }
 }
 
+impl Body {
+    pub(crate) fn new(
+        stream_id: StreamID,
+        offset: Option<u64>,
+        data: Vec<u8>,
+        is_fin: bool,
+    ) -> Self {
+        Self {
+            stream_id,
+            offset: offset.map(u64_to_varint_exact_size),
+            data: StreamData(data),
+            is_fin,
+        }
+    }
+
+    /// The Length field is always written, so that other frames can follow this.
+    pub(crate) fn frame_type(&self) -> u64 {
+        let offset_bit = if self.offset.is_some() { 0x04 } else { 0x00 };
+        let length_bit = 0x02;
+        let fin_bit = if self.is_fin { 0x01 } else { 0x00 };
+        0x08 | offset_bit | length_bit | fin_bit
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            u64_to_varint_exact_size(self.stream_id.0).to_bytes(),
+            self.offset.as_ref().map_or(Vec::new(), |o| o.to_bytes()),
+            u64_to_varint_exact_size(self.data.0.len() as u64).to_bytes(),
+            self.data.0.clone(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn stream_id(&self) -> &StreamID {
+        &self.stream_id
+    }
+
+    /// The Offset field is omitted at the beginning of a stream.
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset.as_ref().map_or(0, |offset| offset.to_u64())
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data.0
+    }
+
+    pub(crate) fn is_fin(&self) -> bool {
+        self.is_fin
+    }
+
+    pub(crate) fn data_length(&self) -> u64 {
+        self.data.0.len() as u64
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        let length = self.data.0.len();
+        size_of_varint(self.stream_id.0)
+            + self.offset.as_ref().map_or(0, |o| o.byte_size())
+            + size_of_varint(length as u64)
+            + length
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
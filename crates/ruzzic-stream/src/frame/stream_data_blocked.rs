@@ -1,8 +1,8 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::{read_varint, stream::StreamID, VarInt};
+use crate::{read_varint, size_of_varint, stream::StreamID, u64_to_varint_exact_size, VarInt};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Body {
     stream_id: StreamID,
     maximum_stream_data: VarInt,
@@ -22,6 +22,35 @@ impl FromReadBytesWith<()> for Body {
     }
 }
 
+impl Body {
+    pub(crate) fn new(stream_id: StreamID, maximum_stream_data: u64) -> Self {
+        Self {
+            stream_id,
+            maximum_stream_data: u64_to_varint_exact_size(maximum_stream_data),
+        }
+    }
+
+    pub(crate) fn stream_id(&self) -> &StreamID {
+        &self.stream_id
+    }
+
+    pub(crate) fn maximum_stream_data(&self) -> u64 {
+        self.maximum_stream_data.to_u64()
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            u64_to_varint_exact_size(self.stream_id.0).to_bytes(),
+            self.maximum_stream_data.to_bytes(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn raw_length(&self) -> usize {
+        size_of_varint(self.stream_id.0) + self.maximum_stream_data.byte_size()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -4,14 +4,17 @@ use derive_more::{From, Into};
 use ruzzic_common::{read_bytes_to::FromReadBytesWith, QuicVersion};
 use std::{io::Cursor, mem::size_of, slice::from_raw_parts};
 
-mod connection;
+pub mod connection;
+pub mod crypto;
+pub mod endpoint;
 mod endpoint_state;
 mod frame;
 pub mod packet;
-mod stream;
+pub mod stream;
+pub mod transport_parameters;
 
 // https://www.rfc-editor.org/rfc/rfc9000.html#name-variable-length-integer-enc
-#[derive(Debug, Into, From, PartialEq)]
+#[derive(Debug, Clone, Into, From, PartialEq)]
 struct VarInt(u64);
 
 fn read_varint(input: &mut impl std::io::Read) -> Result<VarInt, std::io::Error> {
@@ -77,8 +80,17 @@ fn size_of_varint(i: u64) -> usize {
     }
 }
 
+/// The Length field of long header packets is written in at least 2 bytes,
+/// so that padding a packet never changes the size of its header.
+fn length_to_varint(i: u64) -> VarInt {
+    if i < (1 << 14) {
+        to_varint(&(i as u16))
+    } else {
+        u64_to_varint_exact_size(i)
+    }
+}
+
 impl VarInt {
-    #[allow(dead_code)]
     fn byte_size(&self) -> usize {
         if self.0 - (0b00 << 6) < (1 << 6) {
             1
@@ -126,12 +138,17 @@ impl VarInt {
 pub struct Token(Vec<u8>);
 
 impl Token {
-    // TODO: support error handling
-    pub fn read_bytes(input: &mut impl std::io::Read) -> Self {
-        let length = read_varint(input).unwrap();
-        let mut buf = vec![0; length.to_u64() as usize];
-        input.read_exact(&mut buf).unwrap();
-        Token(buf)
+    pub fn read_bytes(input: &mut impl std::io::Read) -> Result<Self, std::io::Error> {
+        let length = read_varint(input)?;
+        Ok(Token(frame::read_exact_length(input, length.to_u64())?))
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            u64_to_varint_exact_size(self.0.len() as u64).to_bytes(),
+            self.0.clone(),
+        ]
+        .concat()
     }
 
     pub(crate) fn raw_length(&self) -> usize {
@@ -149,11 +166,11 @@ impl FromReadBytesWith<()> for Token {
     where
         Self: Sized,
     {
-        Ok(Self::read_bytes(input))
+        Self::read_bytes(input)
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ApplicationProtocolErrorCode(pub(crate) u64);
 
 #[repr(transparent)]
@@ -170,8 +187,7 @@ impl FromReadBytesWith<()> for Version {
 }
 
 impl Version {
-    #[allow(dead_code)]
-    pub(self) fn to_bytes(&self) -> [u8; 4] {
+    pub(crate) fn to_bytes(self) -> [u8; 4] {
         let mut buf = [0u8; 4];
         BigEndian::write_u32(&mut buf, self.0);
         buf
@@ -186,6 +202,12 @@ impl Version {
     }
 }
 
+impl From<QuicVersion> for Version {
+    fn from(version: QuicVersion) -> Self {
+        Self(version.into())
+    }
+}
+
 impl From<Version> for QuicVersion {
     fn from(version: Version) -> Self {
         match version.to_u32() {
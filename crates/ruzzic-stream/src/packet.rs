@@ -1,34 +1,22 @@
-use std::{borrow::Cow, io::Cursor, iter};
+use std::io::Cursor;
 
-use aes_gcm::{
-    aead::{self, Aead, NewAead},
-    aes::{Aes128, BlockEncrypt, NewBlockCipher},
-    Aes128Gcm,
-};
-use bitvec::{field::BitField, macros::internal::funty::IsNumber};
-use byteorder::{BigEndian, ByteOrder};
-use generic_array::GenericArray;
-use hmac::{Hmac, Mac};
-use log::kv::source;
-use ruzzic_common::{
-    read_bytes_to::{FromReadBytes, FromReadBytesWith, ReadBytesTo, ReadBytesToWith},
-    EndpointType, QuicVersion,
-};
-use sha2::{Digest, Sha256};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo, ReadBytesToWith};
 
 use crate::{
-    connection::{Connection, ConnectionID},
+    connection::{Connection, ConnectionID, CONNECTION_ID_LENGTH},
+    crypto::{retry_integrity_tag, DirectionalKeys, EncryptionLevel},
     endpoint_state::EndpointState,
-    frame::Frames,
-    size_of_varint, Version,
+    read_varint, Token, Version,
 };
 
-use self::{long_header::LongHeader, packet_meta::PacketMeta};
+use self::{long_header::LongHeader, packet_meta::PacketMeta, short_header::ShortHeader};
 
 mod long_header;
 pub mod packet_meta;
+mod short_header;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Packet {
     meta: PacketMeta,
     // Rather than Packet Body, something other than Packet Meta is the correct expression.
@@ -38,6 +26,20 @@ pub struct Packet {
 
 impl FromReadBytesWith<()> for Packet {
     fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+    where
+        Self: Sized,
+    {
+        input.read_bytes_to_with(CONNECTION_ID_LENGTH)
+    }
+}
+
+/// A short header packet does not have the length of the destination connection id,
+/// so this takes the length of connection ids issued by ourselves.
+impl FromReadBytesWith<usize> for Packet {
+    fn from_read_bytes_with<R: std::io::Read>(
+        input: &mut R,
+        connection_id_length: usize,
+    ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
@@ -45,7 +47,7 @@ impl FromReadBytesWith<()> for Packet {
         input.read_to_end(&mut raw)?;
         let input = &mut Cursor::new(raw.clone());
         let meta = input.read_bytes_to()?;
-        let body = input.read_bytes_to_with(&meta)?;
+        let body = input.read_bytes_to_with((&meta, connection_id_length))?;
         Ok(Self { meta, body, raw })
     }
 }
@@ -59,33 +61,68 @@ impl Packet {
         self.raw.len()
     }
 
-    pub fn version(&self) -> Version {
+    /// Short header packets do not have a version.
+    pub fn version(&self) -> Option<Version> {
         self.meta.version()
     }
 
     pub fn destination_connection_id(&self) -> Box<ConnectionID> {
-        match &self.body {
-            PacketBody::Long(b) => b.destination_connection_id(),
-            _ => unimplemented!(),
-        }
+        self.body.destination_connection_id()
     }
 
     pub fn source_connection_id(&self) -> Option<Box<ConnectionID>> {
         match &self.body {
             PacketBody::Long(b) => Some(b.source_connection_id()),
-            _ => unimplemented!(),
+            PacketBody::Short(_) => None,
         }
     }
 
+    /// Spin bit of a short header packet.
+    pub fn spin_bit(&self) -> Option<bool> {
+        match &self.body {
+            PacketBody::Long(_) => None,
+            PacketBody::Short(_) => Some(self.meta.first_byte.spin_bit()),
+        }
+    }
+
+    /// Key Phase bit of a short header packet.
+    /// This is meaningful only after header protection is removed.
+    pub fn key_phase(&self) -> Option<bool> {
+        match &self.body {
+            PacketBody::Long(_) => None,
+            PacketBody::Short(_) => Some(self.meta.first_byte.key_phase()),
+        }
+    }
+
+    pub fn packet_number(&self) -> PacketNumber {
+        self.body.packet_number()
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Header as received, which is the associated data of the payload.
     fn get_header_bytes(&self) -> Vec<u8> {
-        let packet_number_length = self.meta.packet_number_length();
-        let packet_body_length = self.body.raw_length(Some(packet_number_length as usize));
-        let packet_meta_length = self.meta.raw_length();
-        let packet_payload_length = self.payload().len();
-        let header_length = packet_meta_length + (packet_body_length - packet_payload_length);
+        let packet_number_length = self.meta.packet_number_length() as usize;
+        let header_length = self.packet_number_offset() + packet_number_length;
         self.raw[..header_length].to_owned()
     }
 
+    /// Serialize the header as it is going to be sent, that is, the Length field covers a payload of `payload_length` bytes.
+    fn header_bytes(&self, payload_length: usize) -> Vec<u8> {
+        let packet_number_length = self.meta.packet_number_length() as usize;
+        [
+            self.meta.to_bytes(),
+            self.body.header_bytes(packet_number_length, payload_length),
+        ]
+        .concat()
+    }
+
+    fn packet_number_offset(&self) -> usize {
+        self.meta.raw_length() + self.body.packet_number_offset()
+    }
+
     fn update_payload(self, payload: PacketPayload) -> Self {
         Self {
             meta: self.meta,
@@ -94,208 +131,302 @@ impl Packet {
         }
     }
 
-    fn new_initial(connection: &Connection, endpoint_state: &EndpointState) -> Self {
-        let unprotected_packet = Self::new_unprotected_initial(connection, endpoint_state);
-        unprotected_packet.encrypt(connection, endpoint_state)
-    }
-
-    fn new_unprotected_initial(connection: &Connection, endpoint_state: &EndpointState) -> Self {
+    /// Build a protected Initial packet which carries the payload.
+    pub(crate) fn new_initial(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
         let meta = PacketMeta::new_initial(connection, endpoint_state);
-        let body = PacketBody::new_initial(connection, endpoint_state);
+        let body = PacketBody::new_initial(connection, endpoint_state, payload);
         let raw = Vec::new();
-        Self { meta, body, raw }
+        Self { meta, body, raw }.encrypt(connection)
     }
 
-    pub fn decrypt(&self, endpoint_state: &EndpointState) -> Self {
-        todo!()
-        // let initial_salt = Into::<QuicVersion>::into(self.version()).initial_salt();
-
-        // let decryption_kit = DecryptionKit::new(
-        //     &initial_salt,
-        //     self.client_id(endpoint_state),
-        //     endpoint_state.type_is(),
-        // );
-        // log::debug!("Decryption kit: {:x?}", decryption_kit);
-
-        // let header_removal_kit = HeaderRemovalKit::new(
-        //     self.destination_connection_id(),
-        //     self.source_connection_id(),
-        //     self.payload(),
-        //     &self.body,
-        //     &self.raw,
-        //     &decryption_kit.hp,
-        //     128 / 8, // AES_128_GCM key length
-        // );
-
-        // let unprotected_packet = header_removal_kit.remove_protection(&self.raw);
+    /// Build a protected Handshake packet which carries the payload.
+    pub(crate) fn new_handshake(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
+        let meta = PacketMeta::new_long(
+            long_header::PacketType::Handshake,
+            connection,
+            endpoint_state,
+        );
+        let body = PacketBody::Long(LongHeader::new_handshake(
+            connection,
+            endpoint_state,
+            payload,
+        ));
+        let raw = Vec::new();
+        Self { meta, body, raw }.encrypt(connection)
+    }
 
-        // let packet_number = unprotected_packet.body.packet_number();
-        // let packet_number_bytes = packet_number.0.to_be_bytes();
-        // let packet_header = unprotected_packet.get_header_bytes();
+    /// Build a protected 0-RTT packet which carries the payload.
+    pub(crate) fn new_zero_rtt(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
+        let meta =
+            PacketMeta::new_long(long_header::PacketType::ZeroRTT, connection, endpoint_state);
+        let body = PacketBody::Long(LongHeader::new_zero_rtt(
+            connection,
+            endpoint_state,
+            payload,
+        ));
+        let raw = Vec::new();
+        Self { meta, body, raw }.encrypt(connection)
+    }
 
-        // let decrypted_payload = decrypt_payload(
-        //     unprotected_packet.payload(),
-        //     &packet_number_bytes,
-        //     decryption_kit,
-        //     &packet_header,
-        // );
+    /// Build a Retry packet sent by a server in reply to the Initial packet of the connection.
+    /// The client is asked to use `source_connection_id` and `retry_token` from then on.
+    pub(crate) fn new_retry(
+        connection: &Connection,
+        source_connection_id: &ConnectionID,
+        retry_token: Token,
+    ) -> Self {
+        let meta = PacketMeta::new_retry(connection);
+        let mut body = long_header::retry::Body::new(
+            connection.destination_connection_id(),
+            source_connection_id,
+            retry_token,
+        );
+        let retry_packet = [meta.to_bytes(), body.bytes_without_tag()].concat();
+        body.retry_integrity_tag = retry_integrity_tag(
+            &(*connection.version()).into(),
+            connection.original_destination_connection_id(),
+            &retry_packet,
+        )
+        .expect("a server sends Retry packets of the versions it supports");
+        let raw = [retry_packet, body.retry_integrity_tag.clone()].concat();
+        let body = PacketBody::Long(LongHeader::Retry(body));
+        Self { meta, body, raw }
+    }
 
-        // unprotected_packet.update_payload(PacketPayload::from_vec(decrypted_payload))
+    /// Retry Token of a Retry packet, which must be sent in Initial packets after that.
+    pub fn retry_token(&self) -> Option<&Token> {
+        match &self.body {
+            PacketBody::Long(LongHeader::Retry(b)) => Some(&b.retry_token),
+            _ => None,
+        }
     }
 
-    fn encrypt(self, connection: &Connection, endpoint_state: &EndpointState) -> Self {
-        todo!()
+    /// Token of an Initial packet, which is empty unless the client has received one from the server.
+    pub fn initial_token(&self) -> Option<&Token> {
+        match &self.body {
+            PacketBody::Long(LongHeader::Initial(b)) => Some(&b.token),
+            _ => None,
+        }
     }
 
-    pub(crate) fn client_id(
+    /// Check the Retry Integrity Tag with the Destination Connection ID of the first Initial packet sent by the client.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity
+    pub fn verify_retry_integrity_tag(
         &self,
+        original_destination_connection_id: &ConnectionID,
+    ) -> bool {
+        let (body, version) = match (&self.body, self.version()) {
+            (PacketBody::Long(LongHeader::Retry(b)), Some(version)) => (b, version),
+            _ => return false,
+        };
+        let retry_packet = &self.raw[..self.raw.len() - body.retry_integrity_tag.len()];
+        // a Retry packet of an unknown version can not be verified
+        retry_integrity_tag(
+            &version.into(),
+            original_destination_connection_id,
+            retry_packet,
+        )
+        .is_some_and(|tag| tag == body.retry_integrity_tag)
+    }
+
+    /// Build a protected 1-RTT packet which carries the payload.
+    pub(crate) fn new_one_rtt(
         connection: &Connection,
         endpoint_state: &EndpointState,
-    ) -> Option<Box<ConnectionID>> {
-        match endpoint_state.type_is() {
-            EndpointType::Client => self.source_connection_id(),
-            EndpointType::Server => Some(self.destination_connection_id()),
+        spin_bit: bool,
+        payload: PacketPayload,
+    ) -> Self {
+        let key_phase = connection
+            .key_sets()
+            .one_rtt()
+            .map(|keys| keys.key_phase())
+            .unwrap_or(false);
+        let meta = PacketMeta::new_short(endpoint_state, spin_bit, key_phase);
+        let body = PacketBody::Short(ShortHeader::new(connection, endpoint_state, payload));
+        let raw = Vec::new();
+        Self { meta, body, raw }.encrypt(connection)
+    }
+
+    /// Encryption level whose keys protect this packet.
+    /// Version Negotiation and Retry packets are not protected.
+    pub fn encryption_level(&self) -> Option<EncryptionLevel> {
+        match &self.body {
+            PacketBody::Long(LongHeader::Initial(_)) => Some(EncryptionLevel::Initial),
+            PacketBody::Long(LongHeader::ZeroRTT(_)) => Some(EncryptionLevel::ZeroRTT),
+            PacketBody::Long(LongHeader::Handshake(_)) => Some(EncryptionLevel::Handshake),
+            PacketBody::Long(LongHeader::VersionNegotiation(_) | LongHeader::Retry(_)) => None,
+            PacketBody::Short(_) => Some(EncryptionLevel::OneRTT),
         }
     }
-}
 
-fn decrypt_payload(
-    payload: &[u8],
-    packet_number: &[u8],
-    decryption_kit: DecryptionKit,
-    packet_header: &[u8],
-) -> Vec<u8> {
-    let packet_number = [
-        &vec![0; decryption_kit.iv.len() - packet_number.len()][..],
-        &packet_number,
-    ]
-    .concat();
-    let nonce = packet_number
-        .iter()
-        .enumerate()
-        .map(|(i, b)| b ^ decryption_kit.iv[i])
-        .collect::<Vec<u8>>();
-
-    let aad = packet_header;
-    let msg = payload;
-
-    let aead_payload = aead::Payload { msg, aad };
-    // Only considered during InitialPacket
-    let aes = Aes128Gcm::new(&GenericArray::clone_from_slice(&decryption_kit.key));
-
-    // TODO: erroro handling
-    match aes.decrypt(&GenericArray::from_slice(&nonce), aead_payload) {
-        Err(_) => {
-            panic!("Crypto Error. Details are not displayed to prevent attacks using error information.")
+    /// Remove packet protection with the keys installed in the connection.
+    /// Keys of 1-RTT packets are chosen by the Key Phase bit.
+    pub fn decrypt(&self, connection: &Connection) -> Result<Self, std::io::Error> {
+        let keys_not_available = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "keys of the encryption level are not available",
+            )
+        };
+        let level = self.encryption_level().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "packet is not protected")
+        })?;
+        let largest_packet_number = connection.largest_received_packet_number(level.into());
+        match level {
+            EncryptionLevel::OneRTT => {
+                let one_rtt_keys = connection
+                    .key_sets()
+                    .one_rtt()
+                    .ok_or_else(keys_not_available)?;
+                // header protection keys are not updated by key updates
+                let unprotected_packet = self.remove_header_protection(
+                    one_rtt_keys.current().remote(),
+                    largest_packet_number,
+                )?;
+                let keys = one_rtt_keys.opening_keys(
+                    unprotected_packet.meta.first_byte.key_phase(),
+                    unprotected_packet.packet_number().to_u64(),
+                );
+                unprotected_packet.decrypt_payload(keys)
+            }
+            level => {
+                let key_set = connection
+                    .key_sets()
+                    .get(level)
+                    .ok_or_else(keys_not_available)?;
+                self.remove_header_protection(key_set.remote(), largest_packet_number)?
+                    .decrypt_payload(key_set.remote())
+            }
         }
-        Ok(result) => result,
     }
-}
 
-fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> Vec<u8> {
-    let mut mac = Hmac::<Sha256>::new_from_slice(salt).expect("HMAC can take a key of any size");
-    mac.update(ikm);
-    mac.finalize().into_bytes().to_vec()
-}
+    /// Remove packet protection as the first packet of the packet number space.
+    pub fn decrypt_with(&self, keys: &DirectionalKeys) -> Result<Self, std::io::Error> {
+        self.remove_header_protection(keys, None)?
+            .decrypt_payload(keys)
+    }
 
-fn hkdf_expand(prk: &[u8], info: &[u8], length: u16) -> Vec<u8> {
-    // TODO: uncheck
-    let length_of_okm = div_ceil(length as usize, sha2::Sha256::output_size()) as u16;
-    let mut okm = Vec::new();
-    let mut t_result = Vec::new();
-    for i in 1..length_of_okm + 1 {
-        let mut t = Hmac::<Sha256>::new_from_slice(prk).expect("HMAC can take a key of any size");
-        t.update(&t_result);
-        t.update(info);
-        t.update(&[i as u8]);
-        t_result = t.finalize().into_bytes().to_vec();
-        okm.append(&mut t_result.clone());
-    }
-    okm[..length as usize].to_vec()
-}
+    /// The truncated packet number is decoded with the largest packet number received in the packet number space.
+    fn remove_header_protection(
+        &self,
+        keys: &DirectionalKeys,
+        largest_packet_number: Option<PacketNumber>,
+    ) -> Result<Self, std::io::Error> {
+        let header_removal_kit = HeaderRemovalKit::new(self, keys)?;
+        let mut unprotected_packet = header_removal_kit.remove_protection(self)?;
+        let packet_number = PacketNumber::decode(
+            largest_packet_number,
+            unprotected_packet.body.packet_number().to_u64(),
+            unprotected_packet.meta.packet_number_length() as usize,
+        );
+        unprotected_packet
+            .body
+            .overwrite_packet_number(packet_number);
+        Ok(unprotected_packet)
+    }
 
-fn hkdf_expand_label(secret: &[u8], label: &[u8], context: &[u8], length: u16) -> Vec<u8> {
-    let length = length;
-    let label = ["tls13 ".as_bytes(), label].concat();
-    let hkdf_label = [
-        &length.to_be_bytes()[..],
-        &[label.len() as u8],
-        &label,
-        &[context.len() as u8],
-        context,
-    ]
-    .concat();
-    hkdf_expand(secret, &hkdf_label, length)
-}
+    fn decrypt_payload(self, keys: &DirectionalKeys) -> Result<Self, std::io::Error> {
+        let unprotected_packet = self;
 
-fn get_key_iv_hp(endpoint_initial_secret: &[u8]) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
-    let key = hkdf_expand_label(
-        endpoint_initial_secret,
-        "quic key".as_bytes(),
-        &[],
-        16, // aes_gcm_128_key_size
-    );
-    let iv = hkdf_expand_label(
-        endpoint_initial_secret,
-        "quic iv".as_bytes(),
-        &[],
-        12, // aes_gcm_128_iv_size
-    );
-    let hp = hkdf_expand_label(
-        endpoint_initial_secret,
-        "quic hp".as_bytes(),
-        &[],
-        16, // aes_gcm_128_key_size
-    );
-    (key, iv, hp)
-}
+        let packet_number = unprotected_packet.body.packet_number();
+        let packet_number_bytes = packet_number.0.to_be_bytes();
+        let packet_header = unprotected_packet.get_header_bytes();
 
-fn div_ceil(a: usize, b: usize) -> usize {
-    (a + b - 1) / b
-}
+        let decrypted_payload = keys.decrypt_payload(
+            unprotected_packet.payload(),
+            &packet_number_bytes,
+            &packet_header,
+        )?;
 
-#[derive(Debug)]
-struct DecryptionKit {
-    pub(self) key: Vec<u8>,
-    pub(self) iv: Vec<u8>,
-    pub(self) hp: Vec<u8>,
-}
+        Ok(unprotected_packet.update_payload(PacketPayload::from_vec(decrypted_payload)))
+    }
 
-impl DecryptionKit {
-    fn new(
-        initial_salt: &[u8],
-        connection_id: &ConnectionID,
-        endpoint_type: &EndpointType,
-    ) -> Self {
-        let (key, iv, hp) = match endpoint_type {
-            EndpointType::Server => {
-                // server received a client packet
-                new_decryption_kit(initial_salt, connection_id, "client in".as_bytes())
-            }
-            EndpointType::Client => {
-                // client received a server packet
-                new_decryption_kit(initial_salt, connection_id, "server in".as_bytes())
-            }
+    /// Apply packet protection with the keys installed in the connection.
+    pub fn encrypt(self, connection: &Connection) -> Self {
+        let key_set = connection
+            .key_sets()
+            .get(
+                self.encryption_level()
+                    .expect("only protected packets are built"),
+            )
+            .expect("keys must be installed before sending packets of the encryption level");
+        self.encrypt_with(key_set.local())
+    }
+
+    pub fn encrypt_with(self, keys: &DirectionalKeys) -> Self {
+        let packet_number = self.body.packet_number();
+        let packet_number_bytes = packet_number.0.to_be_bytes();
+        let encrypted_payload_length = self.payload().len() + keys.tag_length();
+        let packet_header = self.header_bytes(encrypted_payload_length);
+
+        let encrypted_payload =
+            keys.encrypt_payload(self.payload(), &packet_number_bytes, &packet_header);
+
+        let packet_number_length = self.meta.packet_number_length() as usize;
+        let header_protection_kit = HeaderProtectionKit {
+            packet_number_offset: packet_header.len() - packet_number_length,
+            packet_number_length,
         };
+        let raw = header_protection_kit
+            .apply_protection([packet_header, encrypted_payload].concat(), keys);
 
-        Self { key, iv, hp }
+        let mut input = Cursor::new(raw);
+        // this must be succeeded because it has been serialized by ourselves
+        input
+            .read_bytes_to_with(self.body.destination_connection_id().len())
+            .unwrap()
     }
 }
 
-fn new_decryption_kit(
-    initial_salt: &[u8],
-    connection_id: &ConnectionID,
-    label: &[u8],
-) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
-    log::debug!("initial_salt: {initial_salt:x?}");
-    log::debug!("connection_id: {connection_id:x?}",);
-    let initial_secret = hkdf_extract(&initial_salt, &connection_id.to_vec());
-    log::debug!("initial_secret: {initial_secret:x?}");
-
-    let endpoint_initial_secret =
-        hkdf_expand_label(&initial_secret, label, &[], Sha256::output_size() as u16);
+/// Length of the first packet in a datagram, so that coalesced packets are read one by one.
+/// A short header packet, a Version Negotiation packet and a Retry packet take the rest of the datagram.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-coalescing-packets
+pub fn first_packet_length(datagram: &[u8]) -> Result<usize, std::io::Error> {
+    let mut input = Cursor::new(datagram);
+    let first_byte = input.read_u8()?;
+    if first_byte & 0x80 == 0 {
+        return Ok(datagram.len());
+    }
+    let version = input.read_u32::<BigEndian>()?;
+    let packet_type = (first_byte >> 4) & 0x03;
+    if version == 0 || packet_type == 0x03 {
+        return Ok(datagram.len());
+    }
+    // Destination and Source Connection IDs
+    for _ in 0..2 {
+        let length = input.read_u8()? as u64;
+        input.set_position(input.position() + length);
+    }
+    if packet_type == 0x00 {
+        let token_length = read_varint(&mut input)?.to_u64();
+        input.set_position(input.position() + token_length);
+    }
+    let length = read_varint(&mut input)?.to_u64();
+    let end = input.position().saturating_add(length);
+    if end > datagram.len() as u64 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(end as usize)
+}
 
-    get_key_iv_hp(&endpoint_initial_secret)
+fn protected_first_byte_bits(body: &PacketBody) -> u8 {
+    match body {
+        PacketBody::Long(_) => 0x0f,
+        // spin bit is not protected
+        PacketBody::Short(_) => 0x1f,
+    }
 }
 
 #[derive(Debug)]
@@ -307,59 +438,38 @@ struct HeaderRemovalKit {
 }
 
 impl HeaderRemovalKit {
-    fn new(
-        destination_connection_id: ConnectionID,
-        source_connection_id: Option<ConnectionID>,
-        payload: &[u8],
-        body: &PacketBody,
-        raw: &[u8],
-        hp: &[u8],
-        sample_length: usize,
-    ) -> Self {
-        let packet_number_offset = match body {
-            PacketBody::Long(lh) => {
-                7
-                + destination_connection_id.len()
-                // long header packet must have a source connection id
-                + source_connection_id.unwrap().len()
-                + size_of_varint(payload.len() as u64)
-                + match lh {
-                    LongHeader::Initial(b) => b.token_raw_length(),
-                    _ => 0,
-                }
-            }
-            // short header packet
-            _ => 1 + destination_connection_id.len(),
-        };
+    fn new(packet: &Packet, keys: &DirectionalKeys) -> Result<Self, std::io::Error> {
+        let sample_length = keys.sample_length();
+        let raw = &packet.raw;
+        let packet_number_offset = packet.packet_number_offset();
 
+        // the sample is taken as if the packet number were 4 bytes long
         let sample_offset = 4 + packet_number_offset;
-        let sample = &raw[sample_offset..sample_offset + sample_length];
+        let sample = raw
+            .get(sample_offset..sample_offset + sample_length)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "packet is too short to take a header protection sample",
+                )
+            })?;
 
-        let mask = {
-            let mut mask = GenericArray::clone_from_slice(&sample);
-            Aes128::new_from_slice(hp).unwrap().encrypt_block(&mut mask);
-            mask
-        }
-        .to_vec();
+        let mask = keys.header_protection_mask(sample);
 
-        let unprotected_first_byte = match body {
-            PacketBody::Long(_) => raw[0] ^ (mask[0] & 0x0f),
-            // short header packet
-            _ => raw[0] ^ (mask[0] & 0x1f),
-        };
+        let unprotected_first_byte = raw[0] ^ (mask[0] & protected_first_byte_bits(&packet.body));
 
         let packet_number_length = (unprotected_first_byte & 0x03) as usize + 1;
 
-        Self {
+        Ok(Self {
             packet_number_offset,
             packet_number_length,
             mask: mask,
             first_byte: unprotected_first_byte,
-        }
+        })
     }
 
-    fn remove_protection(&self, raw: &[u8]) -> Packet {
-        let mut raw = raw.to_vec();
+    fn remove_protection(&self, packet: &Packet) -> Result<Packet, std::io::Error> {
+        let mut raw = packet.raw.to_vec();
 
         let packet_number = raw
             [self.packet_number_offset..self.packet_number_offset + self.packet_number_length]
@@ -373,54 +483,100 @@ impl HeaderRemovalKit {
         raw[0] = self.first_byte;
 
         let mut input = Cursor::new(raw);
-        // this must be succeeded
-        Packet::from_read_bytes(&mut input).unwrap()
+        input.read_bytes_to_with(packet.body.destination_connection_id().len())
+    }
+}
+
+#[derive(Debug)]
+struct HeaderProtectionKit {
+    pub(self) packet_number_offset: usize,
+    pub(self) packet_number_length: usize,
+}
+
+impl HeaderProtectionKit {
+    fn apply_protection(&self, mut raw: Vec<u8>, keys: &DirectionalKeys) -> Vec<u8> {
+        let sample_offset = 4 + self.packet_number_offset;
+        let mask =
+            keys.header_protection_mask(&raw[sample_offset..sample_offset + keys.sample_length()]);
+
+        let protected_bits = if raw[0] & 0x80 == 0x80 {
+            0x0f
+        } else {
+            // spin bit is not protected
+            0x1f
+        };
+        raw[0] ^= mask[0] & protected_bits;
+        raw[self.packet_number_offset..self.packet_number_offset + self.packet_number_length]
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b ^= mask[i + 1]);
+
+        raw
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum PacketBody {
     Long(LongHeader),
+    Short(ShortHeader),
 }
 
 impl PacketBody {
     fn payload(&self) -> &[u8] {
         match self {
             PacketBody::Long(b) => b.payload(),
-            _ => unimplemented!(),
+            PacketBody::Short(b) => b.payload(),
         }
     }
 
     fn packet_number(&self) -> PacketNumber {
         match self {
             PacketBody::Long(b) => b.packet_number(),
-            _ => unimplemented!(),
-        }
-    }
-
-    fn raw_length(&self, packet_number_length: Option<usize>) -> usize {
-        match self {
-            PacketBody::Long(lh) => lh.raw_length(packet_number_length),
-            _ => unimplemented!(),
+            PacketBody::Short(b) => b.packet_number(),
         }
     }
 
     fn destination_connection_id(&self) -> Box<ConnectionID> {
         match self {
             PacketBody::Long(lh) => lh.destination_connection_id(),
-            _ => unimplemented!(),
+            PacketBody::Short(sh) => sh.destination_connection_id(),
         }
     }
 
     fn update_payload(self, payload: PacketPayload) -> Self {
         match self {
             PacketBody::Long(lh) => PacketBody::Long(lh.update_payload(payload)),
-            _ => unimplemented!(),
+            PacketBody::Short(sh) => PacketBody::Short(sh.update_payload(payload)),
         }
     }
 
-    fn new_initial(connection: &Connection, endpoint_state: &EndpointState) -> Self {
-        Self::Long(LongHeader::new_initial(connection, endpoint_state))
+    fn overwrite_packet_number(&mut self, packet_number: PacketNumber) {
+        match self {
+            PacketBody::Long(lh) => lh.overwrite_packet_number(packet_number),
+            PacketBody::Short(sh) => sh.packet_number = packet_number,
+        }
+    }
+
+    fn packet_number_offset(&self) -> usize {
+        match self {
+            PacketBody::Long(lh) => lh.packet_number_offset(),
+            PacketBody::Short(sh) => sh.packet_number_offset(),
+        }
+    }
+
+    fn header_bytes(&self, packet_number_length: usize, payload_length: usize) -> Vec<u8> {
+        match self {
+            PacketBody::Long(lh) => lh.header_bytes(packet_number_length, payload_length),
+            PacketBody::Short(sh) => sh.header_bytes(packet_number_length),
+        }
+    }
+
+    fn new_initial(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
+        Self::Long(LongHeader::new_initial(connection, endpoint_state, payload))
     }
 }
 
@@ -430,17 +586,40 @@ pub enum PacketBodyType {
     Short,
 }
 
-impl FromReadBytesWith<&PacketMeta> for PacketBody {
+impl FromReadBytesWith<(&PacketMeta, usize)> for PacketBody {
     fn from_read_bytes_with<R: std::io::Read>(
         input: &mut R,
-        meta: &PacketMeta,
+        (meta, connection_id_length): (&PacketMeta, usize),
     ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
         match meta.get_type() {
             PacketBodyType::Long => Ok(PacketBody::Long(input.read_bytes_to_with(meta)?)),
-            _ => unimplemented!(),
+            PacketBodyType::Short => Ok(PacketBody::Short(
+                input.read_bytes_to_with((meta, connection_id_length))?,
+            )),
+        }
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-packet-numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketNumberSpace {
+    Initial,
+    Handshake,
+    ApplicationData,
+}
+
+impl From<EncryptionLevel> for PacketNumberSpace {
+    fn from(level: EncryptionLevel) -> Self {
+        match level {
+            EncryptionLevel::Initial => PacketNumberSpace::Initial,
+            EncryptionLevel::Handshake => PacketNumberSpace::Handshake,
+            // 0-RTT and 1-RTT packets share the application data space
+            EncryptionLevel::ZeroRTT | EncryptionLevel::OneRTT => {
+                PacketNumberSpace::ApplicationData
+            }
         }
     }
 }
@@ -453,42 +632,81 @@ pub enum PacketType {
     Retry,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct PacketNumber(pub(crate) u32);
+/// Packet numbers are integers in the range 0 to 2^62-1.
+/// In a packet header, they are truncated to 1 to 4 bytes.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-packet-numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PacketNumber(pub(crate) u64);
 
 impl PacketNumber {
-    // TODO: check integer casting
+    /// Read a truncated packet number, which must be decoded with `decode` to get the full one.
     pub fn read_bytes_to(
         input: &mut impl std::io::Read,
         length: u8,
     ) -> Result<Self, std::io::Error> {
         let mut buf = vec![0u8; length as usize];
         input.read_exact(&mut buf)?;
-        Ok(PacketNumber(
-            BigEndian::read_uint(&buf, length as usize) as u32
-        ))
-    }
-
-    pub(crate) fn length_in_header(&self) -> u8 {
-        if self.0 < 0xff {
-            0
-        } else if self.0 < 0xffff {
-            1
-        } else if self.0 < 0xffffffff {
-            2
-        } else if self.0 < 1 << 62 - 1 {
-            3
+        Ok(PacketNumber(BigEndian::read_uint(&buf, length as usize)))
+    }
+
+    /// Recover the full packet number from the truncated one with the largest packet number
+    /// successfully processed in the packet number space.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-sample-packet-number-decodi
+    pub fn decode(
+        largest_packet_number: Option<PacketNumber>,
+        truncated_packet_number: u64,
+        packet_number_length: usize,
+    ) -> Self {
+        let expected_packet_number = largest_packet_number.map_or(0, |pn| pn.0 + 1);
+        let packet_number_window = 1u64 << (packet_number_length * 8);
+        let packet_number_half_window = packet_number_window / 2;
+        let packet_number_mask = packet_number_window - 1;
+
+        let candidate_packet_number =
+            (expected_packet_number & !packet_number_mask) | truncated_packet_number;
+        if candidate_packet_number + packet_number_half_window <= expected_packet_number
+            && candidate_packet_number < (1 << 62) - packet_number_window
+        {
+            Self(candidate_packet_number + packet_number_window)
+        } else if candidate_packet_number > expected_packet_number + packet_number_half_window
+            && candidate_packet_number >= packet_number_window
+        {
+            Self(candidate_packet_number - packet_number_window)
         } else {
-            unreachable!(
-                "maximum packet number size is 2^62 - 1 but this is {}",
-                self.0
-            )
+            Self(candidate_packet_number)
         }
     }
 
+    /// The shortest length in bytes to send this packet number, so that the peer can decode it
+    /// with the largest packet number acknowledged by the peer.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-sample-packet-number-encodi
+    pub fn encoded_length(&self, largest_acknowledged: Option<PacketNumber>) -> usize {
+        let number_of_unacknowledged = match largest_acknowledged {
+            Some(largest_acknowledged) => self.0 - largest_acknowledged.0,
+            None => self.0 + 1,
+        };
+        // at least one bit more than the base-2 logarithm of the number of unacknowledged packets
+        let bit_length = 64 - number_of_unacknowledged.leading_zeros() as usize;
+        let minimum_bits = if number_of_unacknowledged.is_power_of_two() {
+            bit_length
+        } else {
+            bit_length + 1
+        };
+        minimum_bits.div_ceil(8).clamp(1, 4)
+    }
+
     pub(crate) fn zero() -> Self {
         Self(0)
     }
+
+    pub(crate) fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Truncate this packet number to the lowest `length` bytes.
+    pub(crate) fn to_bytes(self, length: usize) -> Vec<u8> {
+        self.0.to_be_bytes()[8 - length..].to_vec()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -505,22 +723,6 @@ impl FromReadBytesWith<()> for PacketPayload {
     }
 }
 
-struct PacketData<'a> {
-    pub packet_number: &'a PacketNumber,
-    pub packet_payload: &'a PacketPayload,
-}
-
-impl<'a> PacketData<'a> {
-    fn raw_length(&self, packet_number_length: usize) -> usize {
-        let Self {
-            packet_number,
-            packet_payload,
-        } = self;
-        let packet_data_length = packet_payload.0.len() + packet_number_length;
-        size_of_varint(packet_data_length as u64) + packet_data_length
-    }
-}
-
 #[cfg(test)]
 mod neqo_tests;
 
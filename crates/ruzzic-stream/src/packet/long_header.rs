@@ -12,8 +12,11 @@ use crate::{
 
 use super::{packet_meta::PacketMeta, PacketNumber, PacketPayload};
 
+pub mod handshake;
 pub mod initial;
+pub mod retry;
 pub mod version_negotiation;
+pub mod zero_rtt;
 
 #[derive(Debug, PartialEq)]
 pub struct LongHeaderMeta {
@@ -36,6 +39,17 @@ pub enum PacketType {
     Retry,
 }
 
+impl PacketType {
+    pub(crate) fn to_u8(&self) -> u8 {
+        match self {
+            PacketType::Initial => 0,
+            PacketType::ZeroRTT => 1,
+            PacketType::Handshake => 2,
+            PacketType::Retry => 3,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Versions(Vec<Version>);
 
@@ -43,6 +57,9 @@ pub struct Versions(Vec<Version>);
 pub enum LongHeader {
     VersionNegotiation(version_negotiation::Body),
     Initial(initial::Body),
+    ZeroRTT(zero_rtt::Body),
+    Handshake(handshake::Body),
+    Retry(retry::Body),
 }
 
 impl LongHeader {
@@ -50,6 +67,8 @@ impl LongHeader {
         match self {
             LongHeader::VersionNegotiation(b) => b.payload(),
             LongHeader::Initial(b) => b.payload(),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => b.payload(),
+            LongHeader::Retry(b) => b.payload(),
         }
     }
 
@@ -57,6 +76,8 @@ impl LongHeader {
         match self {
             LongHeader::VersionNegotiation(b) => b.destination_connection_id(),
             LongHeader::Initial(b) => b.destination_connection_id(),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => b.destination_connection_id(),
+            LongHeader::Retry(b) => b.destination_connection_id(),
         }
     }
 
@@ -64,34 +85,81 @@ impl LongHeader {
         match self {
             LongHeader::VersionNegotiation(b) => b.source_connection_id(),
             LongHeader::Initial(b) => b.source_connection_id(),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => b.source_connection_id(),
+            LongHeader::Retry(b) => b.source_connection_id(),
         }
     }
 
     pub(super) fn packet_number(&self) -> PacketNumber {
         match self {
-            LongHeader::VersionNegotiation(b) => unreachable!(),
+            LongHeader::VersionNegotiation(_) | LongHeader::Retry(_) => unreachable!(),
             LongHeader::Initial(b) => b.packet_number(),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => b.packet_number(),
+        }
+    }
+
+    pub(super) fn overwrite_packet_number(&mut self, packet_number: PacketNumber) {
+        match self {
+            LongHeader::VersionNegotiation(_) | LongHeader::Retry(_) => unreachable!(),
+            LongHeader::Initial(b) => b.overwrite_packet_number(packet_number),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => b.packet_number = packet_number,
         }
     }
 
-    pub(super) fn raw_length(&self, packet_number_length: Option<usize>) -> usize {
+    /// Bytes between the version and the packet number.
+    pub(super) fn packet_number_offset(&self) -> usize {
         match self {
-            LongHeader::VersionNegotiation(b) => b.raw_length(),
-            LongHeader::Initial(b) => {
-                b.raw_length(packet_number_length.expect("need packet number length"))
+            LongHeader::VersionNegotiation(_) | LongHeader::Retry(_) => unreachable!(),
+            LongHeader::Initial(b) => b.packet_number_offset(),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => b.packet_number_offset(),
+        }
+    }
+
+    pub(super) fn header_bytes(
+        &self,
+        packet_number_length: usize,
+        payload_length: usize,
+    ) -> Vec<u8> {
+        match self {
+            LongHeader::VersionNegotiation(_) | LongHeader::Retry(_) => unreachable!(),
+            LongHeader::Initial(b) => b.header_bytes(packet_number_length, payload_length),
+            LongHeader::ZeroRTT(b) | LongHeader::Handshake(b) => {
+                b.header_bytes(packet_number_length, payload_length)
             }
         }
     }
 
     pub(crate) fn update_payload(self, payload: PacketPayload) -> Self {
         match self {
-            lh @ LongHeader::VersionNegotiation(_) => lh,
+            lh @ (LongHeader::VersionNegotiation(_) | LongHeader::Retry(_)) => lh,
             LongHeader::Initial(b) => LongHeader::Initial(b.update_payload(payload)),
+            LongHeader::ZeroRTT(b) => LongHeader::ZeroRTT(b.update_payload(payload)),
+            LongHeader::Handshake(b) => LongHeader::Handshake(b.update_payload(payload)),
         }
     }
 
-    pub(crate) fn new_initial(connection: &Connection, endpoint_state: &EndpointState) -> Self {
-        LongHeader::Initial(initial::Body::new(connection, endpoint_state))
+    pub(crate) fn new_initial(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
+        LongHeader::Initial(initial::Body::new(connection, endpoint_state, payload))
+    }
+
+    pub(crate) fn new_handshake(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
+        LongHeader::Handshake(handshake::Body::new(connection, endpoint_state, payload))
+    }
+
+    pub(crate) fn new_zero_rtt(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        payload: PacketPayload,
+    ) -> Self {
+        LongHeader::ZeroRTT(zero_rtt::Body::new(connection, endpoint_state, payload))
     }
 }
 
@@ -103,31 +171,42 @@ impl FromReadBytesWith<&PacketMeta> for LongHeader {
     where
         Self: Sized,
     {
-        if meta.version() == Version(0) {
+        if meta.version() == Some(Version(0)) {
             return Ok(LongHeader::VersionNegotiation(input.read_bytes_to()?));
         }
         Ok(match meta.long_packet_type() {
             PacketType::Initial => LongHeader::Initial(input.read_bytes_to_with(meta)?),
-            _ => unimplemented!(),
+            PacketType::ZeroRTT => LongHeader::ZeroRTT(input.read_bytes_to_with(meta)?),
+            PacketType::Handshake => LongHeader::Handshake(input.read_bytes_to_with(meta)?),
+            PacketType::Retry => LongHeader::Retry(input.read_bytes_to()?),
         })
     }
 }
 
 impl ConnectionIDPair {
-    // TODO: do error handling
-    pub fn read_bytes(input: &mut impl std::io::Read) -> Self {
-        let destination_id_length = input.read_u8().unwrap();
+    pub fn read_bytes(input: &mut impl std::io::Read) -> Result<Self, std::io::Error> {
+        let destination_id_length = input.read_u8()?;
         let mut destination_id = vec![0u8; destination_id_length as usize];
-        input.read_exact(&mut destination_id).unwrap();
+        input.read_exact(&mut destination_id)?;
 
-        let source_id_length = input.read_u8().unwrap();
+        let source_id_length = input.read_u8()?;
         let mut source_id = vec![0u8; source_id_length as usize];
-        input.read_exact(&mut source_id).unwrap();
+        input.read_exact(&mut source_id)?;
 
-        Self {
+        Ok(Self {
             destination_id,
             source_id,
-        }
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            &[self.destination_id.len() as u8][..],
+            &self.destination_id[..],
+            &[self.source_id.len() as u8][..],
+            &self.source_id[..],
+        ]
+        .concat()
     }
 
     pub fn raw_length(&self) -> usize {
@@ -142,7 +221,7 @@ impl FromReadBytesWith<()> for ConnectionIDPair {
     where
         Self: Sized,
     {
-        Ok(Self::read_bytes(input))
+        Self::read_bytes(input)
     }
 }
 
@@ -191,7 +270,7 @@ mod tests {
             .concat(),
         );
 
-        let connection_id_pair = ConnectionIDPair::read_bytes(&mut input);
+        let connection_id_pair = ConnectionIDPair::read_bytes(&mut input).unwrap();
         assert_eq!(connection_id_pair.destination_id, &destination_id);
         assert_eq!(connection_id_pair.source_id, &source_id);
     }
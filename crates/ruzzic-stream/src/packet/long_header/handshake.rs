@@ -0,0 +1,154 @@
+use std::io::{Cursor, Read};
+
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+
+use super::ConnectionIDPair;
+use crate::{
+    connection::{Connection, ConnectionID},
+    endpoint_state::EndpointState,
+    frame, length_to_varint,
+    packet::{packet_meta::PacketMeta, PacketNumber, PacketPayload},
+    read_varint,
+};
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-handshake-packet
+/// 0-RTT packets have the same layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Body {
+    pub connection_id_pair: ConnectionIDPair,
+    pub packet_number: PacketNumber,
+    pub packet_payload: PacketPayload,
+    /// Bytes before the packet number as received, since a peer may encode the Length in more bytes than needed.
+    pub packet_number_offset: usize,
+}
+
+impl FromReadBytesWith<&PacketMeta> for Body {
+    fn from_read_bytes_with<R: Read>(
+        input: &mut R,
+        meta: &PacketMeta,
+    ) -> Result<Self, std::io::Error>
+    where
+        Self: Sized,
+    {
+        let connection_id_pair: ConnectionIDPair = input.read_bytes_to()?;
+        let remainder_length = read_varint(input)?;
+        let packet_number_offset = connection_id_pair.raw_length() + remainder_length.byte_size();
+        let remainder = frame::read_exact_length(input, remainder_length.to_u64())?;
+        let mut remainder_input = Cursor::new(remainder);
+        let packet_number =
+            PacketNumber::read_bytes_to(&mut remainder_input, meta.packet_number_length())?;
+        let packet_payload = remainder_input.read_bytes_to()?;
+        Ok(Self {
+            connection_id_pair,
+            packet_number,
+            packet_payload,
+            packet_number_offset,
+        })
+    }
+}
+
+impl Body {
+    pub(super) fn payload(&self) -> &[u8] {
+        &self.packet_payload.0
+    }
+
+    pub(super) fn destination_connection_id(&self) -> Box<ConnectionID> {
+        Box::new(ConnectionID(
+            self.connection_id_pair.destination_id.to_vec(),
+        ))
+    }
+
+    pub(super) fn source_connection_id(&self) -> Box<ConnectionID> {
+        Box::new(ConnectionID(self.connection_id_pair.source_id.to_vec()))
+    }
+
+    pub(super) fn packet_number(&self) -> PacketNumber {
+        self.packet_number
+    }
+
+    pub(super) fn packet_number_offset(&self) -> usize {
+        self.packet_number_offset
+    }
+
+    pub(super) fn header_bytes(
+        &self,
+        packet_number_length: usize,
+        payload_length: usize,
+    ) -> Vec<u8> {
+        [
+            self.connection_id_pair.to_bytes(),
+            length_to_varint((packet_number_length + payload_length) as u64).to_bytes(),
+            self.packet_number.to_bytes(packet_number_length),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn update_payload(self, payload: PacketPayload) -> Self {
+        Self {
+            connection_id_pair: self.connection_id_pair,
+            packet_number: self.packet_number,
+            packet_payload: payload,
+            packet_number_offset: self.packet_number_offset,
+        }
+    }
+
+    pub(crate) fn new(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        packet_payload: PacketPayload,
+    ) -> Self {
+        let connection_id_pair = ConnectionIDPair {
+            destination_id: connection.destination_connection_id().to_vec(),
+            source_id: connection.source_connection_id().to_vec(),
+        };
+        let packet_number = *endpoint_state.next_packet_number();
+
+        Self {
+            connection_id_pair,
+            packet_number,
+            packet_payload,
+            // known once the packet is serialized and read back
+            packet_number_offset: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{packet::packet_meta::FirstByte, Version};
+    use bitvec::prelude::*;
+    use ruzzic_common::read_bytes_to::ReadBytesToWith;
+    use std::io::Cursor;
+
+    #[test]
+    fn handshake_packet() {
+        let buf = [
+            1, 0x01, // destination connection id
+            2, 0x02, 0x11, // source connection id
+            0x40, 3, // length
+            0x00, 0x01, // packet number
+            0x00, // packet payload
+        ];
+        let mut input = Cursor::new(buf);
+
+        let mut first_byte = bitarr![Msb0, u8; 0; 8];
+        first_byte.store(0b11100001u8);
+        let meta = PacketMeta {
+            first_byte: FirstByte(first_byte),
+            version: Some(Version(1)),
+        };
+        let actual: Body = input.read_bytes_to_with(&meta).unwrap();
+        let expected = Body {
+            connection_id_pair: ConnectionIDPair {
+                destination_id: vec![0x01],
+                source_id: vec![0x02, 0x11],
+            },
+            packet_number: PacketNumber(0x01),
+            packet_payload: PacketPayload(vec![0x00]),
+            packet_number_offset: 7,
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(actual.header_bytes(2, 1), buf[..9]);
+    }
+}
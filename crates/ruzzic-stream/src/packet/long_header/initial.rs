@@ -6,7 +6,8 @@ use super::ConnectionIDPair;
 use crate::{
     connection::{Connection, ConnectionID},
     endpoint_state::EndpointState,
-    packet::{self, packet_meta::PacketMeta, PacketData, PacketNumber, PacketPayload},
+    frame, length_to_varint,
+    packet::{self, packet_meta::PacketMeta, PacketNumber, PacketPayload},
     read_varint, Token,
 };
 
@@ -16,6 +17,9 @@ pub struct Body {
     pub token: Token,
     pub packet_number: PacketNumber,
     pub packet_payload: PacketPayload,
+    /// Bytes before the packet number as received, since a peer may encode the lengths in more bytes than needed.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-16
+    pub packet_number_offset: usize,
 }
 
 impl FromReadBytesWith<&PacketMeta> for Body {
@@ -26,20 +30,25 @@ impl FromReadBytesWith<&PacketMeta> for Body {
     where
         Self: Sized,
     {
-        let connection_id_pair = input.read_bytes_to()?;
-        let token = input.read_bytes_to()?;
-        let remainder_length = read_varint(input).unwrap().to_u64();
-        let mut remainder = vec![0u8; remainder_length as usize];
-        input.read_exact(&mut remainder)?;
+        let connection_id_pair: ConnectionIDPair = input.read_bytes_to()?;
+        let token_length = read_varint(input)?;
+        let token = Token(frame::read_exact_length(input, token_length.to_u64())?);
+        let remainder_length = read_varint(input)?;
+        let packet_number_offset = connection_id_pair.raw_length()
+            + token_length.byte_size()
+            + token.0.len()
+            + remainder_length.byte_size();
+        let remainder = frame::read_exact_length(input, remainder_length.to_u64())?;
         let mut remainder_input = Cursor::new(remainder);
         let packet_number =
-            PacketNumber::read_bytes_to(&mut remainder_input, meta.packet_number_length()).unwrap();
-        let packet_payload = remainder_input.read_bytes_to().unwrap();
+            PacketNumber::read_bytes_to(&mut remainder_input, meta.packet_number_length())?;
+        let packet_payload = remainder_input.read_bytes_to()?;
         Ok(Self {
             connection_id_pair,
             token,
             packet_number,
             packet_payload,
+            packet_number_offset,
         })
     }
 }
@@ -59,27 +68,30 @@ impl Body {
         Box::new(ConnectionID(self.connection_id_pair.source_id.to_vec()))
     }
 
-    pub(crate) fn token_raw_length(&self) -> usize {
-        self.token.raw_length()
+    pub(super) fn packet_number(&self) -> PacketNumber {
+        self.packet_number
     }
 
-    pub(super) fn packet_number(&self) -> PacketNumber {
-        self.packet_number.clone()
+    pub(super) fn packet_number_offset(&self) -> usize {
+        self.packet_number_offset
     }
 
-    pub(super) fn overwrite_packet_number(&mut self, packet_number: PacketNumber) {
-        self.packet_number = packet_number;
+    pub(super) fn header_bytes(
+        &self,
+        packet_number_length: usize,
+        payload_length: usize,
+    ) -> Vec<u8> {
+        [
+            self.connection_id_pair.to_bytes(),
+            self.token.to_bytes(),
+            length_to_varint((packet_number_length + payload_length) as u64).to_bytes(),
+            self.packet_number.to_bytes(packet_number_length),
+        ]
+        .concat()
     }
 
-    pub(super) fn raw_length(&self, packet_number_length: usize) -> usize {
-        let connection_id_pair_length = self.connection_id_pair.raw_length();
-        let token_length = self.token.raw_length();
-        let packet_data_length = PacketData {
-            packet_number: &self.packet_number,
-            packet_payload: &self.packet_payload,
-        }
-        .raw_length(packet_number_length);
-        connection_id_pair_length + token_length + packet_data_length
+    pub(super) fn overwrite_packet_number(&mut self, packet_number: PacketNumber) {
+        self.packet_number = packet_number;
     }
 
     pub(crate) fn update_payload(self, payload: PacketPayload) -> Self {
@@ -88,23 +100,29 @@ impl Body {
             token: self.token,
             packet_number: self.packet_number,
             packet_payload: payload,
+            packet_number_offset: self.packet_number_offset,
         }
     }
 
-    pub(crate) fn new(connection: &Connection, endpoint_state: &EndpointState) -> Self {
+    pub(crate) fn new(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        packet_payload: PacketPayload,
+    ) -> Self {
         let connection_id_pair = ConnectionIDPair {
             destination_id: connection.destination_connection_id().to_vec(),
             source_id: connection.source_connection_id().to_vec(),
         };
         let token = connection.token().clone();
-        let packet_number = endpoint_state.next_packet_number().clone();
-        let packet_payload = connection.next_packet_payload();
+        let packet_number = *endpoint_state.next_packet_number();
 
         Self {
             connection_id_pair,
             token,
             packet_number,
             packet_payload,
+            // known once the packet is serialized and read back
+            packet_number_offset: 0,
         }
     }
 }
@@ -154,7 +172,7 @@ mod tests {
         let actual: Body = input
             .read_bytes_to_with(&PacketMeta {
                 first_byte: FirstByte(first_byte),
-                version: Version(1),
+                version: Some(Version(1)),
             })
             .unwrap();
         let expected = Body {
@@ -165,6 +183,7 @@ mod tests {
             token: Token(vec![0x41]),
             packet_number: PacketNumber(0x01),
             packet_payload: PacketPayload(vec![0x00]),
+            packet_number_offset: 8,
         };
         assert_eq!(actual, expected);
     }
@@ -0,0 +1,115 @@
+use std::io::Read;
+
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+
+use super::ConnectionIDPair;
+use crate::{connection::ConnectionID, Token};
+
+pub(crate) const RETRY_INTEGRITY_TAG_LENGTH: usize = 16;
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-retry-packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Body {
+    pub connection_id_pair: ConnectionIDPair,
+    /// The Retry Token does not have its length, it takes up the rest of the packet except for the tag.
+    pub retry_token: Token,
+    pub retry_integrity_tag: Vec<u8>,
+}
+
+impl FromReadBytesWith<()> for Body {
+    fn from_read_bytes_with<R: Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+    where
+        Self: Sized,
+    {
+        let connection_id_pair = input.read_bytes_to()?;
+        let mut remainder = Vec::new();
+        input.read_to_end(&mut remainder)?;
+        if remainder.len() < RETRY_INTEGRITY_TAG_LENGTH {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "retry packet is too short to have a retry integrity tag",
+            ));
+        }
+        let retry_integrity_tag = remainder.split_off(remainder.len() - RETRY_INTEGRITY_TAG_LENGTH);
+        Ok(Self {
+            connection_id_pair,
+            retry_token: Token(remainder),
+            retry_integrity_tag,
+        })
+    }
+}
+
+impl Body {
+    pub(super) fn payload(&self) -> &[u8] {
+        &[]
+    }
+
+    pub(super) fn destination_connection_id(&self) -> Box<ConnectionID> {
+        Box::new(ConnectionID(self.connection_id_pair.destination_id.clone()))
+    }
+
+    pub(super) fn source_connection_id(&self) -> Box<ConnectionID> {
+        Box::new(ConnectionID(self.connection_id_pair.source_id.clone()))
+    }
+
+    /// Bytes after the version except for the Retry Integrity Tag.
+    pub(crate) fn bytes_without_tag(&self) -> Vec<u8> {
+        [
+            self.connection_id_pair.to_bytes(),
+            self.retry_token.0.clone(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn new(
+        destination_connection_id: &ConnectionID,
+        source_connection_id: &ConnectionID,
+        retry_token: Token,
+    ) -> Self {
+        Self {
+            connection_id_pair: ConnectionIDPair {
+                destination_id: destination_connection_id.to_vec(),
+                source_id: source_connection_id.to_vec(),
+            },
+            retry_token,
+            // computed over the whole packet after it is built
+            retry_integrity_tag: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn retry_packet() {
+        let buf = [
+            &[0, 1, 0x02][..], // connection id pair
+            b"token",
+            &[0x0f; RETRY_INTEGRITY_TAG_LENGTH],
+        ]
+        .concat();
+        let mut input = Cursor::new(buf.clone());
+
+        let actual: Body = input.read_bytes_to().unwrap();
+        let expected = Body {
+            connection_id_pair: ConnectionIDPair {
+                destination_id: vec![],
+                source_id: vec![0x02],
+            },
+            retry_token: Token(b"token".to_vec()),
+            retry_integrity_tag: vec![0x0f; RETRY_INTEGRITY_TAG_LENGTH],
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(
+            actual.bytes_without_tag(),
+            buf[..buf.len() - RETRY_INTEGRITY_TAG_LENGTH]
+        );
+
+        let mut input = Cursor::new([0, 0, 0x0f]);
+        let result: Result<Body, _> = input.read_bytes_to();
+        assert!(result.is_err());
+    }
+}
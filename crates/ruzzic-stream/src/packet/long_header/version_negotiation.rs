@@ -37,15 +37,6 @@ impl Body {
     pub(super) fn source_connection_id(&self) -> Box<ConnectionID> {
         Box::new(ConnectionID(self.connection_id_pair.source_id.clone()))
     }
-
-    pub(super) fn raw_length(&self) -> usize {
-        self.connection_id_pair.raw_length()
-            + self
-                .supported_versions
-                .0
-                .iter()
-                .fold(0, |acc, v| acc + v.raw_length())
-    }
 }
 
 #[cfg(test)]
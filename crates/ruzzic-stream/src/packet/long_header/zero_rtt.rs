@@ -0,0 +1,3 @@
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-0-rtt
+/// A 0-RTT packet differs from a Handshake packet only in its long packet type.
+pub type Body = super::handshake::Body;
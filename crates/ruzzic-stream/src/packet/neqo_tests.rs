@@ -1,9 +1,25 @@
-use crate::frame::Frames;
+use crate::frame::{Frame, Frames};
+use ruzzic_tls::handshake::{Handshake, HandshakeType};
 
 use super::*;
 
 use std::io::Cursor;
 
+/// Decode the TLS message in the CRYPTO frame and encode it again.
+fn assert_handshake_roundtrip(frames: &Frames, handshake_type: HandshakeType) {
+    let crypto_data = frames
+        .frames()
+        .iter()
+        .find_map(|frame| match frame {
+            Frame::Crypto(body) => Some(body.crypto_data()),
+            _ => None,
+        })
+        .unwrap();
+    let handshake: Handshake = Cursor::new(crypto_data).read_bytes_to().unwrap();
+    assert_eq!(handshake.handshake_type(), handshake_type);
+    assert_eq!(handshake.to_bytes(), crypto_data);
+}
+
 #[test]
 fn neqo_client_initial_packet() {
     const NEQO_CLIENT_INITIAL_PACKET: &[u8] = &[
@@ -40,6 +56,7 @@ fn neqo_client_initial_packet() {
     let mut payload_input = Cursor::new(initial_packet.payload());
     let frames: Frames = payload_input.read_bytes_to().unwrap();
     eprintln!("{frames:x?}");
+    assert_handshake_roundtrip(&frames, HandshakeType::ClientHello);
 }
 
 #[test]
@@ -63,6 +80,7 @@ fn neqo_server_initial_packet() {
     let mut payload_input = Cursor::new(initial_packet.payload());
     let frames: Frames = payload_input.read_bytes_to().unwrap();
     eprintln!("{frames:x?}");
+    assert_handshake_roundtrip(&frames, HandshakeType::ServerHello);
 }
 
 #[test]
@@ -81,7 +99,8 @@ fn neqo_nazo_packet() {
     let mut input = Cursor::new(neqo_nazo_packet);
     let nazo_packet: Packet = input.read_bytes_to().unwrap();
     let mut payload = Cursor::new(nazo_packet.payload());
-    let frames: Frames = payload.read_bytes_to().unwrap();
     eprintln!("{nazo_packet:?}");
-    eprintln!("{frames:?}")
+    // the payload is still protected, so it must not be taken as frames
+    let frames: Result<Frames, _> = payload.read_bytes_to();
+    assert!(frames.is_err());
 }
@@ -8,7 +8,8 @@ use super::{long_header, PacketBodyType};
 #[derive(Debug, Clone, PartialEq)]
 pub struct PacketMeta {
     pub(crate) first_byte: FirstByte,
-    pub(crate) version: Version,
+    /// Short header packets do not have a version.
+    pub(crate) version: Option<Version>,
 }
 
 impl FromReadBytesWith<()> for PacketMeta {
@@ -16,8 +17,12 @@ impl FromReadBytesWith<()> for PacketMeta {
     where
         Self: Sized,
     {
-        let first_byte = input.read_bytes_to()?;
-        let version = input.read_bytes_to()?;
+        let first_byte: FirstByte = input.read_bytes_to()?;
+        let version = if first_byte.is_long() {
+            Some(input.read_bytes_to()?)
+        } else {
+            None
+        };
         Ok(Self {
             first_byte,
             version,
@@ -64,6 +69,15 @@ impl FirstByte {
         }
     }
 
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-latency-spin-bit
+    pub fn spin_bit(&self) -> bool {
+        self.0[2]
+    }
+
+    pub fn key_phase(&self) -> bool {
+        self.0[5]
+    }
+
     fn get_type(&self) -> PacketBodyType {
         if self.is_long() {
             PacketBodyType::Long
@@ -80,18 +94,51 @@ impl FirstByte {
         1
     }
 
-    fn new_initial(endpoint_state: &EndpointState) -> Self {
-        let packet_number_length = endpoint_state.next_packet_number().length_in_header();
+    fn to_u8(&self) -> u8 {
+        self.0.load::<u8>()
+    }
 
+    fn new_long(packet_type: &long_header::PacketType, packet_number_length: u8) -> Self {
         let mut first_byte = bitarr![Msb0, u8;
         1, // is long header
         1, // fixed bit
-        0, 0, // long packet type: 0x00(initial)
+        0, 0, // long packet type (fill it after soon)
+        0, 0, // reserved bits
+        0, 0,// packet number length (fill it after soon)
+        ];
+        first_byte[2..4].store(packet_type.to_u8());
+        first_byte[8 /* one byte bits-length */ - 2 /* size of packet number length */..]
+            .store(packet_number_length - 1);
+
+        Self(first_byte)
+    }
+
+    fn new_retry() -> Self {
+        let first_byte = bitarr![Msb0, u8;
+        1, // is long header
+        1, // fixed bit
+        1, 1, // long packet type: 0x03(retry)
+        1, 1, 1, 1, // unused, any value can be set
+        ];
+
+        Self(first_byte)
+    }
+
+    fn new_short(endpoint_state: &EndpointState, spin_bit: bool, key_phase: bool) -> Self {
+        let packet_number_length = endpoint_state.packet_number_length() as u8;
+
+        let mut first_byte = bitarr![Msb0, u8;
+        0, // is short header
+        1, // fixed bit
+        0, // spin bit (fill it after soon)
         0, 0, // reserved bits
+        0, // key phase (fill it after soon)
         0, 0,// packet number length (fill it after soon)
         ];
+        first_byte.set(2, spin_bit);
+        first_byte.set(5, key_phase);
         first_byte[8 /* one byte bits-length */ - 2 /* size of packet number length */..]
-            .store(packet_number_length);
+            .store(packet_number_length - 1);
 
         Self(first_byte)
     }
@@ -106,7 +153,7 @@ impl PacketMeta {
         self.first_byte.long_packet_type()
     }
 
-    pub(crate) fn version(&self) -> Version {
+    pub(crate) fn version(&self) -> Option<Version> {
         self.version
     }
 
@@ -115,14 +162,51 @@ impl PacketMeta {
     }
 
     pub(crate) fn raw_length(&self) -> usize {
-        self.first_byte.raw_length() + self.version.raw_length()
+        self.first_byte.raw_length() + self.version.map_or(0, |v| v.raw_length())
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        [
+            vec![self.first_byte.to_u8()],
+            self.version.map_or(Vec::new(), |v| v.to_bytes().to_vec()),
+        ]
+        .concat()
     }
 
     pub(crate) fn new_initial(connection: &Connection, endpoint_state: &EndpointState) -> Self {
-        let first_byte = FirstByte::new_initial(endpoint_state);
+        Self::new_long(long_header::PacketType::Initial, connection, endpoint_state)
+    }
+
+    pub(crate) fn new_long(
+        packet_type: long_header::PacketType,
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+    ) -> Self {
+        let packet_number_length = endpoint_state.packet_number_length() as u8;
+        let first_byte = FirstByte::new_long(&packet_type, packet_number_length);
+        Self {
+            first_byte,
+            version: Some(*connection.version()),
+        }
+    }
+
+    pub(crate) fn new_retry(connection: &Connection) -> Self {
+        let first_byte = FirstByte::new_retry();
+        Self {
+            first_byte,
+            version: Some(*connection.version()),
+        }
+    }
+
+    pub(crate) fn new_short(
+        endpoint_state: &EndpointState,
+        spin_bit: bool,
+        key_phase: bool,
+    ) -> Self {
+        let first_byte = FirstByte::new_short(endpoint_state, spin_bit, key_phase);
         Self {
             first_byte,
-            version: connection.version().clone(),
+            version: None,
         }
     }
 }
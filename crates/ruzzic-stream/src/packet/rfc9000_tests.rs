@@ -1,114 +1,698 @@
-/*
 use super::*;
+use crate::connection::EarlyDataStatus;
+use crate::crypto::{cipher_suite::TLS_CHACHA20_POLY1305_SHA256, EncryptionLevel, KeySet};
+use crate::frame::{stream, Frame, Frames};
+use crate::stream::StreamID;
+use crate::transport_parameters::{TransportParameterError, TransportParameters};
+use ruzzic_common::EndpointType;
+use ruzzic_tls::handshake::{Handshake, HandshakeType};
 
-use std::io::Cursor;
+use std::{io::Cursor, time::Instant};
+
+// RFC 9001 Appendix A.2
+const PROTECTED_CLIENT_INITIAL_PACKET: &[u8] = &[
+    192, 0, 0, 0, 1, 8, 131, 148, 200, 240, 62, 81, 87, 8, 0, 0, 68, 158, 123, 154, 236, 52, 209,
+    177, 201, 141, 215, 104, 159, 184, 236, 17, 210, 66, 177, 35, 220, 155, 216, 186, 185, 54, 180,
+    125, 146, 236, 53, 108, 11, 171, 125, 245, 151, 109, 39, 205, 68, 159, 99, 48, 0, 153, 243,
+    153, 28, 38, 14, 196, 198, 13, 23, 179, 31, 132, 41, 21, 123, 179, 90, 18, 130, 166, 67, 168,
+    210, 38, 44, 173, 103, 80, 12, 173, 184, 231, 55, 140, 142, 183, 83, 158, 196, 212, 144, 95,
+    237, 27, 238, 31, 200, 170, 251, 161, 124, 117, 14, 44, 122, 206, 1, 230, 0, 95, 128, 252, 183,
+    223, 98, 18, 48, 200, 55, 17, 179, 147, 67, 250, 2, 140, 234, 127, 127, 181, 255, 137, 234,
+    194, 48, 130, 73, 160, 34, 82, 21, 94, 35, 71, 182, 61, 88, 197, 69, 122, 253, 132, 208, 93,
+    255, 253, 178, 3, 146, 132, 74, 232, 18, 21, 70, 130, 233, 207, 1, 47, 144, 33, 166, 240, 190,
+    23, 221, 208, 194, 8, 77, 206, 37, 255, 155, 6, 205, 229, 53, 208, 249, 32, 162, 219, 27, 243,
+    98, 194, 62, 89, 109, 17, 164, 245, 166, 207, 57, 72, 131, 138, 58, 236, 78, 21, 218, 248, 80,
+    10, 110, 246, 158, 196, 227, 254, 182, 177, 217, 142, 97, 10, 200, 183, 236, 63, 175, 106, 215,
+    96, 183, 186, 209, 219, 75, 163, 72, 94, 138, 148, 220, 37, 10, 227, 253, 180, 30, 209, 95,
+    182, 168, 229, 235, 160, 252, 61, 214, 11, 200, 227, 12, 92, 66, 135, 229, 56, 5, 219, 5, 154,
+    224, 100, 141, 178, 246, 66, 100, 237, 94, 57, 190, 46, 32, 216, 45, 245, 102, 218, 141, 213,
+    153, 140, 202, 189, 174, 5, 48, 96, 174, 108, 123, 67, 120, 232, 70, 210, 159, 55, 237, 123,
+    78, 169, 236, 93, 130, 231, 150, 27, 127, 37, 169, 50, 56, 81, 246, 129, 213, 130, 54, 58, 165,
+    248, 153, 55, 245, 166, 114, 88, 191, 99, 173, 111, 26, 11, 29, 150, 219, 212, 250, 221, 252,
+    239, 197, 38, 107, 166, 97, 23, 34, 57, 92, 144, 101, 86, 190, 82, 175, 227, 245, 101, 99, 106,
+    209, 177, 125, 80, 139, 115, 216, 116, 62, 235, 82, 75, 226, 43, 61, 203, 194, 199, 70, 141,
+    84, 17, 156, 116, 104, 68, 154, 19, 216, 227, 185, 88, 17, 161, 152, 243, 73, 29, 227, 231,
+    254, 148, 43, 51, 4, 7, 171, 248, 42, 78, 215, 193, 179, 17, 102, 58, 198, 152, 144, 244, 21,
+    112, 21, 133, 61, 145, 233, 35, 3, 124, 34, 122, 51, 205, 213, 236, 40, 28, 163, 247, 156, 68,
+    84, 107, 157, 144, 202, 0, 240, 100, 201, 158, 61, 217, 121, 17, 211, 159, 233, 197, 208, 178,
+    58, 34, 154, 35, 76, 179, 97, 134, 196, 129, 158, 139, 156, 89, 39, 114, 102, 50, 41, 29, 106,
+    65, 130, 17, 204, 41, 98, 226, 15, 228, 127, 235, 62, 223, 51, 15, 44, 96, 58, 157, 72, 192,
+    252, 181, 105, 157, 191, 229, 137, 100, 37, 197, 186, 196, 174, 232, 46, 87, 168, 90, 175, 78,
+    37, 19, 228, 240, 87, 150, 176, 123, 162, 238, 71, 216, 5, 6, 248, 210, 194, 94, 80, 253, 20,
+    222, 113, 230, 196, 24, 85, 147, 2, 249, 57, 176, 225, 171, 213, 118, 242, 121, 196, 178, 224,
+    254, 184, 92, 31, 40, 255, 24, 245, 136, 145, 255, 239, 19, 46, 239, 47, 160, 147, 70, 174,
+    227, 60, 40, 235, 19, 15, 242, 143, 91, 118, 105, 83, 51, 65, 19, 33, 25, 150, 210, 0, 17, 161,
+    152, 227, 252, 67, 63, 159, 37, 65, 1, 10, 225, 124, 27, 242, 2, 88, 15, 96, 71, 71, 47, 179,
+    104, 87, 254, 132, 59, 25, 245, 152, 64, 9, 221, 195, 36, 4, 78, 132, 122, 79, 74, 10, 179, 79,
+    113, 149, 149, 222, 55, 37, 45, 98, 53, 54, 94, 155, 132, 57, 43, 6, 16, 133, 52, 157, 115, 32,
+    58, 74, 19, 233, 111, 84, 50, 236, 15, 212, 161, 238, 101, 172, 205, 213, 227, 144, 77, 245,
+    76, 29, 165, 16, 176, 255, 32, 220, 192, 199, 127, 203, 44, 14, 14, 182, 5, 203, 5, 4, 219,
+    135, 99, 44, 243, 216, 180, 218, 230, 231, 5, 118, 157, 29, 227, 84, 39, 1, 35, 203, 17, 69,
+    14, 252, 96, 172, 71, 104, 61, 123, 141, 15, 129, 19, 101, 86, 95, 217, 140, 76, 142, 185, 54,
+    188, 171, 141, 6, 159, 195, 59, 216, 1, 176, 58, 222, 162, 225, 251, 197, 170, 70, 61, 8, 202,
+    25, 137, 109, 43, 245, 154, 7, 27, 133, 30, 108, 35, 144, 82, 23, 47, 41, 107, 251, 94, 114,
+    64, 71, 144, 162, 24, 16, 20, 243, 185, 74, 78, 151, 209, 23, 180, 56, 19, 3, 104, 204, 57,
+    219, 178, 209, 152, 6, 90, 227, 152, 101, 71, 146, 108, 210, 22, 47, 64, 162, 159, 12, 60, 135,
+    69, 192, 245, 15, 186, 56, 82, 229, 102, 212, 69, 117, 194, 157, 57, 160, 63, 12, 218, 114, 25,
+    132, 182, 244, 64, 89, 31, 53, 94, 18, 212, 57, 255, 21, 10, 171, 118, 19, 73, 157, 189, 73,
+    173, 171, 200, 103, 110, 239, 2, 59, 21, 182, 91, 252, 92, 160, 105, 72, 16, 159, 35, 243, 80,
+    219, 130, 18, 53, 53, 235, 138, 116, 51, 189, 171, 203, 144, 146, 113, 166, 236, 188, 181, 139,
+    147, 106, 136, 205, 78, 143, 46, 111, 245, 128, 1, 117, 241, 19, 37, 61, 143, 169, 202, 136,
+    133, 194, 245, 82, 230, 87, 220, 96, 63, 37, 46, 26, 142, 48, 143, 118, 240, 190, 121, 226,
+    251, 143, 93, 95, 187, 226, 227, 14, 202, 221, 34, 7, 35, 200, 192, 174, 168, 7, 140, 223, 203,
+    56, 104, 38, 63, 248, 240, 148, 0, 84, 218, 72, 120, 24, 147, 167, 228, 154, 213, 175, 244,
+    175, 48, 12, 216, 4, 166, 182, 39, 154, 179, 255, 58, 251, 100, 73, 28, 133, 25, 74, 171, 118,
+    13, 88, 166, 6, 101, 79, 159, 68, 0, 232, 179, 133, 145, 53, 111, 191, 100, 37, 172, 162, 109,
+    200, 82, 68, 37, 159, 242, 177, 156, 65, 185, 249, 111, 60, 169, 236, 29, 222, 67, 77, 167,
+    210, 211, 146, 185, 5, 221, 243, 209, 249, 175, 147, 209, 175, 89, 80, 189, 73, 63, 90, 167,
+    49, 180, 5, 109, 243, 27, 210, 103, 182, 185, 10, 7, 152, 49, 170, 245, 121, 190, 10, 57, 1,
+    49, 55, 170, 198, 212, 4, 245, 24, 207, 212, 104, 64, 100, 126, 120, 191, 231, 6, 202, 76, 245,
+    233, 197, 69, 62, 159, 124, 253, 43, 139, 76, 141, 22, 154, 68, 229, 92, 136, 212, 169, 167,
+    249, 71, 66, 65, 226, 33, 175, 68, 134, 0, 24, 171, 8, 86, 151, 46, 25, 76, 217, 52,
+];
+
+// RFC 9001 Appendix A.3
+const PROTECTED_SERVER_INITIAL_PACKET: &[u8] = &[
+    207, 0, 0, 0, 1, 0, 8, 240, 103, 165, 80, 42, 66, 98, 181, 0, 64, 117, 192, 217, 90, 72, 44,
+    208, 153, 28, 210, 91, 10, 172, 64, 106, 88, 22, 182, 57, 65, 0, 243, 122, 28, 105, 121, 117,
+    84, 120, 11, 179, 140, 197, 169, 159, 94, 222, 76, 247, 60, 62, 194, 73, 58, 24, 57, 179, 219,
+    203, 163, 246, 234, 70, 197, 183, 104, 77, 243, 84, 142, 125, 222, 185, 195, 191, 156, 115,
+    204, 63, 59, 222, 215, 75, 86, 43, 251, 25, 251, 132, 2, 47, 142, 244, 205, 217, 55, 149, 215,
+    125, 6, 237, 187, 122, 175, 47, 88, 137, 24, 80, 171, 189, 202, 61, 32, 57, 140, 39, 100, 86,
+    203, 196, 33, 88, 64, 125, 208, 116, 238,
+];
+
+const ORIGINAL_DESTINATION_CONNECTION_ID: &[u8] = &[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x8];
 
 #[test]
 fn protected_client_initial_packet() {
-    env_logger::init();
-    const PROTECTED_CLIENT_INITIAL_PACKET: &[u8] = &[
-        192, 0, 0, 0, 1, 8, 131, 148, 200, 240, 62, 81, 87, 8, 0, 0, 68, 158, 123, 154, 236, 52,
-        209, 177, 201, 141, 215, 104, 159, 184, 236, 17, 210, 66, 177, 35, 220, 155, 216, 186, 185,
-        54, 180, 125, 146, 236, 53, 108, 11, 171, 125, 245, 151, 109, 39, 205, 68, 159, 99, 48, 0,
-        153, 243, 153, 28, 38, 14, 196, 198, 13, 23, 179, 31, 132, 41, 21, 123, 179, 90, 18, 130,
-        166, 67, 168, 210, 38, 44, 173, 103, 80, 12, 173, 184, 231, 55, 140, 142, 183, 83, 158,
-        196, 212, 144, 95, 237, 27, 238, 31, 200, 170, 251, 161, 124, 117, 14, 44, 122, 206, 1,
-        230, 0, 95, 128, 252, 183, 223, 98, 18, 48, 200, 55, 17, 179, 147, 67, 250, 2, 140, 234,
-        127, 127, 181, 255, 137, 234, 194, 48, 130, 73, 160, 34, 82, 21, 94, 35, 71, 182, 61, 88,
-        197, 69, 122, 253, 132, 208, 93, 255, 253, 178, 3, 146, 132, 74, 232, 18, 21, 70, 130, 233,
-        207, 1, 47, 144, 33, 166, 240, 190, 23, 221, 208, 194, 8, 77, 206, 37, 255, 155, 6, 205,
-        229, 53, 208, 249, 32, 162, 219, 27, 243, 98, 194, 62, 89, 109, 17, 164, 245, 166, 207, 57,
-        72, 131, 138, 58, 236, 78, 21, 218, 248, 80, 10, 110, 246, 158, 196, 227, 254, 182, 177,
-        217, 142, 97, 10, 200, 183, 236, 63, 175, 106, 215, 96, 183, 186, 209, 219, 75, 163, 72,
-        94, 138, 148, 220, 37, 10, 227, 253, 180, 30, 209, 95, 182, 168, 229, 235, 160, 252, 61,
-        214, 11, 200, 227, 12, 92, 66, 135, 229, 56, 5, 219, 5, 154, 224, 100, 141, 178, 246, 66,
-        100, 237, 94, 57, 190, 46, 32, 216, 45, 245, 102, 218, 141, 213, 153, 140, 202, 189, 174,
-        5, 48, 96, 174, 108, 123, 67, 120, 232, 70, 210, 159, 55, 237, 123, 78, 169, 236, 93, 130,
-        231, 150, 27, 127, 37, 169, 50, 56, 81, 246, 129, 213, 130, 54, 58, 165, 248, 153, 55, 245,
-        166, 114, 88, 191, 99, 173, 111, 26, 11, 29, 150, 219, 212, 250, 221, 252, 239, 197, 38,
-        107, 166, 97, 23, 34, 57, 92, 144, 101, 86, 190, 82, 175, 227, 245, 101, 99, 106, 209, 177,
-        125, 80, 139, 115, 216, 116, 62, 235, 82, 75, 226, 43, 61, 203, 194, 199, 70, 141, 84, 17,
-        156, 116, 104, 68, 154, 19, 216, 227, 185, 88, 17, 161, 152, 243, 73, 29, 227, 231, 254,
-        148, 43, 51, 4, 7, 171, 248, 42, 78, 215, 193, 179, 17, 102, 58, 198, 152, 144, 244, 21,
-        112, 21, 133, 61, 145, 233, 35, 3, 124, 34, 122, 51, 205, 213, 236, 40, 28, 163, 247, 156,
-        68, 84, 107, 157, 144, 202, 0, 240, 100, 201, 158, 61, 217, 121, 17, 211, 159, 233, 197,
-        208, 178, 58, 34, 154, 35, 76, 179, 97, 134, 196, 129, 158, 139, 156, 89, 39, 114, 102, 50,
-        41, 29, 106, 65, 130, 17, 204, 41, 98, 226, 15, 228, 127, 235, 62, 223, 51, 15, 44, 96, 58,
-        157, 72, 192, 252, 181, 105, 157, 191, 229, 137, 100, 37, 197, 186, 196, 174, 232, 46, 87,
-        168, 90, 175, 78, 37, 19, 228, 240, 87, 150, 176, 123, 162, 238, 71, 216, 5, 6, 248, 210,
-        194, 94, 80, 253, 20, 222, 113, 230, 196, 24, 85, 147, 2, 249, 57, 176, 225, 171, 213, 118,
-        242, 121, 196, 178, 224, 254, 184, 92, 31, 40, 255, 24, 245, 136, 145, 255, 239, 19, 46,
-        239, 47, 160, 147, 70, 174, 227, 60, 40, 235, 19, 15, 242, 143, 91, 118, 105, 83, 51, 65,
-        19, 33, 25, 150, 210, 0, 17, 161, 152, 227, 252, 67, 63, 159, 37, 65, 1, 10, 225, 124, 27,
-        242, 2, 88, 15, 96, 71, 71, 47, 179, 104, 87, 254, 132, 59, 25, 245, 152, 64, 9, 221, 195,
-        36, 4, 78, 132, 122, 79, 74, 10, 179, 79, 113, 149, 149, 222, 55, 37, 45, 98, 53, 54, 94,
-        155, 132, 57, 43, 6, 16, 133, 52, 157, 115, 32, 58, 74, 19, 233, 111, 84, 50, 236, 15, 212,
-        161, 238, 101, 172, 205, 213, 227, 144, 77, 245, 76, 29, 165, 16, 176, 255, 32, 220, 192,
-        199, 127, 203, 44, 14, 14, 182, 5, 203, 5, 4, 219, 135, 99, 44, 243, 216, 180, 218, 230,
-        231, 5, 118, 157, 29, 227, 84, 39, 1, 35, 203, 17, 69, 14, 252, 96, 172, 71, 104, 61, 123,
-        141, 15, 129, 19, 101, 86, 95, 217, 140, 76, 142, 185, 54, 188, 171, 141, 6, 159, 195, 59,
-        216, 1, 176, 58, 222, 162, 225, 251, 197, 170, 70, 61, 8, 202, 25, 137, 109, 43, 245, 154,
-        7, 27, 133, 30, 108, 35, 144, 82, 23, 47, 41, 107, 251, 94, 114, 64, 71, 144, 162, 24, 16,
-        20, 243, 185, 74, 78, 151, 209, 23, 180, 56, 19, 3, 104, 204, 57, 219, 178, 209, 152, 6,
-        90, 227, 152, 101, 71, 146, 108, 210, 22, 47, 64, 162, 159, 12, 60, 135, 69, 192, 245, 15,
-        186, 56, 82, 229, 102, 212, 69, 117, 194, 157, 57, 160, 63, 12, 218, 114, 25, 132, 182,
-        244, 64, 89, 31, 53, 94, 18, 212, 57, 255, 21, 10, 171, 118, 19, 73, 157, 189, 73, 173,
-        171, 200, 103, 110, 239, 2, 59, 21, 182, 91, 252, 92, 160, 105, 72, 16, 159, 35, 243, 80,
-        219, 130, 18, 53, 53, 235, 138, 116, 51, 189, 171, 203, 144, 146, 113, 166, 236, 188, 181,
-        139, 147, 106, 136, 205, 78, 143, 46, 111, 245, 128, 1, 117, 241, 19, 37, 61, 143, 169,
-        202, 136, 133, 194, 245, 82, 230, 87, 220, 96, 63, 37, 46, 26, 142, 48, 143, 118, 240, 190,
-        121, 226, 251, 143, 93, 95, 187, 226, 227, 14, 202, 221, 34, 7, 35, 200, 192, 174, 168, 7,
-        140, 223, 203, 56, 104, 38, 63, 248, 240, 148, 0, 84, 218, 72, 120, 24, 147, 167, 228, 154,
-        213, 175, 244, 175, 48, 12, 216, 4, 166, 182, 39, 154, 179, 255, 58, 251, 100, 73, 28, 133,
-        25, 74, 171, 118, 13, 88, 166, 6, 101, 79, 159, 68, 0, 232, 179, 133, 145, 53, 111, 191,
-        100, 37, 172, 162, 109, 200, 82, 68, 37, 159, 242, 177, 156, 65, 185, 249, 111, 60, 169,
-        236, 29, 222, 67, 77, 167, 210, 211, 146, 185, 5, 221, 243, 209, 249, 175, 147, 209, 175,
-        89, 80, 189, 73, 63, 90, 167, 49, 180, 5, 109, 243, 27, 210, 103, 182, 185, 10, 7, 152, 49,
-        170, 245, 121, 190, 10, 57, 1, 49, 55, 170, 198, 212, 4, 245, 24, 207, 212, 104, 64, 100,
-        126, 120, 191, 231, 6, 202, 76, 245, 233, 197, 69, 62, 159, 124, 253, 43, 139, 76, 141, 22,
-        154, 68, 229, 92, 136, 212, 169, 167, 249, 71, 66, 65, 226, 33, 175, 68, 134, 0, 24, 171,
-        8, 86, 151, 46, 25, 76, 217, 52,
-    ];
-
+    let _ = env_logger::builder().is_test(true).try_init();
     let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
     let initial_packet: Packet = input.read_bytes_to().unwrap();
     assert_eq!(
         initial_packet.raw_length(),
         PROTECTED_CLIENT_INITIAL_PACKET.len()
     );
-    let unprotected_header_initial_packet = initial_packet.decrypt(&server_state);
-    eprintln!("{:x?}", unprotected_header_initial_packet);
-    let mut payload_input = Cursor::new(unprotected_header_initial_packet.payload());
-    let frames: Frames = payload_input.read_bytes_to().unwrap();
-    eprintln!("{frames:x?}");
+
+    let connection =
+        Connection::new_with_packet(initial_packet.version().unwrap(), &initial_packet).unwrap();
+    let unprotected_initial_packet = initial_packet.decrypt(&connection).unwrap();
+    assert_eq!(
+        unprotected_initial_packet.body.packet_number(),
+        PacketNumber(2)
+    );
+
+    let payload = unprotected_initial_packet.payload();
+    // CRYPTO frame which has ClientHello and PADDING frames
+    assert_eq!(&payload[..4], &[0x06, 0x00, 0x40, 0xf1]);
+    assert!(payload[4 + 0xf1..].iter().all(|b| *b == 0x00));
+    let mut payload_input = Cursor::new(payload);
+    let _: Frames = payload_input.read_bytes_to().unwrap();
+
+    // the client protects its packet as it does
+    let client_connection = Connection::new_client(
+        initial_packet.version().unwrap(),
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    let protected_initial_packet = unprotected_initial_packet.encrypt(&client_connection);
+    assert_eq!(
+        protected_initial_packet.raw(),
+        PROTECTED_CLIENT_INITIAL_PACKET
+    );
 }
 
 #[test]
 fn protected_server_initial_packet() {
-    const PROTECTED_SERVER_INITIAL_PACKET: &[u8] = &[
-        207, 0, 0, 0, 1, 0, 8, 240, 103, 165, 80, 42, 66, 98, 181, 0, 64, 117, 192, 217, 90, 72,
-        44, 208, 153, 28, 210, 91, 10, 172, 64, 106, 88, 22, 182, 57, 65, 0, 243, 122, 28, 105,
-        121, 117, 84, 120, 11, 179, 140, 197, 169, 159, 94, 222, 76, 247, 60, 62, 194, 73, 58, 24,
-        57, 179, 219, 203, 163, 246, 234, 70, 197, 183, 104, 77, 243, 84, 142, 125, 222, 185, 195,
-        191, 156, 115, 204, 63, 59, 222, 215, 75, 86, 43, 251, 25, 251, 132, 2, 47, 142, 244, 205,
-        217, 55, 149, 215, 125, 6, 237, 187, 122, 175, 47, 88, 137, 24, 80, 171, 189, 202, 61, 32,
-        57, 140, 39, 100, 86, 203, 196, 33, 88, 64, 125, 208, 116, 238,
-    ];
-
+    let _ = env_logger::builder().is_test(true).try_init();
     let mut input = Cursor::new(PROTECTED_SERVER_INITIAL_PACKET);
     let initial_packet: Packet = input.read_bytes_to().unwrap();
     assert_eq!(
         initial_packet.raw_length(),
         PROTECTED_SERVER_INITIAL_PACKET.len()
     );
-    eprintln!("{:x?}", initial_packet);
-    let unprotected_header_initial_packet = initial_packet.decrypt(
+
+    let connection = Connection::new_client(
+        initial_packet.version().unwrap(),
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    let unprotected_initial_packet = initial_packet.decrypt(&connection).unwrap();
+    assert_eq!(
+        unprotected_initial_packet.body.packet_number(),
+        PacketNumber(1)
+    );
+
+    let payload = unprotected_initial_packet.payload();
+    // ACK frame and CRYPTO frame which has ServerHello
+    assert_eq!(
+        &payload[..9],
+        &[0x02, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x40, 0x5a]
+    );
+    let frames: Frames = Cursor::new(payload).read_bytes_to().unwrap();
+    let Frame::Crypto(crypto) = &frames.frames()[1] else {
+        panic!("the second frame is CRYPTO");
+    };
+    let server_hello: Handshake = Cursor::new(crypto.crypto_data()).read_bytes_to().unwrap();
+    assert_eq!(server_hello.handshake_type(), HandshakeType::ServerHello);
+    assert_eq!(server_hello.to_bytes(), crypto.crypto_data());
+
+    // the server protects its packet as it does
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let client_initial_packet: Packet = input.read_bytes_to().unwrap();
+    let server_connection = Connection::new_with_packet(
+        client_initial_packet.version().unwrap(),
+        &client_initial_packet,
+    )
+    .unwrap();
+    let protected_initial_packet = unprotected_initial_packet.encrypt(&server_connection);
+    assert_eq!(
+        protected_initial_packet.raw(),
+        PROTECTED_SERVER_INITIAL_PACKET
+    );
+}
+
+#[test]
+fn decrypt_with_wrong_connection_id() {
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let initial_packet: Packet = input.read_bytes_to().unwrap();
+
+    let connection = Connection::new_client(
+        initial_packet.version().unwrap(),
+        ConnectionID(vec![0x00; 8]),
+        ConnectionID(Vec::new()),
+    );
+    assert!(initial_packet.decrypt(&connection).is_err());
+}
+
+/// Protect a long header packet whose Length is encoded in `length_size` bytes.
+/// A peer may encode it in any size while this endpoint sends two bytes.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-16
+fn protect_with_length_size(
+    header: &[u8],
+    length_size: usize,
+    payload: &[u8],
+    keys: &DirectionalKeys,
+) -> Vec<u8> {
+    let packet_number = PacketNumber(0);
+    let length = (1 + payload.len() + keys.tag_length()) as u64;
+    let mut length = length.to_be_bytes()[8 - length_size..].to_vec();
+    length[0] |= (length_size.trailing_zeros() as u8) << 6;
+    let header = [header, &length, &packet_number.to_bytes(1)].concat();
+    let encrypted_payload = keys.encrypt_payload(payload, &packet_number.0.to_be_bytes(), &header);
+    HeaderProtectionKit {
+        packet_number_offset: header.len() - 1,
+        packet_number_length: 1,
+    }
+    .apply_protection([header, encrypted_payload].concat(), keys)
+}
+
+#[test]
+fn initial_packet_with_any_length_size() {
+    let client = KeySet::new_initial(
+        Version(1).into(),
+        &ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        &EndpointType::Client,
+    );
+    let header = [
+        &[
+            0xc0,
+            0,
+            0,
+            0,
+            1,
+            ORIGINAL_DESTINATION_CONNECTION_ID.len() as u8,
+        ][..],
+        ORIGINAL_DESTINATION_CONNECTION_ID,
+        // no Source Connection ID and no token
+        &[0, 0],
+    ]
+    .concat();
+    // PING and PADDING frames
+    let payload = [&[0x01][..], &[0; 19]].concat();
+    for length_size in [1, 2, 4, 8] {
+        let raw = protect_with_length_size(&header, length_size, &payload, client.local());
+        let packet: Packet = Cursor::new(raw).read_bytes_to().unwrap();
+        let connection = Connection::new_with_packet(packet.version().unwrap(), &packet).unwrap();
+        let unprotected_packet = packet.decrypt(&connection).unwrap();
+        assert_eq!(unprotected_packet.payload(), &payload[..]);
+    }
+}
+
+#[test]
+fn handshake_and_zero_rtt_packets_with_any_length_size() {
+    let client = KeySet::from_secrets(
+        &TLS_CHACHA20_POLY1305_SHA256,
+        &[0x01; 32],
+        &[0x02; 32],
+        &EndpointType::Client,
+    );
+    let server = KeySet::from_secrets(
+        &TLS_CHACHA20_POLY1305_SHA256,
+        &[0x01; 32],
+        &[0x02; 32],
+        &EndpointType::Server,
+    );
+    let payload = [&[0x01][..], &[0; 19]].concat();
+    // Handshake and 0-RTT packet types
+    for first_byte in [0xe0, 0xd0] {
+        let header = [
+            &[
+                first_byte,
+                0,
+                0,
+                0,
+                1,
+                ORIGINAL_DESTINATION_CONNECTION_ID.len() as u8,
+            ][..],
+            ORIGINAL_DESTINATION_CONNECTION_ID,
+            &[0],
+        ]
+        .concat();
+        for length_size in [1, 2, 4, 8] {
+            let raw = protect_with_length_size(&header, length_size, &payload, client.local());
+            let packet: Packet = Cursor::new(raw).read_bytes_to().unwrap();
+            let unprotected_packet = packet.decrypt_with(server.remote()).unwrap();
+            assert_eq!(unprotected_packet.payload(), &payload[..]);
+        }
+    }
+}
+
+#[test]
+fn protect_with_traffic_secrets() {
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let initial_packet: Packet = input.read_bytes_to().unwrap();
+    let connection =
+        Connection::new_with_packet(initial_packet.version().unwrap(), &initial_packet).unwrap();
+    let unprotected_initial_packet = initial_packet.decrypt(&connection).unwrap();
+
+    let client = KeySet::from_secrets(
+        &TLS_CHACHA20_POLY1305_SHA256,
+        &[0x01; 32],
+        &[0x02; 32],
         &EndpointType::Client,
-        Some(vec![0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x8]),
     );
-    eprintln!("{:x?}", unprotected_header_initial_packet);
-    let mut payload_input = Cursor::new(unprotected_header_initial_packet.payload());
-    let frames: Frames = payload_input.read_bytes_to().unwrap();
-    eprintln!("{frames:x?}");
+    let server = KeySet::from_secrets(
+        &TLS_CHACHA20_POLY1305_SHA256,
+        &[0x01; 32],
+        &[0x02; 32],
+        &EndpointType::Server,
+    );
+    let protected_packet = unprotected_initial_packet
+        .clone()
+        .encrypt_with(client.local());
+    assert_ne!(protected_packet.raw(), PROTECTED_CLIENT_INITIAL_PACKET);
+    assert!(protected_packet.decrypt_with(client.remote()).is_err());
+    assert_eq!(
+        protected_packet
+            .decrypt_with(server.remote())
+            .unwrap()
+            .payload(),
+        unprotected_initial_packet.payload()
+    );
+}
+
+#[test]
+fn protected_one_rtt_packet() {
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let initial_packet: Packet = input.read_bytes_to().unwrap();
+    let version = initial_packet.version().unwrap();
+    let mut server_connection = Connection::new_with_packet(version, &initial_packet).unwrap();
+    let mut client_connection = Connection::new_client(
+        version,
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    for (connection, endpoint_type) in [
+        (&mut server_connection, EndpointType::Server),
+        (&mut client_connection, EndpointType::Client),
+    ] {
+        connection.install_key_set(
+            EncryptionLevel::OneRTT,
+            KeySet::from_secrets(
+                &TLS_CHACHA20_POLY1305_SHA256,
+                &[0x01; 32],
+                &[0x02; 32],
+                &endpoint_type,
+            ),
+        );
+    }
+
+    let server_state = EndpointState::new_server(Some(PacketNumber(0x1234)));
+    let payload = PacketPayload::from_vec(vec![0x01; 32]);
+    let packet = Packet::new_one_rtt(&server_connection, &server_state, true, payload.clone());
+    // the client uses a zero-length connection id
+    let mut input = Cursor::new(packet.raw().to_vec());
+    let protected_packet: Packet = input.read_bytes_to_with(0usize).unwrap();
+    assert_eq!(protected_packet.spin_bit(), Some(true));
+    assert_eq!(protected_packet.version(), None);
+
+    let unprotected_packet = protected_packet.decrypt(&client_connection).unwrap();
+    assert_eq!(unprotected_packet.key_phase(), Some(false));
+    assert_eq!(unprotected_packet.packet_number(), PacketNumber(0x1234));
+    assert_eq!(unprotected_packet.payload(), &payload.0[..]);
+
+    // after a key update by the server, the client chooses the next keys by the Key Phase bit
+    server_connection
+        .initiate_key_update(Instant::now())
+        .unwrap();
+    let packet = Packet::new_one_rtt(&server_connection, &server_state, false, payload.clone());
+    let mut input = Cursor::new(packet.raw().to_vec());
+    let protected_packet: Packet = input.read_bytes_to_with(0usize).unwrap();
+    let unprotected_packet = protected_packet.decrypt(&client_connection).unwrap();
+    assert_eq!(unprotected_packet.key_phase(), Some(true));
+    assert_eq!(unprotected_packet.payload(), &payload.0[..]);
+}
+
+// RFC 9001 Appendix A.4
+#[test]
+fn retry_packet() {
+    let retry_packet =
+        hex::decode("ff000000010008f067a5502a4262b5746f6b656e04a265ba2eff4d829058fb3f0f2496ba")
+            .unwrap();
+    let mut input = Cursor::new(retry_packet.clone());
+    let packet: Packet = input.read_bytes_to().unwrap();
+    assert_eq!(packet.retry_token(), Some(&Token(b"token".to_vec())));
+    assert!(packet
+        .verify_retry_integrity_tag(&ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec())));
+    assert!(!packet.verify_retry_integrity_tag(&ConnectionID(vec![0x00])));
+    // a Retry packet is not protected
+    assert_eq!(packet.encryption_level(), None);
+
+    // the tag of an unknown version can not be verified
+    let mut unknown_version = retry_packet.clone();
+    unknown_version[1..5].copy_from_slice(&[0x1a, 0x2a, 0x3a, 0x4a]);
+    let mut input = Cursor::new(unknown_version);
+    let packet: Packet = input.read_bytes_to().unwrap();
+    assert!(!packet
+        .verify_retry_integrity_tag(&ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec())));
+
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let initial_packet: Packet = input.read_bytes_to().unwrap();
+    let version = initial_packet.version().unwrap();
+    let server_connection = Connection::new_with_packet(version, &initial_packet).unwrap();
+    let new_source_connection_id = ConnectionID(hex::decode("f067a5502a4262b5").unwrap());
+    let packet = Packet::new_retry(
+        &server_connection,
+        &new_source_connection_id,
+        Token(b"token".to_vec()),
+    );
+    assert_eq!(packet.raw(), &retry_packet[..]);
+
+    let mut client_connection = Connection::new_client(
+        version,
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    client_connection.on_retry(&packet).unwrap();
+    assert_eq!(
+        client_connection.destination_connection_id(),
+        &new_source_connection_id
+    );
+    assert_eq!(client_connection.token(), &Token(b"token".to_vec()));
+    assert_eq!(
+        client_connection.key_sets().get(EncryptionLevel::Initial),
+        Some(&KeySet::new_initial(
+            version.into(),
+            &new_source_connection_id,
+            &EndpointType::Client
+        ))
+    );
+}
+
+#[test]
+fn transport_parameters_after_retry() {
+    let retry_packet =
+        hex::decode("ff000000010008f067a5502a4262b5746f6b656e04a265ba2eff4d829058fb3f0f2496ba")
+            .unwrap();
+    let mut input = Cursor::new(retry_packet);
+    let packet: Packet = input.read_bytes_to().unwrap();
+    let mut client_connection = Connection::new_client(
+        Version(1),
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    client_connection.on_retry(&packet).unwrap();
+
+    let retry_source_connection_id = ConnectionID(hex::decode("f067a5502a4262b5").unwrap());
+    let mut server_parameters = TransportParameters {
+        original_destination_connection_id: Some(ConnectionID(
+            ORIGINAL_DESTINATION_CONNECTION_ID.to_vec(),
+        )),
+        initial_source_connection_id: Some(retry_source_connection_id.clone()),
+        initial_max_data: 1 << 20,
+        ..Default::default()
+    };
+    // the server must tell the connection ID of the Retry packet
+    assert_eq!(
+        client_connection.on_peer_transport_parameters(&server_parameters.encode()),
+        Err(TransportParameterError::Missing(0x10))
+    );
+    server_parameters.retry_source_connection_id = Some(retry_source_connection_id);
+    client_connection
+        .on_peer_transport_parameters(&server_parameters.encode())
+        .unwrap();
+    assert_eq!(
+        client_connection.peer_transport_parameters(),
+        Some(&server_parameters)
+    );
+    assert_eq!(
+        client_connection
+            .local_transport_parameters()
+            .initial_source_connection_id,
+        Some(ConnectionID(Vec::new()))
+    );
+}
+
+#[test]
+fn protected_handshake_packet() {
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let initial_packet: Packet = input.read_bytes_to().unwrap();
+    let version = initial_packet.version().unwrap();
+    let mut server_connection = Connection::new_with_packet(version, &initial_packet).unwrap();
+    let mut client_connection = Connection::new_client(
+        version,
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    for (connection, endpoint_type) in [
+        (&mut server_connection, EndpointType::Server),
+        (&mut client_connection, EndpointType::Client),
+    ] {
+        connection.install_key_set(
+            EncryptionLevel::Handshake,
+            KeySet::from_secrets(
+                &TLS_CHACHA20_POLY1305_SHA256,
+                &[0x01; 32],
+                &[0x02; 32],
+                &endpoint_type,
+            ),
+        );
+    }
+
+    let client_state = EndpointState::new_client(Some(PacketNumber(0x01)));
+    let payload = PacketPayload::from_vec(vec![0x01; 32]);
+    let packet = Packet::new_handshake(&client_connection, &client_state, payload.clone());
+    assert_eq!(packet.encryption_level(), Some(EncryptionLevel::Handshake));
+    assert_ne!(packet.payload(), &payload.0[..]);
+
+    let unprotected_packet = packet.decrypt(&server_connection).unwrap();
+    assert_eq!(unprotected_packet.packet_number(), PacketNumber(0x01));
+    assert_eq!(unprotected_packet.payload(), &payload.0[..]);
+    // keys of the other level can not remove the protection
+    assert!(packet
+        .decrypt_with(
+            server_connection
+                .key_sets()
+                .get(EncryptionLevel::Initial)
+                .unwrap()
+                .remote()
+        )
+        .is_err());
+}
+
+/// A client resuming with a ticket of this server, after the server takes the Initial packet.
+fn zero_rtt_connections(
+    server_parameters: &TransportParameters,
+) -> (Connection, Connection, EndpointState) {
+    let mut input = Cursor::new(PROTECTED_CLIENT_INITIAL_PACKET);
+    let initial_packet: Packet = input.read_bytes_to().unwrap();
+    let version = initial_packet.version().unwrap();
+    let mut server_connection = Connection::new_with_packet(version, &initial_packet).unwrap();
+    let mut client_connection = Connection::new_client(
+        version,
+        ConnectionID(ORIGINAL_DESTINATION_CONNECTION_ID.to_vec()),
+        ConnectionID(Vec::new()),
+    );
+    let key_set = KeySet::from_early_secret(&TLS_CHACHA20_POLY1305_SHA256, &[0x03; 32]);
+    server_connection.install_key_set(EncryptionLevel::ZeroRTT, key_set.clone());
+    client_connection
+        .start_early_data(key_set, &server_parameters.encode())
+        .unwrap();
+    (
+        client_connection,
+        server_connection,
+        EndpointState::new_client(Some(PacketNumber(0x02))),
+    )
+}
+
+fn server_parameters() -> TransportParameters {
+    TransportParameters {
+        original_destination_connection_id: Some(ConnectionID(
+            ORIGINAL_DESTINATION_CONNECTION_ID.to_vec(),
+        )),
+        initial_source_connection_id: Some(ConnectionID(
+            ORIGINAL_DESTINATION_CONNECTION_ID.to_vec(),
+        )),
+        max_idle_timeout: 30_000,
+        initial_max_data: 10,
+        initial_max_stream_data_bidi_remote: 8,
+        initial_max_streams_bidi: 2,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn zero_rtt_packet() {
+    let (client_connection, server_connection, client_state) =
+        zero_rtt_connections(&server_parameters());
+    // only the limits are remembered
+    let limits = client_connection.peer_transport_parameters().unwrap();
+    assert_eq!(limits.initial_max_data, 10);
+    assert_eq!(limits.max_idle_timeout, 0);
+    assert_eq!(
+        client_connection.early_data_status(),
+        EarlyDataStatus::NotSent
+    );
+
+    let frame = Frame::Stream(stream::Body::new(
+        StreamID(0),
+        Some(0),
+        b"hello".to_vec(),
+        false,
+    ));
+    let packet = Packet::new_zero_rtt(
+        &client_connection,
+        &client_state,
+        PacketPayload::from_vec(frame.to_bytes()),
+    );
+    assert_eq!(packet.encryption_level(), Some(EncryptionLevel::ZeroRTT));
+    let unprotected_packet = packet.decrypt(&server_connection).unwrap();
+    let frames = Frames::decode(unprotected_packet.payload(), EncryptionLevel::ZeroRTT).unwrap();
+    let [Frame::Stream(stream)] = frames.frames() else {
+        panic!("not a STREAM frame: {frames:?}")
+    };
+    assert_eq!(stream.data_length(), 5);
+}
+
+#[test]
+fn zero_rtt_accepted() {
+    let (mut client_connection, _, _) = zero_rtt_connections(&server_parameters());
+    assert!(client_connection.on_early_data_result(true).is_empty());
+    // nothing is sent in 0-RTT packets
+    assert_eq!(
+        client_connection.early_data_status(),
+        EarlyDataStatus::NotSent
+    );
+    let reduced = TransportParameters {
+        initial_max_data: 5,
+        ..server_parameters()
+    };
+    assert_eq!(
+        client_connection.on_peer_transport_parameters(&reduced.encode()),
+        Err(TransportParameterError::Reduced(0x04))
+    );
+
+    let (mut client_connection, _, _) = zero_rtt_connections(&server_parameters());
+    client_connection.on_early_data_result(true);
+    client_connection
+        .on_peer_transport_parameters(&server_parameters().encode())
+        .unwrap();
+    assert_eq!(
+        client_connection.peer_transport_parameters(),
+        Some(&server_parameters())
+    );
+}
+
+// RFC 9000 Appendix A.2
+#[test]
+fn coalesced_packets() {
+    let mut datagram = PROTECTED_CLIENT_INITIAL_PACKET.to_vec();
+    let short_header_packet = [0x40, 1, 2, 3, 4, 5];
+    datagram.extend_from_slice(&short_header_packet);
+    let length = first_packet_length(&datagram).unwrap();
+    assert_eq!(length, PROTECTED_CLIENT_INITIAL_PACKET.len());
+    // a short header packet takes the rest of the datagram
+    assert_eq!(
+        first_packet_length(&datagram[length..]).unwrap(),
+        short_header_packet.len()
+    );
+    assert!(first_packet_length(&datagram[..length - 1]).is_err());
+}
+
+#[test]
+fn packet_number_encoding() {
+    assert_eq!(
+        PacketNumber(0xac5c02).encoded_length(Some(PacketNumber(0xabe8b3))),
+        2
+    );
+    assert_eq!(
+        PacketNumber(0xace8fe).encoded_length(Some(PacketNumber(0xabe8b3))),
+        3
+    );
+    assert_eq!(PacketNumber(0).encoded_length(None), 1);
+    // 128 unacknowledged packets fit in a 1 byte window
+    assert_eq!(PacketNumber(128).encoded_length(Some(PacketNumber(0))), 1);
+    assert_eq!(PacketNumber(129).encoded_length(Some(PacketNumber(0))), 2);
+}
+
+// RFC 9000 Appendix A.3
+#[test]
+fn packet_number_decoding() {
+    assert_eq!(
+        PacketNumber::decode(Some(PacketNumber(0xa82f30ea)), 0x9b32, 2),
+        PacketNumber(0xa82f9b32)
+    );
+    // a packet which is reordered across a window boundary
+    assert_eq!(
+        PacketNumber::decode(Some(PacketNumber(0x1_0000_0005)), 0xffff_fffe, 4),
+        PacketNumber(0xffff_fffe)
+    );
+    assert_eq!(
+        PacketNumber::decode(Some(PacketNumber(0xfffe)), 0x01, 1),
+        PacketNumber(0x1_0001)
+    );
+    assert_eq!(PacketNumber::decode(None, 0x02, 4), PacketNumber(0x02));
+}
+
+proptest::proptest! {
+    #[test]
+    fn packet_number_round_trip(
+        largest_acknowledged in 0u64..(1 << 62) - (1 << 32),
+        distance in 1u64..(1 << 31),
+        received in 0u64..(1 << 31),
+    ) {
+        let packet_number = PacketNumber(largest_acknowledged + distance);
+        let length = packet_number.encoded_length(Some(PacketNumber(largest_acknowledged)));
+        let truncated = PacketNumber::read_bytes_to(
+            &mut Cursor::new(packet_number.to_bytes(length)),
+            length as u8,
+        )
+        .unwrap();
+        // the receiver has received at least the acknowledged packet and not this packet yet
+        let largest_received = largest_acknowledged + received % distance;
+        proptest::prop_assert_eq!(
+            PacketNumber::decode(Some(PacketNumber(largest_received)), truncated.to_u64(), length),
+            packet_number
+        );
+    }
+
+    #[test]
+    fn first_packet_number_round_trip(packet_number in 0u64..(1 << 31)) {
+        let packet_number = PacketNumber(packet_number);
+        let length = packet_number.encoded_length(None);
+        let truncated = packet_number.to_bytes(length);
+        let truncated = PacketNumber::read_bytes_to(&mut Cursor::new(truncated), length as u8).unwrap();
+        proptest::prop_assert_eq!(PacketNumber::decode(None, truncated.to_u64(), length), packet_number);
+    }
 }
-*/
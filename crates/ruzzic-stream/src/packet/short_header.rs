@@ -0,0 +1,130 @@
+use std::io::{Cursor, Read};
+
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+
+use crate::{
+    connection::{Connection, ConnectionID},
+    endpoint_state::EndpointState,
+};
+
+use super::{packet_meta::PacketMeta, PacketNumber, PacketPayload};
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-1-rtt-packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortHeader {
+    pub destination_connection_id: ConnectionID,
+    pub packet_number: PacketNumber,
+    pub packet_payload: PacketPayload,
+}
+
+impl FromReadBytesWith<(&PacketMeta, usize)> for ShortHeader {
+    fn from_read_bytes_with<R: Read>(
+        input: &mut R,
+        (meta, connection_id_length): (&PacketMeta, usize),
+    ) -> Result<Self, std::io::Error>
+    where
+        Self: Sized,
+    {
+        let mut destination_connection_id = vec![0u8; connection_id_length];
+        input.read_exact(&mut destination_connection_id)?;
+        // a short header packet is always the last packet in a datagram
+        let mut remainder = Vec::new();
+        input.read_to_end(&mut remainder)?;
+        let mut remainder_input = Cursor::new(remainder);
+        let packet_number =
+            PacketNumber::read_bytes_to(&mut remainder_input, meta.packet_number_length())?;
+        let packet_payload = remainder_input.read_bytes_to()?;
+        Ok(Self {
+            destination_connection_id: ConnectionID(destination_connection_id),
+            packet_number,
+            packet_payload,
+        })
+    }
+}
+
+impl ShortHeader {
+    pub(super) fn payload(&self) -> &[u8] {
+        &self.packet_payload.0
+    }
+
+    pub(super) fn destination_connection_id(&self) -> Box<ConnectionID> {
+        Box::new(self.destination_connection_id.clone())
+    }
+
+    pub(super) fn packet_number(&self) -> PacketNumber {
+        self.packet_number
+    }
+
+    /// Bytes between the first byte and the packet number.
+    pub(super) fn packet_number_offset(&self) -> usize {
+        self.destination_connection_id.len()
+    }
+
+    pub(super) fn header_bytes(&self, packet_number_length: usize) -> Vec<u8> {
+        [
+            self.destination_connection_id.to_vec(),
+            self.packet_number.to_bytes(packet_number_length),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn update_payload(self, payload: PacketPayload) -> Self {
+        Self {
+            destination_connection_id: self.destination_connection_id,
+            packet_number: self.packet_number,
+            packet_payload: payload,
+        }
+    }
+
+    pub(crate) fn new(
+        connection: &Connection,
+        endpoint_state: &EndpointState,
+        packet_payload: PacketPayload,
+    ) -> Self {
+        Self {
+            destination_connection_id: connection.destination_connection_id().clone(),
+            packet_number: *endpoint_state.next_packet_number(),
+            packet_payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::packet_meta::FirstByte;
+    use bitvec::prelude::*;
+    use ruzzic_common::read_bytes_to::ReadBytesToWith;
+
+    #[test]
+    fn one_rtt_packet() {
+        let buf = [
+            &[0x01, 0x02][..], // destination connection id
+            &[0x00, 0x03],     // packet number
+            &[0x01],           // packet payload
+        ]
+        .concat();
+        let mut input = Cursor::new(buf.clone());
+
+        let mut first_byte = bitarr![Msb0, u8; 0; 8];
+        first_byte.store(0b01100101u8);
+        let meta = PacketMeta {
+            first_byte: FirstByte(first_byte),
+            version: None,
+        };
+        assert!(meta.first_byte.spin_bit());
+        assert!(meta.first_byte.key_phase());
+
+        let actual: ShortHeader = input.read_bytes_to_with((&meta, 2)).unwrap();
+        let expected = ShortHeader {
+            destination_connection_id: ConnectionID(vec![0x01, 0x02]),
+            packet_number: PacketNumber(0x03),
+            packet_payload: PacketPayload(vec![0x01]),
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(
+            [actual.header_bytes(2), actual.payload().to_vec()].concat(),
+            buf
+        );
+    }
+}
@@ -1,17 +1,1013 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+use ruzzic_common::EndpointType;
+
 use crate::{
     connection::ConnectionID,
+    frame::{
+        data_blocked, max_data, max_stream_data, max_streams, reset_stream, stop_sending, stream,
+        stream_data_blocked, streams_blocked, Frame,
+    },
     packet::{PacketNumber, PacketPayload},
+    transport_parameters::TransportParameters,
     Token,
 };
 
-#[derive(Debug, PartialEq)]
+use self::{
+    flow_control::{RecvWindow, SendCredit, MAX_CONNECTION_WINDOW, MAX_STREAM_WINDOW},
+    recv::{Chunk, RecvStream},
+    send::SendStream,
+};
+
+pub use self::{recv::RecvState, send::SendState};
+
+mod flow_control;
+mod recv;
+mod send;
+
+/// The two least significant bits tell who initiated the stream and in which direction it goes.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-stream-types-and-identifier
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StreamID(pub(crate) u64);
 
-#[derive(Debug, PartialEq)]
+impl StreamID {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    fn from_parts(initiator: &EndpointType, direction: &StreamDirection, index: u64) -> Self {
+        let initiator_bit = match initiator {
+            EndpointType::Client => 0x00,
+            EndpointType::Server => 0x01,
+        };
+        let direction_bit = match direction {
+            StreamDirection::Bidirectional => 0x00,
+            StreamDirection::Unidirectional => 0x02,
+        };
+        Self(index << 2 | direction_bit | initiator_bit)
+    }
+
+    pub fn to_u64(&self) -> u64 {
+        self.0
+    }
+
+    pub fn initiator(&self) -> EndpointType {
+        if self.0 & 0x01 == 0 {
+            EndpointType::Client
+        } else {
+            EndpointType::Server
+        }
+    }
+
+    pub fn direction(&self) -> StreamDirection {
+        if self.0 & 0x02 == 0 {
+            StreamDirection::Bidirectional
+        } else {
+            StreamDirection::Unidirectional
+        }
+    }
+
+    /// The order among the streams of the same type.
+    fn index(&self) -> u64 {
+        self.0 >> 2
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct StreamData(pub(crate) Vec<u8>);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StreamDirection {
     Bidirectional,
     Unidirectional,
 }
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum StreamError {
+    #[error("the stream does not exist or is closed")]
+    UnknownStream,
+    #[error("no more streams can be opened")]
+    StreamLimit,
+    #[error("the stream can not be used in the direction")]
+    StreamState,
+    #[error("the final size of the stream is changed")]
+    FinalSize,
+    #[error("the data exceeds the flow control limit")]
+    FlowControl,
+    #[error("the stream is already finished or reset")]
+    Finished,
+    #[error("the peer reset the stream with {0:#x}")]
+    Reset(u64),
+    #[error("the peer asked to stop sending with {0:#x}")]
+    Stopped(u64),
+    #[error("the connection is closed")]
+    ConnectionClosed,
+}
+
+impl StreamError {
+    /// Transport error code to close the connection with, when a frame of the peer causes the error.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-transport-error-codes
+    pub fn transport_error_code(&self) -> Option<u64> {
+        match self {
+            // FLOW_CONTROL_ERROR
+            StreamError::FlowControl => Some(0x03),
+            // STREAM_LIMIT_ERROR
+            StreamError::StreamLimit => Some(0x04),
+            // STREAM_STATE_ERROR
+            StreamError::StreamState => Some(0x05),
+            // FINAL_SIZE_ERROR
+            StreamError::FinalSize => Some(0x06),
+            _ => None,
+        }
+    }
+}
+
+/// Limits of the number of streams of one direction.
+#[derive(Debug, Default)]
+struct StreamCount {
+    /// Streams opened by this endpoint, and how many the peer allows.
+    opened: u64,
+    max_opened: u64,
+    /// Streams opened by the peer, and how many this endpoint allows.
+    accepted: u64,
+    max_accepted: u64,
+    /// The limit is raised as streams of the peer close, and MAX_STREAMS is sent.
+    is_max_update_pending: bool,
+    /// The limit to report in STREAMS_BLOCKED, and the last one reported.
+    blocked: Option<u64>,
+    blocked_at: Option<u64>,
+}
+
+/// The initial_max_stream_data_* parameters of an endpoint.
+#[derive(Debug, Default)]
+struct StreamDataLimits {
+    bidirectional_local: u64,
+    bidirectional_remote: u64,
+    unidirectional: u64,
+}
+
+impl StreamDataLimits {
+    fn new(parameters: &TransportParameters) -> Self {
+        Self {
+            bidirectional_local: parameters.initial_max_stream_data_bidi_local,
+            bidirectional_remote: parameters.initial_max_stream_data_bidi_remote,
+            unidirectional: parameters.initial_max_stream_data_uni,
+        }
+    }
+
+    /// The limit for a stream, depending on whether the endpoint with the limits opened it.
+    fn get(&self, stream_id: &StreamID, is_initiator: bool) -> u64 {
+        match (stream_id.direction(), is_initiator) {
+            (StreamDirection::Unidirectional, _) => self.unidirectional,
+            (StreamDirection::Bidirectional, true) => self.bidirectional_local,
+            (StreamDirection::Bidirectional, false) => self.bidirectional_remote,
+        }
+    }
+}
+
+/// The streams of a connection.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-streams
+pub(crate) struct Streams {
+    endpoint_type: EndpointType,
+    send: BTreeMap<StreamID, SendStream>,
+    recv: BTreeMap<StreamID, RecvStream>,
+    bidirectional: StreamCount,
+    unidirectional: StreamCount,
+    /// Streams which may have data or a reset to deliver.
+    readable: BTreeSet<StreamID>,
+    /// Streams are sent in turn from the one after this.
+    last_sent: Option<StreamID>,
+    local_limits: StreamDataLimits,
+    peer_limits: StreamDataLimits,
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-data-flow-control
+    send_credit: SendCredit,
+    recv_window: RecvWindow,
+    /// Streams the application consumed data of, which may need MAX_STREAM_DATA.
+    consumed: BTreeSet<StreamID>,
+}
+
+impl Streams {
+    pub(crate) fn new(endpoint_type: EndpointType) -> Self {
+        Self {
+            endpoint_type,
+            send: BTreeMap::new(),
+            recv: BTreeMap::new(),
+            bidirectional: StreamCount::default(),
+            unidirectional: StreamCount::default(),
+            readable: BTreeSet::new(),
+            last_sent: None,
+            local_limits: StreamDataLimits::default(),
+            peer_limits: StreamDataLimits::default(),
+            send_credit: SendCredit::default(),
+            recv_window: RecvWindow::new(0, MAX_CONNECTION_WINDOW),
+            consumed: BTreeSet::new(),
+        }
+    }
+
+    fn count_mut(&mut self, direction: &StreamDirection) -> &mut StreamCount {
+        match direction {
+            StreamDirection::Bidirectional => &mut self.bidirectional,
+            StreamDirection::Unidirectional => &mut self.unidirectional,
+        }
+    }
+
+    /// The limits this endpoint sends, before any stream is opened.
+    pub(crate) fn set_local_limits(&mut self, parameters: &TransportParameters) {
+        self.bidirectional.max_accepted = parameters.initial_max_streams_bidi;
+        self.unidirectional.max_accepted = parameters.initial_max_streams_uni;
+        self.local_limits = StreamDataLimits::new(parameters);
+        self.recv_window = RecvWindow::new(parameters.initial_max_data, MAX_CONNECTION_WINDOW);
+    }
+
+    /// The limits of the peer, or the ones remembered for 0-RTT.
+    /// Streams opened with the remembered limits get the ones of the peer.
+    pub(crate) fn set_peer_limits(&mut self, parameters: &TransportParameters) {
+        self.bidirectional.max_opened = parameters.initial_max_streams_bidi;
+        self.unidirectional.max_opened = parameters.initial_max_streams_uni;
+        self.peer_limits = StreamDataLimits::new(parameters);
+        self.send_credit.on_max_data(parameters.initial_max_data);
+        let endpoint_type = self.endpoint_type;
+        for (stream_id, stream) in self.send.iter_mut() {
+            let is_local = stream_id.initiator() == endpoint_type;
+            stream
+                .credit_mut()
+                .on_max_data(self.peer_limits.get(stream_id, !is_local));
+        }
+    }
+
+    /// Returns an error if the peer does not allow more streams, and STREAMS_BLOCKED is sent.
+    pub(crate) fn open(&mut self, direction: StreamDirection) -> Result<StreamID, StreamError> {
+        let count = self.count_mut(&direction);
+        if count.opened >= count.max_opened {
+            if count.blocked_at != Some(count.max_opened) {
+                count.blocked_at = Some(count.max_opened);
+                count.blocked = Some(count.max_opened);
+            }
+            return Err(StreamError::StreamLimit);
+        }
+        let index = count.opened;
+        count.opened += 1;
+        let stream_id = StreamID::from_parts(&self.endpoint_type, &direction, index);
+        self.insert(stream_id.clone());
+        Ok(stream_id)
+    }
+
+    fn is_local(&self, stream_id: &StreamID) -> bool {
+        stream_id.initiator() == self.endpoint_type
+    }
+
+    /// Create the parts of a stream in the directions it goes, with the initial flow control limits.
+    fn insert(&mut self, stream_id: StreamID) {
+        let is_local = self.is_local(&stream_id);
+        let is_bidirectional = stream_id.direction() == StreamDirection::Bidirectional;
+        if is_bidirectional || is_local {
+            let max_data = self.peer_limits.get(&stream_id, !is_local);
+            self.send.insert(
+                stream_id.clone(),
+                SendStream::new(stream_id.clone(), max_data),
+            );
+        }
+        if is_bidirectional || !is_local {
+            let window = RecvWindow::new(
+                self.local_limits.get(&stream_id, is_local),
+                MAX_STREAM_WINDOW,
+            );
+            self.recv
+                .insert(stream_id.clone(), RecvStream::new(stream_id, window));
+        }
+    }
+
+    /// A stream of the peer is opened with the streams of the same type before it.
+    /// Returns an error if the stream does not go in the direction.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-3.2
+    fn on_peer_stream(
+        &mut self,
+        stream_id: &StreamID,
+        is_sending: bool,
+    ) -> Result<(), StreamError> {
+        let direction = stream_id.direction();
+        let is_local = self.is_local(stream_id);
+        if direction == StreamDirection::Unidirectional && is_local != is_sending {
+            return Err(StreamError::StreamState);
+        }
+        let endpoint_type = self.endpoint_type;
+        let count = self.count_mut(&direction);
+        if is_local {
+            // the peer can not use a stream not opened yet
+            if stream_id.index() >= count.opened {
+                return Err(StreamError::StreamState);
+            }
+            return Ok(());
+        }
+        if stream_id.index() >= count.max_accepted {
+            return Err(StreamError::StreamLimit);
+        }
+        let peer = match endpoint_type {
+            EndpointType::Client => EndpointType::Server,
+            EndpointType::Server => EndpointType::Client,
+        };
+        let start = count.accepted;
+        count.accepted = count.accepted.max(stream_id.index() + 1);
+        for index in start..stream_id.index() + 1 {
+            self.insert(StreamID::from_parts(&peer, &direction, index));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&mut self, stream_id: &StreamID, data: &[u8]) -> Result<(), StreamError> {
+        self.send_stream(stream_id)?.write(data)
+    }
+
+    pub(crate) fn finish(&mut self, stream_id: &StreamID) -> Result<(), StreamError> {
+        self.send_stream(stream_id)?.finish()
+    }
+
+    /// RESET_STREAM to send, if the stream has not ended.
+    pub(crate) fn reset(
+        &mut self,
+        stream_id: &StreamID,
+        error_code: u64,
+    ) -> Result<Option<Frame>, StreamError> {
+        Ok(self.send_stream(stream_id)?.reset(error_code))
+    }
+
+    /// STOP_SENDING to send, if the stream has not ended.
+    pub(crate) fn stop_sending(
+        &mut self,
+        stream_id: &StreamID,
+        error_code: u64,
+    ) -> Result<Option<Frame>, StreamError> {
+        let stream = self
+            .recv
+            .get_mut(stream_id)
+            .ok_or(StreamError::UnknownStream)?;
+        let received = stream.received();
+        let frame = stream.stop_sending(error_code);
+        self.on_received(stream_id, received)?;
+        Ok(frame)
+    }
+
+    fn send_stream(&mut self, stream_id: &StreamID) -> Result<&mut SendStream, StreamError> {
+        self.send
+            .get_mut(stream_id)
+            .ok_or(StreamError::UnknownStream)
+    }
+
+    pub(crate) fn on_stream_frame(&mut self, body: &stream::Body) -> Result<(), StreamError> {
+        self.on_peer_stream(body.stream_id(), false)?;
+        // a closed stream
+        let Some(stream) = self.recv.get_mut(body.stream_id()) else {
+            return Ok(());
+        };
+        let received = stream.received();
+        stream.on_stream_frame(body.offset(), body.data(), body.is_fin())?;
+        self.on_received(body.stream_id(), received)?;
+        self.readable.insert(body.stream_id().clone());
+        Ok(())
+    }
+
+    /// The data a stream received beyond `received` counts against the connection window,
+    /// and the data discarded is given back to it.
+    fn on_received(&mut self, stream_id: &StreamID, received: u64) -> Result<(), StreamError> {
+        let Some(stream) = self.recv.get_mut(stream_id) else {
+            return Ok(());
+        };
+        let end = self.recv_window.received() + stream.received() - received;
+        self.recv_window.on_received(end)?;
+        self.recv_window.on_consumed(stream.take_discarded());
+        Ok(())
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
+    pub(crate) fn on_reset_stream(&mut self, body: &reset_stream::Body) -> Result<(), StreamError> {
+        self.on_peer_stream(body.stream_id(), false)?;
+        let Some(stream) = self.recv.get_mut(body.stream_id()) else {
+            return Ok(());
+        };
+        let received = stream.received();
+        let is_readable = stream.on_reset(body.error_code(), body.final_size())?;
+        self.on_received(body.stream_id(), received)?;
+        if is_readable {
+            self.readable.insert(body.stream_id().clone());
+        }
+        Ok(())
+    }
+
+    /// Returns RESET_STREAM to reply with.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-stop_sending-frames
+    pub(crate) fn on_stop_sending(
+        &mut self,
+        body: &stop_sending::Body,
+    ) -> Result<Option<Frame>, StreamError> {
+        self.on_peer_stream(body.stream_id(), true)?;
+        Ok(self
+            .send
+            .get_mut(body.stream_id())
+            .and_then(|stream| stream.on_stop_sending(body.error_code())))
+    }
+
+    /// Data or a reset of a stream to deliver to the application.
+    pub(crate) fn read(&mut self) -> Option<(StreamID, Result<Chunk, StreamError>)> {
+        while let Some(stream_id) = self.readable.first().cloned() {
+            let Some(stream) = self.recv.get_mut(&stream_id) else {
+                self.readable.remove(&stream_id);
+                continue;
+            };
+            match stream.read() {
+                Ok(Some(chunk)) => return Some((stream_id, Ok(chunk))),
+                Ok(None) => {
+                    self.readable.remove(&stream_id);
+                    self.remove_if_closed(&stream_id);
+                }
+                Err(error) => return Some((stream_id, Err(error))),
+            }
+        }
+        None
+    }
+
+    /// The application has consumed data delivered, which gives the peer more credit.
+    pub(crate) fn on_consumed(&mut self, stream_id: &StreamID, length: u64) {
+        self.recv_window.on_consumed(length);
+        if let Some(stream) = self.recv.get_mut(stream_id) {
+            stream.window_mut().on_consumed(length);
+            self.consumed.insert(stream_id.clone());
+        }
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-max_data-frames
+    pub(crate) fn on_max_data(&mut self, body: &max_data::Body) {
+        self.send_credit.on_max_data(body.maximum_data());
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-max_stream_data-frames
+    pub(crate) fn on_max_stream_data(
+        &mut self,
+        body: &max_stream_data::Body,
+    ) -> Result<(), StreamError> {
+        self.on_peer_stream(body.stream_id(), true)?;
+        if let Some(stream) = self.send.get_mut(body.stream_id()) {
+            stream.credit_mut().on_max_data(body.maximum_stream_data());
+        }
+        Ok(())
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-max_streams-frames
+    pub(crate) fn on_max_streams(&mut self, body: &max_streams::Body) {
+        let count = self.count_mut(body.kind());
+        count.max_opened = count.max_opened.max(body.maximum_streams());
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-data_blocked-frames
+    pub(crate) fn on_data_blocked(&mut self, body: &data_blocked::Body) {
+        self.recv_window.on_blocked(body.maximum_data());
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-stream_data_blocked-frames
+    pub(crate) fn on_stream_data_blocked(
+        &mut self,
+        body: &stream_data_blocked::Body,
+    ) -> Result<(), StreamError> {
+        self.on_peer_stream(body.stream_id(), false)?;
+        if let Some(stream) = self.recv.get_mut(body.stream_id()) {
+            stream.window_mut().on_blocked(body.maximum_stream_data());
+            self.consumed.insert(body.stream_id().clone());
+        }
+        Ok(())
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-streams_blocked-frames
+    pub(crate) fn on_streams_blocked(&mut self, body: &streams_blocked::Body) {
+        let count = self.count_mut(body.direction());
+        if body.maximum_streams() < count.max_accepted {
+            count.is_max_update_pending = true;
+        }
+    }
+
+    /// MAX_* frames which raise the limits of the peer, and *_BLOCKED frames which tell the peer
+    /// this endpoint is blocked by its limits.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-flow-control
+    pub(crate) fn flow_control_frames(&mut self, now: Instant, rtt: Duration) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for stream_id in std::mem::take(&mut self.consumed) {
+            let Some(stream) = self.recv.get_mut(&stream_id) else {
+                continue;
+            };
+            if !stream.is_receiving() {
+                continue;
+            }
+            let window = stream.window_mut();
+            if let Some(max_data) = window.update(now, rtt) {
+                // the connection window is kept larger than the ones of streams
+                self.recv_window
+                    .ensure_window(window.window() + window.window() / 2);
+                frames.push(Frame::MaxStreamData(max_stream_data::Body::new(
+                    stream_id, max_data,
+                )));
+            }
+        }
+        if let Some(max_data) = self.recv_window.update(now, rtt) {
+            frames.push(Frame::MaxData(max_data::Body::new(max_data)));
+        }
+        for direction in [
+            StreamDirection::Bidirectional,
+            StreamDirection::Unidirectional,
+        ] {
+            let count = self.count_mut(&direction);
+            if std::mem::take(&mut count.is_max_update_pending) {
+                frames.push(Frame::MaxStreams(max_streams::Body::new(
+                    direction.clone(),
+                    count.max_accepted,
+                )));
+            }
+            if let Some(max_streams) = count.blocked.take() {
+                // the limit may be raised in the meantime
+                if max_streams == count.max_opened {
+                    frames.push(Frame::StreamsBlocked(streams_blocked::Body::new(
+                        direction,
+                        max_streams,
+                    )));
+                }
+            }
+        }
+        if self.send.values().any(SendStream::has_new_data) {
+            if let Some(max_data) = self.send_credit.blocked() {
+                frames.push(Frame::DataBlocked(data_blocked::Body::new(max_data)));
+            }
+        }
+        for (stream_id, stream) in self.send.iter_mut() {
+            if let Some(max_data) = stream.blocked() {
+                frames.push(Frame::StreamDataBlocked(stream_data_blocked::Body::new(
+                    stream_id.clone(),
+                    max_data,
+                )));
+            }
+        }
+        frames
+    }
+
+    /// STREAM frame which fits in `max_length` bytes, of the streams in turn,
+    /// within the credit of the streams and the connection.
+    pub(crate) fn next_frame(&mut self, max_length: usize) -> Option<Frame> {
+        let after = self
+            .last_sent
+            .as_ref()
+            .map(|last| self.send.range(last..).skip(1))
+            .into_iter()
+            .flatten();
+        let available = self.send_credit.available();
+        let stream_id = after
+            .chain(self.send.iter())
+            .find(|(_, stream)| stream.has_data_to_send(available))
+            .map(|(stream_id, _)| stream_id.clone())?;
+        let stream = self.send.get_mut(&stream_id)?;
+        let sent_offset = stream.sent_offset();
+        let frame = stream.next_frame(max_length, available)?;
+        self.send_credit.on_sent(stream.sent_offset() - sent_offset);
+        self.last_sent = Some(stream_id);
+        Some(frame)
+    }
+
+    /// Must be called when a packet with a STREAM frame is acknowledged.
+    pub(crate) fn on_frame_acked(&mut self, body: &stream::Body) {
+        if let Some(stream) = self.send.get_mut(body.stream_id()) {
+            stream.on_frame_acked(body.offset(), body.data_length(), body.is_fin());
+        }
+        self.remove_if_closed(body.stream_id());
+    }
+
+    /// Must be called when a packet with a STREAM frame is lost.
+    pub(crate) fn on_frame_lost(&mut self, body: &stream::Body) {
+        if let Some(stream) = self.send.get_mut(body.stream_id()) {
+            stream.on_frame_lost(body.offset(), body.data_length(), body.is_fin());
+        }
+    }
+
+    /// Must be called when a packet with a flow control frame is lost.
+    /// The frame is sent again with the current limit if the peer does not have a newer one.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-13.3
+    pub(crate) fn on_flow_control_frame_lost(&mut self, frame: &Frame) {
+        match frame {
+            Frame::MaxData(body) => self.recv_window.on_update_lost(body.maximum_data()),
+            Frame::MaxStreamData(body) => {
+                if let Some(stream) = self.recv.get_mut(body.stream_id()) {
+                    stream
+                        .window_mut()
+                        .on_update_lost(body.maximum_stream_data());
+                    self.consumed.insert(body.stream_id().clone());
+                }
+            }
+            Frame::MaxStreams(body) => {
+                let count = self.count_mut(body.kind());
+                if body.maximum_streams() == count.max_accepted {
+                    count.is_max_update_pending = true;
+                }
+            }
+            Frame::DataBlocked(body) => self.send_credit.on_blocked_lost(body.maximum_data()),
+            Frame::StreamDataBlocked(body) => {
+                if let Some(stream) = self.send.get_mut(body.stream_id()) {
+                    stream
+                        .credit_mut()
+                        .on_blocked_lost(body.maximum_stream_data());
+                }
+            }
+            Frame::StreamsBlocked(body) => {
+                let count = self.count_mut(body.direction());
+                if count.blocked_at == Some(body.maximum_streams()) {
+                    count.blocked = Some(body.maximum_streams());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Must be called when a packet with a RESET_STREAM frame is acknowledged.
+    pub(crate) fn on_reset_acked(&mut self, stream_id: &StreamID) {
+        if let Some(stream) = self.send.get_mut(stream_id) {
+            stream.on_reset_acked();
+        }
+        self.remove_if_closed(stream_id);
+    }
+
+    /// A stream is forgotten when both parts reach a terminal state.
+    fn remove_if_closed(&mut self, stream_id: &StreamID) {
+        let is_send_closed = self.send.get(stream_id).is_none_or(|stream| {
+            matches!(stream.state(), SendState::DataRecvd | SendState::ResetRecvd)
+        });
+        let is_recv_closed = self.recv.get(stream_id).is_none_or(|stream| {
+            matches!(stream.state(), RecvState::DataRead | RecvState::ResetRead)
+        });
+        if !is_send_closed || !is_recv_closed {
+            return;
+        }
+        let is_removed =
+            self.send.remove(stream_id).is_some() | self.recv.remove(stream_id).is_some();
+        // the peer can open another stream
+        if is_removed && !self.is_local(stream_id) {
+            let count = self.count_mut(&stream_id.direction());
+            if count.max_accepted < 1 << 60 {
+                count.max_accepted += 1;
+                count.is_max_update_pending = true;
+            }
+        }
+    }
+
+    pub(crate) fn send_state(&self, stream_id: &StreamID) -> Option<SendState> {
+        self.send.get(stream_id).map(SendStream::state)
+    }
+
+    pub(crate) fn recv_state(&self, stream_id: &StreamID) -> Option<RecvState> {
+        self.recv.get(stream_id).map(RecvStream::state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(bidirectional: u64, unidirectional: u64) -> TransportParameters {
+        TransportParameters {
+            initial_max_data: 1000,
+            initial_max_stream_data_bidi_local: 100,
+            initial_max_stream_data_bidi_remote: 100,
+            initial_max_stream_data_uni: 100,
+            initial_max_streams_bidi: bidirectional,
+            initial_max_streams_uni: unidirectional,
+            ..Default::default()
+        }
+    }
+
+    /// The length of the data in STREAM frames sent until nothing is left to send.
+    fn sent(streams: &mut Streams) -> u64 {
+        std::iter::from_fn(|| streams.next_frame(1200))
+            .map(|frame| match frame {
+                Frame::Stream(body) => body.data_length(),
+                _ => panic!("not a STREAM frame"),
+            })
+            .sum()
+    }
+
+    fn stream_frame(stream_id: u64, offset: u64, data: &[u8], is_fin: bool) -> stream::Body {
+        stream::Body::new(StreamID(stream_id), Some(offset), data.to_vec(), is_fin)
+    }
+
+    #[test]
+    fn stream_id() {
+        let stream_id =
+            StreamID::from_parts(&EndpointType::Server, &StreamDirection::Unidirectional, 2);
+        assert_eq!(stream_id, StreamID(11));
+        assert_eq!(stream_id.initiator(), EndpointType::Server);
+        assert_eq!(stream_id.direction(), StreamDirection::Unidirectional);
+        assert_eq!(stream_id.index(), 2);
+        assert_eq!(StreamID(4).initiator(), EndpointType::Client);
+        assert_eq!(StreamID(4).direction(), StreamDirection::Bidirectional);
+    }
+
+    #[test]
+    fn open() {
+        let mut streams = Streams::new(EndpointType::Server);
+        assert_eq!(
+            streams.open(StreamDirection::Bidirectional),
+            Err(StreamError::StreamLimit)
+        );
+        streams.set_peer_limits(&limits(2, 1));
+        assert_eq!(
+            streams.open(StreamDirection::Bidirectional),
+            Ok(StreamID(1))
+        );
+        assert_eq!(
+            streams.open(StreamDirection::Bidirectional),
+            Ok(StreamID(5))
+        );
+        assert_eq!(
+            streams.open(StreamDirection::Unidirectional),
+            Ok(StreamID(3))
+        );
+        assert_eq!(
+            streams.open(StreamDirection::Unidirectional),
+            Err(StreamError::StreamLimit)
+        );
+        // a unidirectional stream only sends
+        assert!(streams.send_state(&StreamID(3)).is_some());
+        assert!(streams.recv_state(&StreamID(3)).is_none());
+    }
+
+    #[test]
+    fn peer_streams() {
+        let mut streams = Streams::new(EndpointType::Server);
+        streams.set_local_limits(&limits(3, 1));
+        // the streams before it are opened as well
+        streams
+            .on_stream_frame(&stream_frame(8, 0, b"c", false))
+            .unwrap();
+        for stream_id in [0, 4, 8] {
+            assert_eq!(
+                streams.recv_state(&StreamID(stream_id)),
+                Some(RecvState::Recv)
+            );
+            assert_eq!(
+                streams.send_state(&StreamID(stream_id)),
+                Some(SendState::Ready)
+            );
+        }
+        assert_eq!(
+            streams.on_stream_frame(&stream_frame(12, 0, b"d", false)),
+            Err(StreamError::StreamLimit)
+        );
+        assert_eq!(
+            streams.on_stream_frame(&stream_frame(6, 0, b"e", false)),
+            Err(StreamError::StreamLimit)
+        );
+        // streams of the server
+        assert_eq!(
+            streams.on_stream_frame(&stream_frame(1, 0, b"f", false)),
+            Err(StreamError::StreamState)
+        );
+        streams.set_peer_limits(&limits(0, 1));
+        let stream_id = streams.open(StreamDirection::Unidirectional).unwrap();
+        assert_eq!(
+            streams.on_stream_frame(&stream_frame(stream_id.0, 0, b"g", false)),
+            Err(StreamError::StreamState)
+        );
+        // STOP_SENDING for a stream the client only sends on
+        assert_eq!(
+            streams.on_stop_sending(&stop_sending::Body::new(StreamID(2), 0)),
+            Err(StreamError::StreamState)
+        );
+    }
+
+    #[test]
+    fn read_in_order() {
+        let mut streams = Streams::new(EndpointType::Client);
+        streams.set_local_limits(&limits(0, 2));
+        streams
+            .on_stream_frame(&stream_frame(3, 2, b"c", true))
+            .unwrap();
+        streams
+            .on_stream_frame(&stream_frame(7, 0, b"x", false))
+            .unwrap();
+        streams
+            .on_stream_frame(&stream_frame(3, 0, b"ab", false))
+            .unwrap();
+
+        let (stream_id, chunk) = streams.read().unwrap();
+        assert_eq!(stream_id, StreamID(3));
+        let chunk = chunk.unwrap();
+        assert_eq!(
+            (chunk.offset, &chunk.data[..], chunk.is_fin),
+            (0, &b"abc"[..], true)
+        );
+        let (stream_id, chunk) = streams.read().unwrap();
+        assert_eq!(
+            (stream_id, chunk.unwrap().data),
+            (StreamID(7), b"x".to_vec())
+        );
+        assert!(streams.read().is_none());
+        // all the data of the unidirectional stream is read
+        assert!(streams.recv_state(&StreamID(3)).is_none());
+
+        streams
+            .on_reset_stream(&reset_stream::Body::new(StreamID(7), 5, 1))
+            .unwrap();
+        assert_eq!(
+            streams.read(),
+            Some((StreamID(7), Err(StreamError::Reset(5))))
+        );
+        assert!(streams.read().is_none());
+    }
+
+    #[test]
+    fn streams_in_turn() {
+        let mut streams = Streams::new(EndpointType::Client);
+        streams.set_peer_limits(&limits(2, 0));
+        let first = streams.open(StreamDirection::Bidirectional).unwrap();
+        let second = streams.open(StreamDirection::Bidirectional).unwrap();
+        streams.write(&first, &[1; 100]).unwrap();
+        streams.write(&second, &[2; 100]).unwrap();
+
+        let stream_ids = std::iter::from_fn(|| streams.next_frame(50))
+            .map(|frame| match frame {
+                Frame::Stream(body) => body.stream_id().clone(),
+                _ => panic!("not a STREAM frame"),
+            })
+            .take(4)
+            .collect::<Vec<_>>();
+        assert_eq!(stream_ids, [first.clone(), second.clone(), first, second]);
+    }
+
+    #[test]
+    fn blocked() {
+        let now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut streams = Streams::new(EndpointType::Client);
+        streams.set_peer_limits(&TransportParameters {
+            initial_max_data: 150,
+            ..limits(2, 0)
+        });
+        let first = streams.open(StreamDirection::Bidirectional).unwrap();
+        let second = streams.open(StreamDirection::Bidirectional).unwrap();
+        assert_eq!(
+            streams.open(StreamDirection::Bidirectional),
+            Err(StreamError::StreamLimit)
+        );
+        streams.write(&first, &[1; 200]).unwrap();
+        streams.write(&second, &[2; 200]).unwrap();
+        // the first stream by its limit, and the second by the one of the connection
+        assert_eq!(sent(&mut streams), 150);
+        assert_eq!(
+            streams.flow_control_frames(now, rtt),
+            [
+                Frame::StreamsBlocked(streams_blocked::Body::new(
+                    StreamDirection::Bidirectional,
+                    2
+                )),
+                Frame::DataBlocked(data_blocked::Body::new(150)),
+                Frame::StreamDataBlocked(stream_data_blocked::Body::new(first.clone(), 100)),
+            ]
+        );
+        assert!(streams.flow_control_frames(now, rtt).is_empty());
+
+        streams.on_max_data(&max_data::Body::new(1000));
+        streams.on_max_streams(&max_streams::Body::new(StreamDirection::Bidirectional, 3));
+        assert!(streams.open(StreamDirection::Bidirectional).is_ok());
+        assert_eq!(sent(&mut streams), 50);
+        streams
+            .on_max_stream_data(&max_stream_data::Body::new(first.clone(), 150))
+            .unwrap();
+        assert_eq!(sent(&mut streams), 50);
+        assert_eq!(
+            streams.flow_control_frames(now, rtt),
+            [
+                Frame::StreamDataBlocked(stream_data_blocked::Body::new(first, 150)),
+                Frame::StreamDataBlocked(stream_data_blocked::Body::new(second, 100)),
+            ]
+        );
+    }
+
+    #[test]
+    fn credit_is_given_back() {
+        let now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut streams = Streams::new(EndpointType::Server);
+        streams.set_local_limits(&limits(1, 0));
+        streams
+            .on_stream_frame(&stream_frame(0, 0, &[1; 60], false))
+            .unwrap();
+        assert_eq!(
+            streams.on_stream_frame(&stream_frame(0, 60, &[1; 41], false)),
+            Err(StreamError::FlowControl)
+        );
+        let (stream_id, chunk) = streams.read().unwrap();
+        assert_eq!(chunk.unwrap().data.len(), 60);
+        // the data is not consumed yet
+        assert!(streams.flow_control_frames(now, rtt).is_empty());
+        streams.on_consumed(&stream_id, 60);
+        let max_stream_data = || Frame::MaxStreamData(max_stream_data::Body::new(StreamID(0), 160));
+        assert_eq!(streams.flow_control_frames(now, rtt), [max_stream_data()]);
+        // the peer does not have the limit
+        streams
+            .on_stream_data_blocked(&stream_data_blocked::Body::new(StreamID(0), 100))
+            .unwrap();
+        assert_eq!(streams.flow_control_frames(now, rtt), [max_stream_data()]);
+
+        // the peer can open another stream when the stream closes
+        streams
+            .on_stream_frame(&stream_frame(0, 60, b"", true))
+            .unwrap();
+        assert!(streams.read().unwrap().1.unwrap().is_fin);
+        streams.finish(&stream_id).unwrap();
+        let Some(Frame::Stream(fin)) = streams.next_frame(1200) else {
+            panic!("no STREAM frame")
+        };
+        streams.on_frame_acked(&fin);
+        assert!(streams.read().is_none());
+        assert!(streams.send_state(&stream_id).is_none());
+        let max_streams =
+            || Frame::MaxStreams(max_streams::Body::new(StreamDirection::Bidirectional, 2));
+        assert_eq!(streams.flow_control_frames(now, rtt), [max_streams()]);
+        streams.on_streams_blocked(&streams_blocked::Body::new(
+            StreamDirection::Bidirectional,
+            1,
+        ));
+        assert_eq!(streams.flow_control_frames(now, rtt), [max_streams()]);
+    }
+
+    #[test]
+    fn lost_flow_control_frames() {
+        let now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut streams = Streams::new(EndpointType::Client);
+        streams.set_local_limits(&limits(0, 0));
+        streams.set_peer_limits(&limits(1, 0));
+        let stream_id = streams.open(StreamDirection::Bidirectional).unwrap();
+        assert!(streams.open(StreamDirection::Bidirectional).is_err());
+        streams.write(&stream_id, &[1; 200]).unwrap();
+        assert_eq!(sent(&mut streams), 100);
+        streams
+            .on_stream_frame(&stream_frame(0, 0, &[1; 60], false))
+            .unwrap();
+        streams.read().unwrap().1.unwrap();
+        streams.on_consumed(&stream_id, 60);
+        let frames = streams.flow_control_frames(now, rtt);
+        assert_eq!(
+            frames,
+            [
+                Frame::MaxStreamData(max_stream_data::Body::new(stream_id.clone(), 160)),
+                Frame::StreamsBlocked(streams_blocked::Body::new(
+                    StreamDirection::Bidirectional,
+                    1
+                )),
+                Frame::StreamDataBlocked(stream_data_blocked::Body::new(stream_id.clone(), 100)),
+            ]
+        );
+        for frame in &frames {
+            streams.on_flow_control_frame_lost(frame);
+        }
+        assert_eq!(streams.flow_control_frames(now, rtt), frames);
+
+        // the limits have changed since the frames are sent
+        streams
+            .on_max_stream_data(&max_stream_data::Body::new(stream_id.clone(), 200))
+            .unwrap();
+        streams.on_max_streams(&max_streams::Body::new(StreamDirection::Bidirectional, 2));
+        streams.on_consumed(&stream_id, 50);
+        assert_eq!(
+            streams.flow_control_frames(now + rtt * 3, rtt),
+            [Frame::MaxStreamData(max_stream_data::Body::new(
+                stream_id.clone(),
+                210
+            ))]
+        );
+        for frame in &frames {
+            streams.on_flow_control_frame_lost(frame);
+        }
+        assert!(streams.flow_control_frames(now, rtt).is_empty());
+    }
+
+    #[test]
+    fn stop_sending_is_answered() {
+        let mut streams = Streams::new(EndpointType::Client);
+        streams.set_peer_limits(&limits(1, 0));
+        let stream_id = streams.open(StreamDirection::Bidirectional).unwrap();
+        streams.write(&stream_id, b"abc").unwrap();
+        let reset = streams
+            .on_stop_sending(&stop_sending::Body::new(stream_id.clone(), 8))
+            .unwrap();
+        assert_eq!(
+            reset,
+            Some(Frame::ResetStream(reset_stream::Body::new(
+                stream_id.clone(),
+                8,
+                0
+            )))
+        );
+        assert_eq!(
+            streams.write(&stream_id, b"d"),
+            Err(StreamError::Stopped(8))
+        );
+        streams.on_reset_acked(&stream_id);
+        assert_eq!(streams.send_state(&stream_id), Some(SendState::ResetRecvd));
+    }
+}
@@ -0,0 +1,234 @@
+//! Credit-based flow control of streams and of the connection.
+//! https://www.rfc-editor.org/rfc/rfc9000.html#name-flow-control
+
+use std::time::{Duration, Instant};
+
+use super::StreamError;
+
+/// The receive window of a stream grows up to this.
+pub(crate) const MAX_STREAM_WINDOW: u64 = 16 * 1024 * 1024;
+/// The receive window of the connection grows up to this.
+pub(crate) const MAX_CONNECTION_WINDOW: u64 = 24 * 1024 * 1024;
+
+/// The limit of the peer on the data this endpoint sends.
+#[derive(Debug, Default)]
+pub(crate) struct SendCredit {
+    max_data: u64,
+    sent: u64,
+    /// The limit a BLOCKED frame is sent at. It is sent once for each limit.
+    blocked_at: Option<u64>,
+}
+
+impl SendCredit {
+    pub(crate) fn new(max_data: u64) -> Self {
+        Self {
+            max_data,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn available(&self) -> u64 {
+        self.max_data.saturating_sub(self.sent)
+    }
+
+    /// A limit smaller than the current one is ignored, since the frames may arrive out of order.
+    pub(crate) fn on_max_data(&mut self, max_data: u64) {
+        self.max_data = self.max_data.max(max_data);
+    }
+
+    pub(crate) fn on_sent(&mut self, length: u64) {
+        self.sent += length;
+    }
+
+    /// The limit to report in a BLOCKED frame, if no credit is left and it is not reported yet.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-4.1
+    pub(crate) fn blocked(&mut self) -> Option<u64> {
+        if self.available() > 0 || self.blocked_at == Some(self.max_data) {
+            return None;
+        }
+        self.blocked_at = Some(self.max_data);
+        Some(self.max_data)
+    }
+
+    /// A BLOCKED frame is lost, which is sent again if the limit is still the same.
+    pub(crate) fn on_blocked_lost(&mut self, max_data: u64) {
+        if self.blocked_at == Some(max_data) {
+            self.blocked_at = None;
+        }
+    }
+}
+
+/// The limit this endpoint gives the peer.
+/// The limit is raised when the application consumes half of the window,
+/// and the window doubles when that happens within two round trips,
+/// so that the window keeps up with the bandwidth-delay product.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#section-4.2
+#[derive(Debug)]
+pub(crate) struct RecvWindow {
+    /// The limit advertised to the peer.
+    max_data: u64,
+    window: u64,
+    max_window: u64,
+    /// The largest offset received, which counts against the limit.
+    received: u64,
+    /// Data consumed by the application, or discarded.
+    consumed: u64,
+    /// When the limit is raised last.
+    updated_at: Option<Instant>,
+    /// The limit is sent again even if it does not change, when the peer may not have it.
+    is_update_forced: bool,
+}
+
+impl RecvWindow {
+    pub(crate) fn new(window: u64, max_window: u64) -> Self {
+        Self {
+            max_data: window,
+            window,
+            max_window: max_window.max(window),
+            received: 0,
+            consumed: 0,
+            updated_at: None,
+            is_update_forced: false,
+        }
+    }
+
+    pub(crate) fn window(&self) -> u64 {
+        self.window
+    }
+
+    pub(crate) fn received(&self) -> u64 {
+        self.received
+    }
+
+    pub(crate) fn on_received(&mut self, end: u64) -> Result<(), StreamError> {
+        if end > self.max_data {
+            return Err(StreamError::FlowControl);
+        }
+        self.received = self.received.max(end);
+        Ok(())
+    }
+
+    pub(crate) fn on_consumed(&mut self, length: u64) {
+        self.consumed += length;
+    }
+
+    /// The peer is blocked at the limit. If it is smaller than the current one,
+    /// the frame which raised the limit may be lost, so the limit is sent again.
+    pub(crate) fn on_blocked(&mut self, max_data: u64) {
+        if max_data < self.max_data {
+            self.is_update_forced = true;
+        }
+    }
+
+    /// A frame with the limit is lost. It is sent again unless a larger one is sent after it.
+    pub(crate) fn on_update_lost(&mut self, max_data: u64) {
+        if max_data == self.max_data {
+            self.is_update_forced = true;
+        }
+    }
+
+    /// Grow the window to at least `window`, to keep the connection window larger than the ones of streams.
+    pub(crate) fn ensure_window(&mut self, window: u64) {
+        self.window = self.window.max(window.min(self.max_window));
+    }
+
+    /// The limit to send, if the application has consumed half of the window,
+    /// or if the peer may not have the current one.
+    pub(crate) fn update(&mut self, now: Instant, rtt: Duration) -> Option<u64> {
+        let is_due = self.max_data - self.consumed <= self.window / 2
+            && self.consumed + self.window > self.max_data;
+        if !is_due && !self.is_update_forced {
+            return None;
+        }
+        self.is_update_forced = false;
+        if is_due {
+            if self
+                .updated_at
+                .is_some_and(|updated_at| now.duration_since(updated_at) < rtt * 2)
+            {
+                self.window = (self.window * 2).min(self.max_window);
+            }
+            self.updated_at = Some(now);
+            self.max_data = self.consumed + self.window;
+        }
+        Some(self.max_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_credit() {
+        let mut credit = SendCredit::new(100);
+        credit.on_sent(60);
+        assert_eq!(credit.available(), 40);
+        assert_eq!(credit.blocked(), None);
+        credit.on_sent(40);
+        assert_eq!(credit.blocked(), Some(100));
+        assert_eq!(credit.blocked(), None);
+        credit.on_max_data(50);
+        assert_eq!(credit.available(), 0);
+        credit.on_max_data(150);
+        assert_eq!(credit.available(), 50);
+        credit.on_sent(50);
+        assert_eq!(credit.blocked(), Some(150));
+        // STREAM_DATA_BLOCKED is lost
+        credit.on_blocked_lost(100);
+        assert_eq!(credit.blocked(), None);
+        credit.on_blocked_lost(150);
+        assert_eq!(credit.blocked(), Some(150));
+    }
+
+    #[test]
+    fn recv_window() {
+        let now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut window = RecvWindow::new(100, 1000);
+        window.on_received(80).unwrap();
+        assert_eq!(window.on_received(101), Err(StreamError::FlowControl));
+        window.on_consumed(49);
+        assert_eq!(window.update(now, rtt), None);
+        window.on_consumed(1);
+        assert_eq!(window.update(now, rtt), Some(150));
+
+        // consumed slowly
+        window.on_consumed(50);
+        assert_eq!(window.update(now + rtt * 3, rtt), Some(200));
+        assert_eq!(window.window(), 100);
+
+        // consumed within two round trips
+        window.on_consumed(50);
+        assert_eq!(window.update(now + rtt * 4, rtt), Some(350));
+        assert_eq!(window.window(), 200);
+        window.on_consumed(150);
+        assert_eq!(window.update(now + rtt * 5, rtt), Some(700));
+        window.on_consumed(300);
+        assert_eq!(window.update(now + rtt * 6, rtt), Some(1400));
+        assert_eq!(window.window(), 800);
+        window.on_consumed(700);
+        assert_eq!(window.update(now + rtt * 7, rtt), Some(2300));
+        assert_eq!(window.window(), 1000);
+    }
+
+    #[test]
+    fn blocked_peer() {
+        let now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        let mut window = RecvWindow::new(100, 100);
+        window.on_blocked(100);
+        assert_eq!(window.update(now, rtt), None);
+        window.on_consumed(60);
+        assert_eq!(window.update(now, rtt), Some(160));
+        // MAX_DATA is lost
+        window.on_blocked(100);
+        assert_eq!(window.update(now, rtt), Some(160));
+        assert_eq!(window.update(now, rtt), None);
+        // only the latest limit is sent again
+        window.on_update_lost(150);
+        assert_eq!(window.update(now, rtt), None);
+        window.on_update_lost(160);
+        assert_eq!(window.update(now, rtt), Some(160));
+    }
+}
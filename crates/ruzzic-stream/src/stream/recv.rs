@@ -0,0 +1,324 @@
+//! The receiving part of a stream.
+//! https://www.rfc-editor.org/rfc/rfc9000.html#name-receiving-stream-states
+
+use std::collections::BTreeMap;
+
+use super::{flow_control::RecvWindow, StreamError, StreamID};
+use crate::frame::{stop_sending, Frame};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvState {
+    Recv,
+    /// The FIN bit is received.
+    SizeKnown,
+    /// All the data is received.
+    DataRecvd,
+    ResetRecvd,
+    /// All the data is delivered to the application.
+    DataRead,
+    /// The application is told that the stream is reset.
+    ResetRead,
+}
+
+/// Data delivered in order.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Chunk {
+    pub(crate) offset: u64,
+    pub(crate) data: Vec<u8>,
+    pub(crate) is_fin: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct RecvStream {
+    stream_id: StreamID,
+    state: RecvState,
+    /// Data received out of order, by offset.
+    buffer: BTreeMap<u64, Vec<u8>>,
+    /// Offset up to which data is delivered.
+    read_offset: u64,
+    window: RecvWindow,
+    final_size: Option<u64>,
+    reset_error_code: Option<u64>,
+    /// Data is discarded after the application asks the peer to stop sending.
+    is_stopped: bool,
+    /// Data not delivered because of a reset or STOP_SENDING,
+    /// which is given back to the connection window.
+    discarded: u64,
+}
+
+impl RecvStream {
+    pub(crate) fn new(stream_id: StreamID, window: RecvWindow) -> Self {
+        Self {
+            stream_id,
+            state: RecvState::Recv,
+            buffer: BTreeMap::new(),
+            read_offset: 0,
+            window,
+            final_size: None,
+            reset_error_code: None,
+            is_stopped: false,
+            discarded: 0,
+        }
+    }
+
+    pub(crate) fn state(&self) -> RecvState {
+        self.state
+    }
+
+    pub(crate) fn window_mut(&mut self) -> &mut RecvWindow {
+        &mut self.window
+    }
+
+    /// The largest offset received, which counts against the connection window.
+    pub(crate) fn received(&self) -> u64 {
+        self.window.received()
+    }
+
+    /// Whether more data may come, for which the peer needs MAX_STREAM_DATA.
+    pub(crate) fn is_receiving(&self) -> bool {
+        self.state == RecvState::Recv && !self.is_stopped
+    }
+
+    pub(crate) fn take_discarded(&mut self) -> u64 {
+        std::mem::take(&mut self.discarded)
+    }
+
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-stream-final-size
+    fn check_final_size(&self, end: u64, is_fin: bool) -> Result<(), StreamError> {
+        match self.final_size {
+            Some(final_size) if end > final_size || (is_fin && end != final_size) => {
+                Err(StreamError::FinalSize)
+            }
+            None if is_fin && end < self.window.received() => Err(StreamError::FinalSize),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn on_stream_frame(
+        &mut self,
+        offset: u64,
+        data: &[u8],
+        is_fin: bool,
+    ) -> Result<(), StreamError> {
+        let end = offset + data.len() as u64;
+        self.check_final_size(end, is_fin)?;
+        let received = self.window.received();
+        self.window.on_received(end)?;
+        if is_fin {
+            self.final_size = Some(end);
+        }
+        if self.state == RecvState::Recv && is_fin {
+            self.state = RecvState::SizeKnown;
+        }
+        if !matches!(self.state, RecvState::Recv | RecvState::SizeKnown) || self.is_stopped {
+            self.discarded += self.window.received() - received;
+            return Ok(());
+        }
+        if end > self.read_offset {
+            let skip = self.read_offset.saturating_sub(offset);
+            let buffered = self.buffer.entry(offset + skip).or_default();
+            if data.len() - skip as usize > buffered.len() {
+                *buffered = data[skip as usize..].to_vec();
+            }
+        }
+        if self.state == RecvState::SizeKnown && self.is_complete() {
+            self.state = RecvState::DataRecvd;
+        }
+        Ok(())
+    }
+
+    /// Whether the data up to the final size is in the buffer.
+    fn is_complete(&self) -> bool {
+        let mut offset = self.read_offset;
+        for (start, data) in &self.buffer {
+            if *start > offset {
+                return false;
+            }
+            offset = offset.max(start + data.len() as u64);
+        }
+        Some(offset) == self.final_size
+    }
+
+    /// Returns whether the application has to be told.
+    pub(crate) fn on_reset(
+        &mut self,
+        error_code: u64,
+        final_size: u64,
+    ) -> Result<bool, StreamError> {
+        self.check_final_size(final_size, true)?;
+        let received = self.window.received();
+        self.window.on_received(final_size)?;
+        self.final_size = Some(final_size);
+        if !matches!(self.state, RecvState::Recv | RecvState::SizeKnown) {
+            return Ok(false);
+        }
+        // nothing after what is delivered is read
+        self.discarded += if self.is_stopped {
+            final_size - received
+        } else {
+            final_size - self.read_offset
+        };
+        self.state = RecvState::ResetRecvd;
+        self.reset_error_code = Some(error_code);
+        self.buffer.clear();
+        Ok(true)
+    }
+
+    /// Ask the peer to stop sending, unless all the data is received.
+    pub(crate) fn stop_sending(&mut self, error_code: u64) -> Option<Frame> {
+        if !matches!(self.state, RecvState::Recv | RecvState::SizeKnown) || self.is_stopped {
+            return None;
+        }
+        self.is_stopped = true;
+        self.buffer.clear();
+        self.discarded += self.window.received() - self.read_offset;
+        Some(Frame::StopSending(stop_sending::Body::new(
+            self.stream_id.clone(),
+            error_code,
+        )))
+    }
+
+    /// Data contiguous with what is delivered so far, or the error code the peer reset the stream with.
+    pub(crate) fn read(&mut self) -> Result<Option<Chunk>, StreamError> {
+        if self.state == RecvState::ResetRecvd {
+            self.state = RecvState::ResetRead;
+            return Err(StreamError::Reset(self.reset_error_code.unwrap_or(0)));
+        }
+        if matches!(self.state, RecvState::DataRead | RecvState::ResetRead) || self.is_stopped {
+            return Ok(None);
+        }
+        let offset = self.read_offset;
+        let mut data = Vec::new();
+        while let Some(entry) = self.buffer.first_entry() {
+            if *entry.key() > self.read_offset {
+                break;
+            }
+            let (start, buffered) = entry.remove_entry();
+            let skip = (self.read_offset - start) as usize;
+            if skip < buffered.len() {
+                data.extend_from_slice(&buffered[skip..]);
+                self.read_offset += (buffered.len() - skip) as u64;
+            }
+        }
+        let is_fin =
+            self.state == RecvState::DataRecvd && Some(self.read_offset) == self.final_size;
+        if data.is_empty() && !is_fin {
+            return Ok(None);
+        }
+        if is_fin {
+            self.state = RecvState::DataRead;
+        }
+        Ok(Some(Chunk {
+            offset,
+            data,
+            is_fin,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(offset: u64, data: &[u8], is_fin: bool) -> Chunk {
+        Chunk {
+            offset,
+            data: data.to_vec(),
+            is_fin,
+        }
+    }
+
+    #[test]
+    fn reassembly() {
+        let mut stream = RecvStream::new(StreamID(0), RecvWindow::new(100, 100));
+        stream.on_stream_frame(3, b"def", false).unwrap();
+        assert_eq!(stream.read(), Ok(None));
+        stream.on_stream_frame(0, b"abc", false).unwrap();
+        stream.on_stream_frame(8, b"i", true).unwrap();
+        assert_eq!(stream.state(), RecvState::SizeKnown);
+        assert_eq!(stream.read(), Ok(Some(chunk(0, b"abcdef", false))));
+
+        // overlaps what is delivered
+        stream.on_stream_frame(5, b"fgh", false).unwrap();
+        assert_eq!(stream.state(), RecvState::DataRecvd);
+        assert_eq!(stream.read(), Ok(Some(chunk(6, b"ghi", true))));
+        assert_eq!(stream.state(), RecvState::DataRead);
+        assert_eq!(stream.read(), Ok(None));
+    }
+
+    #[test]
+    fn empty_fin() {
+        let mut stream = RecvStream::new(StreamID(0), RecvWindow::new(100, 100));
+        stream.on_stream_frame(0, b"ab", false).unwrap();
+        assert_eq!(stream.read(), Ok(Some(chunk(0, b"ab", false))));
+        stream.on_stream_frame(2, b"", true).unwrap();
+        assert_eq!(stream.read(), Ok(Some(chunk(2, b"", true))));
+    }
+
+    #[test]
+    fn final_size() {
+        let mut stream = RecvStream::new(StreamID(0), RecvWindow::new(100, 100));
+        stream.on_stream_frame(0, b"abcd", false).unwrap();
+        assert_eq!(
+            stream.on_stream_frame(0, b"ab", true),
+            Err(StreamError::FinalSize)
+        );
+        stream.on_stream_frame(4, b"e", true).unwrap();
+        assert_eq!(
+            stream.on_stream_frame(5, b"f", false),
+            Err(StreamError::FinalSize)
+        );
+        assert_eq!(
+            stream.on_stream_frame(0, b"abcdef", true),
+            Err(StreamError::FinalSize)
+        );
+        // the same frame again
+        stream.on_stream_frame(4, b"e", true).unwrap();
+        assert_eq!(stream.on_reset(1, 4), Err(StreamError::FinalSize));
+    }
+
+    #[test]
+    fn reset() {
+        let mut stream = RecvStream::new(StreamID(1), RecvWindow::new(100, 100));
+        stream.on_stream_frame(0, b"abc", false).unwrap();
+        stream.on_stream_frame(5, b"f", false).unwrap();
+        assert_eq!(stream.on_reset(9, 5), Err(StreamError::FinalSize));
+        assert_eq!(stream.on_reset(9, 10), Ok(true));
+        assert_eq!(stream.state(), RecvState::ResetRecvd);
+        // none of the data is delivered
+        assert_eq!(stream.take_discarded(), 10);
+        assert_eq!(stream.read(), Err(StreamError::Reset(9)));
+        assert_eq!(stream.state(), RecvState::ResetRead);
+        assert_eq!(stream.read(), Ok(None));
+        assert_eq!(stream.on_reset(9, 10), Ok(false));
+        // data after the reset is ignored
+        stream.on_stream_frame(3, b"de", false).unwrap();
+        assert_eq!(stream.read(), Ok(None));
+    }
+
+    #[test]
+    fn stop_sending() {
+        let mut stream = RecvStream::new(StreamID(0), RecvWindow::new(100, 100));
+        stream.on_stream_frame(0, b"abc", false).unwrap();
+        assert_eq!(
+            stream.stop_sending(4),
+            Some(Frame::StopSending(stop_sending::Body::new(StreamID(0), 4)))
+        );
+        assert!(stream.stop_sending(4).is_none());
+        stream.on_stream_frame(3, b"d", true).unwrap();
+        assert_eq!(stream.read(), Ok(None));
+        assert_eq!(stream.take_discarded(), 4);
+    }
+
+    #[test]
+    fn flow_control() {
+        let mut stream = RecvStream::new(StreamID(0), RecvWindow::new(4, 4));
+        stream.on_stream_frame(2, b"cd", false).unwrap();
+        assert_eq!(
+            stream.on_stream_frame(3, b"de", false),
+            Err(StreamError::FlowControl)
+        );
+        assert_eq!(stream.on_reset(0, 5), Err(StreamError::FlowControl));
+        assert_eq!(stream.received(), 4);
+    }
+}
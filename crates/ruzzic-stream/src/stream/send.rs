@@ -0,0 +1,400 @@
+//! The sending part of a stream.
+//! https://www.rfc-editor.org/rfc/rfc9000.html#name-sending-stream-states
+
+use std::collections::{BTreeMap, VecDeque};
+
+use super::{flow_control::SendCredit, StreamError, StreamID};
+use crate::{
+    frame::{reset_stream, stream, Frame},
+    size_of_varint,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendState {
+    Ready,
+    Send,
+    /// All the data and the FIN bit are sent.
+    DataSent,
+    ResetSent,
+    DataRecvd,
+    ResetRecvd,
+}
+
+#[derive(Debug)]
+pub(crate) struct SendStream {
+    stream_id: StreamID,
+    state: SendState,
+    /// Data written by the application and not acknowledged yet, from `buffer_offset`.
+    buffer: VecDeque<u8>,
+    buffer_offset: u64,
+    /// Offset up to which data is sent for the first time.
+    sent_offset: u64,
+    /// Ranges to send again, by start offset to end offset.
+    lost: BTreeMap<u64, u64>,
+    /// Ranges acknowledged beyond `buffer_offset`.
+    acked: BTreeMap<u64, u64>,
+    is_finished: bool,
+    is_fin_sent: bool,
+    is_fin_acked: bool,
+    /// The error code the peer asked to stop sending with.
+    stopped: Option<u64>,
+    credit: SendCredit,
+}
+
+impl SendStream {
+    pub(crate) fn new(stream_id: StreamID, max_data: u64) -> Self {
+        Self {
+            stream_id,
+            state: SendState::Ready,
+            buffer: VecDeque::new(),
+            buffer_offset: 0,
+            sent_offset: 0,
+            lost: BTreeMap::new(),
+            acked: BTreeMap::new(),
+            is_finished: false,
+            is_fin_sent: false,
+            is_fin_acked: false,
+            stopped: None,
+            credit: SendCredit::new(max_data),
+        }
+    }
+
+    pub(crate) fn state(&self) -> SendState {
+        self.state
+    }
+
+    pub(crate) fn credit_mut(&mut self) -> &mut SendCredit {
+        &mut self.credit
+    }
+
+    /// Offset up to which data is sent, which counts against the connection limit.
+    pub(crate) fn sent_offset(&self) -> u64 {
+        self.sent_offset
+    }
+
+    /// Offset of the end of the data written.
+    fn write_offset(&self) -> u64 {
+        self.buffer_offset + self.buffer.len() as u64
+    }
+
+    pub(crate) fn write(&mut self, data: &[u8]) -> Result<(), StreamError> {
+        self.check_writable()?;
+        self.buffer.extend(data);
+        Ok(())
+    }
+
+    /// No more data is written after this. The FIN bit is sent with the last data.
+    pub(crate) fn finish(&mut self) -> Result<(), StreamError> {
+        self.check_writable()?;
+        self.is_finished = true;
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), StreamError> {
+        if let Some(error_code) = self.stopped {
+            return Err(StreamError::Stopped(error_code));
+        }
+        if self.is_finished || matches!(self.state, SendState::ResetSent | SendState::ResetRecvd) {
+            return Err(StreamError::Finished);
+        }
+        Ok(())
+    }
+
+    /// Abandon the data and return RESET_STREAM to send,
+    /// unless all the data is already acknowledged or the stream is reset.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-operations-on-streams
+    pub(crate) fn reset(&mut self, error_code: u64) -> Option<Frame> {
+        if matches!(
+            self.state,
+            SendState::DataRecvd | SendState::ResetSent | SendState::ResetRecvd
+        ) {
+            return None;
+        }
+        self.state = SendState::ResetSent;
+        self.buffer.clear();
+        self.lost.clear();
+        self.acked.clear();
+        // the final size is the amount of data sent
+        Some(Frame::ResetStream(reset_stream::Body::new(
+            self.stream_id.clone(),
+            error_code,
+            self.sent_offset,
+        )))
+    }
+
+    /// The stream is reset with the error code of STOP_SENDING.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-solicited-state-transitions
+    pub(crate) fn on_stop_sending(&mut self, error_code: u64) -> Option<Frame> {
+        self.stopped.get_or_insert(error_code);
+        self.reset(error_code)
+    }
+
+    pub(crate) fn on_reset_acked(&mut self) {
+        if self.state == SendState::ResetSent {
+            self.state = SendState::ResetRecvd;
+        }
+    }
+
+    /// Data written and not sent yet, which needs flow control credit.
+    pub(crate) fn has_new_data(&self) -> bool {
+        matches!(
+            self.state,
+            SendState::Ready | SendState::Send | SendState::DataSent
+        ) && self.sent_offset < self.write_offset()
+    }
+
+    /// Whether a frame can be sent with `available` bytes of connection credit.
+    pub(crate) fn has_data_to_send(&self, available: u64) -> bool {
+        match self.state {
+            SendState::Ready | SendState::Send | SendState::DataSent => {
+                !self.lost.is_empty()
+                    || (self.has_new_data() && self.credit.available().min(available) > 0)
+                    || (self.is_finished
+                        && !self.is_fin_sent
+                        && self.sent_offset == self.write_offset())
+            }
+            _ => false,
+        }
+    }
+
+    /// The limit to report in STREAM_DATA_BLOCKED, if data waits for credit of the stream.
+    pub(crate) fn blocked(&mut self) -> Option<u64> {
+        if !self.has_new_data() {
+            return None;
+        }
+        self.credit.blocked()
+    }
+
+    /// STREAM frame which fits in `max_length` bytes,
+    /// of the data lost first and then of the data not sent yet within the credit.
+    /// `available` is the credit of the connection.
+    pub(crate) fn next_frame(&mut self, max_length: usize, available: u64) -> Option<Frame> {
+        if !self.has_data_to_send(available) {
+            return None;
+        }
+        let (offset, end) = self.next_range(available)?;
+        let overhead = 1
+            + size_of_varint(self.stream_id.to_u64())
+            + size_of_varint(offset)
+            + size_of_varint(max_length as u64);
+        let length = (end - offset).min(max_length.checked_sub(overhead)? as u64);
+        let end = offset + length;
+        let is_fin = self.is_finished && end == self.write_offset();
+        if length == 0 && (!is_fin || self.is_fin_sent) {
+            return None;
+        }
+        if end < self.lost.get(&offset).copied().unwrap_or(0) {
+            let lost_end = self.lost.remove(&offset).unwrap();
+            self.lost.insert(end, lost_end);
+        } else {
+            self.lost.remove(&offset);
+        }
+        self.credit.on_sent(end.saturating_sub(self.sent_offset));
+        self.sent_offset = self.sent_offset.max(end);
+        self.is_fin_sent |= is_fin;
+        self.state = if self.is_fin_sent && self.sent_offset == self.write_offset() {
+            SendState::DataSent
+        } else {
+            SendState::Send
+        };
+        let start = (offset - self.buffer_offset) as usize;
+        let data = self
+            .buffer
+            .range(start..start + length as usize)
+            .copied()
+            .collect();
+        Some(Frame::Stream(stream::Body::new(
+            self.stream_id.clone(),
+            Some(offset),
+            data,
+            is_fin,
+        )))
+    }
+
+    /// The range of the lost data or the data not sent, in the buffer.
+    fn next_range(&mut self, available: u64) -> Option<(u64, u64)> {
+        while let Some((&start, &end)) = self.lost.first_key_value() {
+            // acknowledged later on
+            if end <= self.buffer_offset {
+                self.lost.remove(&start);
+                continue;
+            }
+            if start < self.buffer_offset {
+                self.lost.remove(&start);
+                self.lost.insert(self.buffer_offset, end);
+                continue;
+            }
+            return Some((start, end));
+        }
+        let credit = self.credit.available().min(available);
+        Some((
+            self.sent_offset,
+            self.write_offset().min(self.sent_offset + credit),
+        ))
+    }
+
+    /// Must be called when a packet with the STREAM frame is acknowledged.
+    pub(crate) fn on_frame_acked(&mut self, offset: u64, length: u64, is_fin: bool) {
+        if matches!(self.state, SendState::ResetSent | SendState::ResetRecvd) {
+            return;
+        }
+        let end = offset + length;
+        if end > self.buffer_offset {
+            let acked_end = self.acked.entry(offset).or_insert(end);
+            *acked_end = (*acked_end).max(end);
+        }
+        self.is_fin_acked |= is_fin;
+        // release the data acknowledged from the beginning of the buffer
+        while let Some((&start, &end)) = self.acked.first_key_value() {
+            if start > self.buffer_offset {
+                break;
+            }
+            self.acked.remove(&start);
+            if end > self.buffer_offset {
+                self.buffer.drain(..(end - self.buffer_offset) as usize);
+                self.buffer_offset = end;
+            }
+        }
+        if self.state == SendState::DataSent && self.is_fin_acked && self.buffer.is_empty() {
+            self.state = SendState::DataRecvd;
+        }
+    }
+
+    /// Must be called when a packet with the STREAM frame is lost.
+    pub(crate) fn on_frame_lost(&mut self, offset: u64, length: u64, is_fin: bool) {
+        if matches!(
+            self.state,
+            SendState::ResetSent | SendState::ResetRecvd | SendState::DataRecvd
+        ) {
+            return;
+        }
+        let end = offset + length;
+        if end > self.buffer_offset {
+            let lost_end = self.lost.entry(offset).or_insert(end);
+            *lost_end = (*lost_end).max(end);
+        }
+        if is_fin && !self.is_fin_acked {
+            self.is_fin_sent = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_frame(frame: Option<Frame>) -> stream::Body {
+        match frame {
+            Some(Frame::Stream(body)) => body,
+            frame => panic!("not a STREAM frame: {frame:?}"),
+        }
+    }
+
+    #[test]
+    fn send_and_acknowledge() {
+        let mut stream = SendStream::new(StreamID(0), 1000);
+        assert!(stream.next_frame(100, 1000).is_none());
+        stream.write(&[1; 50]).unwrap();
+        stream.write(&[2; 50]).unwrap();
+        stream.finish().unwrap();
+        assert_eq!(stream.write(b"late"), Err(StreamError::Finished));
+
+        let first = stream_frame(stream.next_frame(60, 1000));
+        // 4 bytes for the frame type, the stream id, the offset and the length
+        assert_eq!((first.offset(), first.data().len()), (0, 56));
+        assert!(!first.is_fin());
+        assert_eq!(stream.state(), SendState::Send);
+        let second = stream_frame(stream.next_frame(100, 1000));
+        assert_eq!((second.offset(), second.data().len()), (56, 44));
+        assert!(second.is_fin());
+        assert_eq!(stream.state(), SendState::DataSent);
+        assert!(stream.next_frame(100, 1000).is_none());
+
+        // out of order
+        stream.on_frame_acked(56, 44, true);
+        assert_eq!(stream.buffer.len(), 100);
+        stream.on_frame_acked(0, 56, false);
+        assert!(stream.buffer.is_empty());
+        assert_eq!(stream.state(), SendState::DataRecvd);
+    }
+
+    #[test]
+    fn retransmission() {
+        let mut stream = SendStream::new(StreamID(4), 1000);
+        stream.write(&(0..100).collect::<Vec<u8>>()).unwrap();
+        let first = stream_frame(stream.next_frame(1200, 1000));
+        assert_eq!(first.data().len(), 100);
+        stream.write(&[100; 10]).unwrap();
+        stream.finish().unwrap();
+
+        stream.on_frame_acked(0, 20, false);
+        stream.on_frame_lost(0, 100, false);
+        // the lost data is sent again before the new data, except what is acknowledged
+        let resent = stream_frame(stream.next_frame(50, 1000));
+        assert_eq!(resent.offset(), 20);
+        assert_eq!(resent.data(), &(20..66).collect::<Vec<u8>>()[..]);
+        let resent = stream_frame(stream.next_frame(1200, 1000));
+        assert_eq!((resent.offset(), resent.data().len()), (66, 34));
+        assert!(!resent.is_fin());
+        let last = stream_frame(stream.next_frame(1200, 1000));
+        assert_eq!((last.offset(), last.data()), (100, &[100; 10][..]));
+        assert!(last.is_fin());
+
+        // only the FIN bit is lost
+        stream.on_frame_acked(20, 90, false);
+        stream.on_frame_lost(100, 0, true);
+        let fin = stream_frame(stream.next_frame(1200, 1000));
+        assert_eq!((fin.offset(), fin.data().len()), (110, 0));
+        assert!(fin.is_fin());
+    }
+
+    #[test]
+    fn reset() {
+        let mut stream = SendStream::new(StreamID(1), 1000);
+        stream.write(&[1; 30]).unwrap();
+        stream_frame(stream.next_frame(20, 1000));
+        let Some(Frame::ResetStream(reset)) = stream.reset(7) else {
+            panic!("no RESET_STREAM frame")
+        };
+        // the final size is what is sent
+        assert_eq!(reset, reset_stream::Body::new(StreamID(1), 7, 16));
+        assert_eq!(stream.state(), SendState::ResetSent);
+        assert!(stream.next_frame(100, 1000).is_none());
+        assert!(stream.reset(7).is_none());
+        assert_eq!(stream.write(b"late"), Err(StreamError::Finished));
+        stream.on_reset_acked();
+        assert_eq!(stream.state(), SendState::ResetRecvd);
+    }
+
+    #[test]
+    fn stop_sending() {
+        let mut stream = SendStream::new(StreamID(0), 1000);
+        stream.write(&[1; 10]).unwrap();
+        let Some(Frame::ResetStream(reset)) = stream.on_stop_sending(3) else {
+            panic!("no RESET_STREAM frame")
+        };
+        assert_eq!(reset, reset_stream::Body::new(StreamID(0), 3, 0));
+        assert_eq!(stream.write(b"late"), Err(StreamError::Stopped(3)));
+    }
+
+    #[test]
+    fn flow_control() {
+        let mut stream = SendStream::new(StreamID(0), 10);
+        stream.write(&[1; 30]).unwrap();
+        stream.finish().unwrap();
+        assert_eq!(stream_frame(stream.next_frame(100, 1000)).data().len(), 10);
+        assert!(!stream.has_data_to_send(1000));
+        assert_eq!(stream.blocked(), Some(10));
+        assert_eq!(stream.blocked(), None);
+
+        // the connection has less credit than the stream
+        stream.credit_mut().on_max_data(40);
+        let frame = stream_frame(stream.next_frame(100, 5));
+        assert_eq!((frame.offset(), frame.data().len()), (10, 5));
+        assert!(!stream.has_data_to_send(0));
+        let last = stream_frame(stream.next_frame(100, 1000));
+        assert_eq!((last.offset(), last.data().len()), (15, 15));
+        assert!(last.is_fin());
+        assert_eq!(stream.blocked(), None);
+    }
+}
@@ -0,0 +1,693 @@
+use std::{
+    io::Cursor,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+};
+
+use ruzzic_common::EndpointType;
+
+use crate::{connection::ConnectionID, read_varint, u64_to_varint_exact_size};
+
+const ORIGINAL_DESTINATION_CONNECTION_ID: u64 = 0x00;
+const MAX_IDLE_TIMEOUT: u64 = 0x01;
+const STATELESS_RESET_TOKEN: u64 = 0x02;
+const MAX_UDP_PAYLOAD_SIZE: u64 = 0x03;
+const INITIAL_MAX_DATA: u64 = 0x04;
+const INITIAL_MAX_STREAM_DATA_BIDI_LOCAL: u64 = 0x05;
+const INITIAL_MAX_STREAM_DATA_BIDI_REMOTE: u64 = 0x06;
+const INITIAL_MAX_STREAM_DATA_UNI: u64 = 0x07;
+const INITIAL_MAX_STREAMS_BIDI: u64 = 0x08;
+const INITIAL_MAX_STREAMS_UNI: u64 = 0x09;
+const ACK_DELAY_EXPONENT: u64 = 0x0a;
+const MAX_ACK_DELAY: u64 = 0x0b;
+const DISABLE_ACTIVE_MIGRATION: u64 = 0x0c;
+const PREFERRED_ADDRESS: u64 = 0x0d;
+const ACTIVE_CONNECTION_ID_LIMIT: u64 = 0x0e;
+const INITIAL_SOURCE_CONNECTION_ID: u64 = 0x0f;
+const RETRY_SOURCE_CONNECTION_ID: u64 = 0x10;
+
+/// Parameters a client must not send.
+const SERVER_ONLY: [u64; 4] = [
+    ORIGINAL_DESTINATION_CONNECTION_ID,
+    STATELESS_RESET_TOKEN,
+    PREFERRED_ADDRESS,
+    RETRY_SOURCE_CONNECTION_ID,
+];
+
+const MAX_CONNECTION_ID_LENGTH: usize = 20;
+const MAX_STREAMS: u64 = 1 << 60;
+
+/// The quic_transport_parameters TLS extension.
+/// Absent parameters take the default values.
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-transport-parameter-definit
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransportParameters {
+    pub original_destination_connection_id: Option<ConnectionID>,
+    /// In milliseconds. 0 disables the idle timeout.
+    pub max_idle_timeout: u64,
+    pub stateless_reset_token: Option<u128>,
+    pub max_udp_payload_size: u64,
+    pub initial_max_data: u64,
+    pub initial_max_stream_data_bidi_local: u64,
+    pub initial_max_stream_data_bidi_remote: u64,
+    pub initial_max_stream_data_uni: u64,
+    pub initial_max_streams_bidi: u64,
+    pub initial_max_streams_uni: u64,
+    pub ack_delay_exponent: u64,
+    /// In milliseconds.
+    pub max_ack_delay: u64,
+    pub disable_active_migration: bool,
+    pub preferred_address: Option<PreferredAddress>,
+    pub active_connection_id_limit: u64,
+    pub initial_source_connection_id: Option<ConnectionID>,
+    pub retry_source_connection_id: Option<ConnectionID>,
+}
+
+/// https://www.rfc-editor.org/rfc/rfc9000.html#name-preferred-address
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreferredAddress {
+    pub ipv4: Option<SocketAddrV4>,
+    pub ipv6: Option<SocketAddrV6>,
+    pub connection_id: ConnectionID,
+    pub stateless_reset_token: u128,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TransportParameterError {
+    #[error("transport parameters are truncated")]
+    UnexpectedEnd,
+    #[error("transport parameter {0:#x} appears more than once")]
+    Duplicate(u64),
+    #[error("transport parameter {0:#x} must not be sent by a client")]
+    ServerOnly(u64),
+    #[error("transport parameter {0:#x} has an invalid value")]
+    InvalidValue(u64),
+    #[error("transport parameter {0:#x} is missing")]
+    Missing(u64),
+    #[error("transport parameter {0:#x} does not match the connection ID in the packets")]
+    ConnectionIdMismatch(u64),
+    #[error("transport parameter {0:#x} is reduced from the one remembered for 0-RTT")]
+    Reduced(u64),
+}
+
+impl TransportParameterError {
+    /// Transport error code to close the connection with.
+    pub fn transport_error_code(&self) -> u64 {
+        match self {
+            // PROTOCOL_VIOLATION
+            TransportParameterError::ConnectionIdMismatch(_)
+            | TransportParameterError::Reduced(_) => 0x0a,
+            // TRANSPORT_PARAMETER_ERROR
+            _ => 0x08,
+        }
+    }
+}
+
+impl Default for TransportParameters {
+    fn default() -> Self {
+        Self {
+            original_destination_connection_id: None,
+            max_idle_timeout: 0,
+            stateless_reset_token: None,
+            max_udp_payload_size: 65527,
+            initial_max_data: 0,
+            initial_max_stream_data_bidi_local: 0,
+            initial_max_stream_data_bidi_remote: 0,
+            initial_max_stream_data_uni: 0,
+            initial_max_streams_bidi: 0,
+            initial_max_streams_uni: 0,
+            ack_delay_exponent: 3,
+            max_ack_delay: 25,
+            disable_active_migration: false,
+            preferred_address: None,
+            active_connection_id_limit: 2,
+            initial_source_connection_id: None,
+            retry_source_connection_id: None,
+        }
+    }
+}
+
+impl TransportParameters {
+    /// Decode the parameters sent by the peer of the endpoint type.
+    /// Unknown parameters are ignored, which includes reserved ones for greasing.
+    pub fn decode(buf: &[u8], sender: &EndpointType) -> Result<Self, TransportParameterError> {
+        let mut parameters = Self::default();
+        let mut seen = Vec::new();
+        let mut input = Cursor::new(buf);
+        while (input.position() as usize) < buf.len() {
+            let id = read_varint(&mut input)
+                .map_err(|_| TransportParameterError::UnexpectedEnd)?
+                .to_u64();
+            let length = read_varint(&mut input)
+                .map_err(|_| TransportParameterError::UnexpectedEnd)?
+                .to_u64();
+            let start = input.position() as usize;
+            let value = usize::try_from(length)
+                .ok()
+                .and_then(|length| buf.get(start..start.checked_add(length)?))
+                .ok_or(TransportParameterError::UnexpectedEnd)?;
+            input.set_position((start + value.len()) as u64);
+
+            if seen.contains(&id) {
+                return Err(TransportParameterError::Duplicate(id));
+            }
+            seen.push(id);
+            if *sender == EndpointType::Client && SERVER_ONLY.contains(&id) {
+                return Err(TransportParameterError::ServerOnly(id));
+            }
+            parameters.set(id, value)?;
+        }
+        Ok(parameters)
+    }
+
+    fn set(&mut self, id: u64, value: &[u8]) -> Result<(), TransportParameterError> {
+        let invalid = TransportParameterError::InvalidValue(id);
+        match id {
+            ORIGINAL_DESTINATION_CONNECTION_ID => {
+                self.original_destination_connection_id = Some(decode_connection_id(id, value)?)
+            }
+            MAX_IDLE_TIMEOUT => self.max_idle_timeout = decode_integer(id, value)?,
+            STATELESS_RESET_TOKEN => {
+                let token = <[u8; 16]>::try_from(value).map_err(|_| invalid)?;
+                self.stateless_reset_token = Some(u128::from_be_bytes(token));
+            }
+            MAX_UDP_PAYLOAD_SIZE => {
+                self.max_udp_payload_size = decode_integer(id, value)?;
+                if self.max_udp_payload_size < 1200 {
+                    return Err(invalid);
+                }
+            }
+            INITIAL_MAX_DATA => self.initial_max_data = decode_integer(id, value)?,
+            INITIAL_MAX_STREAM_DATA_BIDI_LOCAL => {
+                self.initial_max_stream_data_bidi_local = decode_integer(id, value)?
+            }
+            INITIAL_MAX_STREAM_DATA_BIDI_REMOTE => {
+                self.initial_max_stream_data_bidi_remote = decode_integer(id, value)?
+            }
+            INITIAL_MAX_STREAM_DATA_UNI => {
+                self.initial_max_stream_data_uni = decode_integer(id, value)?
+            }
+            INITIAL_MAX_STREAMS_BIDI => {
+                self.initial_max_streams_bidi = decode_integer(id, value)?;
+                if self.initial_max_streams_bidi > MAX_STREAMS {
+                    return Err(invalid);
+                }
+            }
+            INITIAL_MAX_STREAMS_UNI => {
+                self.initial_max_streams_uni = decode_integer(id, value)?;
+                if self.initial_max_streams_uni > MAX_STREAMS {
+                    return Err(invalid);
+                }
+            }
+            ACK_DELAY_EXPONENT => {
+                self.ack_delay_exponent = decode_integer(id, value)?;
+                if self.ack_delay_exponent > 20 {
+                    return Err(invalid);
+                }
+            }
+            MAX_ACK_DELAY => {
+                self.max_ack_delay = decode_integer(id, value)?;
+                if self.max_ack_delay >= 1 << 14 {
+                    return Err(invalid);
+                }
+            }
+            DISABLE_ACTIVE_MIGRATION => {
+                if !value.is_empty() {
+                    return Err(invalid);
+                }
+                self.disable_active_migration = true;
+            }
+            PREFERRED_ADDRESS => {
+                self.preferred_address = Some(PreferredAddress::decode(value).ok_or(invalid)?)
+            }
+            ACTIVE_CONNECTION_ID_LIMIT => {
+                self.active_connection_id_limit = decode_integer(id, value)?;
+                if self.active_connection_id_limit < 2 {
+                    return Err(invalid);
+                }
+            }
+            INITIAL_SOURCE_CONNECTION_ID => {
+                self.initial_source_connection_id = Some(decode_connection_id(id, value)?)
+            }
+            RETRY_SOURCE_CONNECTION_ID => {
+                self.retry_source_connection_id = Some(decode_connection_id(id, value)?)
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Parameters with the default values are omitted.
+    pub fn encode(&self) -> Vec<u8> {
+        let default = Self::default();
+        let mut buf = Vec::new();
+        let mut put = |id: u64, value: &[u8]| {
+            buf.extend(u64_to_varint_exact_size(id).to_bytes());
+            buf.extend(u64_to_varint_exact_size(value.len() as u64).to_bytes());
+            buf.extend_from_slice(value);
+        };
+        let integers = [
+            (
+                MAX_IDLE_TIMEOUT,
+                self.max_idle_timeout,
+                default.max_idle_timeout,
+            ),
+            (
+                MAX_UDP_PAYLOAD_SIZE,
+                self.max_udp_payload_size,
+                default.max_udp_payload_size,
+            ),
+            (
+                INITIAL_MAX_DATA,
+                self.initial_max_data,
+                default.initial_max_data,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_BIDI_LOCAL,
+                self.initial_max_stream_data_bidi_local,
+                default.initial_max_stream_data_bidi_local,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_BIDI_REMOTE,
+                self.initial_max_stream_data_bidi_remote,
+                default.initial_max_stream_data_bidi_remote,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_UNI,
+                self.initial_max_stream_data_uni,
+                default.initial_max_stream_data_uni,
+            ),
+            (
+                INITIAL_MAX_STREAMS_BIDI,
+                self.initial_max_streams_bidi,
+                default.initial_max_streams_bidi,
+            ),
+            (
+                INITIAL_MAX_STREAMS_UNI,
+                self.initial_max_streams_uni,
+                default.initial_max_streams_uni,
+            ),
+            (
+                ACK_DELAY_EXPONENT,
+                self.ack_delay_exponent,
+                default.ack_delay_exponent,
+            ),
+            (MAX_ACK_DELAY, self.max_ack_delay, default.max_ack_delay),
+            (
+                ACTIVE_CONNECTION_ID_LIMIT,
+                self.active_connection_id_limit,
+                default.active_connection_id_limit,
+            ),
+        ];
+
+        if let Some(id) = &self.original_destination_connection_id {
+            put(ORIGINAL_DESTINATION_CONNECTION_ID, &id.0);
+        }
+        if let Some(token) = self.stateless_reset_token {
+            put(STATELESS_RESET_TOKEN, &token.to_be_bytes());
+        }
+        for (id, value, default) in integers {
+            if value != default {
+                put(id, &u64_to_varint_exact_size(value).to_bytes());
+            }
+        }
+        if self.disable_active_migration {
+            put(DISABLE_ACTIVE_MIGRATION, &[]);
+        }
+        if let Some(address) = &self.preferred_address {
+            put(PREFERRED_ADDRESS, &address.encode());
+        }
+        if let Some(id) = &self.initial_source_connection_id {
+            put(INITIAL_SOURCE_CONNECTION_ID, &id.0);
+        }
+        if let Some(id) = &self.retry_source_connection_id {
+            put(RETRY_SOURCE_CONNECTION_ID, &id.0);
+        }
+        buf
+    }
+
+    /// The limits a client remembers with a ticket to send 0-RTT data within.
+    /// Other parameters take the default values.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-7.4.1
+    pub fn remembered(&self) -> Self {
+        Self {
+            active_connection_id_limit: self.active_connection_id_limit,
+            initial_max_data: self.initial_max_data,
+            initial_max_stream_data_bidi_local: self.initial_max_stream_data_bidi_local,
+            initial_max_stream_data_bidi_remote: self.initial_max_stream_data_bidi_remote,
+            initial_max_stream_data_uni: self.initial_max_stream_data_uni,
+            initial_max_streams_bidi: self.initial_max_streams_bidi,
+            initial_max_streams_uni: self.initial_max_streams_uni,
+            ..Default::default()
+        }
+    }
+
+    /// A server which accepts 0-RTT must not reduce the limits the client remembers.
+    pub(crate) fn check_remembered(
+        &self,
+        remembered: &Self,
+    ) -> Result<(), TransportParameterError> {
+        let limits = [
+            (
+                ACTIVE_CONNECTION_ID_LIMIT,
+                self.active_connection_id_limit,
+                remembered.active_connection_id_limit,
+            ),
+            (
+                INITIAL_MAX_DATA,
+                self.initial_max_data,
+                remembered.initial_max_data,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_BIDI_LOCAL,
+                self.initial_max_stream_data_bidi_local,
+                remembered.initial_max_stream_data_bidi_local,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_BIDI_REMOTE,
+                self.initial_max_stream_data_bidi_remote,
+                remembered.initial_max_stream_data_bidi_remote,
+            ),
+            (
+                INITIAL_MAX_STREAM_DATA_UNI,
+                self.initial_max_stream_data_uni,
+                remembered.initial_max_stream_data_uni,
+            ),
+            (
+                INITIAL_MAX_STREAMS_BIDI,
+                self.initial_max_streams_bidi,
+                remembered.initial_max_streams_bidi,
+            ),
+            (
+                INITIAL_MAX_STREAMS_UNI,
+                self.initial_max_streams_uni,
+                remembered.initial_max_streams_uni,
+            ),
+        ];
+        match limits
+            .iter()
+            .find(|(_, value, remembered)| value < remembered)
+        {
+            Some((id, _, _)) => Err(TransportParameterError::Reduced(*id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the connection IDs in the parameters of the peer against ones in the Initial packets,
+    /// so that an attacker can not change them during the handshake.
+    /// `retry_source_connection_id` is the Source Connection ID of the Retry packet the client received.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#name-authenticating-connection-i
+    pub(crate) fn authenticate_connection_ids(
+        &self,
+        sender: &EndpointType,
+        initial_source_connection_id: &ConnectionID,
+        original_destination_connection_id: &ConnectionID,
+        retry_source_connection_id: Option<&ConnectionID>,
+    ) -> Result<(), TransportParameterError> {
+        expect_connection_id(
+            INITIAL_SOURCE_CONNECTION_ID,
+            self.initial_source_connection_id.as_ref(),
+            initial_source_connection_id,
+        )?;
+        if *sender == EndpointType::Client {
+            return Ok(());
+        }
+        expect_connection_id(
+            ORIGINAL_DESTINATION_CONNECTION_ID,
+            self.original_destination_connection_id.as_ref(),
+            original_destination_connection_id,
+        )?;
+        match (retry_source_connection_id, &self.retry_source_connection_id) {
+            (Some(expected), actual) => {
+                expect_connection_id(RETRY_SOURCE_CONNECTION_ID, actual.as_ref(), expected)
+            }
+            (None, Some(_)) => Err(TransportParameterError::ConnectionIdMismatch(
+                RETRY_SOURCE_CONNECTION_ID,
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn expect_connection_id(
+    id: u64,
+    actual: Option<&ConnectionID>,
+    expected: &ConnectionID,
+) -> Result<(), TransportParameterError> {
+    match actual {
+        None => Err(TransportParameterError::Missing(id)),
+        Some(actual) if actual != expected => {
+            Err(TransportParameterError::ConnectionIdMismatch(id))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// An integer parameter is a variable-length integer which fills the whole value.
+fn decode_integer(id: u64, value: &[u8]) -> Result<u64, TransportParameterError> {
+    let mut input = Cursor::new(value);
+    let integer = read_varint(&mut input).map_err(|_| TransportParameterError::InvalidValue(id))?;
+    if input.position() as usize != value.len() {
+        return Err(TransportParameterError::InvalidValue(id));
+    }
+    Ok(integer.to_u64())
+}
+
+fn decode_connection_id(id: u64, value: &[u8]) -> Result<ConnectionID, TransportParameterError> {
+    if value.len() > MAX_CONNECTION_ID_LENGTH {
+        return Err(TransportParameterError::InvalidValue(id));
+    }
+    Ok(ConnectionID(value.to_vec()))
+}
+
+impl PreferredAddress {
+    fn decode(value: &[u8]) -> Option<Self> {
+        let ipv4 = <[u8; 4]>::try_from(value.get(0..4)?).ok()?;
+        let ipv4_port = u16::from_be_bytes(value.get(4..6)?.try_into().ok()?);
+        let ipv6 = <[u8; 16]>::try_from(value.get(6..22)?).ok()?;
+        let ipv6_port = u16::from_be_bytes(value.get(22..24)?.try_into().ok()?);
+        let length = *value.get(24)? as usize;
+        // a zero-length connection ID can not be used with a preferred address
+        if length == 0 || length > MAX_CONNECTION_ID_LENGTH {
+            return None;
+        }
+        let connection_id = ConnectionID(value.get(25..25 + length)?.to_vec());
+        let token = <[u8; 16]>::try_from(value.get(25 + length..)?).ok()?;
+
+        // an address family is omitted by setting all zero
+        let ipv4 = SocketAddrV4::new(Ipv4Addr::from(ipv4), ipv4_port);
+        let ipv6 = SocketAddrV6::new(Ipv6Addr::from(ipv6), ipv6_port, 0, 0);
+        Some(Self {
+            ipv4: (ipv4 != SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).then_some(ipv4),
+            ipv6: (ipv6 != SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0)).then_some(ipv6),
+            connection_id,
+            stateless_reset_token: u128::from_be_bytes(token),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let ipv4 = self
+            .ipv4
+            .unwrap_or_else(|| SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+        let ipv6 = self
+            .ipv6
+            .unwrap_or_else(|| SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0));
+        [
+            &ipv4.ip().octets()[..],
+            &ipv4.port().to_be_bytes(),
+            &ipv6.ip().octets(),
+            &ipv6.port().to_be_bytes(),
+            &[self.connection_id.len() as u8],
+            &self.connection_id.0,
+            &self.stateless_reset_token.to_be_bytes(),
+        ]
+        .concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_parameters() -> TransportParameters {
+        TransportParameters {
+            original_destination_connection_id: Some(ConnectionID(vec![1, 2, 3, 4])),
+            max_idle_timeout: 30_000,
+            stateless_reset_token: Some(0x0011_2233_4455_6677_8899_aabb_ccdd_eeff),
+            max_udp_payload_size: 1472,
+            initial_max_data: 1 << 20,
+            initial_max_stream_data_bidi_local: 1 << 16,
+            initial_max_stream_data_bidi_remote: 1 << 16,
+            initial_max_stream_data_uni: 1 << 16,
+            initial_max_streams_bidi: 100,
+            initial_max_streams_uni: 3,
+            ack_delay_exponent: 10,
+            max_ack_delay: 50,
+            disable_active_migration: true,
+            preferred_address: Some(PreferredAddress {
+                ipv4: Some("192.0.2.1:443".parse().unwrap()),
+                ipv6: None,
+                connection_id: ConnectionID(vec![5; 8]),
+                stateless_reset_token: 1,
+            }),
+            active_connection_id_limit: 4,
+            initial_source_connection_id: Some(ConnectionID(vec![6; 8])),
+            retry_source_connection_id: Some(ConnectionID(vec![7; 8])),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let parameters = server_parameters();
+        let buf = parameters.encode();
+        assert_eq!(
+            TransportParameters::decode(&buf, &EndpointType::Server),
+            Ok(parameters)
+        );
+
+        let parameters = TransportParameters::default();
+        assert_eq!(parameters.encode(), Vec::<u8>::new());
+        assert_eq!(
+            TransportParameters::decode(&[], &EndpointType::Client),
+            Ok(parameters)
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let buf = [
+            0x01, 0x02, 0x40, 0x64, // max_idle_timeout: 100
+            0x0c, 0x00, // disable_active_migration
+            0x1b, 0x01, 0xff, // reserved for greasing
+            0x0f, 0x02, 0xaa, 0xbb, // initial_source_connection_id
+        ];
+        let parameters = TransportParameters::decode(&buf, &EndpointType::Client).unwrap();
+        assert_eq!(parameters.max_idle_timeout, 100);
+        assert!(parameters.disable_active_migration);
+        assert_eq!(
+            parameters.initial_source_connection_id,
+            Some(ConnectionID(vec![0xaa, 0xbb]))
+        );
+        assert_eq!(parameters.ack_delay_exponent, 3);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let decode = |buf: &[u8]| TransportParameters::decode(buf, &EndpointType::Server);
+
+        assert_eq!(
+            decode(&[0x04, 0x01, 0x00, 0x04, 0x01, 0x00]),
+            Err(TransportParameterError::Duplicate(INITIAL_MAX_DATA))
+        );
+        assert_eq!(
+            TransportParameters::decode(&[0x10, 0x00], &EndpointType::Client),
+            Err(TransportParameterError::ServerOnly(
+                RETRY_SOURCE_CONNECTION_ID
+            ))
+        );
+        // max_udp_payload_size below 1200
+        assert_eq!(
+            decode(&[0x03, 0x02, 0x44, 0xaf]),
+            Err(TransportParameterError::InvalidValue(MAX_UDP_PAYLOAD_SIZE))
+        );
+        assert_eq!(
+            decode(&[0x0a, 0x01, 21]),
+            Err(TransportParameterError::InvalidValue(ACK_DELAY_EXPONENT))
+        );
+        assert_eq!(
+            decode(&[0x0e, 0x01, 1]),
+            Err(TransportParameterError::InvalidValue(
+                ACTIVE_CONNECTION_ID_LIMIT
+            ))
+        );
+        // the integer does not fill the value
+        assert_eq!(
+            decode(&[0x04, 0x02, 0x00, 0x00]),
+            Err(TransportParameterError::InvalidValue(INITIAL_MAX_DATA))
+        );
+        assert_eq!(
+            decode(&[0x02, 0x01, 0x00]),
+            Err(TransportParameterError::InvalidValue(STATELESS_RESET_TOKEN))
+        );
+        assert_eq!(
+            decode(&[0x04, 0x04, 0x00]),
+            Err(TransportParameterError::UnexpectedEnd)
+        );
+        assert_eq!(
+            TransportParameterError::Duplicate(0).transport_error_code(),
+            0x08
+        );
+    }
+
+    #[test]
+    fn authenticate_connection_ids() {
+        let parameters = server_parameters();
+        let initial_source = ConnectionID(vec![6; 8]);
+        let original_destination = ConnectionID(vec![1, 2, 3, 4]);
+        let retry_source = ConnectionID(vec![7; 8]);
+        assert_eq!(
+            parameters.authenticate_connection_ids(
+                &EndpointType::Server,
+                &initial_source,
+                &original_destination,
+                Some(&retry_source),
+            ),
+            Ok(())
+        );
+        // the client did not receive a Retry packet
+        assert_eq!(
+            parameters.authenticate_connection_ids(
+                &EndpointType::Server,
+                &initial_source,
+                &original_destination,
+                None,
+            ),
+            Err(TransportParameterError::ConnectionIdMismatch(
+                RETRY_SOURCE_CONNECTION_ID
+            ))
+        );
+        assert_eq!(
+            parameters.authenticate_connection_ids(
+                &EndpointType::Server,
+                &initial_source,
+                &retry_source,
+                Some(&retry_source),
+            ),
+            Err(TransportParameterError::ConnectionIdMismatch(
+                ORIGINAL_DESTINATION_CONNECTION_ID
+            ))
+        );
+
+        let parameters = TransportParameters::default();
+        assert_eq!(
+            parameters.authenticate_connection_ids(
+                &EndpointType::Client,
+                &initial_source,
+                &original_destination,
+                None,
+            ),
+            Err(TransportParameterError::Missing(
+                INITIAL_SOURCE_CONNECTION_ID
+            ))
+        );
+    }
+
+    #[test]
+    fn remembered() {
+        let parameters = server_parameters();
+        let remembered = parameters.remembered();
+        assert_eq!(remembered.initial_max_data, 1 << 20);
+        assert_eq!(remembered.active_connection_id_limit, 4);
+        assert_eq!(remembered.max_idle_timeout, 0);
+        assert_eq!(remembered.initial_source_connection_id, None);
+        assert_eq!(parameters.check_remembered(&remembered), Ok(()));
+
+        let reduced = TransportParameters {
+            initial_max_streams_bidi: 10,
+            ..parameters
+        };
+        assert_eq!(
+            reduced.check_remembered(&remembered),
+            Err(TransportParameterError::Reduced(INITIAL_MAX_STREAMS_BIDI))
+        );
+    }
+}
@@ -0,0 +1,58 @@
+/// Alerts to abort the handshake with.
+/// QUIC does not send them in records but in CONNECTION_CLOSE frames.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertDescription {
+    UnexpectedMessage = 10,
+    BadRecordMac = 20,
+    HandshakeFailure = 40,
+    BadCertificate = 42,
+    UnsupportedCertificate = 43,
+    CertificateRevoked = 44,
+    CertificateExpired = 45,
+    CertificateUnknown = 46,
+    IllegalParameter = 47,
+    UnknownCa = 48,
+    DecodeError = 50,
+    DecryptError = 51,
+    ProtocolVersion = 70,
+    InsufficientSecurity = 71,
+    InternalError = 80,
+    MissingExtension = 109,
+    UnsupportedExtension = 110,
+    UnrecognizedName = 112,
+    CertificateRequired = 116,
+    NoApplicationProtocol = 120,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("{description:?}: {reason}")]
+pub struct TlsError {
+    description: AlertDescription,
+    reason: String,
+}
+
+impl TlsError {
+    pub fn new(description: AlertDescription, reason: impl Into<String>) -> Self {
+        Self {
+            description,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn description(&self) -> AlertDescription {
+        self.description
+    }
+
+    /// Transport error code to close the connection with, which is CRYPTO_ERROR with the alert.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#name-tls-errors
+    pub fn transport_error_code(&self) -> u64 {
+        0x0100 + self.description as u64
+    }
+}
+
+impl From<std::io::Error> for TlsError {
+    fn from(e: std::io::Error) -> Self {
+        TlsError::new(AlertDescription::DecodeError, e.to_string())
+    }
+}
@@ -1,32 +1,32 @@
 use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo, ReadBytesToWith};
 
-use crate::handshake::HandshakeType;
+use crate::{handshake::HandshakeType, read_list, read_vector, write_vector};
 
-mod application_layer_protocol_negotiation;
+pub(crate) mod application_layer_protocol_negotiation;
 mod certificate_authorities;
 mod client_certificate_type;
-mod cookie;
-mod early_data;
+pub(crate) mod cookie;
+pub(crate) mod early_data;
 mod heartbeat;
-mod key_share;
+pub(crate) mod key_share;
 mod max_fragment_length;
 mod oid_filters;
 mod others;
 mod padding;
 mod post_handshake_auth;
-mod pre_shared_key;
-mod psk_key_exchange_modes;
-mod quic_transport_parameters;
+pub(crate) mod pre_shared_key;
+pub(crate) mod psk_key_exchange_modes;
+pub(crate) mod quic_transport_parameters;
 mod renegotiation_info;
 mod server_certificate_type;
-mod server_name;
-mod signature_algorithms;
+pub(crate) mod server_name;
+pub(crate) mod signature_algorithms;
 mod signature_algorithms_cert;
 mod signed_certificate_timestamp;
 mod status_request;
-mod supported_groups;
-mod supported_versions;
+pub(crate) mod supported_groups;
+pub(crate) mod supported_versions;
 mod use_srtp;
 
 #[derive(Debug, PartialEq)]
@@ -60,6 +60,27 @@ pub enum Extension {
 
 pub type Extensions = Vec<Extension>;
 
+pub use self::supported_groups::NamedCurve;
+
+/// Read extensions with the length in front of them.
+pub(crate) fn read_extensions<R: std::io::Read>(
+    input: &mut R,
+    handshake_type: HandshakeType,
+) -> Result<Extensions, std::io::Error> {
+    read_list(&read_vector(input, 2)?, |input| {
+        input.read_bytes_to_with(handshake_type.clone())
+    })
+}
+
+/// Write extensions with the length in front of them.
+pub(crate) fn write_extensions(output: &mut Vec<u8>, extensions: &Extensions) {
+    let buf = extensions
+        .iter()
+        .flat_map(|e| e.to_bytes())
+        .collect::<Vec<_>>();
+    write_vector(output, 2, &buf);
+}
+
 impl FromReadBytesWith<HandshakeType> for Extension {
     fn from_read_bytes_with<R: std::io::Read>(
         input: &mut R,
@@ -82,55 +103,85 @@ impl FromReadBytesWith<HandshakeType> for Extension {
             0x13 => Extension::ClientCertificateType(input.read_bytes_to()?),
             0x14 => Extension::ServerCertificateType(input.read_bytes_to()?),
             0x15 => Extension::Padding(input.read_bytes_to()?),
-            0x29 => Extension::PreSharedKey(input.read_bytes_to()?),
+            0x29 => Extension::PreSharedKey(input.read_bytes_to_with(handshake_type)?),
             0x2a => Extension::EarlyData(input.read_bytes_to_with(handshake_type)?),
-            0x2b => Extension::SupportedVersions(input.read_bytes_to()?),
+            0x2b => Extension::SupportedVersions(input.read_bytes_to_with(handshake_type)?),
             0x2c => Extension::Cookie(input.read_bytes_to()?),
             0x2d => Extension::PskKeyExchangeModes(input.read_bytes_to()?),
             0x2f => Extension::CertificateAuthorities(input.read_bytes_to()?),
             0x30 => Extension::OidFilters(input.read_bytes_to()?),
             0x31 => Extension::PostHandshakeAuth(input.read_bytes_to()?),
             0x32 => Extension::SignatureAlgorithmsCert(input.read_bytes_to()?),
-            0x33 => Extension::KeyShare(input.read_bytes_to()?),
+            0x33 => Extension::KeyShare(input.read_bytes_to_with(handshake_type)?),
             0x39 => Extension::QuicTransportParameters(input.read_bytes_to()?),
             0xff01 => Extension::RenegotiationInfo(input.read_bytes_to()?),
-            _ => Extension::Others(input.read_bytes_to()?),
+            _ => Extension::Others(input.read_bytes_to_with(extension_type)?),
         })
     }
 }
 
 impl Extension {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.body_size()
-    }
-
-    fn body_size(&self) -> usize {
+    pub fn extension_type(&self) -> u16 {
         match self {
-            Extension::ServerName(b) => b.size_of(),
-            Extension::MaxFragmentLength(b) => b.size_of(),
-            Extension::StatusRequest(b) => b.size_of(),
-            Extension::SupportedGroups(b) => b.size_of(),
-            Extension::SignatureAlgorithms(b) => b.size_of(),
-            Extension::UseSrtp(b) => b.size_of(),
-            Extension::Heartbeat(b) => b.size_of(),
-            Extension::ApplicationLayerProtocolNegotiation(b) => b.size_of(),
-            Extension::SignedCertificateTimestamp(b) => b.size_of(),
-            Extension::ClientCertificateType(b) => b.size_of(),
-            Extension::ServerCertificateType(b) => b.size_of(),
-            Extension::Padding(b) => b.size_of(),
-            Extension::PreSharedKey(b) => b.size_of(),
-            Extension::EarlyData(b) => b.size_of(),
-            Extension::SupportedVersions(b) => b.size_of(),
-            Extension::Cookie(b) => b.size_of(),
-            Extension::PskKeyExchangeModes(b) => b.size_of(),
-            Extension::CertificateAuthorities(b) => b.size_of(),
-            Extension::OidFilters(b) => b.size_of(),
-            Extension::PostHandshakeAuth(b) => b.size_of(),
-            Extension::SignatureAlgorithmsCert(b) => b.size_of(),
-            Extension::KeyShare(b) => b.size_of(),
-            Extension::RenegotiationInfo(b) => b.size_of(),
-            Extension::QuicTransportParameters(b) => b.size_of(),
-            Extension::Others(b) => b.size_of(),
+            Extension::ServerName(_) => 0x00,
+            Extension::MaxFragmentLength(_) => 0x01,
+            Extension::StatusRequest(_) => 0x05,
+            Extension::SupportedGroups(_) => 0x0a,
+            Extension::SignatureAlgorithms(_) => 0x0d,
+            Extension::UseSrtp(_) => 0x0e,
+            Extension::Heartbeat(_) => 0x0f,
+            Extension::ApplicationLayerProtocolNegotiation(_) => 0x10,
+            Extension::SignedCertificateTimestamp(_) => 0x12,
+            Extension::ClientCertificateType(_) => 0x13,
+            Extension::ServerCertificateType(_) => 0x14,
+            Extension::Padding(_) => 0x15,
+            Extension::PreSharedKey(_) => 0x29,
+            Extension::EarlyData(_) => 0x2a,
+            Extension::SupportedVersions(_) => 0x2b,
+            Extension::Cookie(_) => 0x2c,
+            Extension::PskKeyExchangeModes(_) => 0x2d,
+            Extension::CertificateAuthorities(_) => 0x2f,
+            Extension::OidFilters(_) => 0x30,
+            Extension::PostHandshakeAuth(_) => 0x31,
+            Extension::SignatureAlgorithmsCert(_) => 0x32,
+            Extension::KeyShare(_) => 0x33,
+            Extension::QuicTransportParameters(_) => 0x39,
+            Extension::RenegotiationInfo(_) => 0xff01,
+            Extension::Others(b) => b.extension_type(),
         }
     }
+
+    /// Serialize the extension with its type and length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = match self {
+            Extension::ServerName(b) => b.to_bytes(),
+            Extension::MaxFragmentLength(b) => b.to_bytes(),
+            Extension::StatusRequest(b) => b.to_bytes(),
+            Extension::SupportedGroups(b) => b.to_bytes(),
+            Extension::SignatureAlgorithms(b) => b.to_bytes(),
+            Extension::UseSrtp(b) => b.to_bytes(),
+            Extension::Heartbeat(b) => b.to_bytes(),
+            Extension::ApplicationLayerProtocolNegotiation(b) => b.to_bytes(),
+            Extension::SignedCertificateTimestamp(b) => b.to_bytes(),
+            Extension::ClientCertificateType(b) => b.to_bytes(),
+            Extension::ServerCertificateType(b) => b.to_bytes(),
+            Extension::Padding(b) => b.to_bytes(),
+            Extension::PreSharedKey(b) => b.to_bytes(),
+            Extension::EarlyData(b) => b.to_bytes(),
+            Extension::SupportedVersions(b) => b.to_bytes(),
+            Extension::Cookie(b) => b.to_bytes(),
+            Extension::PskKeyExchangeModes(b) => b.to_bytes(),
+            Extension::CertificateAuthorities(b) => b.to_bytes(),
+            Extension::OidFilters(b) => b.to_bytes(),
+            Extension::PostHandshakeAuth(b) => b.to_bytes(),
+            Extension::SignatureAlgorithmsCert(b) => b.to_bytes(),
+            Extension::KeyShare(b) => b.to_bytes(),
+            Extension::RenegotiationInfo(b) => b.to_bytes(),
+            Extension::QuicTransportParameters(b) => b.to_bytes(),
+            Extension::Others(b) => b.to_bytes(),
+        };
+        let mut buf = self.extension_type().to_be_bytes().to_vec();
+        write_vector(&mut buf, 2, &body);
+        buf
+    }
 }
@@ -1,12 +1,13 @@
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
-use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_list, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc7301.html#section-3.1
 #[derive(Debug, PartialEq)]
 pub struct Body {
     protocol_names: Vec<ProtocolName>,
-    total_length: usize,
 }
 
 #[derive(Debug, PartialEq)]
@@ -19,32 +20,45 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut input = {
-            let mut buf = vec![0u8; length as usize];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
-        let mut protocol_names = Vec::new();
-        let mut total_length = 0;
-        while total_length < length {
-            let name_length = input.read_u8()?;
-            let mut name = vec![0; name_length as usize];
-            input.read_exact(&mut name)?;
-            protocol_names.push(ProtocolName { name });
-            total_length += name_length as usize + 1;
-        }
-
-        assert_eq!(total_length, length);
-        Ok(Self {
-            protocol_names,
-            total_length,
-        })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let list = read_vector(&mut input, 2)?;
+        ensure_consumed(&input)?;
+        let protocol_names = read_list(&list, |input| {
+            let name = read_vector(input, 1)?;
+            if name.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "empty protocol name",
+                ));
+            }
+            Ok(ProtocolName { name })
+        })?;
+        Ok(Self { protocol_names })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.total_length
+    pub(crate) fn new(protocol_names: Vec<Vec<u8>>) -> Self {
+        Self {
+            protocol_names: protocol_names
+                .into_iter()
+                .map(|name| ProtocolName { name })
+                .collect(),
+        }
+    }
+
+    pub fn protocol_names(&self) -> impl Iterator<Item = &[u8]> {
+        self.protocol_names.iter().map(|p| &p.name[..])
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut list = Vec::new();
+        for protocol_name in &self.protocol_names {
+            write_vector(&mut list, 1, &protocol_name.name);
+        }
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 2, &list);
+        buf
     }
 }
@@ -1,14 +1,16 @@
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
-use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_list, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.4
 #[derive(Debug, PartialEq)]
 pub struct Body {
     authorities: Vec<Authority>,
-    total_length: usize,
 }
 
+/// DER of a DistinguishedName.
 #[derive(Debug, PartialEq)]
 pub struct Authority {
     name: Vec<u8>,
@@ -19,32 +21,27 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut input = {
-            let mut buf = vec![0u8; length as usize];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
-        let mut authorities = Vec::new();
-        let mut total_length = 0;
-        while total_length < length {
-            let name_length = input.read_u8()?;
-            let mut name = vec![0; name_length as usize];
-            input.read_exact(&mut name)?;
-            authorities.push(Authority { name });
-            total_length += name_length as usize + 1;
-        }
-
-        assert_eq!(total_length, length);
-        Ok(Self {
-            authorities,
-            total_length,
-        })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let list = read_vector(&mut input, 2)?;
+        ensure_consumed(&input)?;
+        let authorities = read_list(&list, |input| {
+            Ok(Authority {
+                name: read_vector(input, 2)?,
+            })
+        })?;
+        Ok(Self { authorities })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.total_length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut list = Vec::new();
+        for authority in &self.authorities {
+            write_vector(&mut list, 2, &authority.name);
+        }
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 2, &list);
+        buf
     }
 }
@@ -1,11 +1,11 @@
-use std::io::Cursor;
-
-use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::read_vector;
+
+/// https://www.rfc-editor.org/rfc/rfc7250.html#section-3
+/// The value is kept as it is because it is not used in TLS 1.3 for QUIC.
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
     value: Vec<u8>,
 }
 
@@ -14,15 +14,14 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        Ok(Self {
+            value: read_vector(input, 2)?,
+        })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        return self.length;
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.value.clone()
     }
 }
@@ -1,6 +1,10 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Cursor;
+
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.2
 #[derive(Debug, PartialEq)]
 pub struct Body {
     cookie: Vec<u8>,
@@ -11,18 +15,26 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()?;
-        let cookie = {
-            let mut cookie = vec![0u8; length as usize];
-            input.read_exact(&mut cookie)?;
-            cookie
-        };
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let cookie = read_vector(&mut input, 2)?;
+        ensure_consumed(&input)?;
         Ok(Body { cookie })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.cookie.len()
+    pub(crate) fn new(cookie: Vec<u8>) -> Self {
+        Self { cookie }
+    }
+
+    pub fn cookie(&self) -> &[u8] {
+        &self.cookie
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 2, &self.cookie);
+        buf
     }
 }
@@ -1,22 +1,25 @@
 use std::io::Cursor;
 
 use byteorder::{NetworkEndian, ReadBytesExt};
-use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesToWith};
+use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::handshake::HandshakeType;
+use crate::{ensure_consumed, handshake::HandshakeType, read_vector};
 
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.10
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
     value: EarlyData,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum EarlyData {
-    NewSessionTicket { max_early_data_size: u32 },
+    /// QUIC requires the value to be 0xffffffff.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6.1
+    NewSessionTicket {
+        max_early_data_size: u32,
+    },
     ClientHello,
     EncryptedExtension,
-    Others(HandshakeType),
 }
 
 impl FromReadBytesWith<HandshakeType> for Body {
@@ -27,41 +30,41 @@ impl FromReadBytesWith<HandshakeType> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut input = {
-            let mut buf = vec![0u8; length as usize];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
-        let value = input.read_bytes_to_with(handshake_type)?;
-        Ok(Self { length, value })
-    }
-}
-
-impl FromReadBytesWith<HandshakeType> for EarlyData {
-    fn from_read_bytes_with<R: std::io::Read>(
-        input: &mut R,
-        handshake_type: HandshakeType,
-    ) -> Result<Self, std::io::Error>
-    where
-        Self: Sized,
-    {
-        Ok(match handshake_type {
-            HandshakeType::NewSessionTicket => {
-                let max_early_data_size = input.read_u32::<NetworkEndian>()?;
-                EarlyData::NewSessionTicket {
-                    max_early_data_size,
-                }
-            }
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let value = match handshake_type {
+            HandshakeType::NewSessionTicket => EarlyData::NewSessionTicket {
+                max_early_data_size: input.read_u32::<NetworkEndian>()?,
+            },
             HandshakeType::ClientHello => EarlyData::ClientHello,
             HandshakeType::EncryptedExtensions => EarlyData::EncryptedExtension,
-            x => EarlyData::Others(x),
-        })
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "early_data in an unexpected message",
+                ))
+            }
+        };
+        ensure_consumed(&input)?;
+        Ok(Self { value })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn new(value: EarlyData) -> Self {
+        Self { value }
+    }
+
+    pub fn value(&self) -> &EarlyData {
+        &self.value
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        match self.value {
+            EarlyData::NewSessionTicket {
+                max_early_data_size,
+            } => max_early_data_size.to_be_bytes().to_vec(),
+            EarlyData::ClientHello | EarlyData::EncryptedExtension => Vec::new(),
+        }
     }
 }
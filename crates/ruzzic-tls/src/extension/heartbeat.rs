@@ -1,16 +1,17 @@
 use std::io::Cursor;
 
-use byteorder::{NetworkEndian, ReadBytesExt};
+use byteorder::ReadBytesExt;
 use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
 
+use crate::{ensure_consumed, read_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc6520.html#section-2
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
     value: HeartbeatMode,
 }
 
 #[derive(Debug, PartialEq)]
-#[repr(u8)]
 pub enum HeartbeatMode {
     PeerAllowedToSend,
     PeerNotAllowedToSend,
@@ -22,14 +23,11 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut input = {
-            let mut buf = vec![0u8; length as usize];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
         let value = input.read_bytes_to()?;
-        Ok(Self { length, value })
+        ensure_consumed(&input)?;
+        Ok(Self { value })
     }
 }
 
@@ -48,7 +46,11 @@ impl FromReadBytesWith<()> for HeartbeatMode {
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        vec![match self.value {
+            HeartbeatMode::PeerAllowedToSend => 1,
+            HeartbeatMode::PeerNotAllowedToSend => 2,
+            HeartbeatMode::Others(x) => x,
+        }]
     }
 }
@@ -1,26 +1,98 @@
+use std::io::Cursor;
+
 use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, handshake::HandshakeType, read_list, read_vector, write_vector};
+
+use super::NamedCurve;
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.8
 #[derive(Debug, PartialEq)]
-pub struct Body {
-    length: usize,
-    value: Vec<u8>,
+pub enum Body {
+    ClientHello(Vec<KeyShareEntry>),
+    ServerHello(KeyShareEntry),
+    HelloRetryRequest(NamedCurve),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyShareEntry {
+    group: NamedCurve,
+    key_exchange: Vec<u8>,
+}
+
+impl KeyShareEntry {
+    pub(crate) fn new(group: NamedCurve, key_exchange: Vec<u8>) -> Self {
+        Self {
+            group,
+            key_exchange,
+        }
+    }
+
+    pub fn group(&self) -> NamedCurve {
+        self.group
+    }
+
+    pub fn key_exchange(&self) -> &[u8] {
+        &self.key_exchange
+    }
+
+    fn read(input: &mut impl std::io::Read) -> Result<Self, std::io::Error> {
+        let group = NamedCurve::from_u16(input.read_u16::<NetworkEndian>()?);
+        let key_exchange = read_vector(input, 2)?;
+        Ok(Self {
+            group,
+            key_exchange,
+        })
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.group.to_u16().to_be_bytes());
+        write_vector(output, 2, &self.key_exchange);
+    }
 }
 
-impl FromReadBytesWith<()> for Body {
-    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+impl FromReadBytesWith<HandshakeType> for Body {
+    fn from_read_bytes_with<R: std::io::Read>(
+        input: &mut R,
+        handshake_type: HandshakeType,
+    ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let body = match handshake_type {
+            HandshakeType::ClientHello => {
+                Body::ClientHello(read_list(&read_vector(&mut input, 2)?, |input| {
+                    KeyShareEntry::read(input)
+                })?)
+            }
+            // a HelloRetryRequest only has the group, which is shorter than any entry
+            _ if value.len() == 2 => {
+                Body::HelloRetryRequest(NamedCurve::from_u16(input.read_u16::<NetworkEndian>()?))
+            }
+            _ => Body::ServerHello(KeyShareEntry::read(&mut input)?),
+        };
+        ensure_consumed(&input)?;
+        Ok(body)
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Body::ClientHello(entries) => {
+                let mut list = Vec::new();
+                for entry in entries {
+                    entry.write(&mut list);
+                }
+                write_vector(&mut buf, 2, &list);
+            }
+            Body::ServerHello(entry) => entry.write(&mut buf),
+            Body::HelloRetryRequest(group) => buf.extend_from_slice(&group.to_u16().to_be_bytes()),
+        }
+        buf
     }
 }
@@ -1,9 +1,11 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::read_vector;
+
+/// https://www.rfc-editor.org/rfc/rfc6066.html#section-4
+/// The value is kept as it is because it is not used in TLS 1.3 for QUIC.
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
     value: Vec<u8>,
 }
 
@@ -12,15 +14,14 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        Ok(Self {
+            value: read_vector(input, 2)?,
+        })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.value.clone()
     }
 }
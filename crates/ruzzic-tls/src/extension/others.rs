@@ -1,26 +1,35 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::read_vector;
+
+/// An extension not known here, kept as is.
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
+    extension_type: u16,
     value: Vec<u8>,
 }
 
-impl FromReadBytesWith<()> for Body {
-    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+impl FromReadBytesWith<u16> for Body {
+    fn from_read_bytes_with<R: std::io::Read>(
+        input: &mut R,
+        extension_type: u16,
+    ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        Ok(Self {
+            extension_type,
+            value: read_vector(input, 2)?,
+        })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn extension_type(&self) -> u16 {
+        self.extension_type
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.value.clone()
     }
 }
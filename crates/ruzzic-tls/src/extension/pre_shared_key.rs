@@ -1,26 +1,114 @@
+use std::io::Cursor;
+
 use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, handshake::HandshakeType, read_list, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11
 #[derive(Debug, PartialEq)]
-pub struct Body {
-    length: usize,
-    value: Vec<u8>,
+pub enum Body {
+    ClientHello {
+        identities: Vec<PskIdentity>,
+        binders: Vec<Vec<u8>>,
+    },
+    ServerHello {
+        selected_identity: u16,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PskIdentity {
+    identity: Vec<u8>,
+    obfuscated_ticket_age: u32,
 }
 
-impl FromReadBytesWith<()> for Body {
-    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+impl PskIdentity {
+    pub(crate) fn new(identity: Vec<u8>, obfuscated_ticket_age: u32) -> Self {
+        Self {
+            identity,
+            obfuscated_ticket_age,
+        }
+    }
+
+    pub fn identity(&self) -> &[u8] {
+        &self.identity
+    }
+
+    pub fn obfuscated_ticket_age(&self) -> u32 {
+        self.obfuscated_ticket_age
+    }
+}
+
+impl FromReadBytesWith<HandshakeType> for Body {
+    fn from_read_bytes_with<R: std::io::Read>(
+        input: &mut R,
+        handshake_type: HandshakeType,
+    ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let body = match handshake_type {
+            HandshakeType::ClientHello => {
+                let identities = read_list(&read_vector(&mut input, 2)?, |input| {
+                    Ok(PskIdentity {
+                        identity: read_vector(&mut *input, 2)?,
+                        obfuscated_ticket_age: input.read_u32::<NetworkEndian>()?,
+                    })
+                })?;
+                let binders =
+                    read_list(&read_vector(&mut input, 2)?, |input| read_vector(input, 1))?;
+                Body::ClientHello {
+                    identities,
+                    binders,
+                }
+            }
+            _ => Body::ServerHello {
+                selected_identity: input.read_u16::<NetworkEndian>()?,
+            },
+        };
+        ensure_consumed(&input)?;
+        Ok(body)
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    /// Size of the binders at the end of the ClientHello, which the binders do not cover.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11.2
+    pub(crate) fn binders_size(&self) -> usize {
+        match self {
+            Body::ClientHello { binders, .. } => {
+                2 + binders.iter().map(|b| 1 + b.len()).sum::<usize>()
+            }
+            Body::ServerHello { .. } => 0,
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Body::ClientHello {
+                identities,
+                binders,
+            } => {
+                let mut list = Vec::new();
+                for identity in identities {
+                    write_vector(&mut list, 2, &identity.identity);
+                    list.extend_from_slice(&identity.obfuscated_ticket_age.to_be_bytes());
+                }
+                write_vector(&mut buf, 2, &list);
+                let mut list = Vec::new();
+                for binder in binders {
+                    write_vector(&mut list, 1, binder);
+                }
+                write_vector(&mut buf, 2, &list);
+            }
+            Body::ServerHello { selected_identity } => {
+                buf.extend_from_slice(&selected_identity.to_be_bytes())
+            }
+        }
+        buf
     }
 }
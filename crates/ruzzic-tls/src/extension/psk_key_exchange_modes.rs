@@ -1,10 +1,38 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Cursor;
+
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.9
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
-    value: Vec<u8>,
+    ke_modes: Vec<PskKeyExchangeMode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PskKeyExchangeMode {
+    PskKe,
+    PskDheKe,
+    Others(u8),
+}
+
+impl PskKeyExchangeMode {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => PskKeyExchangeMode::PskKe,
+            1 => PskKeyExchangeMode::PskDheKe,
+            x => PskKeyExchangeMode::Others(x),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            PskKeyExchangeMode::PskKe => 0,
+            PskKeyExchangeMode::PskDheKe => 1,
+            PskKeyExchangeMode::Others(x) => x,
+        }
+    }
 }
 
 impl FromReadBytesWith<()> for Body {
@@ -12,15 +40,30 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let ke_modes = read_vector(&mut input, 1)?
+            .into_iter()
+            .map(PskKeyExchangeMode::from_u8)
+            .collect();
+        ensure_consumed(&input)?;
+        Ok(Self { ke_modes })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn new(ke_modes: Vec<PskKeyExchangeMode>) -> Self {
+        Self { ke_modes }
+    }
+
+    pub fn ke_modes(&self) -> &[PskKeyExchangeMode] {
+        &self.ke_modes
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let modes = self.ke_modes.iter().map(|m| m.to_u8()).collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 1, &modes);
+        buf
     }
 }
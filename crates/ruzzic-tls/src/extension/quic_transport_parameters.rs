@@ -1,11 +1,12 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::read_vector;
+
+/// https://www.rfc-editor.org/rfc/rfc9001.html#section-8.2
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
-    // Detailed analysis of the values will not be done here, but will be done in ruzzic-stream.
-    // This is because the information here is used by quic-transport.
+    // Detailed analysis of the values will not be done here, but will be done in ruzzic-stream
+    // by `TransportParameters`, because the information here is used by quic-transport.
     value: Vec<u8>,
 }
 
@@ -14,15 +15,22 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        Ok(Self {
+            value: read_vector(input, 2)?,
+        })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub fn new(value: Vec<u8>) -> Self {
+        Self { value }
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.value.clone()
     }
 }
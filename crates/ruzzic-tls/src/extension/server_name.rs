@@ -1,18 +1,21 @@
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
 use byteorder::ReadBytesExt;
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_list, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc6066.html#section-3
+/// The extension of the server has no names.
 #[derive(Debug, PartialEq)]
 pub struct Body {
     names: Vec<ServerName>,
-    total_length: usize,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct ServerName {
     name_type: NameType,
-    host_name: Option<Vec<u8>>,
+    name: Vec<u8>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -26,44 +29,58 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<byteorder::NetworkEndian>()? as usize;
-        let mut input = {
-            let mut buf = vec![0u8; length as usize];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
-        let mut names = Vec::new();
-        let mut total_length = 0usize;
-        while total_length < length {
-            let _ = input.read_u16::<byteorder::NetworkEndian>()? as usize;
+        let value = read_vector(input, 2)?;
+        if value.is_empty() {
+            return Ok(Self { names: Vec::new() });
+        }
+        let mut input = Cursor::new(&value[..]);
+        let names = read_list(&read_vector(&mut input, 2)?, |input| {
             let name_type = match input.read_u8()? {
                 0 => NameType::HostName,
                 x => NameType::Unknown(x),
             };
-            let host_name = match name_type {
-                NameType::HostName => {
-                    let length = input.read_u16::<byteorder::NetworkEndian>()? as usize;
-                    let mut host_name = vec![0u8; length as usize];
-                    input.read_exact(&mut host_name)?;
-                    Some(host_name)
-                }
-                NameType::Unknown(_) => None,
-            };
-            total_length += 2 + 1 + 2 + host_name.as_ref().map(|x| x.len()).unwrap_or(0);
-            names.push(ServerName {
+            Ok(ServerName {
                 name_type,
-                host_name,
-            });
-        }
-        Ok(Self {
-            names,
-            total_length,
-        })
+                name: read_vector(input, 2)?,
+            })
+        })?;
+        ensure_consumed(&input)?;
+        Ok(Self { names })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.total_length
+    pub(crate) fn new(host_name: &str) -> Self {
+        Self {
+            names: vec![ServerName {
+                name_type: NameType::HostName,
+                name: host_name.as_bytes().to_vec(),
+            }],
+        }
+    }
+
+    /// The first host name in the list, which is the only one allowed.
+    pub fn host_name(&self) -> Option<&[u8]> {
+        self.names
+            .iter()
+            .find(|n| n.name_type == NameType::HostName)
+            .map(|n| &n.name[..])
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        if self.names.is_empty() {
+            return Vec::new();
+        }
+        let mut list = Vec::new();
+        for name in &self.names {
+            list.push(match name.name_type {
+                NameType::HostName => 0,
+                NameType::Unknown(x) => x,
+            });
+            write_vector(&mut list, 2, &name.name);
+        }
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 2, &list);
+        buf
     }
 }
@@ -1,10 +1,14 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
-use ruzzic_common::read_bytes_to::FromReadBytesWith;
+use std::io::Cursor;
+
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+
+use crate::{ensure_consumed, read_list, read_vector, write_vector, SignatureScheme};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.3
 
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
-    value: Vec<u8>,
+    supported_signature_algorithms: Vec<SignatureScheme>,
 }
 
 impl FromReadBytesWith<()> for Body {
@@ -12,15 +16,36 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let supported_signature_algorithms =
+            read_list(&read_vector(&mut input, 2)?, |input| input.read_bytes_to())?;
+        ensure_consumed(&input)?;
+        Ok(Self {
+            supported_signature_algorithms,
+        })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn new(supported_signature_algorithms: Vec<SignatureScheme>) -> Self {
+        Self {
+            supported_signature_algorithms,
+        }
+    }
+
+    pub fn supported_signature_algorithms(&self) -> &[SignatureScheme] {
+        &self.supported_signature_algorithms
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let list = self
+            .supported_signature_algorithms
+            .iter()
+            .flat_map(|s| s.to_u16().to_be_bytes())
+            .collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 2, &list);
+        buf
     }
 }
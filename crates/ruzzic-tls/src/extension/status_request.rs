@@ -1,25 +1,31 @@
 use std::io::Cursor;
 
-use byteorder::{NetworkEndian, ReadBytesExt};
-use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+use byteorder::ReadBytesExt;
+use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_list, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc6066.html#section-8
 #[derive(Debug, PartialEq)]
 pub struct Body {
-    length: usize,
     value: StatusRequest,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum StatusRequest {
+    /// The response of the server is empty.
+    Empty,
     Ocsp(OcspStatusRequest),
-    Others(u8),
+    Others {
+        status_type: u8,
+        request: Vec<u8>,
+    },
 }
 
 #[derive(Debug, PartialEq)]
 pub struct OcspStatusRequest {
     responder_ids: Vec<ResponderId>,
     extensions: Vec<u8>,
-    total_length: usize,
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,69 +36,60 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut input = {
-            let mut buf = vec![0u8; length];
-            input.read_exact(&mut buf)?;
-            Cursor::new(buf)
-        };
-        let value = input.read_bytes_to()?;
-        Ok(Body { length, value })
-    }
-}
-
-impl FromReadBytesWith<()> for StatusRequest {
-    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
-    where
-        Self: Sized,
-    {
-        let status_type = input.read_u8()?;
-        Ok(match status_type {
-            1 => Self::Ocsp(input.read_bytes_to()?),
-            x => Self::Others(x),
-        })
-    }
-}
-
-impl FromReadBytesWith<()> for OcspStatusRequest {
-    fn from_read_bytes_with<R: std::io::Read>(
-        input: &mut R,
-        with: (),
-    ) -> Result<Self, std::io::Error>
-    where
-        Self: Sized,
-    {
-        let (responder_ids, responder_ids_length) = {
-            let length = input.read_u16::<NetworkEndian>()? as usize;
-            let mut total_length = 0;
-            let mut responder_ids = Vec::new();
-            while total_length < length {
-                let length = input.read_u16::<NetworkEndian>()? as usize;
-                let mut responder_id = vec![0u8; length];
-                input.read_exact(&mut responder_id);
-                total_length += length;
-                responder_ids.push(ResponderId(responder_id));
+        let value = read_vector(input, 2)?;
+        if value.is_empty() {
+            return Ok(Body {
+                value: StatusRequest::Empty,
+            });
+        }
+        let mut input = Cursor::new(&value[..]);
+        let value = match input.read_u8()? {
+            1 => {
+                let responder_ids = read_list(&read_vector(&mut input, 2)?, |input| {
+                    Ok(ResponderId(read_vector(input, 2)?))
+                })?;
+                let extensions = read_vector(&mut input, 2)?;
+                ensure_consumed(&input)?;
+                StatusRequest::Ocsp(OcspStatusRequest {
+                    responder_ids,
+                    extensions,
+                })
+            }
+            status_type => {
+                let mut request = Vec::new();
+                std::io::Read::read_to_end(&mut input, &mut request)?;
+                StatusRequest::Others {
+                    status_type,
+                    request,
+                }
             }
-            (responder_ids, total_length)
-        };
-
-        let (extensions, extensions_length) = {
-            let length = input.read_u16::<NetworkEndian>()? as usize;
-            let mut extensions = vec![0u8; length];
-            input.read_exact(&mut extensions);
-            (extensions, length)
         };
-
-        Ok(Self {
-            responder_ids,
-            extensions,
-            total_length: responder_ids_length + extensions_length,
-        })
+        Ok(Body { value })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match &self.value {
+            StatusRequest::Empty => {}
+            StatusRequest::Ocsp(ocsp) => {
+                buf.push(1);
+                let mut responder_ids = Vec::new();
+                for responder_id in &ocsp.responder_ids {
+                    write_vector(&mut responder_ids, 2, &responder_id.0);
+                }
+                write_vector(&mut buf, 2, &responder_ids);
+                write_vector(&mut buf, 2, &ocsp.extensions);
+            }
+            StatusRequest::Others {
+                status_type,
+                request,
+            } => {
+                buf.push(*status_type);
+                buf.extend_from_slice(request);
+            }
+        }
+        buf
     }
 }
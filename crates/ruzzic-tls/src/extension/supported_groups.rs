@@ -1,13 +1,16 @@
+use std::io::Cursor;
+
 use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_list, read_vector, write_vector};
+
 #[derive(Debug, PartialEq)]
 pub struct Body {
     named_curve_list: Vec<NamedCurve>,
-    total_length: usize,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NamedCurve {
     Deprecated(u16),
     Reserved(u16),
@@ -19,40 +22,65 @@ pub enum NamedCurve {
     Others(u16),
 }
 
+impl NamedCurve {
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            x @ 0x01..=0x16 => NamedCurve::Deprecated(x),
+            x @ 0xFF01..=0xFF02 => NamedCurve::Deprecated(x),
+            x @ 0xFE00..=0xFEFF => NamedCurve::Reserved(x),
+            0x17 => NamedCurve::Secp256r1,
+            0x18 => NamedCurve::Secp384rl,
+            0x19 => NamedCurve::Secp521r1,
+            0x1D => NamedCurve::X25519,
+            0x1E => NamedCurve::X448,
+            x => NamedCurve::Others(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            NamedCurve::Secp256r1 => 0x17,
+            NamedCurve::Secp384rl => 0x18,
+            NamedCurve::Secp521r1 => 0x19,
+            NamedCurve::X25519 => 0x1D,
+            NamedCurve::X448 => 0x1E,
+            NamedCurve::Deprecated(x) | NamedCurve::Reserved(x) | NamedCurve::Others(x) => *x,
+        }
+    }
+}
+
 impl FromReadBytesWith<()> for Body {
     fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut total_length = 0usize;
-        let mut named_curve_list = Vec::new();
-        while total_length < length {
-            let named_curve = match input.read_u16::<NetworkEndian>()? {
-                x @ 0x01..=0x16 => NamedCurve::Deprecated(x),
-                x @ 0xFF01..=0xFF02 => NamedCurve::Deprecated(x),
-                x @ 0xFE00..=0xFEFF => NamedCurve::Reserved(x),
-                0x17 => NamedCurve::Secp256r1,
-                0x18 => NamedCurve::Secp384rl,
-                0x19 => NamedCurve::Secp521r1,
-                0x1D => NamedCurve::X25519,
-                0x1E => NamedCurve::X448,
-                x => NamedCurve::Others(x),
-            };
-            named_curve_list.push(named_curve);
-            total_length += 2;
-        }
-        println!("{named_curve_list:?}");
-
-        Ok(Self {
-            named_curve_list,
-            total_length,
-        })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let named_curve_list = read_list(&read_vector(&mut input, 2)?, |input| {
+            Ok(NamedCurve::from_u16(input.read_u16::<NetworkEndian>()?))
+        })?;
+        ensure_consumed(&input)?;
+        Ok(Self { named_curve_list })
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.total_length
+    pub(crate) fn new(named_curve_list: Vec<NamedCurve>) -> Self {
+        Self { named_curve_list }
+    }
+
+    pub fn named_curve_list(&self) -> &[NamedCurve] {
+        &self.named_curve_list
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let list = self
+            .named_curve_list
+            .iter()
+            .flat_map(|c| c.to_u16().to_be_bytes())
+            .collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 2, &list);
+        buf
     }
 }
@@ -1,26 +1,55 @@
+use std::io::Cursor;
+
 use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, handshake::HandshakeType, read_list, read_vector, write_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.1
 #[derive(Debug, PartialEq)]
-pub struct Body {
-    length: usize,
-    value: Vec<u8>,
+pub enum Body {
+    ClientHello { versions: Vec<u16> },
+    ServerHello { selected_version: u16 },
 }
 
-impl FromReadBytesWith<()> for Body {
-    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+impl FromReadBytesWith<HandshakeType> for Body {
+    fn from_read_bytes_with<R: std::io::Read>(
+        input: &mut R,
+        handshake_type: HandshakeType,
+    ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        let length = input.read_u16::<NetworkEndian>()? as usize;
-        let mut value = vec![0u8; length as usize];
-        input.read_exact(&mut value)?;
-        Ok(Self { length, value })
+        let value = read_vector(input, 2)?;
+        let mut input = Cursor::new(&value[..]);
+        let body = match handshake_type {
+            HandshakeType::ClientHello => Body::ClientHello {
+                versions: read_list(&read_vector(&mut input, 1)?, |input| {
+                    input.read_u16::<NetworkEndian>()
+                })?,
+            },
+            _ => Body::ServerHello {
+                selected_version: input.read_u16::<NetworkEndian>()?,
+            },
+        };
+        ensure_consumed(&input)?;
+        Ok(body)
     }
 }
 
 impl Body {
-    pub(crate) fn size_of(&self) -> usize {
-        2 + self.length
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Body::ClientHello { versions } => {
+                let list = versions
+                    .iter()
+                    .flat_map(|v| v.to_be_bytes())
+                    .collect::<Vec<_>>();
+                let mut buf = Vec::new();
+                write_vector(&mut buf, 1, &list);
+                buf
+            }
+            Body::ServerHello { selected_version } => selected_version.to_be_bytes().to_vec(),
+        }
     }
 }
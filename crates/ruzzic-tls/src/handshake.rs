@@ -1,16 +1,18 @@
 use byteorder::ReadBytesExt;
 use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo, ReadBytesToWith};
 
-mod certificate;
-mod certificate_request;
-mod certificate_verify;
-mod client_hello;
-mod encrypted_extensions;
-mod finished;
+use crate::{read_vector, write_vector};
+
+pub(crate) mod certificate;
+pub(crate) mod certificate_request;
+pub(crate) mod certificate_verify;
+pub(crate) mod client_hello;
+pub(crate) mod encrypted_extensions;
+pub(crate) mod finished;
 mod key_update;
-mod new_session_ticket;
+pub(crate) mod new_session_ticket;
 mod others;
-mod server_hello;
+pub(crate) mod server_hello;
 
 #[derive(Debug, PartialEq)]
 pub enum Handshake {
@@ -24,7 +26,8 @@ pub enum Handshake {
     CertificateVerify(certificate_verify::Body),
     Finished(finished::Body),
     KeyUpdate(key_update::Body),
-    MessageHash,
+    /// The hash of a ClientHello replacing it in the transcript after a HelloRetryRequest.
+    MessageHash(Vec<u8>),
     Others(others::Body),
 }
 
@@ -41,12 +44,7 @@ pub enum HandshakeType {
     Finished,
     KeyUpdate,
     MessageHash,
-    Others,
-}
-
-#[derive(Debug, PartialEq)]
-pub(crate) struct HandshakeContext {
-    pub handshake_type: HandshakeType,
+    Others(u8),
 }
 
 impl FromReadBytesWith<()> for Handshake {
@@ -63,7 +61,15 @@ impl FromReadBytesWith<()> for Handshake {
                 Handshake::ServerHello(input.read_bytes_to_with(handshake_type)?)
             }
             HandshakeType::NewSessionTicket => Handshake::NewSessionTicket(input.read_bytes_to()?),
-            HandshakeType::EndOfEarlyData => Handshake::EndOfEarlyData,
+            HandshakeType::EndOfEarlyData => {
+                if !read_vector(input, 3)?.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "EndOfEarlyData with a body",
+                    ));
+                }
+                Handshake::EndOfEarlyData
+            }
             HandshakeType::EncryptedExtensions => {
                 Handshake::EncryptedExtensions(input.read_bytes_to()?)
             }
@@ -76,12 +82,73 @@ impl FromReadBytesWith<()> for Handshake {
             }
             HandshakeType::Finished => Handshake::Finished(input.read_bytes_to()?),
             HandshakeType::KeyUpdate => Handshake::KeyUpdate(input.read_bytes_to()?),
-            HandshakeType::MessageHash => Handshake::MessageHash,
-            _ => Handshake::Others(input.read_bytes_to()?),
+            HandshakeType::MessageHash => Handshake::MessageHash(read_vector(input, 3)?),
+            HandshakeType::Others(handshake_type) => {
+                Handshake::Others(input.read_bytes_to_with(handshake_type)?)
+            }
         })
     }
 }
 
+impl Handshake {
+    pub fn handshake_type(&self) -> HandshakeType {
+        match self {
+            Handshake::ClientHello(_) => HandshakeType::ClientHello,
+            Handshake::ServerHello(_) => HandshakeType::ServerHello,
+            Handshake::NewSessionTicket(_) => HandshakeType::NewSessionTicket,
+            Handshake::EndOfEarlyData => HandshakeType::EndOfEarlyData,
+            Handshake::EncryptedExtensions(_) => HandshakeType::EncryptedExtensions,
+            Handshake::Certificate(_) => HandshakeType::Certificate,
+            Handshake::CertificateRequest(_) => HandshakeType::CertificateRequest,
+            Handshake::CertificateVerify(_) => HandshakeType::CertificateVerify,
+            Handshake::Finished(_) => HandshakeType::Finished,
+            Handshake::KeyUpdate(_) => HandshakeType::KeyUpdate,
+            Handshake::MessageHash(_) => HandshakeType::MessageHash,
+            Handshake::Others(b) => HandshakeType::Others(b.handshake_type()),
+        }
+    }
+
+    /// Serialize the message with its type and length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = match self {
+            Handshake::ClientHello(b) => b.to_bytes(),
+            Handshake::ServerHello(b) => b.to_bytes(),
+            Handshake::NewSessionTicket(b) => b.to_bytes(),
+            Handshake::EndOfEarlyData => Vec::new(),
+            Handshake::EncryptedExtensions(b) => b.to_bytes(),
+            Handshake::Certificate(b) => b.to_bytes(),
+            Handshake::CertificateRequest(b) => b.to_bytes(),
+            Handshake::CertificateVerify(b) => b.to_bytes(),
+            Handshake::Finished(b) => b.to_bytes(),
+            Handshake::KeyUpdate(b) => b.to_bytes(),
+            Handshake::MessageHash(hash) => hash.clone(),
+            Handshake::Others(b) => b.to_bytes(),
+        };
+        let mut buf = vec![self.handshake_type().to_u8()];
+        write_vector(&mut buf, 3, &body);
+        buf
+    }
+}
+
+impl HandshakeType {
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            HandshakeType::ClientHello => 0x01,
+            HandshakeType::ServerHello => 0x02,
+            HandshakeType::NewSessionTicket => 0x04,
+            HandshakeType::EndOfEarlyData => 0x05,
+            HandshakeType::EncryptedExtensions => 0x08,
+            HandshakeType::Certificate => 0x0b,
+            HandshakeType::CertificateRequest => 0x0d,
+            HandshakeType::CertificateVerify => 0x0f,
+            HandshakeType::Finished => 0x14,
+            HandshakeType::KeyUpdate => 0x18,
+            HandshakeType::MessageHash => 0xfe,
+            HandshakeType::Others(handshake_type) => *handshake_type,
+        }
+    }
+}
+
 impl FromReadBytesWith<()> for HandshakeType {
     fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
     where
@@ -100,10 +167,12 @@ impl FromReadBytesWith<()> for HandshakeType {
             0x14 => HandshakeType::Finished,
             0x18 => HandshakeType::KeyUpdate,
             0xfe => HandshakeType::MessageHash,
-            _ => HandshakeType::Others,
+            x => HandshakeType::Others(x),
         })
     }
 }
 
 #[cfg(test)]
 mod rfc9000_tests;
+#[cfg(test)]
+mod tests;
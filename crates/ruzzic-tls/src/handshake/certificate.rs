@@ -1,6 +1,14 @@
+use std::io::Cursor;
+
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::CertificateEntry;
+use crate::{
+    ensure_consumed,
+    extension::{read_extensions, write_extensions},
+    read_list, read_vector, write_vector, Certificate, CertificateEntry,
+};
+
+use super::HandshakeType;
 
 #[derive(Debug, PartialEq)]
 pub struct Body {
@@ -13,6 +21,55 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        unimplemented!()
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
+        let certificate_request_context = read_vector(&mut input, 1)?;
+        let certificate_list = read_list(&read_vector(&mut input, 3)?, |input| {
+            // only X.509 certificates are supported
+            let cert_data = read_vector(&mut *input, 3)?;
+            let extension = read_extensions(input, HandshakeType::Certificate)?;
+            Ok(CertificateEntry {
+                certificate: Certificate::X509 { cert_data },
+                extension,
+            })
+        })?;
+        ensure_consumed(&input)?;
+        Ok(Self {
+            certificate_request_context,
+            certificate_list,
+        })
+    }
+}
+
+impl Body {
+    pub(crate) fn new(
+        certificate_request_context: Vec<u8>,
+        certificate_list: Vec<CertificateEntry>,
+    ) -> Self {
+        Self {
+            certificate_request_context,
+            certificate_list,
+        }
+    }
+
+    pub fn certificate_request_context(&self) -> &[u8] {
+        &self.certificate_request_context
+    }
+
+    /// The end-entity certificate comes first.
+    pub fn certificate_list(&self) -> &[CertificateEntry] {
+        &self.certificate_list
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut list = Vec::new();
+        for entry in &self.certificate_list {
+            write_vector(&mut list, 3, entry.data());
+            write_extensions(&mut list, &entry.extension);
+        }
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 1, &self.certificate_request_context);
+        write_vector(&mut buf, 3, &list);
+        buf
     }
 }
@@ -1,9 +1,16 @@
+use std::io::Cursor;
+
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::extension::Extensions;
+use crate::{
+    ensure_consumed,
+    extension::{read_extensions, write_extensions, Extensions},
+    read_vector, write_vector,
+};
 
 use super::HandshakeType;
 
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.3.2
 #[derive(Debug, PartialEq)]
 pub struct Body {
     certificate_request_context: Vec<u8>,
@@ -13,11 +20,43 @@ pub struct Body {
 impl FromReadBytesWith<HandshakeType> for Body {
     fn from_read_bytes_with<R: std::io::Read>(
         input: &mut R,
-        _: HandshakeType,
+        handshake_type: HandshakeType,
     ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        unimplemented!()
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
+        let certificate_request_context = read_vector(&mut input, 1)?;
+        let extensions = read_extensions(&mut input, handshake_type)?;
+        ensure_consumed(&input)?;
+        Ok(Self {
+            certificate_request_context,
+            extensions,
+        })
+    }
+}
+
+impl Body {
+    pub(crate) fn new(certificate_request_context: Vec<u8>, extensions: Extensions) -> Self {
+        Self {
+            certificate_request_context,
+            extensions,
+        }
+    }
+
+    pub fn certificate_request_context(&self) -> &[u8] {
+        &self.certificate_request_context
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_vector(&mut buf, 1, &self.certificate_request_context);
+        write_extensions(&mut buf, &self.extensions);
+        buf
     }
 }
@@ -1,6 +1,8 @@
-use ruzzic_common::read_bytes_to::FromReadBytesWith;
+use std::io::Cursor;
 
-use crate::SignatureScheme;
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+
+use crate::{ensure_consumed, read_vector, write_vector, SignatureScheme};
 
 #[derive(Debug, PartialEq)]
 pub struct Body {
@@ -13,6 +15,37 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        unimplemented!()
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
+        let algorithm = input.read_bytes_to()?;
+        let signature = read_vector(&mut input, 2)?;
+        ensure_consumed(&input)?;
+        Ok(Self {
+            algorithm,
+            signature,
+        })
+    }
+}
+
+impl Body {
+    pub(crate) fn new(algorithm: SignatureScheme, signature: Vec<u8>) -> Self {
+        Self {
+            algorithm,
+            signature,
+        }
+    }
+
+    pub fn algorithm(&self) -> SignatureScheme {
+        self.algorithm
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.algorithm.to_u16().to_be_bytes().to_vec();
+        write_vector(&mut buf, 2, &self.signature);
+        buf
     }
 }
@@ -1,11 +1,14 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
-use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo, ReadBytesToWith};
+use std::io::{Cursor, Read};
+
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
 
 use crate::{
-    extension::{Extension, Extensions},
-    CipherSuite, LegacyVersion,
+    ensure_consumed,
+    extension::{read_extensions, write_extensions, Extensions},
+    read_list, read_vector, write_vector, CipherSuite, LegacyVersion,
 };
 
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.2
 use super::HandshakeType;
 
 #[derive(Debug, PartialEq)]
@@ -26,45 +29,19 @@ impl FromReadBytesWith<HandshakeType> for Body {
     where
         Self: Sized,
     {
-        let _remain_length = input.read_uint::<NetworkEndian>(3)? as usize;
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
         let legacy_version = input.read_bytes_to()?;
         let random = {
             let mut buf = [0; 32];
             input.read_exact(&mut buf)?;
             buf
         };
-        let legacy_session_id = {
-            let length = input.read_u8()?;
-            let mut buf = vec![0; length as usize];
-            input.read_exact(&mut buf)?;
-            buf
-        };
-        let cipher_suites = {
-            let length = input.read_u16::<NetworkEndian>()? as usize;
-            let mut cipher_suites = Vec::new();
-            for i in 0..length / CipherSuite::size_of() {
-                let cipher_suite = input.read_bytes_to()?;
-                cipher_suites.push(cipher_suite);
-            }
-            cipher_suites
-        };
-        let legacy_compression_methods = {
-            let length = input.read_u8()? as usize;
-            let mut buf = vec![0; length];
-            input.read_exact(&mut buf)?;
-            buf
-        };
-        let extensions = {
-            let length = input.read_u16::<NetworkEndian>()? as usize;
-            let mut total_length = 0;
-            let mut extensions = Vec::new();
-            while total_length < length {
-                let e: Extension = input.read_bytes_to_with(handshake_type.clone())?;
-                total_length += e.size_of();
-                extensions.push(e);
-            }
-            extensions
-        };
+        let legacy_session_id = read_vector(&mut input, 1)?;
+        let cipher_suites = read_list(&read_vector(&mut input, 2)?, |input| input.read_bytes_to())?;
+        let legacy_compression_methods = read_vector(&mut input, 1)?;
+        let extensions = read_extensions(&mut input, handshake_type)?;
+        ensure_consumed(&input)?;
         Ok(Self {
             legacy_version,
             random,
@@ -75,3 +52,56 @@ impl FromReadBytesWith<HandshakeType> for Body {
         })
     }
 }
+
+impl Body {
+    pub(crate) fn new(
+        random: [u8; 32],
+        cipher_suites: Vec<CipherSuite>,
+        extensions: Extensions,
+    ) -> Self {
+        Self {
+            legacy_version: LegacyVersion::TLS12,
+            random,
+            // QUIC does not use the middlebox compatibility mode
+            legacy_session_id: Vec::new(),
+            cipher_suites,
+            legacy_compression_methods: vec![0],
+            extensions,
+        }
+    }
+
+    pub fn random(&self) -> &[u8; 32] {
+        &self.random
+    }
+
+    pub fn legacy_session_id(&self) -> &[u8] {
+        &self.legacy_session_id
+    }
+
+    pub fn cipher_suites(&self) -> &[CipherSuite] {
+        &self.cipher_suites
+    }
+
+    pub fn legacy_compression_methods(&self) -> &[u8] {
+        &self.legacy_compression_methods
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.legacy_version.to_bytes().to_vec();
+        buf.extend_from_slice(&self.random);
+        write_vector(&mut buf, 1, &self.legacy_session_id);
+        let cipher_suites = self
+            .cipher_suites
+            .iter()
+            .flat_map(|c| c.to_u16().to_be_bytes())
+            .collect::<Vec<_>>();
+        write_vector(&mut buf, 2, &cipher_suites);
+        write_vector(&mut buf, 1, &self.legacy_compression_methods);
+        write_extensions(&mut buf, &self.extensions);
+        buf
+    }
+}
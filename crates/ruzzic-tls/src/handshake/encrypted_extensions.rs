@@ -1,6 +1,14 @@
+use std::io::Cursor;
+
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::Extensions;
+use crate::{
+    ensure_consumed,
+    extension::{read_extensions, write_extensions},
+    read_vector, Extensions,
+};
+
+use super::HandshakeType;
 
 #[derive(Debug, PartialEq)]
 pub struct Body {
@@ -12,6 +20,26 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        unimplemented!()
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
+        let extensions = read_extensions(&mut input, HandshakeType::EncryptedExtensions)?;
+        ensure_consumed(&input)?;
+        Ok(Self { extensions })
+    }
+}
+
+impl Body {
+    pub(crate) fn new(extensions: Extensions) -> Self {
+        Self { extensions }
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_extensions(&mut buf, &self.extensions);
+        buf
     }
 }
@@ -1,5 +1,7 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::read_vector;
+
 #[derive(Debug, PartialEq)]
 pub struct Body {
     verify_data: Vec<u8>,
@@ -10,6 +12,22 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        unimplemented!()
+        Ok(Self {
+            verify_data: read_vector(input, 3)?,
+        })
+    }
+}
+
+impl Body {
+    pub(crate) fn new(verify_data: Vec<u8>) -> Self {
+        Self { verify_data }
+    }
+
+    pub fn verify_data(&self) -> &[u8] {
+        &self.verify_data
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.verify_data.clone()
     }
 }
@@ -1,5 +1,12 @@
+use std::io::Cursor;
+
+use byteorder::ReadBytesExt;
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::{ensure_consumed, read_vector};
+
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.6.3
+/// QUIC forbids the message, but it is decoded to be rejected properly.
 #[derive(Debug, PartialEq)]
 pub struct Body {
     key_update_request: KeyUpdateRequest,
@@ -17,6 +24,28 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        unimplemented!()
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
+        let key_update_request = match input.read_u8()? {
+            0 => KeyUpdateRequest::UpdateNotRequested,
+            1 => KeyUpdateRequest::UpdateRequested,
+            x => KeyUpdateRequest::Others(x),
+        };
+        ensure_consumed(&input)?;
+        Ok(Self { key_update_request })
+    }
+}
+
+impl Body {
+    pub fn key_update_request(&self) -> &KeyUpdateRequest {
+        &self.key_update_request
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        vec![match self.key_update_request {
+            KeyUpdateRequest::UpdateNotRequested => 0,
+            KeyUpdateRequest::UpdateRequested => 1,
+            KeyUpdateRequest::Others(x) => x,
+        }]
     }
 }
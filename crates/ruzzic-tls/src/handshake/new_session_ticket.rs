@@ -1,7 +1,17 @@
+use std::io::Cursor;
+
+use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
-use crate::Extensions;
+use crate::{
+    ensure_consumed,
+    extension::{read_extensions, write_extensions},
+    read_vector, write_vector, Extensions,
+};
+
+use super::HandshakeType;
 
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.6.1
 #[derive(Debug, PartialEq)]
 pub struct Body {
     ticket_lifetime: u32,
@@ -16,6 +26,68 @@ impl FromReadBytesWith<()> for Body {
     where
         Self: Sized,
     {
-        unimplemented!()
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
+        let ticket_lifetime = input.read_u32::<NetworkEndian>()?;
+        let ticket_age_add = input.read_u32::<NetworkEndian>()?;
+        let ticket_nonce = read_vector(&mut input, 1)?;
+        let ticket = read_vector(&mut input, 2)?;
+        let extensions = read_extensions(&mut input, HandshakeType::NewSessionTicket)?;
+        ensure_consumed(&input)?;
+        Ok(Self {
+            ticket_lifetime,
+            ticket_age_add,
+            ticket_nonce,
+            ticket,
+            extensions,
+        })
+    }
+}
+
+impl Body {
+    pub(crate) fn new(
+        ticket_lifetime: u32,
+        ticket_age_add: u32,
+        ticket_nonce: Vec<u8>,
+        ticket: Vec<u8>,
+        extensions: Extensions,
+    ) -> Self {
+        Self {
+            ticket_lifetime,
+            ticket_age_add,
+            ticket_nonce,
+            ticket,
+            extensions,
+        }
+    }
+
+    /// Seconds the ticket may be used for.
+    pub fn ticket_lifetime(&self) -> u32 {
+        self.ticket_lifetime
+    }
+
+    pub fn ticket_age_add(&self) -> u32 {
+        self.ticket_age_add
+    }
+
+    pub fn ticket_nonce(&self) -> &[u8] {
+        &self.ticket_nonce
+    }
+
+    pub fn ticket(&self) -> &[u8] {
+        &self.ticket
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.ticket_lifetime.to_be_bytes().to_vec();
+        buf.extend_from_slice(&self.ticket_age_add.to_be_bytes());
+        write_vector(&mut buf, 1, &self.ticket_nonce);
+        write_vector(&mut buf, 2, &self.ticket);
+        write_extensions(&mut buf, &self.extensions);
+        buf
     }
 }
@@ -1,13 +1,35 @@
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+use crate::read_vector;
+
+/// A message not known here, kept as is.
 #[derive(Debug, PartialEq)]
-pub struct Body;
+pub struct Body {
+    handshake_type: u8,
+    body: Vec<u8>,
+}
 
-impl FromReadBytesWith<()> for Body {
-    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+impl FromReadBytesWith<u8> for Body {
+    fn from_read_bytes_with<R: std::io::Read>(
+        input: &mut R,
+        handshake_type: u8,
+    ) -> Result<Self, std::io::Error>
     where
         Self: Sized,
     {
-        unimplemented!()
+        Ok(Self {
+            handshake_type,
+            body: read_vector(input, 3)?,
+        })
+    }
+}
+
+impl Body {
+    pub(crate) fn handshake_type(&self) -> u8 {
+        self.handshake_type
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.body.clone()
     }
 }
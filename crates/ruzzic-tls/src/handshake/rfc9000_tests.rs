@@ -1,6 +1,6 @@
 use ruzzic_common::read_bytes_to::ReadBytesTo;
 
-use crate::handshake::Handshake;
+use crate::handshake::{Handshake, HandshakeType};
 
 use std::io::Cursor;
 
@@ -24,4 +24,6 @@ fn server_initial_packet_client_hello() {
     let mut input = Cursor::new(SERVER_INITIAL_PACKET_CLIENT_HELLO);
     let tls_handshake: Handshake = input.read_bytes_to().unwrap();
     eprintln!("{tls_handshake:x?}");
+    assert_eq!(tls_handshake.handshake_type(), HandshakeType::ClientHello);
+    assert_eq!(tls_handshake.to_bytes(), SERVER_INITIAL_PACKET_CLIENT_HELLO);
 }
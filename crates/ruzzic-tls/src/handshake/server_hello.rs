@@ -1,10 +1,23 @@
-use byteorder::{NetworkEndian, ReadBytesExt};
-use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo, ReadBytesToWith};
+use std::io::{Cursor, Read};
 
-use crate::{extension::Extension, CipherSuite, Extensions, LegacyVersion};
+use byteorder::ReadBytesExt;
+use ruzzic_common::read_bytes_to::{FromReadBytesWith, ReadBytesTo};
+
+use crate::{
+    ensure_consumed,
+    extension::{read_extensions, write_extensions},
+    read_vector, write_vector, CipherSuite, Extensions, LegacyVersion,
+};
 
 use super::HandshakeType;
 
+/// The random of a ServerHello which is a HelloRetryRequest.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.3
+pub(crate) const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
 #[derive(Debug, PartialEq)]
 pub struct Body {
     legacy_version: LegacyVersion,
@@ -23,37 +36,20 @@ impl FromReadBytesWith<HandshakeType> for Body {
     where
         Self: Sized,
     {
-        let _remain_length = input.read_uint::<NetworkEndian>(3)? as usize;
+        let body = read_vector(input, 3)?;
+        let mut input = Cursor::new(&body[..]);
         let legacy_version = input.read_bytes_to()?;
         let random = {
             let mut buf = [0; 32];
             input.read_exact(&mut buf)?;
             buf
         };
-        let legacy_session_id = {
-            let length = input.read_u8()?;
-            let mut buf = vec![0; length as usize];
-            input.read_exact(&mut buf)?;
-            buf
-        };
+        let legacy_session_id = read_vector(&mut input, 1)?;
         let cipher_suite = input.read_bytes_to()?;
-        let legacy_compression_methods = {
-            let length = input.read_u8()? as usize;
-            let mut buf = vec![0; length];
-            input.read_exact(&mut buf)?;
-            buf
-        };
-        let extensions = {
-            let length = input.read_u16::<NetworkEndian>()? as usize;
-            let mut total_length = 0;
-            let mut extensions = Vec::new();
-            while total_length < length {
-                let e: Extension = input.read_bytes_to_with(handshake_type.clone())?;
-                total_length += e.size_of();
-                extensions.push(e);
-            }
-            extensions
-        };
+        // a ServerHello has a single legacy_compression_method, not a vector of them
+        let legacy_compression_methods = vec![input.read_u8()?];
+        let extensions = read_extensions(&mut input, handshake_type)?;
+        ensure_consumed(&input)?;
         Ok(Self {
             legacy_version,
             random,
@@ -64,3 +60,65 @@ impl FromReadBytesWith<HandshakeType> for Body {
         })
     }
 }
+
+impl Body {
+    pub(crate) fn new(
+        random: [u8; 32],
+        legacy_session_id: Vec<u8>,
+        cipher_suite: CipherSuite,
+        extensions: Extensions,
+    ) -> Self {
+        Self {
+            legacy_version: LegacyVersion::TLS12,
+            random,
+            legacy_session_id,
+            cipher_suite,
+            legacy_compression_methods: vec![0],
+            extensions,
+        }
+    }
+
+    /// A HelloRetryRequest, which is a ServerHello with the special random.
+    pub(crate) fn hello_retry_request(
+        legacy_session_id: Vec<u8>,
+        cipher_suite: CipherSuite,
+        extensions: Extensions,
+    ) -> Self {
+        Self::new(
+            HELLO_RETRY_REQUEST_RANDOM,
+            legacy_session_id,
+            cipher_suite,
+            extensions,
+        )
+    }
+
+    pub fn random(&self) -> &[u8; 32] {
+        &self.random
+    }
+
+    pub fn is_hello_retry_request(&self) -> bool {
+        self.random == HELLO_RETRY_REQUEST_RANDOM
+    }
+
+    pub fn legacy_session_id(&self) -> &[u8] {
+        &self.legacy_session_id
+    }
+
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.cipher_suite
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.legacy_version.to_bytes().to_vec();
+        buf.extend_from_slice(&self.random);
+        write_vector(&mut buf, 1, &self.legacy_session_id);
+        buf.extend_from_slice(&self.cipher_suite.to_u16().to_be_bytes());
+        buf.extend_from_slice(&self.legacy_compression_methods);
+        write_extensions(&mut buf, &self.extensions);
+        buf
+    }
+}
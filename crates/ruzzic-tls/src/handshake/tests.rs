@@ -0,0 +1,80 @@
+use std::io::Cursor;
+
+use ruzzic_common::read_bytes_to::ReadBytesTo;
+
+use super::{Handshake, HandshakeType};
+
+fn assert_roundtrip(message: &[u8], handshake_type: HandshakeType) -> Handshake {
+    let handshake: Handshake = Cursor::new(message).read_bytes_to().unwrap();
+    assert_eq!(handshake.handshake_type(), handshake_type);
+    assert_eq!(handshake.to_bytes(), message);
+    handshake
+}
+
+#[test]
+fn new_session_ticket() {
+    const NEW_SESSION_TICKET: &[u8] = &[
+        0x04, 0x00, 0x00, 0x1a, // type and length
+        0x00, 0x00, 0x1c, 0x20, // ticket_lifetime
+        0x01, 0x02, 0x03, 0x04, // ticket_age_add
+        0x01, 0x00, // ticket_nonce
+        0x00, 0x04, 0xaa, 0xbb, 0xcc, 0xdd, // ticket
+        0x00, 0x08, 0x00, 0x2a, 0x00, 0x04, 0xff, 0xff, 0xff, 0xff, // early_data
+    ];
+    let Handshake::NewSessionTicket(ticket) =
+        assert_roundtrip(NEW_SESSION_TICKET, HandshakeType::NewSessionTicket)
+    else {
+        unreachable!()
+    };
+    assert_eq!(ticket.ticket_lifetime(), 7200);
+    assert_eq!(ticket.ticket_nonce(), &[0x00]);
+    assert_eq!(ticket.ticket(), &[0xaa, 0xbb, 0xcc, 0xdd]);
+}
+
+#[test]
+fn certificate_request_and_key_update() {
+    const CERTIFICATE_REQUEST: &[u8] = &[
+        0x0d, 0x00, 0x00, 0x0b, // type and length
+        0x00, // certificate_request_context
+        0x00, 0x08, 0x00, 0x0d, 0x00, 0x04, 0x00, 0x02, 0x08, 0x07, // signature_algorithms
+    ];
+    assert_roundtrip(CERTIFICATE_REQUEST, HandshakeType::CertificateRequest);
+    assert_roundtrip(&[0x18, 0x00, 0x00, 0x01, 0x01], HandshakeType::KeyUpdate);
+}
+
+#[test]
+fn client_hello_with_pre_shared_key() {
+    let mut message = vec![0x01, 0x00, 0x00, 0x71, 0x03, 0x03];
+    message.extend_from_slice(&[0x11; 32]);
+    message.extend_from_slice(&[
+        0x00, // legacy_session_id
+        0x00, 0x02, 0x13, 0x01, // cipher_suites
+        0x01, 0x00, // legacy_compression_methods
+        0x00, 0x46, // extensions
+        0x00, 0x2d, 0x00, 0x02, 0x01, 0x01, // psk_key_exchange_modes
+        0x00, 0x2a, 0x00, 0x00, // early_data
+        0x00, 0x2c, 0x00, 0x05, 0x00, 0x03, 0x01, 0x02, 0x03, // cookie
+        0x00, 0x29, 0x00, 0x2f, // pre_shared_key
+        0x00, 0x0a, 0x00, 0x04, 0xaa, 0xbb, 0xcc, 0xdd, 0x00, 0x00, 0x00, 0x07, // identities
+        0x00, 0x21, 0x20, // binders
+    ]);
+    message.extend_from_slice(&[0x22; 32]);
+    assert_roundtrip(&message, HandshakeType::ClientHello);
+}
+
+#[test]
+fn trailing_bytes() {
+    // KeyUpdate with a byte after key_update_request
+    let result: Result<Handshake, _> =
+        Cursor::new(&[0x18, 0x00, 0x00, 0x02, 0x01, 0x00]).read_bytes_to();
+    assert!(result.is_err());
+    // supported_versions with a byte after the versions
+    let mut message = vec![0x01, 0x00, 0x00, 0x33, 0x03, 0x03];
+    message.extend_from_slice(&[0x11; 32]);
+    message.extend_from_slice(&[
+        0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00, 0x00, 0x08, 0x00, 0x2b, 0x00, 0x04, 0x02, 0x03,
+        0x04, 0x00,
+    ]);
+    let result: Result<Handshake, _> = Cursor::new(&message).read_bytes_to();
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
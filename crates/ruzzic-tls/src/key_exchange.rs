@@ -0,0 +1,95 @@
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use rand::rngs::OsRng;
+
+use crate::{extension::NamedCurve, AlertDescription, TlsError};
+
+/// Groups a key share can be generated for, in the default order of preference.
+pub(crate) const SUPPORTED_GROUPS: &[NamedCurve] = &[NamedCurve::X25519, NamedCurve::Secp256r1];
+
+enum Secret {
+    X25519(x25519_dalek::EphemeralSecret),
+    Secp256r1(p256::ecdh::EphemeralSecret),
+}
+
+/// Ephemeral (EC)DHE key pair for the key_share extension.
+pub(crate) struct KeyExchange {
+    group: NamedCurve,
+    secret: Secret,
+    public_key: Vec<u8>,
+}
+
+impl KeyExchange {
+    /// Generate a key pair, or None if the group is not supported.
+    pub(crate) fn new(group: NamedCurve) -> Option<Self> {
+        let (secret, public_key) = match group {
+            NamedCurve::X25519 => {
+                let secret = x25519_dalek::EphemeralSecret::random_from_rng(OsRng);
+                let public_key = x25519_dalek::PublicKey::from(&secret).as_bytes().to_vec();
+                (Secret::X25519(secret), public_key)
+            }
+            NamedCurve::Secp256r1 => {
+                let secret = p256::ecdh::EphemeralSecret::random(&mut OsRng);
+                // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.8.2
+                let public_key = secret
+                    .public_key()
+                    .to_encoded_point(false)
+                    .as_bytes()
+                    .to_vec();
+                (Secret::Secp256r1(secret), public_key)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            group,
+            secret,
+            public_key,
+        })
+    }
+
+    pub(crate) fn group(&self) -> NamedCurve {
+        self.group
+    }
+
+    pub(crate) fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Compute the shared secret with the public key of the peer.
+    pub(crate) fn complete(self, peer_public_key: &[u8]) -> Result<Vec<u8>, TlsError> {
+        match self.secret {
+            Secret::X25519(secret) => {
+                let peer_public_key: [u8; 32] = peer_public_key.try_into().map_err(|_| {
+                    TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "invalid X25519 public key",
+                    )
+                })?;
+                let shared_secret =
+                    secret.diffie_hellman(&x25519_dalek::PublicKey::from(peer_public_key));
+                // https://www.rfc-editor.org/rfc/rfc8446.html#section-7.4.2
+                if !shared_secret.was_contributory() {
+                    return Err(TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "X25519 shared secret is all zero",
+                    ));
+                }
+                Ok(shared_secret.as_bytes().to_vec())
+            }
+            Secret::Secp256r1(secret) => {
+                // only the uncompressed form is allowed, and the point is validated on decoding
+                let peer_public_key = (peer_public_key.first() == Some(&0x04))
+                    .then(|| p256::PublicKey::from_sec1_bytes(peer_public_key).ok())
+                    .flatten()
+                    .ok_or_else(|| {
+                        TlsError::new(
+                            AlertDescription::IllegalParameter,
+                            "invalid secp256r1 public key",
+                        )
+                    })?;
+                // the x-coordinate of the point is the shared secret
+                let shared_secret = secret.diffie_hellman(&peer_public_key);
+                Ok(shared_secret.raw_secret_bytes().to_vec())
+            }
+        }
+    }
+}
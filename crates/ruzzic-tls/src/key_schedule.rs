@@ -0,0 +1,252 @@
+//! https://www.rfc-editor.org/rfc/rfc8446.html#section-7.1
+
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::CipherSuite;
+
+/// Hash of the cipher suite used for the transcript and HKDF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+}
+
+impl HashAlgorithm {
+    pub fn of(cipher_suite: CipherSuite) -> Option<Self> {
+        match cipher_suite {
+            CipherSuite::TlsAes128GcmSha256
+            | CipherSuite::TlsChacha20Poly1305Sha256
+            | CipherSuite::TlsAes128CcmSha256
+            | CipherSuite::TlsAes128Ccm8Sha256 => Some(HashAlgorithm::Sha256),
+            CipherSuite::TlsAes256GcmSha384 => Some(HashAlgorithm::Sha384),
+            CipherSuite::Others(_) => None,
+        }
+    }
+
+    pub fn output_length(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+        }
+    }
+
+    pub fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+        }
+    }
+
+    pub fn hmac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+            let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC takes a key of any size");
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        }
+        match self {
+            HashAlgorithm::Sha256 => hmac::<Hmac<Sha256>>(key, data),
+            HashAlgorithm::Sha384 => hmac::<Hmac<Sha384>>(key, data),
+        }
+    }
+
+    pub fn hkdf_extract(&self, salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Hkdf::<Sha256>::extract(Some(salt), ikm).0.to_vec(),
+            HashAlgorithm::Sha384 => Hkdf::<Sha384>::extract(Some(salt), ikm).0.to_vec(),
+        }
+    }
+
+    pub fn hkdf_expand(&self, prk: &[u8], info: &[u8], length: usize) -> Vec<u8> {
+        let mut okm = vec![0; length];
+        match self {
+            HashAlgorithm::Sha256 => Hkdf::<Sha256>::from_prk(prk)
+                .expect("a secret is as long as the hash")
+                .expand(info, &mut okm),
+            HashAlgorithm::Sha384 => Hkdf::<Sha384>::from_prk(prk)
+                .expect("a secret is as long as the hash")
+                .expand(info, &mut okm),
+        }
+        .expect("the length is small enough");
+        okm
+    }
+
+    /// HKDF-Expand-Label(Secret, Label, Context, Length)
+    pub fn hkdf_expand_label(
+        &self,
+        secret: &[u8],
+        label: &[u8],
+        context: &[u8],
+        length: usize,
+    ) -> Vec<u8> {
+        self.hkdf_expand(secret, &hkdf_label(label, context, length as u16), length)
+    }
+}
+
+/// The HkdfLabel structure, used as the info of HKDF-Expand.
+/// QUIC derives its packet protection keys with the same structure.
+pub fn hkdf_label(label: &[u8], context: &[u8], length: u16) -> Vec<u8> {
+    [
+        &length.to_be_bytes()[..],
+        &[6 + label.len() as u8],
+        b"tls13 ",
+        label,
+        &[context.len() as u8],
+        context,
+    ]
+    .concat()
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Sha256(Sha256),
+    Sha384(Sha384),
+}
+
+/// Running hash of the handshake messages.
+/// Messages are kept until the cipher suite decides the hash.
+#[derive(Clone)]
+pub(crate) struct Transcript {
+    messages: Vec<u8>,
+    hasher: Option<Hasher>,
+}
+
+impl Transcript {
+    pub(crate) fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+            hasher: None,
+        }
+    }
+
+    pub(crate) fn start_hash(&mut self, algorithm: HashAlgorithm) {
+        let mut hasher = match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha384 => Hasher::Sha384(Sha384::new()),
+        };
+        match &mut hasher {
+            Hasher::Sha256(h) => h.update(&self.messages),
+            Hasher::Sha384(h) => h.update(&self.messages),
+        }
+        self.messages = Vec::new();
+        self.hasher = Some(hasher);
+    }
+
+    pub(crate) fn update(&mut self, message: &[u8]) {
+        match &mut self.hasher {
+            Some(Hasher::Sha256(h)) => h.update(message),
+            Some(Hasher::Sha384(h)) => h.update(message),
+            None => self.messages.extend_from_slice(message),
+        }
+    }
+
+    /// Drop the messages hashed so far, keeping the hash algorithm.
+    pub(crate) fn reset(&mut self) {
+        self.hasher = match &self.hasher {
+            Some(Hasher::Sha256(_)) => Some(Hasher::Sha256(Sha256::new())),
+            Some(Hasher::Sha384(_)) => Some(Hasher::Sha384(Sha384::new())),
+            None => None,
+        };
+    }
+
+    /// The hash with one more message, starting the hash with the algorithm if not yet.
+    pub(crate) fn hash_with(&self, algorithm: HashAlgorithm, message: &[u8]) -> Vec<u8> {
+        let mut transcript = self.clone();
+        if transcript.hasher.is_none() {
+            transcript.start_hash(algorithm);
+        }
+        transcript.update(message);
+        transcript.current_hash()
+    }
+
+    pub(crate) fn current_hash(&self) -> Vec<u8> {
+        match &self.hasher {
+            Some(Hasher::Sha256(h)) => h.clone().finalize().to_vec(),
+            Some(Hasher::Sha384(h)) => h.clone().finalize().to_vec(),
+            None => panic!("the hash is decided in the hellos"),
+        }
+    }
+}
+
+/// The chain of the early, handshake and master secrets.
+pub(crate) struct KeySchedule {
+    algorithm: HashAlgorithm,
+    secret: Vec<u8>,
+}
+
+impl KeySchedule {
+    /// Start from the early secret, with the PSK if resuming.
+    pub(crate) fn new(algorithm: HashAlgorithm, psk: Option<&[u8]>) -> Self {
+        let zeros = vec![0; algorithm.output_length()];
+        Self {
+            algorithm,
+            secret: algorithm.hkdf_extract(&zeros, psk.unwrap_or(&zeros)),
+        }
+    }
+
+    pub(crate) fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Move on to the next secret, the handshake secret with the (EC)DHE shared secret
+    /// and then the master secret without input.
+    pub(crate) fn advance(&mut self, input: Option<&[u8]>) {
+        let zeros = vec![0; self.algorithm.output_length()];
+        let derived = self.derive_secret(b"derived", &self.algorithm.hash(&[]));
+        self.secret = self
+            .algorithm
+            .hkdf_extract(&derived, input.unwrap_or(&zeros));
+    }
+
+    /// Derive-Secret(Secret, Label, Messages) with the hash of the messages.
+    pub(crate) fn derive_secret(&self, label: &[u8], transcript_hash: &[u8]) -> Vec<u8> {
+        self.algorithm.hkdf_expand_label(
+            &self.secret,
+            label,
+            transcript_hash,
+            self.algorithm.output_length(),
+        )
+    }
+
+    /// verify_data of a Finished message sent with the traffic secret.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.4.4
+    pub(crate) fn finished_verify_data(
+        &self,
+        traffic_secret: &[u8],
+        transcript_hash: &[u8],
+    ) -> Vec<u8> {
+        let finished_key = self.algorithm.hkdf_expand_label(
+            traffic_secret,
+            b"finished",
+            &[],
+            self.algorithm.output_length(),
+        );
+        self.algorithm.hmac(&finished_key, transcript_hash)
+    }
+
+    /// Binder of a resumption PSK over the truncated ClientHello, from the early secret.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11.2
+    pub(crate) fn resumption_binder(&self, transcript_hash: &[u8]) -> Vec<u8> {
+        let binder_key = self.derive_secret(b"res binder", &self.algorithm.hash(&[]));
+        self.finished_verify_data(&binder_key, transcript_hash)
+    }
+
+    /// PSK of a ticket.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.6.1
+    pub(crate) fn resumption_psk(
+        &self,
+        resumption_master_secret: &[u8],
+        ticket_nonce: &[u8],
+    ) -> Vec<u8> {
+        self.algorithm.hkdf_expand_label(
+            resumption_master_secret,
+            b"resumption",
+            ticket_nonce,
+            self.algorithm.output_length(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod rfc8448_tests;
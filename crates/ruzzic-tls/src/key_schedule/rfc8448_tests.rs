@@ -0,0 +1,153 @@
+//! https://www.rfc-editor.org/rfc/rfc8448.html
+
+use super::{HashAlgorithm, KeySchedule};
+
+fn h(s: &str) -> Vec<u8> {
+    hex::decode(s.replace(' ', "")).unwrap()
+}
+
+/// 3. Simple 1-RTT Handshake
+#[test]
+fn simple_1rtt_handshake() {
+    let mut key_schedule = KeySchedule::new(HashAlgorithm::Sha256, None);
+    assert_eq!(
+        key_schedule.secret,
+        h("33ad0a1c607ec03b09e6cd9893680ce210adf300aa1f2660e1b22e10f170f92a")
+    );
+    assert_eq!(
+        key_schedule.derive_secret(b"derived", &HashAlgorithm::Sha256.hash(&[])),
+        h("6f2615a108c702c5678f54fc9dbab69716c076189c48250cebeac3576c3611ba")
+    );
+
+    key_schedule.advance(Some(&h(
+        "8bd4054fb55b9d63fdfbacf9f04b9f0d35e6d63f537563efd46272900f89492d",
+    )));
+    assert_eq!(
+        key_schedule.secret,
+        h("1dc826e93606aa6fdc0aadc12f741b01046aa6b99f691ed221a9f0ca043fbeac")
+    );
+    let hello_hash = h("860c06edc07858ee8e78f0e7428c58edd6b43f2ca3e6e95f02ed063cf0e1cad8");
+    let client_handshake_traffic_secret = key_schedule.derive_secret(b"c hs traffic", &hello_hash);
+    assert_eq!(
+        client_handshake_traffic_secret,
+        h("b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21")
+    );
+    let server_handshake_traffic_secret = key_schedule.derive_secret(b"s hs traffic", &hello_hash);
+    assert_eq!(
+        server_handshake_traffic_secret,
+        h("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38")
+    );
+    // traffic keys of the server handshake
+    assert_eq!(
+        HashAlgorithm::Sha256.hkdf_expand_label(&server_handshake_traffic_secret, b"key", &[], 16),
+        h("3fce516009c21727d0f2e4e86ee403bc")
+    );
+    assert_eq!(
+        HashAlgorithm::Sha256.hkdf_expand_label(&server_handshake_traffic_secret, b"iv", &[], 12),
+        h("5d313eb2671276ee13000b30")
+    );
+    assert_eq!(
+        HashAlgorithm::Sha256.hkdf_expand_label(&client_handshake_traffic_secret, b"key", &[], 16),
+        h("dbfaa693d1762c5b666af5d950258d01")
+    );
+    assert_eq!(
+        HashAlgorithm::Sha256.hkdf_expand_label(&client_handshake_traffic_secret, b"iv", &[], 12),
+        h("5bd3c71b836e0b76bb73265f")
+    );
+}
+
+/// Secrets derived from the master secret.
+#[test]
+fn master_secret_derivations() {
+    let key_schedule = KeySchedule {
+        algorithm: HashAlgorithm::Sha256,
+        secret: h("e2d32d4ed66dd37897a0e80c84107503ce58bf8aad4cb55a5002d77ecb890ece"),
+    };
+    let server_finished_hash =
+        h("b0aeffc46a2cfe33114e6fd7d51f9f04b1ca3c497dab08934a774a9d9ad7dbf3");
+    assert_eq!(
+        key_schedule.derive_secret(b"c ap traffic", &server_finished_hash),
+        h("2abbf2b8e381d23dbebe1dd2a7d16a8bf484cb4950d23fb7fb7fa8547062d9a1")
+    );
+    assert_eq!(
+        key_schedule.derive_secret(b"s ap traffic", &server_finished_hash),
+        h("cc21f1bf8feb7dd5fa505bd9c4b468a9984d554a993dc49e6d285598fb672691")
+    );
+    assert_eq!(
+        key_schedule.derive_secret(b"exp master", &server_finished_hash),
+        h("3fd93d4ffddc98e64b14dd107aedf8ee4add23f4510f58a4592d0b201bee56b4")
+    );
+    let client_finished_hash =
+        h("c3c122e0bd907a4a3ff6112d8fd53dbf89c773d9552e8b6b9d56d361b3a97bf6");
+    assert_eq!(
+        key_schedule.derive_secret(b"res master", &client_finished_hash),
+        h("5e95bdf1f89005ea2e9aa0ba85e728e3c19c5fe0c699e3f5bee59faebd0b5406")
+    );
+}
+
+/// 4. Resumed 0-RTT Handshake
+#[test]
+fn resumed_0rtt_handshake() {
+    // PSK from the resumption master secret and the ticket nonce
+    let psk = HashAlgorithm::Sha256.hkdf_expand_label(
+        &h("7df235f2031d2a051287d02b0241b0bfdaf86cc856231f2d5aba46c434ec196c"),
+        b"resumption",
+        &[0, 0],
+        32,
+    );
+    assert_eq!(
+        psk,
+        h("4ecd0eb6ec3b4d87f5d6028f922ca4c5851a277fd41311c9e62d2c9492e1c4f3")
+    );
+    let key_schedule = KeySchedule::new(HashAlgorithm::Sha256, Some(&psk));
+    assert_eq!(
+        key_schedule.secret,
+        h("9b2188e9b2fc6d64d71dc329900e20bb41915000f678aa839cbb797cb7d8332c")
+    );
+    let client_hello_hash = h("08ad0fa05d7c7233b1775ba2ff9f4c5b8b59276b7f227f13a976245f5d960913");
+    assert_eq!(
+        key_schedule.derive_secret(b"c e traffic", &client_hello_hash),
+        h("3fbbe6a60deb66c30a32795aba0eff7eaa10105586e7be5c09678d63b6caab62")
+    );
+    assert_eq!(
+        key_schedule.derive_secret(b"e exp master", &client_hello_hash),
+        h("b2026866610937d7423e5be90862ccf24c0e6091186d34f812089ff5be2ef7df")
+    );
+
+    let key_schedule = KeySchedule {
+        algorithm: HashAlgorithm::Sha256,
+        secret: h("005cb112fd8eb4ccc623bb88a07c64b3ede1605363fc7d0df8c7ce4ff0fb4ae6"),
+    };
+    let hello_hash = h("f736cb34fe25e701551bee6fd24c1cc7102a7daf9405cb15d97aafe16f757d03");
+    let client_handshake_traffic_secret = key_schedule.derive_secret(b"c hs traffic", &hello_hash);
+    assert_eq!(
+        client_handshake_traffic_secret,
+        h("2faac08f851d35fea3604fcb4de82dc62c9b164a70974d0462e27f1ab278700f")
+    );
+    assert_eq!(
+        key_schedule.derive_secret(b"s hs traffic", &hello_hash),
+        h("fe927ae271312e8bf0275b581c54eef020450dc4ecffaa05a1a35d27518e7803")
+    );
+    // finished_key of the client
+    assert_eq!(
+        HashAlgorithm::Sha256.hkdf_expand_label(
+            &client_handshake_traffic_secret,
+            b"finished",
+            &[],
+            32
+        ),
+        h("5ace394c26980d581243f627d1150ae27e37fa52364e0a7f20ac686d09cd0e8e")
+    );
+}
+
+/// The chain with SHA-384 has secrets of its length.
+#[test]
+fn sha384() {
+    let mut key_schedule = KeySchedule::new(HashAlgorithm::Sha384, None);
+    assert_eq!(key_schedule.secret.len(), 48);
+    key_schedule.advance(Some(&[1; 32]));
+    let hash = HashAlgorithm::Sha384.hash(b"hello");
+    let secret = key_schedule.derive_secret(b"c hs traffic", &hash);
+    assert_eq!(secret.len(), 48);
+    assert_eq!(key_schedule.finished_verify_data(&secret, &hash).len(), 48);
+}
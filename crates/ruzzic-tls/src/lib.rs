@@ -1,13 +1,25 @@
+use std::io::Cursor;
+
 use byteorder::{NetworkEndian, ReadBytesExt};
 use ruzzic_common::read_bytes_to::FromReadBytesWith;
 
+mod alert;
 pub mod extension;
 pub mod handshake;
+mod key_exchange;
+pub mod key_schedule;
+pub mod pki;
+pub mod resumption;
+pub mod session;
+pub mod sign;
+pub mod verify;
 
 use crate::extension::Extensions;
 
-#[derive(Debug, PartialEq)]
-enum CipherSuite {
+pub use crate::alert::{AlertDescription, TlsError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
     TlsAes128GcmSha256,
     TlsAes256GcmSha384,
     TlsChacha20Poly1305Sha256,
@@ -34,37 +46,47 @@ impl FromReadBytesWith<()> for CipherSuite {
 }
 
 impl CipherSuite {
-    pub(crate) fn size_of() -> usize {
-        2
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            CipherSuite::TlsAes128GcmSha256 => 0x1301,
+            CipherSuite::TlsAes256GcmSha384 => 0x1302,
+            CipherSuite::TlsChacha20Poly1305Sha256 => 0x1303,
+            CipherSuite::TlsAes128CcmSha256 => 0x1304,
+            CipherSuite::TlsAes128Ccm8Sha256 => 0x1305,
+            CipherSuite::Others(x) => *x,
+        }
     }
 }
 
 #[derive(Debug, PartialEq)]
-struct CertificateEntry {
+pub struct CertificateEntry {
     certificate: Certificate,
     extension: Extensions,
 }
 
-#[derive(Debug, PartialEq)]
-enum Certificate {
-    X509 {
-        cert_data: Vec<u8>,
-    },
-    OpenPgpReserved,
-    RawPublicKey {
-        ans1_subject_public_key_info: Vec<u8>,
-    },
+impl CertificateEntry {
+    pub(crate) fn new_x509(cert_data: Vec<u8>) -> Self {
+        Self {
+            certificate: Certificate::X509 { cert_data },
+            extension: Vec::new(),
+        }
+    }
+
+    /// DER of the X.509 certificate.
+    pub fn data(&self) -> &[u8] {
+        match &self.certificate {
+            Certificate::X509 { cert_data } => cert_data,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
-pub enum CertificateType {
-    X509,
-    OpenPgpReserved,
-    RawPublicKey,
+enum Certificate {
+    X509 { cert_data: Vec<u8> },
 }
 
-#[derive(Debug, PartialEq)]
-enum SignatureScheme {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
     // RSASSA-PKCS1-v1_5
     RsaPkcs1Sha256,
     RsaPkcs1Sha384,
@@ -78,8 +100,8 @@ enum SignatureScheme {
     RsaPssRsaeSha384,
     RsaPssRsaeSha512,
     // EdDSA
-    ed25519,
-    ed448,
+    Ed25519,
+    Ed448,
     // RSASSA-PSS with public key OID RSASSA-PSS
     RsaPssPssSha256,
     RsaPssPssSha384,
@@ -93,6 +115,64 @@ enum SignatureScheme {
     Others(u16),
 }
 
+impl FromReadBytesWith<()> for SignatureScheme {
+    fn from_read_bytes_with<R: std::io::Read>(input: &mut R, _: ()) -> Result<Self, std::io::Error>
+    where
+        Self: Sized,
+    {
+        Ok(SignatureScheme::from_u16(
+            input.read_u16::<NetworkEndian>()?,
+        ))
+    }
+}
+
+impl SignatureScheme {
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            0x0401 => SignatureScheme::RsaPkcs1Sha256,
+            0x0501 => SignatureScheme::RsaPkcs1Sha384,
+            0x0601 => SignatureScheme::RsaPkcs1Sha512,
+            0x0403 => SignatureScheme::EcdsaSecp256r1Sha256,
+            0x0503 => SignatureScheme::EcdsaSecp384r1Sha384,
+            0x0603 => SignatureScheme::EcdsaSecp521r1Sha512,
+            0x0804 => SignatureScheme::RsaPssRsaeSha256,
+            0x0805 => SignatureScheme::RsaPssRsaeSha384,
+            0x0806 => SignatureScheme::RsaPssRsaeSha512,
+            0x0807 => SignatureScheme::Ed25519,
+            0x0808 => SignatureScheme::Ed448,
+            0x0809 => SignatureScheme::RsaPssPssSha256,
+            0x080a => SignatureScheme::RsaPssPssSha384,
+            0x080b => SignatureScheme::RsaPssPssSha512,
+            0x0201 => SignatureScheme::RsaPkcs1Sha1,
+            0x0203 => SignatureScheme::EcdsaSha1,
+            x @ 0xfe00..=0xffff => SignatureScheme::PrivateUse(x),
+            x => SignatureScheme::Others(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            SignatureScheme::RsaPkcs1Sha256 => 0x0401,
+            SignatureScheme::RsaPkcs1Sha384 => 0x0501,
+            SignatureScheme::RsaPkcs1Sha512 => 0x0601,
+            SignatureScheme::EcdsaSecp256r1Sha256 => 0x0403,
+            SignatureScheme::EcdsaSecp384r1Sha384 => 0x0503,
+            SignatureScheme::EcdsaSecp521r1Sha512 => 0x0603,
+            SignatureScheme::RsaPssRsaeSha256 => 0x0804,
+            SignatureScheme::RsaPssRsaeSha384 => 0x0805,
+            SignatureScheme::RsaPssRsaeSha512 => 0x0806,
+            SignatureScheme::Ed25519 => 0x0807,
+            SignatureScheme::Ed448 => 0x0808,
+            SignatureScheme::RsaPssPssSha256 => 0x0809,
+            SignatureScheme::RsaPssPssSha384 => 0x080a,
+            SignatureScheme::RsaPssPssSha512 => 0x080b,
+            SignatureScheme::RsaPkcs1Sha1 => 0x0201,
+            SignatureScheme::EcdsaSha1 => 0x0203,
+            SignatureScheme::PrivateUse(x) | SignatureScheme::Others(x) => *x,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct LegacyVersion(u16);
 
@@ -106,7 +186,61 @@ impl FromReadBytesWith<()> for LegacyVersion {
 }
 
 impl LegacyVersion {
+    /// TLS 1.3 messages always say TLS 1.2 here.
+    const TLS12: Self = LegacyVersion(0x0303);
+
     fn is_tls12(&self) -> bool {
         self.0 == 0x0303
     }
+
+    fn to_bytes(&self) -> [u8; 2] {
+        self.0.to_be_bytes()
+    }
+}
+
+/// The version in the supported_versions extension.
+pub(crate) const TLS13: u16 = 0x0304;
+
+/// Read a field with a length prefix of `length_size` bytes.
+pub(crate) fn read_vector(
+    mut input: impl std::io::Read,
+    length_size: usize,
+) -> Result<Vec<u8>, std::io::Error> {
+    let length = input.read_uint::<NetworkEndian>(length_size)?;
+    let mut buf = Vec::new();
+    std::io::Read::read_to_end(&mut input.take(length), &mut buf)?;
+    if (buf.len() as u64) < length {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+/// Read the items of a vector until its end.
+pub(crate) fn read_list<T>(
+    value: &[u8],
+    mut read_item: impl FnMut(&mut Cursor<&[u8]>) -> Result<T, std::io::Error>,
+) -> Result<Vec<T>, std::io::Error> {
+    let mut input = Cursor::new(value);
+    let mut items = Vec::new();
+    while (input.position() as usize) < value.len() {
+        items.push(read_item(&mut input)?);
+    }
+    Ok(items)
+}
+
+/// Fail unless the whole value has been read.
+pub(crate) fn ensure_consumed(input: &Cursor<&[u8]>) -> Result<(), std::io::Error> {
+    if input.position() as usize != input.get_ref().len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "trailing bytes",
+        ));
+    }
+    Ok(())
+}
+
+/// Write a field with a length prefix of `length_size` bytes.
+pub(crate) fn write_vector(output: &mut Vec<u8>, length_size: usize, value: &[u8]) {
+    output.extend_from_slice(&(value.len() as u64).to_be_bytes()[8 - length_size..]);
+    output.extend_from_slice(value);
 }
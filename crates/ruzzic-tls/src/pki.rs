@@ -0,0 +1,84 @@
+//! Loading certificate chains and private keys, either in PEM or in DER.
+
+use std::sync::Arc;
+
+use rustls_pemfile::Item;
+use x509_cert::der::Decode;
+
+use crate::sign::{EcdsaP256SigningKey, Ed25519SigningKey, RsaPssSigningKey, SigningKey};
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoadError {
+    #[error("invalid PEM: {0}")]
+    Pem(#[from] std::io::Error),
+    #[error("no certificate")]
+    NoCertificate,
+    #[error("invalid certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("no private key")]
+    NoPrivateKey,
+    #[error("invalid private key: {0}")]
+    InvalidPrivateKey(String),
+}
+
+fn is_pem(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(b"-----BEGIN")
+}
+
+/// Load the certificates of a chain, the end-entity certificate first.
+/// DER holds a single certificate.
+pub fn load_certificate_chain(data: &[u8]) -> Result<Vec<Vec<u8>>, LoadError> {
+    let chain = if is_pem(data) {
+        rustls_pemfile::certs(&mut &data[..])
+            .map(|cert| cert.map(|cert| cert.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![data.to_vec()]
+    };
+    if chain.is_empty() {
+        return Err(LoadError::NoCertificate);
+    }
+    for cert in &chain {
+        x509_cert::Certificate::from_der(cert)
+            .map_err(|e| LoadError::InvalidCertificate(e.to_string()))?;
+    }
+    Ok(chain)
+}
+
+/// Load a PKCS #8, SEC1 (ECDSA) or PKCS #1 (RSA) private key.
+/// ECDSA keys are on P-256, and RSA keys sign with RSASSA-PSS.
+pub fn load_private_key(data: &[u8]) -> Result<Arc<dyn SigningKey>, LoadError> {
+    if !is_pem(data) {
+        return pkcs8_private_key(data)
+            .or_else(|_| EcdsaP256SigningKey::from_sec1_der(data).map(into_arc))
+            .or_else(|_| RsaPssSigningKey::from_pkcs1_der(data).map(into_arc));
+    }
+    for item in rustls_pemfile::read_all(&mut &data[..]) {
+        match item? {
+            Item::Pkcs8Key(der) => return pkcs8_private_key(der.secret_pkcs8_der()),
+            Item::Sec1Key(der) => {
+                return EcdsaP256SigningKey::from_sec1_der(der.secret_sec1_der()).map(into_arc)
+            }
+            Item::Pkcs1Key(der) => {
+                return RsaPssSigningKey::from_pkcs1_der(der.secret_pkcs1_der()).map(into_arc)
+            }
+            _ => {}
+        }
+    }
+    Err(LoadError::NoPrivateKey)
+}
+
+fn pkcs8_private_key(der: &[u8]) -> Result<Arc<dyn SigningKey>, LoadError> {
+    Ed25519SigningKey::from_pkcs8_der(der)
+        .map(into_arc)
+        .or_else(|_| EcdsaP256SigningKey::from_pkcs8_der(der).map(into_arc))
+        .or_else(|_| RsaPssSigningKey::from_pkcs8_der(der).map(into_arc))
+        .map_err(|_| LoadError::InvalidPrivateKey("unsupported PKCS #8 key".to_string()))
+}
+
+fn into_arc(key: impl SigningKey + 'static) -> Arc<dyn SigningKey> {
+    Arc::new(key)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use p256::pkcs8::DecodePrivateKey;
+
+use crate::SignatureScheme;
+
+use super::{load_certificate_chain, load_private_key, LoadError};
+
+const RSA_2048_PEM: &str = include_str!("rsa_2048.pem");
+
+fn self_signed(key_pair: &rcgen::KeyPair) -> rcgen::Certificate {
+    rcgen::CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(key_pair)
+        .unwrap()
+}
+
+#[test]
+fn certificate_chain() {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let end_entity = self_signed(&key_pair);
+    let issuer = self_signed(&key_pair);
+    let pem = format!("{}\n{}", end_entity.pem(), issuer.pem());
+
+    let chain = load_certificate_chain(pem.as_bytes()).unwrap();
+    assert_eq!(
+        chain,
+        vec![end_entity.der().to_vec(), issuer.der().to_vec()]
+    );
+    let chain = load_certificate_chain(end_entity.der()).unwrap();
+    assert_eq!(chain, vec![end_entity.der().to_vec()]);
+
+    assert!(matches!(
+        load_certificate_chain(key_pair.serialize_pem().as_bytes()),
+        Err(LoadError::NoCertificate)
+    ));
+    assert!(matches!(
+        load_certificate_chain(&[0x30, 0x03, 0x02, 0x01, 0x00]),
+        Err(LoadError::InvalidCertificate(_))
+    ));
+}
+
+#[test]
+fn private_keys() {
+    let ed25519 = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+    let key = load_private_key(ed25519.serialize_pem().as_bytes()).unwrap();
+    assert_eq!(key.schemes(), [SignatureScheme::Ed25519]);
+
+    let ecdsa = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let key = load_private_key(&ecdsa.serialize_der()).unwrap();
+    assert_eq!(key.schemes(), [SignatureScheme::EcdsaSecp256r1Sha256]);
+    let sec1 = p256::SecretKey::from_pkcs8_der(&ecdsa.serialize_der())
+        .unwrap()
+        .to_sec1_der()
+        .unwrap();
+    let key = load_private_key(&sec1).unwrap();
+    assert_eq!(key.schemes(), [SignatureScheme::EcdsaSecp256r1Sha256]);
+
+    // PKCS #1
+    let key = load_private_key(RSA_2048_PEM.as_bytes()).unwrap();
+    assert_eq!(
+        key.choose_scheme(&[
+            SignatureScheme::RsaPkcs1Sha256,
+            SignatureScheme::RsaPssRsaeSha384
+        ]),
+        Some(SignatureScheme::RsaPssRsaeSha384)
+    );
+    assert_eq!(key.choose_scheme(&[SignatureScheme::RsaPkcs1Sha256]), None);
+
+    let certificate = self_signed(&ecdsa);
+    assert!(matches!(
+        load_private_key(certificate.pem().as_bytes()),
+        Err(LoadError::NoPrivateKey)
+    ));
+    assert!(load_private_key(&[0x30, 0x03, 0x02, 0x01, 0x00]).is_err());
+}
@@ -0,0 +1,339 @@
+//! Session tickets to resume a connection with a PSK instead of certificates.
+//! https://www.rfc-editor.org/rfc/rfc8446.html#section-2.2
+
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    io::Cursor,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aes_gcm::{
+    aead::{Aead, NewAead, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use ruzzic_common::read_bytes_to::ReadBytesTo;
+
+use crate::{ensure_consumed, read_list, read_vector, write_vector, CipherSuite};
+
+/// Upper limit of ticket_lifetime.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.6.1
+pub const MAX_TICKET_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const KEY_NAME_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+/// Encrypts the state of a session into a ticket, so that the server keeps nothing.
+/// The key is replaced every ticket lifetime,
+/// and tickets encrypted with the previous key are still accepted until they expire.
+pub struct Ticketer {
+    lifetime: Duration,
+    keys: Mutex<TicketKeys>,
+}
+
+struct TicketKeys {
+    current: TicketKey,
+    previous: Option<TicketKey>,
+    rotated_at: SystemTime,
+}
+
+struct TicketKey {
+    /// Tells which key a ticket is encrypted with.
+    name: [u8; KEY_NAME_SIZE],
+    cipher: Aes256Gcm,
+}
+
+impl TicketKey {
+    fn generate() -> Self {
+        let key: [u8; 32] = rand::random();
+        Self {
+            name: rand::random(),
+            cipher: Aes256Gcm::new(&Key::from(key)),
+        }
+    }
+}
+
+impl Ticketer {
+    /// The lifetime is capped at [`MAX_TICKET_LIFETIME`].
+    pub fn new(lifetime: Duration) -> Self {
+        Self {
+            lifetime: lifetime.min(MAX_TICKET_LIFETIME),
+            keys: Mutex::new(TicketKeys {
+                current: TicketKey::generate(),
+                previous: None,
+                rotated_at: SystemTime::now(),
+            }),
+        }
+    }
+
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    fn keys(&self, now: SystemTime) -> std::sync::MutexGuard<'_, TicketKeys> {
+        let mut keys = self.keys.lock().unwrap();
+        if now
+            .duration_since(keys.rotated_at)
+            .is_ok_and(|elapsed| elapsed >= self.lifetime)
+        {
+            let previous = std::mem::replace(&mut keys.current, TicketKey::generate());
+            keys.previous = Some(previous);
+            keys.rotated_at = now;
+        }
+        keys
+    }
+
+    pub(crate) fn encrypt(&self, plaintext: &[u8], now: SystemTime) -> Vec<u8> {
+        let keys = self.keys(now);
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let ciphertext = keys
+            .current
+            .cipher
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &keys.current.name,
+                },
+            )
+            .expect("the ticket is small enough");
+        [&keys.current.name[..], &nonce, &ciphertext].concat()
+    }
+
+    /// None for a ticket of another server, of an expired key or tampered with.
+    pub(crate) fn decrypt(&self, ticket: &[u8], now: SystemTime) -> Option<Vec<u8>> {
+        if ticket.len() < KEY_NAME_SIZE + NONCE_SIZE {
+            return None;
+        }
+        let (name, rest) = ticket.split_at(KEY_NAME_SIZE);
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+        let nonce: [u8; NONCE_SIZE] = nonce.try_into().expect("split at the size");
+        let keys = self.keys(now);
+        let key = [Some(&keys.current), keys.previous.as_ref()]
+            .into_iter()
+            .flatten()
+            .find(|key| key.name == name)?;
+        key.cipher
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: name,
+                },
+            )
+            .ok()
+    }
+}
+
+/// What the server keeps in a ticket.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ServerTicket {
+    pub(crate) cipher_suite: CipherSuite,
+    pub(crate) psk: Vec<u8>,
+    pub(crate) ticket_age_add: u32,
+    pub(crate) issued_at: SystemTime,
+    /// Chain of the client in a mutual TLS session, to keep its identity on resumption.
+    pub(crate) client_certificates: Vec<Vec<u8>>,
+    /// Whether the ticket allows 0-RTT data.
+    pub(crate) early_data: bool,
+    /// 0-RTT is rejected unless the server has the same context as when the ticket is issued.
+    pub(crate) early_data_context: Vec<u8>,
+    /// Empty if no protocol is negotiated with ALPN.
+    pub(crate) alpn_protocol: Vec<u8>,
+}
+
+impl ServerTicket {
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.cipher_suite.to_u16().to_be_bytes().to_vec();
+        write_vector(&mut buf, 1, &self.psk);
+        buf.extend_from_slice(&self.ticket_age_add.to_be_bytes());
+        buf.extend_from_slice(&unix_millis(self.issued_at).to_be_bytes());
+        let mut list = Vec::new();
+        for certificate in &self.client_certificates {
+            write_vector(&mut list, 3, certificate);
+        }
+        write_vector(&mut buf, 3, &list);
+        buf.push(self.early_data as u8);
+        write_vector(&mut buf, 2, &self.early_data_context);
+        write_vector(&mut buf, 1, &self.alpn_protocol);
+        buf
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        let mut input = Cursor::new(bytes);
+        let cipher_suite = input.read_bytes_to()?;
+        let psk = read_vector(&mut input, 1)?;
+        let ticket_age_add = input.read_u32::<NetworkEndian>()?;
+        let issued_at = UNIX_EPOCH + Duration::from_millis(input.read_u64::<NetworkEndian>()?);
+        let client_certificates =
+            read_list(&read_vector(&mut input, 3)?, |input| read_vector(input, 3))?;
+        let early_data = input.read_u8()? != 0;
+        let early_data_context = read_vector(&mut input, 2)?;
+        let alpn_protocol = read_vector(&mut input, 1)?;
+        ensure_consumed(&input)?;
+        Ok(Self {
+            cipher_suite,
+            psk,
+            ticket_age_add,
+            issued_at,
+            client_certificates,
+            early_data,
+            early_data_context,
+            alpn_protocol,
+        })
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// What a client keeps from a NewSessionTicket to resume with.
+#[derive(Debug, Clone)]
+pub struct StoredSession {
+    pub(crate) cipher_suite: CipherSuite,
+    pub(crate) psk: Vec<u8>,
+    pub(crate) ticket: Vec<u8>,
+    pub(crate) ticket_age_add: u32,
+    pub(crate) lifetime: Duration,
+    pub(crate) received_at: SystemTime,
+    /// Chain of the server verified in the original handshake.
+    pub(crate) server_certificates: Vec<Vec<u8>>,
+    /// Whether the ticket allows 0-RTT data.
+    pub(crate) early_data: bool,
+    /// quic_transport_parameters of the server in the original connection,
+    /// which limit what is sent in 0-RTT.
+    pub(crate) transport_parameters: Vec<u8>,
+    /// 0-RTT data is sent in this protocol.
+    pub(crate) alpn_protocol: Option<Vec<u8>>,
+}
+
+impl StoredSession {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        now.duration_since(self.received_at)
+            .map_or(true, |age| age >= self.lifetime)
+    }
+
+    /// The age of the ticket in milliseconds, added to ticket_age_add.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11.1
+    pub(crate) fn obfuscated_ticket_age(&self, now: SystemTime) -> u32 {
+        let age = now
+            .duration_since(self.received_at)
+            .unwrap_or_default()
+            .as_millis() as u32;
+        age.wrapping_add(self.ticket_age_add)
+    }
+}
+
+/// Decides whether a server accepts the 0-RTT data of a ClientHello,
+/// since 0-RTT data can be replayed by an attacker.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-8
+pub trait AntiReplay: Send + Sync {
+    /// `binder` is unique to the ClientHello.
+    /// `client_ticket_age` is the age of the ticket the client reports,
+    /// and `ticket_age` is the one the server measures.
+    fn accept_early_data(
+        &self,
+        binder: &[u8],
+        client_ticket_age: Duration,
+        ticket_age: Duration,
+        now: SystemTime,
+    ) -> bool;
+}
+
+/// Accepts a ClientHello only if the ticket age is within a window of the one measured,
+/// and the same ClientHello has not been seen in the window.
+/// It works only for a single server instance.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-8.2
+pub struct ReplayWindow {
+    window: Duration,
+    /// Binders of the ClientHellos accepted, with when they are accepted.
+    seen: Mutex<HashMap<Vec<u8>, SystemTime>>,
+}
+
+impl ReplayWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl AntiReplay for ReplayWindow {
+    fn accept_early_data(
+        &self,
+        binder: &[u8],
+        client_ticket_age: Duration,
+        ticket_age: Duration,
+        now: SystemTime,
+    ) -> bool {
+        // https://www.rfc-editor.org/rfc/rfc8446.html#section-8.3
+        if client_ticket_age.abs_diff(ticket_age) > self.window {
+            return false;
+        }
+        let mut seen = self.seen.lock().unwrap();
+        // a replay after twice the window fails the check of the age
+        seen.retain(|_, accepted_at| {
+            now.duration_since(*accepted_at)
+                .map_or(true, |elapsed| elapsed <= self.window * 2)
+        });
+        match seen.entry(binder.to_vec()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+}
+
+/// Where a client keeps tickets between connections.
+/// It is a trait so that tickets can outlive the process.
+pub trait SessionStore: Send + Sync {
+    fn put(&self, server_name: &str, session: StoredSession);
+
+    /// Remove a session to resume with, since a ticket should be used only once.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#appendix-C.4
+    fn take(&self, server_name: &str) -> Option<StoredSession>;
+}
+
+/// Keeps the latest tickets of each server in memory.
+pub struct MemorySessionStore {
+    sessions_per_server: usize,
+    sessions: Mutex<HashMap<String, VecDeque<StoredSession>>>,
+}
+
+impl MemorySessionStore {
+    pub fn new(sessions_per_server: usize) -> Self {
+        Self {
+            sessions_per_server,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn put(&self, server_name: &str, session: StoredSession) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let queue = sessions.entry(server_name.to_string()).or_default();
+        queue.push_back(session);
+        while queue.len() > self.sessions_per_server {
+            queue.pop_front();
+        }
+    }
+
+    fn take(&self, server_name: &str) -> Option<StoredSession> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get_mut(server_name)?
+            .pop_back()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,120 @@
+use std::time::{Duration, SystemTime};
+
+use crate::CipherSuite;
+
+use super::{
+    AntiReplay, MemorySessionStore, ReplayWindow, ServerTicket, SessionStore, StoredSession,
+    Ticketer,
+};
+
+const LIFETIME: Duration = Duration::from_secs(3600);
+
+#[test]
+fn ticket_key_rotation() {
+    let ticketer = Ticketer::new(LIFETIME);
+    let now = SystemTime::now();
+    let ticket = ticketer.encrypt(b"state", now);
+    assert_eq!(ticketer.decrypt(&ticket, now).unwrap(), b"state");
+
+    // the key is rotated, but the previous one is kept
+    let ticket_after_rotation = ticketer.encrypt(b"new state", now + LIFETIME);
+    assert_ne!(ticket[..16], ticket_after_rotation[..16]);
+    assert_eq!(ticketer.decrypt(&ticket, now + LIFETIME).unwrap(), b"state");
+
+    assert_eq!(ticketer.decrypt(&ticket, now + LIFETIME * 2), None);
+    assert_eq!(
+        ticketer
+            .decrypt(&ticket_after_rotation, now + LIFETIME * 2)
+            .unwrap(),
+        b"new state"
+    );
+}
+
+#[test]
+fn invalid_ticket() {
+    let ticketer = Ticketer::new(LIFETIME);
+    let now = SystemTime::now();
+    let mut ticket = ticketer.encrypt(b"state", now);
+    *ticket.last_mut().unwrap() ^= 1;
+    assert_eq!(ticketer.decrypt(&ticket, now), None);
+    assert_eq!(ticketer.decrypt(&ticket[..20], now), None);
+
+    let other = Ticketer::new(LIFETIME);
+    assert_eq!(other.decrypt(&ticketer.encrypt(b"state", now), now), None);
+}
+
+#[test]
+fn server_ticket() {
+    let ticket = ServerTicket {
+        cipher_suite: CipherSuite::TlsAes256GcmSha384,
+        psk: vec![1; 48],
+        ticket_age_add: 0x12345678,
+        issued_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_704_067_200_123),
+        client_certificates: vec![vec![2; 300], vec![3; 10]],
+        early_data: true,
+        early_data_context: vec![4; 20],
+        alpn_protocol: b"h3".to_vec(),
+    };
+    assert_eq!(
+        ServerTicket::from_bytes(&ticket.to_bytes()).unwrap(),
+        ticket
+    );
+    assert!(ServerTicket::from_bytes(&[ticket.to_bytes(), vec![0]].concat()).is_err());
+}
+
+fn stored_session(ticket: u8) -> StoredSession {
+    StoredSession {
+        cipher_suite: CipherSuite::TlsAes128GcmSha256,
+        psk: vec![0; 32],
+        ticket: vec![ticket],
+        ticket_age_add: u32::MAX,
+        lifetime: LIFETIME,
+        received_at: SystemTime::now(),
+        server_certificates: Vec::new(),
+        early_data: false,
+        transport_parameters: Vec::new(),
+        alpn_protocol: None,
+    }
+}
+
+#[test]
+fn stored_session_age() {
+    let session = stored_session(0);
+    let now = session.received_at + Duration::from_millis(1500);
+    // wraps around
+    assert_eq!(session.obfuscated_ticket_age(now), 1499);
+    assert!(!session.is_expired(now));
+    assert!(session.is_expired(session.received_at + LIFETIME));
+}
+
+#[test]
+fn memory_session_store() {
+    let store = MemorySessionStore::new(2);
+    for ticket in 0..3 {
+        store.put("example.com", stored_session(ticket));
+    }
+    assert_eq!(store.take("example.com").unwrap().ticket, [2]);
+    assert_eq!(store.take("example.com").unwrap().ticket, [1]);
+    assert!(store.take("example.com").is_none());
+    assert!(store.take("example.org").is_none());
+}
+
+#[test]
+fn replay_window() {
+    let window = Duration::from_secs(10);
+    let anti_replay = ReplayWindow::new(window);
+    let now = SystemTime::now();
+    let age = Duration::from_secs(100);
+    assert!(anti_replay.accept_early_data(b"binder", age, age + Duration::from_secs(1), now));
+    // replayed
+    assert!(!anti_replay.accept_early_data(b"binder", age, age, now + window));
+    assert!(anti_replay.accept_early_data(b"other", age, age, now));
+
+    // the age reported by the client is too far from the one measured
+    assert!(!anti_replay.accept_early_data(b"stale", age, age + window * 2, now));
+    assert!(!anti_replay.accept_early_data(b"future", age + window * 2, age, now));
+
+    // forgotten after twice the window, when a replay fails the check of the age
+    let later = now + window * 3;
+    assert!(anti_replay.accept_early_data(b"binder", age, age, later));
+}
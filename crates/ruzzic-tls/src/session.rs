@@ -0,0 +1,665 @@
+//! Sans-IO TLS 1.3 handshake for QUIC.
+//! The transport feeds CRYPTO frame data with [`Session::read_handshake`],
+//! sends what [`Session::write_handshake`] returns in CRYPTO frames of the level,
+//! and installs the keys of [`Session::next_secrets`].
+//! https://www.rfc-editor.org/rfc/rfc9001.html#section-4
+
+use std::{collections::VecDeque, io::Cursor, sync::Arc};
+
+use byteorder::{NetworkEndian, ReadBytesExt};
+use ruzzic_common::read_bytes_to::ReadBytesTo;
+
+use crate::{
+    extension::{Extension, Extensions, NamedCurve},
+    handshake::{certificate, certificate_verify, Handshake, HandshakeType},
+    key_exchange::{KeyExchange, SUPPORTED_GROUPS},
+    key_schedule::{HashAlgorithm, KeySchedule, Transcript},
+    pki::{load_certificate_chain, load_private_key, LoadError},
+    resumption::{AntiReplay, MemorySessionStore, SessionStore, Ticketer},
+    sign::{certificate_verify_message, verify_signature, SigningKey, SUPPORTED_SIGNATURE_SCHEMES},
+    verify::{ClientCertVerifier, ServerCertVerifier},
+    AlertDescription, CertificateEntry, CipherSuite, SignatureScheme, TlsError,
+};
+
+mod client;
+mod server;
+
+use self::client::{CertificateResponse, ClientHelloParameters};
+
+/// Large enough for a certificate chain.
+const MAX_HANDSHAKE_MESSAGE_SIZE: usize = 0xffff;
+
+/// Cipher suites QUIC can protect packets with.
+const SUPPORTED_CIPHER_SUITES: &[CipherSuite] = &[
+    CipherSuite::TlsAes128GcmSha256,
+    CipherSuite::TlsAes256GcmSha384,
+    CipherSuite::TlsChacha20Poly1305Sha256,
+];
+
+/// Encryption level the handshake messages and the secrets are for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Initial,
+    /// Carries no handshake messages.
+    ZeroRtt,
+    Handshake,
+    OneRtt,
+}
+
+impl Level {
+    fn index(&self) -> usize {
+        match self {
+            Level::Initial => 0,
+            Level::ZeroRtt => unreachable!("no handshake messages in 0-RTT"),
+            Level::Handshake => 1,
+            Level::OneRtt => 2,
+        }
+    }
+}
+
+/// Traffic secrets for the packets of a level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Secrets {
+    pub level: Level,
+    pub cipher_suite: CipherSuite,
+    pub client: Vec<u8>,
+    /// Empty in 0-RTT, which only the client sends.
+    pub server: Vec<u8>,
+}
+
+/// What became of 0-RTT data in a resumed connection.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyData {
+    NotOffered,
+    /// Offered by the client, waiting for EncryptedExtensions.
+    Offered,
+    Accepted,
+    /// What the client sent in 0-RTT must be sent again in 1-RTT.
+    Rejected,
+}
+
+#[derive(Clone)]
+pub struct ClientConfig {
+    /// In order of preference.
+    pub cipher_suites: Vec<CipherSuite>,
+    /// In order of preference. A key share is sent only for the first one,
+    /// and the server asks for another with a HelloRetryRequest.
+    pub groups: Vec<NamedCurve>,
+    pub verifier: Arc<dyn ServerCertVerifier>,
+    /// Sent if the server asks for a certificate.
+    pub client_certificate: Option<ClientCertificate>,
+    /// Tickets to resume with, shared by the connections with the config.
+    pub session_store: Option<Arc<dyn SessionStore>>,
+    /// Protocols offered with ALPN, in order of preference.
+    /// QUIC requires the server to choose one of them.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-8.1
+    pub alpn_protocols: Vec<Vec<u8>>,
+    /// Offer 0-RTT data when a ticket allows it.
+    /// The data may be replayed, so it must be safe to process more than once.
+    pub early_data: bool,
+}
+
+impl ClientConfig {
+    pub fn new(verifier: Arc<dyn ServerCertVerifier>) -> Self {
+        Self {
+            cipher_suites: SUPPORTED_CIPHER_SUITES.to_vec(),
+            groups: SUPPORTED_GROUPS.to_vec(),
+            verifier,
+            client_certificate: None,
+            session_store: Some(Arc::new(MemorySessionStore::new(4))),
+            alpn_protocols: Vec::new(),
+            early_data: false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClientCertificate {
+    /// DER of the certificates, the end-entity certificate first.
+    pub certificate_chain: Vec<Vec<u8>>,
+    pub signing_key: Arc<dyn SigningKey>,
+}
+
+impl ClientCertificate {
+    /// Load the certificate chain and the private key, either in PEM or in DER.
+    pub fn load(certificate_chain: &[u8], private_key: &[u8]) -> Result<Self, LoadError> {
+        Ok(Self {
+            certificate_chain: load_certificate_chain(certificate_chain)?,
+            signing_key: load_private_key(private_key)?,
+        })
+    }
+}
+
+/// Whether the server asks the client for a certificate.
+#[derive(Clone, Default)]
+pub enum ClientAuth {
+    #[default]
+    Off,
+    /// Verify the certificate if the client sends one.
+    Optional(Arc<dyn ClientCertVerifier>),
+    /// Abort the handshake unless the client sends a trusted certificate.
+    Required(Arc<dyn ClientCertVerifier>),
+}
+
+pub struct ServerConfig {
+    /// DER of the certificates, the end-entity certificate first.
+    pub certificate_chain: Vec<Vec<u8>>,
+    pub signing_key: Arc<dyn SigningKey>,
+    /// In order of preference.
+    pub cipher_suites: Vec<CipherSuite>,
+    /// In order of preference. A group the client sent a key share for is preferred
+    /// to a HelloRetryRequest for a better one.
+    pub groups: Vec<NamedCurve>,
+    pub client_auth: ClientAuth,
+    /// Protocols accepted with ALPN, in order of preference.
+    /// If any, the handshake fails with no_application_protocol unless the client offers one of them.
+    pub alpn_protocols: Vec<Vec<u8>>,
+    /// Issues session tickets and accepts them for resumption.
+    pub ticketer: Option<Arc<Ticketer>>,
+    /// Tickets allow 0-RTT data if this is set,
+    /// and it is accepted if the policy accepts the ClientHello.
+    pub early_data: Option<Arc<dyn AntiReplay>>,
+}
+
+impl ServerConfig {
+    pub fn new(certificate_chain: Vec<Vec<u8>>, signing_key: Arc<dyn SigningKey>) -> Self {
+        Self {
+            certificate_chain,
+            signing_key,
+            cipher_suites: SUPPORTED_CIPHER_SUITES.to_vec(),
+            groups: SUPPORTED_GROUPS.to_vec(),
+            client_auth: ClientAuth::Off,
+            alpn_protocols: Vec::new(),
+            ticketer: None,
+            early_data: None,
+        }
+    }
+
+    /// Load the certificate chain and the private key, either in PEM or in DER.
+    pub fn load(certificate_chain: &[u8], private_key: &[u8]) -> Result<Self, LoadError> {
+        Ok(Self::new(
+            load_certificate_chain(certificate_chain)?,
+            load_private_key(private_key)?,
+        ))
+    }
+}
+
+enum State {
+    // client
+    WaitServerHello {
+        key_exchange: KeyExchange,
+        hello: ClientHelloParameters,
+        hello_retried: bool,
+    },
+    WaitEncryptedExtensions {
+        hello: ClientHelloParameters,
+        resumed: bool,
+    },
+    WaitCertificate {
+        hello: ClientHelloParameters,
+        certificate_response: Option<CertificateResponse>,
+    },
+    WaitCertificateVerify {
+        end_entity: Vec<u8>,
+        certificate_response: Option<CertificateResponse>,
+    },
+    WaitFinished {
+        certificate_response: Option<CertificateResponse>,
+    },
+    // server
+    WaitClientHello {
+        config: Arc<ServerConfig>,
+        /// The group asked for in the HelloRetryRequest.
+        hello_retry_group: Option<NamedCurve>,
+    },
+    WaitClientCertificate {
+        verifier: Arc<dyn ClientCertVerifier>,
+        required: bool,
+    },
+    WaitClientCertificateVerify {
+        end_entity: Vec<u8>,
+    },
+    WaitClientFinished,
+    Connected,
+    Closed(TlsError),
+}
+
+impl State {
+    /// Level the next message is expected in.
+    fn read_level(&self) -> Option<Level> {
+        match self {
+            State::WaitServerHello { .. } | State::WaitClientHello { .. } => Some(Level::Initial),
+            State::WaitEncryptedExtensions { .. }
+            | State::WaitCertificate { .. }
+            | State::WaitCertificateVerify { .. }
+            | State::WaitFinished { .. }
+            | State::WaitClientCertificate { .. }
+            | State::WaitClientCertificateVerify { .. }
+            | State::WaitClientFinished => Some(Level::Handshake),
+            State::Connected => Some(Level::OneRtt),
+            State::Closed(_) => None,
+        }
+    }
+}
+
+pub struct Session {
+    is_server: bool,
+    state: State,
+    transcript: Transcript,
+    /// Starts when the cipher suite is negotiated.
+    key_schedule: Option<KeySchedule>,
+    cipher_suite: Option<CipherSuite>,
+    client_handshake_secret: Vec<u8>,
+    server_handshake_secret: Vec<u8>,
+    incoming: [Vec<u8>; 3],
+    outgoing: VecDeque<(Level, Vec<u8>)>,
+    secrets: VecDeque<Secrets>,
+    local_transport_parameters: Vec<u8>,
+    peer_transport_parameters: Option<Vec<u8>>,
+    peer_certificates: Vec<Vec<u8>>,
+    alpn_protocol: Option<Vec<u8>>,
+    /// Whether the handshake is authenticated with a ticket instead of certificates.
+    resumed: bool,
+    resumption_master_secret: Vec<u8>,
+    /// Where a client puts tickets, with the name of the server.
+    session_store: Option<(Arc<dyn SessionStore>, String)>,
+    /// What a server encrypts tickets with.
+    ticketer: Option<Arc<Ticketer>>,
+    /// Whether a server issues tickets which allow 0-RTT.
+    ticket_allows_early_data: bool,
+    /// What must not change since a ticket is issued for a server to accept 0-RTT.
+    early_data_context: Vec<u8>,
+    early_data: EarlyData,
+    /// quic_transport_parameters of the server remembered with the ticket a client offers 0-RTT with.
+    remembered_transport_parameters: Option<Vec<u8>>,
+}
+
+impl Session {
+    fn new(is_server: bool, state: State, local_transport_parameters: Vec<u8>) -> Self {
+        Self {
+            is_server,
+            state,
+            transcript: Transcript::new(),
+            key_schedule: None,
+            cipher_suite: None,
+            client_handshake_secret: Vec::new(),
+            server_handshake_secret: Vec::new(),
+            incoming: Default::default(),
+            outgoing: VecDeque::new(),
+            secrets: VecDeque::new(),
+            local_transport_parameters,
+            peer_transport_parameters: None,
+            peer_certificates: Vec::new(),
+            alpn_protocol: None,
+            resumed: false,
+            resumption_master_secret: Vec::new(),
+            session_store: None,
+            ticketer: None,
+            ticket_allows_early_data: false,
+            early_data_context: Vec::new(),
+            early_data: EarlyData::NotOffered,
+            remembered_transport_parameters: None,
+        }
+    }
+
+    pub fn is_server(&self) -> bool {
+        self.is_server
+    }
+
+    /// Feed the CRYPTO stream data received in a level.
+    pub fn read_handshake(&mut self, level: Level, data: &[u8]) -> Result<(), TlsError> {
+        if let State::Closed(e) = &self.state {
+            return Err(e.clone());
+        }
+        let result = self.read_handshake_inner(level, data);
+        if let Err(e) = &result {
+            self.state = State::Closed(e.clone());
+        }
+        result
+    }
+
+    fn read_handshake_inner(&mut self, level: Level, data: &[u8]) -> Result<(), TlsError> {
+        if self.state.read_level() != Some(level) {
+            return Err(TlsError::new(
+                AlertDescription::UnexpectedMessage,
+                format!("handshake data in {level:?}"),
+            ));
+        }
+        self.incoming[level.index()].extend_from_slice(data);
+        while let Some(message) = self.next_message(level)? {
+            self.handle_message(&message)?;
+            // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.3
+            if self.state.read_level() != Some(level) {
+                if !self.incoming[level.index()].is_empty() {
+                    return Err(TlsError::new(
+                        AlertDescription::UnexpectedMessage,
+                        "handshake data left before the key change",
+                    ));
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Take a whole message out of the buffer.
+    fn next_message(&mut self, level: Level) -> Result<Option<Vec<u8>>, TlsError> {
+        let buf = &mut self.incoming[level.index()];
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        let length = Cursor::new(&buf[1..]).read_uint::<NetworkEndian>(3)? as usize;
+        if length > MAX_HANDSHAKE_MESSAGE_SIZE {
+            return Err(TlsError::new(
+                AlertDescription::DecodeError,
+                "handshake message too large",
+            ));
+        }
+        if buf.len() < 4 + length {
+            return Ok(None);
+        }
+        Ok(Some(buf.drain(..4 + length).collect()))
+    }
+
+    fn handle_message(&mut self, message: &[u8]) -> Result<(), TlsError> {
+        let handshake_type: HandshakeType = Cursor::new(message).read_bytes_to()?;
+        let state = std::mem::replace(
+            &mut self.state,
+            State::Closed(TlsError::new(
+                AlertDescription::InternalError,
+                "failed in the middle of the handshake",
+            )),
+        );
+        self.state = if self.is_server {
+            self.handle_server_message(state, handshake_type, message)?
+        } else {
+            self.handle_client_message(state, handshake_type, message)?
+        };
+        Ok(())
+    }
+
+    /// Data to send in CRYPTO frames of the level.
+    pub fn write_handshake(&mut self) -> Option<(Level, Vec<u8>)> {
+        self.outgoing.pop_front()
+    }
+
+    /// Secrets to install in the order they become available.
+    pub fn next_secrets(&mut self) -> Option<Secrets> {
+        self.secrets.pop_front()
+    }
+
+    pub fn is_handshake_complete(&self) -> bool {
+        matches!(self.state, State::Connected)
+    }
+
+    /// The raw quic_transport_parameters extension of the peer.
+    pub fn peer_transport_parameters(&self) -> Option<&[u8]> {
+        self.peer_transport_parameters.as_deref()
+    }
+
+    /// The verified certificate chain of the peer in DER, the end-entity certificate first.
+    /// It is empty for a client that sent no certificate.
+    pub fn peer_certificates(&self) -> &[Vec<u8>] {
+        &self.peer_certificates
+    }
+
+    /// The protocol negotiated with ALPN, to choose the application with.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// Whether the handshake resumed a session with a ticket.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    pub fn early_data(&self) -> EarlyData {
+        self.early_data
+    }
+
+    /// The quic_transport_parameters the server sent in the connection the ticket is from.
+    /// A client limits 0-RTT data with them until the ones of this connection arrive.
+    /// https://www.rfc-editor.org/rfc/rfc9000.html#section-7.4.1
+    pub fn remembered_transport_parameters(&self) -> Option<&[u8]> {
+        self.remembered_transport_parameters.as_deref()
+    }
+
+    pub fn cipher_suite(&self) -> Option<CipherSuite> {
+        self.cipher_suite
+    }
+
+    fn send(&mut self, level: Level, message: Handshake) {
+        let bytes = message.to_bytes();
+        self.transcript.update(&bytes);
+        self.outgoing.push_back((level, bytes));
+    }
+
+    /// Start the transcript hash and the key schedule with the hash of the cipher suite.
+    fn set_cipher_suite(&mut self, cipher_suite: CipherSuite) -> Result<(), TlsError> {
+        let algorithm = HashAlgorithm::of(cipher_suite).ok_or_else(|| {
+            TlsError::new(
+                AlertDescription::IllegalParameter,
+                format!("unknown cipher suite {:#x}", cipher_suite.to_u16()),
+            )
+        })?;
+        self.cipher_suite = Some(cipher_suite);
+        self.transcript.start_hash(algorithm);
+        self.key_schedule = Some(KeySchedule::new(algorithm, None));
+        Ok(())
+    }
+
+    /// Replace the first ClientHello in the transcript with its hash before a HelloRetryRequest.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.4.1
+    fn hash_client_hello(&mut self) {
+        let hash = self.transcript.current_hash();
+        self.transcript.reset();
+        self.transcript
+            .update(&Handshake::MessageHash(hash).to_bytes());
+    }
+
+    /// Start the key schedule again from the early secret with a resumption PSK.
+    fn use_psk(&mut self, psk: &[u8]) {
+        let algorithm = self.key_schedule().algorithm();
+        self.key_schedule = Some(KeySchedule::new(algorithm, Some(psk)));
+        self.resumed = true;
+    }
+
+    /// Derive the secret tickets are made from, once the client Finished is in the transcript.
+    fn start_resumption_secret(&mut self) {
+        let hash = self.transcript.current_hash();
+        self.resumption_master_secret = self.key_schedule().derive_secret(b"res master", &hash);
+    }
+
+    fn key_schedule(&mut self) -> &mut KeySchedule {
+        self.key_schedule
+            .as_mut()
+            .expect("the cipher suite is negotiated in the hellos")
+    }
+
+    /// verify_data of the Finished message with the handshake traffic secret of a side.
+    fn finished_verify_data(&mut self, is_server: bool) -> Vec<u8> {
+        let hash = self.transcript.current_hash();
+        let secret = if is_server {
+            self.server_handshake_secret.clone()
+        } else {
+            self.client_handshake_secret.clone()
+        };
+        self.key_schedule().finished_verify_data(&secret, &hash)
+    }
+
+    /// Send Certificate, and CertificateVerify unless the chain is empty.
+    fn send_certificate(
+        &mut self,
+        certificate_request_context: Vec<u8>,
+        certificate_chain: &[Vec<u8>],
+        signing_key: Option<(&dyn SigningKey, SignatureScheme)>,
+    ) -> Result<(), TlsError> {
+        self.send(
+            Level::Handshake,
+            Handshake::Certificate(certificate::Body::new(
+                certificate_request_context,
+                certificate_chain
+                    .iter()
+                    .map(|c| CertificateEntry::new_x509(c.clone()))
+                    .collect(),
+            )),
+        );
+        if let Some((signing_key, scheme)) = signing_key {
+            let signature = signing_key.sign(
+                scheme,
+                &certificate_verify_message(self.is_server, &self.transcript.current_hash()),
+            )?;
+            self.send(
+                Level::Handshake,
+                Handshake::CertificateVerify(certificate_verify::Body::new(scheme, signature)),
+            );
+        }
+        Ok(())
+    }
+
+    /// Check the CertificateVerify of the peer with the public key of its certificate.
+    fn verify_certificate_verify(
+        &mut self,
+        message: &[u8],
+        end_entity: &[u8],
+    ) -> Result<(), TlsError> {
+        let Handshake::CertificateVerify(certificate_verify) = parse_message(message)? else {
+            unreachable!("the type is checked")
+        };
+        if !SUPPORTED_SIGNATURE_SCHEMES.contains(&certificate_verify.algorithm()) {
+            return Err(TlsError::new(
+                AlertDescription::IllegalParameter,
+                format!(
+                    "{:?} is not offered in signature_algorithms",
+                    certificate_verify.algorithm()
+                ),
+            ));
+        }
+        verify_signature(
+            certificate_verify.algorithm(),
+            end_entity,
+            &certificate_verify_message(!self.is_server, &self.transcript.current_hash()),
+            certificate_verify.signature(),
+        )?;
+        self.transcript.update(message);
+        Ok(())
+    }
+
+    /// Derive the client early traffic secret when the ClientHello is the whole transcript.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.1
+    fn start_early_secret(
+        &mut self,
+        cipher_suite: CipherSuite,
+        psk: &[u8],
+        transcript_hash: &[u8],
+    ) {
+        let algorithm = HashAlgorithm::of(cipher_suite).expect("the cipher suite is known");
+        let client =
+            KeySchedule::new(algorithm, Some(psk)).derive_secret(b"c e traffic", transcript_hash);
+        self.secrets.push_back(Secrets {
+            level: Level::ZeroRtt,
+            cipher_suite,
+            client,
+            server: Vec::new(),
+        });
+    }
+
+    /// Derive the handshake traffic secrets after the ServerHello.
+    fn start_handshake_secrets(&mut self, shared_secret: &[u8]) {
+        let hash = self.transcript.current_hash();
+        let key_schedule = self.key_schedule();
+        key_schedule.advance(Some(shared_secret));
+        let client = key_schedule.derive_secret(b"c hs traffic", &hash);
+        let server = key_schedule.derive_secret(b"s hs traffic", &hash);
+        self.client_handshake_secret = client;
+        self.server_handshake_secret = server;
+        self.secrets.push_back(Secrets {
+            level: Level::Handshake,
+            cipher_suite: self.cipher_suite.expect("negotiated in the hellos"),
+            client: self.client_handshake_secret.clone(),
+            server: self.server_handshake_secret.clone(),
+        });
+    }
+
+    /// Derive the application traffic secrets after the server Finished.
+    fn start_application_secrets(&mut self) {
+        let hash = self.transcript.current_hash();
+        let cipher_suite = self.cipher_suite.expect("negotiated in the hellos");
+        let key_schedule = self.key_schedule();
+        key_schedule.advance(None);
+        let secrets = Secrets {
+            level: Level::OneRtt,
+            cipher_suite,
+            client: key_schedule.derive_secret(b"c ap traffic", &hash),
+            server: key_schedule.derive_secret(b"s ap traffic", &hash),
+        };
+        self.secrets.push_back(secrets);
+    }
+}
+
+fn unexpected_message(handshake_type: HandshakeType) -> TlsError {
+    TlsError::new(
+        AlertDescription::UnexpectedMessage,
+        format!("unexpected {handshake_type:?}"),
+    )
+}
+
+/// Parse a whole message whose type is already checked.
+fn parse_message(message: &[u8]) -> Result<Handshake, TlsError> {
+    let mut input = Cursor::new(message);
+    let handshake: Handshake = input.read_bytes_to()?;
+    if input.position() as usize != message.len() {
+        return Err(TlsError::new(
+            AlertDescription::DecodeError,
+            "trailing bytes in the handshake message",
+        ));
+    }
+    Ok(handshake)
+}
+
+/// DER of the certificates, the end-entity certificate first.
+fn certificate_chain(certificate: &certificate::Body) -> Vec<Vec<u8>> {
+    certificate
+        .certificate_list()
+        .iter()
+        .map(|entry| entry.data().to_vec())
+        .collect()
+}
+
+/// An extension must not appear more than once in a message.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2
+fn check_duplicate_extensions(extensions: &Extensions) -> Result<(), TlsError> {
+    let mut types = extensions
+        .iter()
+        .map(|e| e.extension_type())
+        .collect::<Vec<_>>();
+    types.sort_unstable();
+    if types.windows(2).any(|w| w[0] == w[1]) {
+        return Err(TlsError::new(
+            AlertDescription::IllegalParameter,
+            "duplicate extensions",
+        ));
+    }
+    Ok(())
+}
+
+/// QUIC requires the quic_transport_parameters extension in both directions.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#section-8.2
+fn find_transport_parameters(extensions: &Extensions) -> Result<Vec<u8>, TlsError> {
+    extensions
+        .iter()
+        .find_map(|e| match e {
+            Extension::QuicTransportParameters(b) => Some(b.value().to_vec()),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            TlsError::new(
+                AlertDescription::MissingExtension,
+                "no quic_transport_parameters",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests;
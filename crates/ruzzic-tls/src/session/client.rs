@@ -0,0 +1,706 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    extension::{
+        application_layer_protocol_negotiation, cookie, early_data,
+        key_share::{self, KeyShareEntry},
+        pre_shared_key::{self, PskIdentity},
+        psk_key_exchange_modes::{self, PskKeyExchangeMode},
+        quic_transport_parameters, server_name, signature_algorithms, supported_groups,
+        supported_versions, Extension, NamedCurve,
+    },
+    handshake::{client_hello, finished, server_hello, Handshake, HandshakeType},
+    key_exchange::KeyExchange,
+    key_schedule::{HashAlgorithm, KeySchedule, Transcript},
+    resumption::{StoredSession, MAX_TICKET_LIFETIME},
+    sign::SUPPORTED_SIGNATURE_SCHEMES,
+    AlertDescription, SignatureScheme, TlsError, TLS13,
+};
+
+use super::{
+    certificate_chain, check_duplicate_extensions, find_transport_parameters, parse_message,
+    unexpected_message, ClientCertificate, ClientConfig, EarlyData, Level, Session, State,
+};
+
+/// What the ClientHello is built from, kept to send it again after a HelloRetryRequest.
+pub(super) struct ClientHelloParameters {
+    config: ClientConfig,
+    server_name: String,
+    random: [u8; 32],
+    /// The session offered for resumption.
+    resumption: Option<StoredSession>,
+    /// Whether 0-RTT data is offered with the ticket.
+    early_data: bool,
+}
+
+impl ClientHelloParameters {
+    /// Build the ClientHello, with the binder of the PSK computed over the transcript.
+    fn client_hello(
+        &self,
+        key_exchange: &KeyExchange,
+        cookie: Option<&[u8]>,
+        transport_parameters: &[u8],
+        transcript: &Transcript,
+    ) -> Handshake {
+        let Some(session) = &self.resumption else {
+            return self.build_client_hello(key_exchange, cookie, transport_parameters, None);
+        };
+        let algorithm = HashAlgorithm::of(session.cipher_suite).expect("the cipher suite is known");
+        let identity = PskIdentity::new(
+            session.ticket.clone(),
+            session.obfuscated_ticket_age(SystemTime::now()),
+        );
+        // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11.2
+        let placeholder = pre_shared_key::Body::ClientHello {
+            identities: vec![identity.clone()],
+            binders: vec![vec![0; algorithm.output_length()]],
+        };
+        let binders_size = placeholder.binders_size();
+        let client_hello = self
+            .build_client_hello(
+                key_exchange,
+                cookie,
+                transport_parameters,
+                Some(placeholder),
+            )
+            .to_bytes();
+        let truncated = &client_hello[..client_hello.len() - binders_size];
+        let binder = KeySchedule::new(algorithm, Some(&session.psk))
+            .resumption_binder(&transcript.hash_with(algorithm, truncated));
+        self.build_client_hello(
+            key_exchange,
+            cookie,
+            transport_parameters,
+            Some(pre_shared_key::Body::ClientHello {
+                identities: vec![identity],
+                binders: vec![binder],
+            }),
+        )
+    }
+
+    fn build_client_hello(
+        &self,
+        key_exchange: &KeyExchange,
+        cookie: Option<&[u8]>,
+        transport_parameters: &[u8],
+        pre_shared_key: Option<pre_shared_key::Body>,
+    ) -> Handshake {
+        let mut extensions = vec![
+            Extension::ServerName(server_name::Body::new(&self.server_name)),
+            Extension::SupportedGroups(supported_groups::Body::new(self.config.groups.clone())),
+            Extension::SignatureAlgorithms(signature_algorithms::Body::new(
+                SUPPORTED_SIGNATURE_SCHEMES.to_vec(),
+            )),
+            Extension::SupportedVersions(supported_versions::Body::ClientHello {
+                versions: vec![TLS13],
+            }),
+            Extension::KeyShare(key_share::Body::ClientHello(vec![KeyShareEntry::new(
+                key_exchange.group(),
+                key_exchange.public_key().to_vec(),
+            )])),
+        ];
+        if let Some(cookie) = cookie {
+            extensions.push(Extension::Cookie(cookie::Body::new(cookie.to_vec())));
+        }
+        if pre_shared_key.is_some() {
+            extensions.push(Extension::PskKeyExchangeModes(
+                psk_key_exchange_modes::Body::new(vec![PskKeyExchangeMode::PskDheKe]),
+            ));
+            if self.early_data {
+                extensions.push(Extension::EarlyData(early_data::Body::new(
+                    early_data::EarlyData::ClientHello,
+                )));
+            }
+        }
+        if !self.config.alpn_protocols.is_empty() {
+            extensions.push(Extension::ApplicationLayerProtocolNegotiation(
+                application_layer_protocol_negotiation::Body::new(
+                    self.config.alpn_protocols.clone(),
+                ),
+            ));
+        }
+        extensions.push(Extension::QuicTransportParameters(
+            quic_transport_parameters::Body::new(transport_parameters.to_vec()),
+        ));
+        // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11
+        if let Some(pre_shared_key) = pre_shared_key {
+            extensions.push(Extension::PreSharedKey(pre_shared_key));
+        }
+        Handshake::ClientHello(client_hello::Body::new(
+            self.random,
+            self.config.cipher_suites.clone(),
+            extensions,
+        ))
+    }
+}
+
+impl Session {
+    pub fn new_client(
+        config: &ClientConfig,
+        server_name: &str,
+        transport_parameters: Vec<u8>,
+    ) -> Self {
+        let group = config.groups.first().copied().unwrap_or(NamedCurve::X25519);
+        let key_exchange = KeyExchange::new(group).expect("the group is supported");
+        let now = SystemTime::now();
+        let resumption = config
+            .session_store
+            .as_ref()
+            .and_then(|store| store.take(server_name))
+            .filter(|s| !s.is_expired(now) && config.cipher_suites.contains(&s.cipher_suite));
+        let hello = ClientHelloParameters {
+            config: config.clone(),
+            server_name: server_name.to_string(),
+            random: rand::random(),
+            // 0-RTT data is in the protocol of the original connection
+            early_data: config.early_data
+                && resumption.as_ref().is_some_and(|s| {
+                    s.early_data
+                        && s.alpn_protocol
+                            .as_ref()
+                            .is_none_or(|p| config.alpn_protocols.contains(p))
+                }),
+            resumption,
+        };
+        let client_hello = hello.client_hello(
+            &key_exchange,
+            None,
+            &transport_parameters,
+            &Transcript::new(),
+        );
+        // 0-RTT is protected with the cipher suite of the ticket
+        let early_data = hello
+            .resumption
+            .as_ref()
+            .filter(|_| hello.early_data)
+            .map(|s| {
+                let algorithm =
+                    HashAlgorithm::of(s.cipher_suite).expect("the cipher suite is known");
+                let hash = Transcript::new().hash_with(algorithm, &client_hello.to_bytes());
+                (
+                    s.cipher_suite,
+                    s.psk.clone(),
+                    hash,
+                    s.transport_parameters.clone(),
+                )
+            });
+        let mut session = Session::new(
+            false,
+            State::WaitServerHello {
+                key_exchange,
+                hello,
+                hello_retried: false,
+            },
+            transport_parameters,
+        );
+        session.session_store = config
+            .session_store
+            .clone()
+            .map(|store| (store, server_name.to_string()));
+        session.send(Level::Initial, client_hello);
+        if let Some((cipher_suite, psk, hash, transport_parameters)) = early_data {
+            session.start_early_secret(cipher_suite, &psk, &hash);
+            session.early_data = EarlyData::Offered;
+            session.remembered_transport_parameters = Some(transport_parameters);
+        }
+        session
+    }
+
+    pub(super) fn handle_client_message(
+        &mut self,
+        state: State,
+        handshake_type: HandshakeType,
+        message: &[u8],
+    ) -> Result<State, TlsError> {
+        match (state, handshake_type) {
+            (
+                State::WaitServerHello {
+                    key_exchange,
+                    hello,
+                    hello_retried,
+                },
+                HandshakeType::ServerHello,
+            ) => {
+                let Handshake::ServerHello(server_hello) = parse_message(message)? else {
+                    unreachable!("the type is checked")
+                };
+                check_duplicate_extensions(server_hello.extensions())?;
+                if server_hello.is_hello_retry_request() {
+                    if hello_retried {
+                        return Err(TlsError::new(
+                            AlertDescription::UnexpectedMessage,
+                            "second HelloRetryRequest",
+                        ));
+                    }
+                    return self.handle_hello_retry_request(
+                        key_exchange,
+                        hello,
+                        &server_hello,
+                        message,
+                    );
+                }
+                let mut selected_version = None;
+                let mut key_share = None;
+                let mut selected_identity = None;
+                for extension in server_hello.extensions() {
+                    match extension {
+                        Extension::SupportedVersions(supported_versions::Body::ServerHello {
+                            selected_version: v,
+                        }) => selected_version = Some(*v),
+                        Extension::KeyShare(key_share::Body::ServerHello(entry)) => {
+                            key_share = Some(entry)
+                        }
+                        Extension::PreSharedKey(pre_shared_key::Body::ServerHello {
+                            selected_identity: i,
+                        }) => selected_identity = Some(*i),
+                        _ => return Err(unsupported_extension(extension, "ServerHello")),
+                    }
+                }
+                check_server_hello(&server_hello, selected_version, &hello.config)?;
+                let key_share = key_share.ok_or_else(|| {
+                    TlsError::new(AlertDescription::MissingExtension, "no key_share")
+                })?;
+                if key_share.group() != key_exchange.group() {
+                    return Err(TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "key share of a group not offered",
+                    ));
+                }
+                let shared_secret = key_exchange.complete(key_share.key_exchange())?;
+                match self.cipher_suite {
+                    // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.4
+                    Some(cipher_suite) if cipher_suite != server_hello.cipher_suite() => {
+                        return Err(TlsError::new(
+                            AlertDescription::IllegalParameter,
+                            "cipher suite differs from the HelloRetryRequest",
+                        ))
+                    }
+                    Some(_) => {}
+                    None => self.set_cipher_suite(server_hello.cipher_suite())?,
+                }
+                if let Some(selected_identity) = selected_identity {
+                    // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11
+                    let session = hello
+                        .resumption
+                        .as_ref()
+                        .filter(|s| {
+                            selected_identity == 0
+                                && HashAlgorithm::of(s.cipher_suite)
+                                    == HashAlgorithm::of(server_hello.cipher_suite())
+                        })
+                        .ok_or_else(|| {
+                            TlsError::new(
+                                AlertDescription::IllegalParameter,
+                                "selected_identity is not offered",
+                            )
+                        })?;
+                    self.peer_certificates = session.server_certificates.clone();
+                    let psk = session.psk.clone();
+                    self.use_psk(&psk);
+                }
+                self.transcript.update(message);
+                self.start_handshake_secrets(&shared_secret);
+                Ok(State::WaitEncryptedExtensions {
+                    hello,
+                    resumed: selected_identity.is_some(),
+                })
+            }
+            (
+                State::WaitEncryptedExtensions { hello, resumed },
+                HandshakeType::EncryptedExtensions,
+            ) => {
+                let Handshake::EncryptedExtensions(encrypted_extensions) = parse_message(message)?
+                else {
+                    unreachable!("the type is checked")
+                };
+                check_duplicate_extensions(encrypted_extensions.extensions())?;
+                self.peer_transport_parameters = Some(find_transport_parameters(
+                    encrypted_extensions.extensions(),
+                )?);
+                self.alpn_protocol =
+                    selected_alpn_protocol(&hello.config, encrypted_extensions.extensions())?;
+                let early_data_accepted = encrypted_extensions
+                    .extensions()
+                    .iter()
+                    .any(|e| matches!(e, Extension::EarlyData(_)));
+                // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.10
+                self.early_data = match (self.early_data, early_data_accepted) {
+                    // 0-RTT must be in the cipher suite and the protocol it is sent in
+                    (EarlyData::Offered, true)
+                        if resumed
+                            && hello.resumption.as_ref().is_some_and(|s| {
+                                Some(s.cipher_suite) == self.cipher_suite
+                                    && s.alpn_protocol == self.alpn_protocol
+                            }) =>
+                    {
+                        EarlyData::Accepted
+                    }
+                    (EarlyData::Offered, false) => EarlyData::Rejected,
+                    (_, true) => {
+                        return Err(TlsError::new(
+                            AlertDescription::IllegalParameter,
+                            "early_data is accepted unexpectedly",
+                        ))
+                    }
+                    (early_data, false) => early_data,
+                };
+                self.transcript.update(message);
+                if resumed {
+                    return Ok(State::WaitFinished {
+                        certificate_response: None,
+                    });
+                }
+                Ok(State::WaitCertificate {
+                    hello,
+                    certificate_response: None,
+                })
+            }
+            (
+                State::WaitCertificate {
+                    hello,
+                    certificate_response: None,
+                },
+                HandshakeType::CertificateRequest,
+            ) => {
+                let certificate_response = certificate_response(&hello.config, message)?;
+                self.transcript.update(message);
+                Ok(State::WaitCertificate {
+                    hello,
+                    certificate_response: Some(certificate_response),
+                })
+            }
+            (
+                State::WaitCertificate {
+                    hello,
+                    certificate_response,
+                },
+                HandshakeType::Certificate,
+            ) => {
+                let Handshake::Certificate(certificate) = parse_message(message)? else {
+                    unreachable!("the type is checked")
+                };
+                if !certificate.certificate_request_context().is_empty() {
+                    return Err(TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "certificate_request_context from the server",
+                    ));
+                }
+                let chain = certificate_chain(&certificate);
+                let (end_entity, intermediates) = chain.split_first().ok_or_else(|| {
+                    TlsError::new(AlertDescription::DecodeError, "no server certificate")
+                })?;
+                hello.config.verifier.verify_server_cert(
+                    end_entity,
+                    intermediates,
+                    &hello.server_name,
+                    SystemTime::now(),
+                )?;
+                let end_entity = end_entity.clone();
+                self.peer_certificates = chain;
+                self.transcript.update(message);
+                Ok(State::WaitCertificateVerify {
+                    end_entity,
+                    certificate_response,
+                })
+            }
+            (
+                State::WaitCertificateVerify {
+                    end_entity,
+                    certificate_response,
+                },
+                HandshakeType::CertificateVerify,
+            ) => {
+                self.verify_certificate_verify(message, &end_entity)?;
+                Ok(State::WaitFinished {
+                    certificate_response,
+                })
+            }
+            (
+                State::WaitFinished {
+                    certificate_response,
+                },
+                HandshakeType::Finished,
+            ) => {
+                let Handshake::Finished(server_finished) = parse_message(message)? else {
+                    unreachable!("the type is checked")
+                };
+                let expected = self.finished_verify_data(true);
+                if server_finished.verify_data() != expected {
+                    return Err(TlsError::new(
+                        AlertDescription::DecryptError,
+                        "server Finished does not match",
+                    ));
+                }
+                self.transcript.update(message);
+                self.start_application_secrets();
+                if let Some(response) = certificate_response {
+                    // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.4.2
+                    match &response.certificate {
+                        Some((certificate, scheme)) => self.send_certificate(
+                            response.certificate_request_context,
+                            &certificate.certificate_chain,
+                            Some((certificate.signing_key.as_ref(), *scheme)),
+                        )?,
+                        None => {
+                            self.send_certificate(response.certificate_request_context, &[], None)?
+                        }
+                    }
+                }
+                let verify_data = self.finished_verify_data(false);
+                self.send(
+                    Level::Handshake,
+                    Handshake::Finished(finished::Body::new(verify_data)),
+                );
+                self.start_resumption_secret();
+                Ok(State::Connected)
+            }
+            (State::Connected, HandshakeType::NewSessionTicket) => {
+                self.handle_new_session_ticket(message)?;
+                Ok(State::Connected)
+            }
+            (_, handshake_type) => Err(unexpected_message(handshake_type)),
+        }
+    }
+
+    /// Keep the ticket in the session store.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.6.1
+    fn handle_new_session_ticket(&mut self, message: &[u8]) -> Result<(), TlsError> {
+        let Handshake::NewSessionTicket(ticket) = parse_message(message)? else {
+            unreachable!("the type is checked")
+        };
+        check_duplicate_extensions(ticket.extensions())?;
+        let Some((store, server_name)) = &self.session_store else {
+            return Ok(());
+        };
+        let mut early_data = false;
+        for extension in ticket.extensions() {
+            if let Extension::EarlyData(b) = extension {
+                // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6.1
+                if b.value()
+                    != &(early_data::EarlyData::NewSessionTicket {
+                        max_early_data_size: 0xffffffff,
+                    })
+                {
+                    return Err(TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "max_early_data_size is not 0xffffffff",
+                    ));
+                }
+                early_data = true;
+            }
+        }
+        let lifetime = Duration::from_secs(ticket.ticket_lifetime().into());
+        if lifetime.is_zero() {
+            return Ok(());
+        }
+        let store = store.clone();
+        let server_name = server_name.clone();
+        let resumption_master_secret = self.resumption_master_secret.clone();
+        let psk = self
+            .key_schedule()
+            .resumption_psk(&resumption_master_secret, ticket.ticket_nonce());
+        store.put(
+            &server_name,
+            StoredSession {
+                cipher_suite: self.cipher_suite.expect("negotiated in the hellos"),
+                psk,
+                ticket: ticket.ticket().to_vec(),
+                ticket_age_add: ticket.ticket_age_add(),
+                lifetime: lifetime.min(MAX_TICKET_LIFETIME),
+                received_at: SystemTime::now(),
+                server_certificates: self.peer_certificates.clone(),
+                early_data,
+                transport_parameters: self.peer_transport_parameters.clone().unwrap_or_default(),
+                alpn_protocol: self.alpn_protocol.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Send the ClientHello again with what the server asked for.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.4
+    fn handle_hello_retry_request(
+        &mut self,
+        key_exchange: KeyExchange,
+        mut hello: ClientHelloParameters,
+        hello_retry_request: &server_hello::Body,
+        message: &[u8],
+    ) -> Result<State, TlsError> {
+        let mut selected_version = None;
+        let mut group = None;
+        let mut cookie = None;
+        for extension in hello_retry_request.extensions() {
+            match extension {
+                Extension::SupportedVersions(supported_versions::Body::ServerHello {
+                    selected_version: v,
+                }) => selected_version = Some(*v),
+                Extension::KeyShare(key_share::Body::HelloRetryRequest(g)) => group = Some(*g),
+                Extension::Cookie(c) => cookie = Some(c.cookie()),
+                _ => return Err(unsupported_extension(extension, "HelloRetryRequest")),
+            }
+        }
+        check_server_hello(hello_retry_request, selected_version, &hello.config)?;
+        let key_exchange = match group {
+            Some(group)
+                if group == key_exchange.group() || !hello.config.groups.contains(&group) =>
+            {
+                return Err(TlsError::new(
+                    AlertDescription::IllegalParameter,
+                    format!("HelloRetryRequest for {group:?}"),
+                ))
+            }
+            Some(group) => KeyExchange::new(group).ok_or_else(|| {
+                TlsError::new(
+                    AlertDescription::IllegalParameter,
+                    format!("HelloRetryRequest for {group:?}"),
+                )
+            })?,
+            None if cookie.is_none() => {
+                return Err(TlsError::new(
+                    AlertDescription::IllegalParameter,
+                    "HelloRetryRequest without any change",
+                ))
+            }
+            None => key_exchange,
+        };
+        self.set_cipher_suite(hello_retry_request.cipher_suite())?;
+        self.hash_client_hello();
+        // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.2
+        if hello.early_data {
+            hello.early_data = false;
+            self.early_data = EarlyData::Rejected;
+        }
+        self.transcript.update(message);
+        // the PSK cannot be used with a cipher suite of another hash
+        if hello.resumption.as_ref().is_some_and(|s| {
+            HashAlgorithm::of(s.cipher_suite)
+                != HashAlgorithm::of(hello_retry_request.cipher_suite())
+        }) {
+            hello.resumption = None;
+        }
+        let client_hello = hello.client_hello(
+            &key_exchange,
+            cookie,
+            &self.local_transport_parameters,
+            &self.transcript,
+        );
+        self.send(Level::Initial, client_hello);
+        Ok(State::WaitServerHello {
+            key_exchange,
+            hello,
+            hello_retried: true,
+        })
+    }
+}
+
+/// What the client answers a CertificateRequest with.
+pub(super) struct CertificateResponse {
+    certificate_request_context: Vec<u8>,
+    /// None if no certificate is configured or the server accepts none of its schemes.
+    certificate: Option<(ClientCertificate, SignatureScheme)>,
+}
+
+/// Choose the certificate and the signature scheme to answer a CertificateRequest with.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.3.2
+fn certificate_response(
+    config: &ClientConfig,
+    message: &[u8],
+) -> Result<CertificateResponse, TlsError> {
+    let Handshake::CertificateRequest(certificate_request) = parse_message(message)? else {
+        unreachable!("the type is checked")
+    };
+    check_duplicate_extensions(certificate_request.extensions())?;
+    if !certificate_request.certificate_request_context().is_empty() {
+        return Err(TlsError::new(
+            AlertDescription::IllegalParameter,
+            "certificate_request_context in the handshake",
+        ));
+    }
+    // unknown extensions are ignored
+    let offered = certificate_request
+        .extensions()
+        .iter()
+        .find_map(|extension| match extension {
+            Extension::SignatureAlgorithms(b) => Some(b.supported_signature_algorithms()),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            TlsError::new(
+                AlertDescription::MissingExtension,
+                "no signature_algorithms in CertificateRequest",
+            )
+        })?;
+    let certificate = config.client_certificate.as_ref().and_then(|certificate| {
+        let scheme = certificate.signing_key.choose_scheme(offered)?;
+        Some((certificate.clone(), scheme))
+    });
+    Ok(CertificateResponse {
+        certificate_request_context: Vec::new(),
+        certificate,
+    })
+}
+
+/// The protocol the server chose from the ones offered.
+/// https://www.rfc-editor.org/rfc/rfc7301.html#section-3.2
+fn selected_alpn_protocol(
+    config: &ClientConfig,
+    extensions: &[Extension],
+) -> Result<Option<Vec<u8>>, TlsError> {
+    let selected = extensions.iter().find_map(|e| match e {
+        Extension::ApplicationLayerProtocolNegotiation(b) => Some(b),
+        _ => None,
+    });
+    let Some(selected) = selected else {
+        // https://www.rfc-editor.org/rfc/rfc9001.html#section-8.1
+        if config.alpn_protocols.is_empty() {
+            return Ok(None);
+        }
+        return Err(TlsError::new(
+            AlertDescription::NoApplicationProtocol,
+            "no application protocol is selected",
+        ));
+    };
+    match selected.protocol_names().collect::<Vec<_>>()[..] {
+        [protocol] if config.alpn_protocols.iter().any(|p| p == protocol) => {
+            Ok(Some(protocol.to_vec()))
+        }
+        _ => Err(TlsError::new(
+            AlertDescription::IllegalParameter,
+            "application protocol is not offered",
+        )),
+    }
+}
+
+/// Checks common to a ServerHello and a HelloRetryRequest.
+fn check_server_hello(
+    server_hello: &server_hello::Body,
+    selected_version: Option<u16>,
+    config: &ClientConfig,
+) -> Result<(), TlsError> {
+    if selected_version != Some(TLS13) {
+        return Err(TlsError::new(
+            AlertDescription::ProtocolVersion,
+            "TLS 1.3 is not selected",
+        ));
+    }
+    if !config.cipher_suites.contains(&server_hello.cipher_suite()) {
+        return Err(TlsError::new(
+            AlertDescription::IllegalParameter,
+            "cipher suite is not offered",
+        ));
+    }
+    if !server_hello.legacy_session_id().is_empty() {
+        return Err(TlsError::new(
+            AlertDescription::IllegalParameter,
+            "legacy_session_id is not echoed",
+        ));
+    }
+    Ok(())
+}
+
+fn unsupported_extension(extension: &Extension, message: &str) -> TlsError {
+    TlsError::new(
+        AlertDescription::UnsupportedExtension,
+        format!("extension {:#x} in {message}", extension.extension_type()),
+    )
+}
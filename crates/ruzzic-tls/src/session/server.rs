@@ -0,0 +1,516 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    extension::{
+        application_layer_protocol_negotiation, early_data,
+        key_share::{self, KeyShareEntry},
+        pre_shared_key,
+        psk_key_exchange_modes::PskKeyExchangeMode,
+        quic_transport_parameters, signature_algorithms, supported_versions, Extension, NamedCurve,
+    },
+    handshake::{
+        certificate_request, encrypted_extensions, finished, new_session_ticket, server_hello,
+        Handshake, HandshakeType,
+    },
+    key_exchange::{KeyExchange, SUPPORTED_GROUPS},
+    key_schedule::{HashAlgorithm, KeySchedule},
+    resumption::{ServerTicket, Ticketer},
+    sign::SUPPORTED_SIGNATURE_SCHEMES,
+    AlertDescription, CipherSuite, TlsError, TLS13,
+};
+
+use super::{
+    certificate_chain, check_duplicate_extensions, find_transport_parameters, parse_message,
+    unexpected_message, ClientAuth, EarlyData, Level, ServerConfig, Session, State,
+};
+
+impl Session {
+    pub fn new_server(config: Arc<ServerConfig>, transport_parameters: Vec<u8>) -> Self {
+        let ticketer = config.ticketer.clone();
+        let ticket_allows_early_data = ticketer.is_some() && config.early_data.is_some();
+        let mut session = Session::new(
+            true,
+            State::WaitClientHello {
+                config,
+                hello_retry_group: None,
+            },
+            transport_parameters,
+        );
+        session.ticketer = ticketer;
+        session.ticket_allows_early_data = ticket_allows_early_data;
+        session
+    }
+
+    /// Set what must be the same as when a ticket is issued for 0-RTT with it to be accepted,
+    /// such as the transport parameters a client remembers.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6.2
+    pub fn set_early_data_context(&mut self, context: Vec<u8>) {
+        self.early_data_context = context;
+    }
+
+    pub(super) fn handle_server_message(
+        &mut self,
+        state: State,
+        handshake_type: HandshakeType,
+        message: &[u8],
+    ) -> Result<State, TlsError> {
+        match (state, handshake_type) {
+            (
+                State::WaitClientHello {
+                    config,
+                    hello_retry_group,
+                },
+                HandshakeType::ClientHello,
+            ) => self.handle_client_hello(config, hello_retry_group, message),
+            (State::WaitClientCertificate { verifier, required }, HandshakeType::Certificate) => {
+                let Handshake::Certificate(certificate) = parse_message(message)? else {
+                    unreachable!("the type is checked")
+                };
+                if !certificate.certificate_request_context().is_empty() {
+                    return Err(TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "certificate_request_context does not match",
+                    ));
+                }
+                let chain = certificate_chain(&certificate);
+                self.transcript.update(message);
+                let Some((end_entity, intermediates)) = chain.split_first() else {
+                    // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.4.2.4
+                    if required {
+                        return Err(TlsError::new(
+                            AlertDescription::CertificateRequired,
+                            "no client certificate",
+                        ));
+                    }
+                    return Ok(State::WaitClientFinished);
+                };
+                verifier.verify_client_cert(end_entity, intermediates, SystemTime::now())?;
+                let end_entity = end_entity.clone();
+                self.peer_certificates = chain;
+                Ok(State::WaitClientCertificateVerify { end_entity })
+            }
+            (
+                State::WaitClientCertificateVerify { end_entity },
+                HandshakeType::CertificateVerify,
+            ) => {
+                self.verify_certificate_verify(message, &end_entity)?;
+                Ok(State::WaitClientFinished)
+            }
+            (State::WaitClientFinished, HandshakeType::Finished) => {
+                let Handshake::Finished(client_finished) = parse_message(message)? else {
+                    unreachable!("the type is checked")
+                };
+                if client_finished.verify_data() != self.finished_verify_data(false) {
+                    return Err(TlsError::new(
+                        AlertDescription::DecryptError,
+                        "client Finished does not match",
+                    ));
+                }
+                self.transcript.update(message);
+                self.start_resumption_secret();
+                if let Some(ticketer) = self.ticketer.clone() {
+                    self.send_new_session_ticket(&ticketer);
+                }
+                Ok(State::Connected)
+            }
+            (_, handshake_type) => Err(unexpected_message(handshake_type)),
+        }
+    }
+
+    fn handle_client_hello(
+        &mut self,
+        config: Arc<ServerConfig>,
+        hello_retry_group: Option<NamedCurve>,
+        message: &[u8],
+    ) -> Result<State, TlsError> {
+        let Handshake::ClientHello(client_hello) = parse_message(message)? else {
+            unreachable!("the type is checked")
+        };
+        check_duplicate_extensions(client_hello.extensions())?;
+        if client_hello.legacy_compression_methods() != [0] {
+            return Err(TlsError::new(
+                AlertDescription::IllegalParameter,
+                "legacy_compression_methods is not null",
+            ));
+        }
+        let mut supports_tls13 = false;
+        let mut key_shares: &[KeyShareEntry] = &[];
+        let mut signature_algorithms = None;
+        let mut supported_groups: &[NamedCurve] = &[];
+        let mut psk_offer = None;
+        let mut psk_modes: &[PskKeyExchangeMode] = &[];
+        let mut early_data_offered = false;
+        let mut alpn_offer = None;
+        for (i, extension) in client_hello.extensions().iter().enumerate() {
+            match extension {
+                // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11
+                Extension::PreSharedKey(_) if i + 1 != client_hello.extensions().len() => {
+                    return Err(TlsError::new(
+                        AlertDescription::IllegalParameter,
+                        "pre_shared_key is not the last extension",
+                    ))
+                }
+                Extension::PreSharedKey(b) => psk_offer = Some(b),
+                Extension::PskKeyExchangeModes(b) => psk_modes = b.ke_modes(),
+                Extension::EarlyData(_) => early_data_offered = true,
+                Extension::ApplicationLayerProtocolNegotiation(b) => alpn_offer = Some(b),
+                Extension::SupportedVersions(supported_versions::Body::ClientHello {
+                    versions,
+                }) => supports_tls13 = versions.contains(&TLS13),
+                Extension::KeyShare(key_share::Body::ClientHello(entries)) => key_shares = entries,
+                Extension::SignatureAlgorithms(b) => {
+                    signature_algorithms = Some(b.supported_signature_algorithms())
+                }
+                Extension::SupportedGroups(b) => supported_groups = b.named_curve_list(),
+                _ => {}
+            }
+        }
+        if !supports_tls13 {
+            return Err(TlsError::new(
+                AlertDescription::ProtocolVersion,
+                "TLS 1.3 is not offered",
+            ));
+        }
+        let cipher_suite = config
+            .cipher_suites
+            .iter()
+            .find(|c| client_hello.cipher_suites().contains(c))
+            .copied()
+            .ok_or_else(|| {
+                TlsError::new(AlertDescription::HandshakeFailure, "no common cipher suite")
+            })?;
+        let scheme = signature_algorithms
+            .and_then(|offered| config.signing_key.choose_scheme(offered))
+            .ok_or_else(|| {
+                TlsError::new(
+                    AlertDescription::HandshakeFailure,
+                    "no signature scheme of the key is offered",
+                )
+            })?;
+        let key_share = config.groups.iter().find_map(|group| {
+            let entry = key_shares.iter().find(|entry| entry.group() == *group)?;
+            KeyExchange::new(*group).map(|k| (k, entry))
+        });
+        self.peer_transport_parameters =
+            Some(find_transport_parameters(client_hello.extensions())?);
+        self.alpn_protocol = select_alpn_protocol(&config, alpn_offer)?;
+        if let Some(group) = hello_retry_group {
+            // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.2
+            if key_shares.len() != 1 || key_shares[0].group() != group {
+                return Err(TlsError::new(
+                    AlertDescription::IllegalParameter,
+                    format!("no key share for {group:?} after HelloRetryRequest"),
+                ));
+            }
+            if self.cipher_suite != Some(cipher_suite) {
+                return Err(TlsError::new(
+                    AlertDescription::IllegalParameter,
+                    "cipher suite differs from the HelloRetryRequest",
+                ));
+            }
+        } else {
+            self.set_cipher_suite(cipher_suite)?;
+        }
+        let ticket = match psk_offer {
+            Some(offer) if psk_modes.contains(&PskKeyExchangeMode::PskDheKe) => {
+                self.select_ticket(&config, offer, message)?
+            }
+            _ => None,
+        };
+        self.transcript.update(message);
+        if early_data_offered {
+            let accepted_psk = match (&ticket, psk_offer) {
+                // no 0-RTT with a HelloRetryRequest
+                (Some((0, ticket)), Some(offer))
+                    if hello_retry_group.is_none()
+                        && key_share.is_some()
+                        && self.accept_early_data(&config, offer, ticket, cipher_suite) =>
+                {
+                    Some(ticket.psk.clone())
+                }
+                _ => None,
+            };
+            self.early_data = match accepted_psk {
+                Some(psk) => {
+                    let hash = self.transcript.current_hash();
+                    self.start_early_secret(cipher_suite, &psk, &hash);
+                    EarlyData::Accepted
+                }
+                None => EarlyData::Rejected,
+            };
+        }
+        let Some((key_exchange, peer_key_share)) = key_share else {
+            // https://www.rfc-editor.org/rfc/rfc8446.html#section-4.1.4
+            let group = config
+                .groups
+                .iter()
+                .find(|group| supported_groups.contains(group) && SUPPORTED_GROUPS.contains(group))
+                .copied()
+                .ok_or_else(|| {
+                    TlsError::new(AlertDescription::HandshakeFailure, "no common group")
+                })?;
+            self.hash_client_hello();
+            self.send(
+                Level::Initial,
+                Handshake::ServerHello(server_hello::Body::hello_retry_request(
+                    client_hello.legacy_session_id().to_vec(),
+                    cipher_suite,
+                    vec![
+                        Extension::SupportedVersions(supported_versions::Body::ServerHello {
+                            selected_version: TLS13,
+                        }),
+                        Extension::KeyShare(key_share::Body::HelloRetryRequest(group)),
+                    ],
+                )),
+            );
+            return Ok(State::WaitClientHello {
+                config,
+                hello_retry_group: Some(group),
+            });
+        };
+
+        let mut extensions = vec![
+            Extension::SupportedVersions(supported_versions::Body::ServerHello {
+                selected_version: TLS13,
+            }),
+            Extension::KeyShare(key_share::Body::ServerHello(KeyShareEntry::new(
+                key_exchange.group(),
+                key_exchange.public_key().to_vec(),
+            ))),
+        ];
+        if let Some((selected_identity, ticket)) = &ticket {
+            extensions.push(Extension::PreSharedKey(pre_shared_key::Body::ServerHello {
+                selected_identity: *selected_identity,
+            }));
+            self.use_psk(&ticket.psk);
+        }
+        let server_hello = server_hello::Body::new(
+            rand::random(),
+            client_hello.legacy_session_id().to_vec(),
+            cipher_suite,
+            extensions,
+        );
+        let shared_secret = key_exchange.complete(peer_key_share.key_exchange())?;
+        self.send(Level::Initial, Handshake::ServerHello(server_hello));
+        self.start_handshake_secrets(&shared_secret);
+
+        let mut extensions = vec![Extension::QuicTransportParameters(
+            quic_transport_parameters::Body::new(self.local_transport_parameters.clone()),
+        )];
+        if let Some(protocol) = &self.alpn_protocol {
+            extensions.push(Extension::ApplicationLayerProtocolNegotiation(
+                application_layer_protocol_negotiation::Body::new(vec![protocol.clone()]),
+            ));
+        }
+        if self.early_data == EarlyData::Accepted {
+            extensions.push(Extension::EarlyData(early_data::Body::new(
+                early_data::EarlyData::EncryptedExtension,
+            )));
+        }
+        self.send(
+            Level::Handshake,
+            Handshake::EncryptedExtensions(encrypted_extensions::Body::new(extensions)),
+        );
+        if let Some((_, ticket)) = ticket {
+            // the ticket authenticates both sides
+            self.peer_certificates = ticket.client_certificates;
+            let verify_data = self.finished_verify_data(true);
+            self.send(
+                Level::Handshake,
+                Handshake::Finished(finished::Body::new(verify_data)),
+            );
+            self.start_application_secrets();
+            return Ok(State::WaitClientFinished);
+        }
+        let (verifier, required) = match &config.client_auth {
+            ClientAuth::Off => (None, false),
+            ClientAuth::Optional(verifier) => (Some(verifier.clone()), false),
+            ClientAuth::Required(verifier) => (Some(verifier.clone()), true),
+        };
+        if verifier.is_some() {
+            self.send(
+                Level::Handshake,
+                Handshake::CertificateRequest(certificate_request::Body::new(
+                    Vec::new(),
+                    vec![Extension::SignatureAlgorithms(
+                        signature_algorithms::Body::new(SUPPORTED_SIGNATURE_SCHEMES.to_vec()),
+                    )],
+                )),
+            );
+        }
+        self.send_certificate(
+            Vec::new(),
+            &config.certificate_chain,
+            Some((config.signing_key.as_ref(), scheme)),
+        )?;
+        let verify_data = self.finished_verify_data(true);
+        self.send(
+            Level::Handshake,
+            Handshake::Finished(finished::Body::new(verify_data)),
+        );
+        self.start_application_secrets();
+        Ok(match verifier {
+            Some(verifier) => State::WaitClientCertificate { verifier, required },
+            None => State::WaitClientFinished,
+        })
+    }
+
+    /// The first ticket offered that can be resumed, after checking its binder.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.11
+    fn select_ticket(
+        &mut self,
+        config: &ServerConfig,
+        offer: &pre_shared_key::Body,
+        message: &[u8],
+    ) -> Result<Option<(u16, ServerTicket)>, TlsError> {
+        let pre_shared_key::Body::ClientHello {
+            identities,
+            binders,
+        } = offer
+        else {
+            unreachable!("read in a ClientHello")
+        };
+        if identities.len() != binders.len() {
+            return Err(TlsError::new(
+                AlertDescription::IllegalParameter,
+                "binders do not match the identities",
+            ));
+        }
+        let Some(ticketer) = &config.ticketer else {
+            return Ok(None);
+        };
+        let algorithm = self.key_schedule().algorithm();
+        let now = SystemTime::now();
+        let selected = identities.iter().enumerate().find_map(|(i, identity)| {
+            let ticket =
+                ServerTicket::from_bytes(&ticketer.decrypt(identity.identity(), now)?).ok()?;
+            let fresh = now
+                .duration_since(ticket.issued_at)
+                .is_ok_and(|age| age < ticketer.lifetime());
+            // a full handshake asks for the certificate the session lacks
+            let authenticated = !matches!(config.client_auth, ClientAuth::Required(_))
+                || !ticket.client_certificates.is_empty();
+            (fresh && authenticated && HashAlgorithm::of(ticket.cipher_suite) == Some(algorithm))
+                .then_some((i, ticket))
+        });
+        let Some((i, ticket)) = selected else {
+            return Ok(None);
+        };
+        let truncated = &message[..message.len() - offer.binders_size()];
+        let binder = KeySchedule::new(algorithm, Some(&ticket.psk))
+            .resumption_binder(&self.transcript.hash_with(algorithm, truncated));
+        if binders[i] != binder {
+            return Err(TlsError::new(
+                AlertDescription::DecryptError,
+                "PSK binder does not match",
+            ));
+        }
+        Ok(Some((i as u16, ticket)))
+    }
+
+    /// 0-RTT is accepted only with the first ticket offered, in the same cipher suite, protocol
+    /// and context as the original connection, and if the policy allows it.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.10
+    fn accept_early_data(
+        &self,
+        config: &ServerConfig,
+        offer: &pre_shared_key::Body,
+        ticket: &ServerTicket,
+        cipher_suite: CipherSuite,
+    ) -> bool {
+        let (
+            Some(policy),
+            pre_shared_key::Body::ClientHello {
+                identities,
+                binders,
+            },
+        ) = (&config.early_data, offer)
+        else {
+            return false;
+        };
+        if !ticket.early_data
+            || ticket.cipher_suite != cipher_suite
+            || ticket.early_data_context != self.early_data_context
+            || ticket.alpn_protocol != self.alpn_protocol.as_deref().unwrap_or_default()
+        {
+            return false;
+        }
+        let now = SystemTime::now();
+        let client_ticket_age = Duration::from_millis(
+            identities[0]
+                .obfuscated_ticket_age()
+                .wrapping_sub(ticket.ticket_age_add)
+                .into(),
+        );
+        let ticket_age = now.duration_since(ticket.issued_at).unwrap_or_default();
+        policy.accept_early_data(&binders[0], client_ticket_age, ticket_age, now)
+    }
+
+    /// Send a ticket for the session in 1-RTT.
+    /// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.6.1
+    fn send_new_session_ticket(&mut self, ticketer: &Ticketer) {
+        let now = SystemTime::now();
+        // a single ticket is sent, so the nonce does not need to vary
+        let ticket_nonce = vec![0];
+        let resumption_master_secret = self.resumption_master_secret.clone();
+        let psk = self
+            .key_schedule()
+            .resumption_psk(&resumption_master_secret, &ticket_nonce);
+        let ticket = ServerTicket {
+            cipher_suite: self.cipher_suite.expect("negotiated in the hellos"),
+            psk,
+            ticket_age_add: rand::random(),
+            issued_at: now,
+            client_certificates: self.peer_certificates.clone(),
+            early_data: self.ticket_allows_early_data,
+            early_data_context: self.early_data_context.clone(),
+            alpn_protocol: self.alpn_protocol.clone().unwrap_or_default(),
+        };
+        let mut extensions = Vec::new();
+        if ticket.early_data {
+            // https://www.rfc-editor.org/rfc/rfc9001.html#section-4.6.1
+            extensions.push(Extension::EarlyData(early_data::Body::new(
+                early_data::EarlyData::NewSessionTicket {
+                    max_early_data_size: 0xffffffff,
+                },
+            )));
+        }
+        self.send(
+            Level::OneRtt,
+            Handshake::NewSessionTicket(new_session_ticket::Body::new(
+                ticketer.lifetime().as_secs() as u32,
+                ticket.ticket_age_add,
+                ticket_nonce,
+                ticketer.encrypt(&ticket.to_bytes(), now),
+                extensions,
+            )),
+        );
+    }
+}
+
+/// The first protocol of the server the client offers.
+/// QUIC requires a protocol to be negotiated if the server has any.
+/// https://www.rfc-editor.org/rfc/rfc9001.html#section-8.1
+fn select_alpn_protocol(
+    config: &ServerConfig,
+    offer: Option<&application_layer_protocol_negotiation::Body>,
+) -> Result<Option<Vec<u8>>, TlsError> {
+    if config.alpn_protocols.is_empty() {
+        return Ok(None);
+    }
+    let offered = offer.map_or(Vec::new(), |b| b.protocol_names().collect());
+    config
+        .alpn_protocols
+        .iter()
+        .find(|protocol| offered.contains(&&protocol[..]))
+        .map(|protocol| Some(protocol.clone()))
+        .ok_or_else(|| {
+            TlsError::new(
+                AlertDescription::NoApplicationProtocol,
+                "no application protocol in common",
+            )
+        })
+}
@@ -0,0 +1,685 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use rsa::{pkcs1::DecodeRsaPrivateKey, pkcs8::EncodePrivateKey};
+
+use crate::{
+    extension::NamedCurve,
+    handshake::server_hello::HELLO_RETRY_REQUEST_RANDOM,
+    pki::load_private_key,
+    resumption::{AntiReplay, ReplayWindow, Ticketer},
+    sign::{Ed25519SigningKey, SigningKey},
+    verify::{AcceptAnyServerCert, ClientCertVerifier, PinnedPublicKey, WebPkiVerifier},
+    AlertDescription, SignatureScheme, TlsError,
+};
+
+use super::{
+    ClientAuth, ClientCertificate, ClientConfig, EarlyData, Level, Secrets, ServerConfig, Session,
+};
+
+fn server_config() -> Arc<ServerConfig> {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+    let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    let signing_key = Ed25519SigningKey::from_pkcs8_der(&key_pair.serialize_der()).unwrap();
+    Arc::new(ServerConfig::new(
+        vec![cert.der().to_vec()],
+        Arc::new(signing_key),
+    ))
+}
+
+fn client_config() -> ClientConfig {
+    ClientConfig::new(Arc::new(AcceptAnyServerCert))
+}
+
+/// Move all pending handshake data from one side to the other.
+fn transfer(from: &mut Session, to: &mut Session) -> Result<(), crate::TlsError> {
+    while let Some((level, data)) = from.write_handshake() {
+        to.read_handshake(level, &data)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn handshake() {
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1, 2, 3]);
+    let mut server = Session::new_server(server_config(), vec![4, 5]);
+
+    transfer(&mut client, &mut server).unwrap();
+    assert_eq!(server.peer_transport_parameters(), Some(&[1, 2, 3][..]));
+    assert!(!server.is_handshake_complete());
+    transfer(&mut server, &mut client).unwrap();
+    assert_eq!(client.peer_transport_parameters(), Some(&[4, 5][..]));
+    assert!(client.is_handshake_complete());
+    transfer(&mut client, &mut server).unwrap();
+    assert!(server.is_handshake_complete());
+
+    for level in [Level::Handshake, Level::OneRtt] {
+        let client_secrets = client.next_secrets().unwrap();
+        let server_secrets = server.next_secrets().unwrap();
+        assert_eq!(client_secrets.level, level);
+        assert_eq!(client_secrets, server_secrets);
+        assert_ne!(client_secrets.client, client_secrets.server);
+    }
+    assert_eq!(client.next_secrets(), None);
+    assert_eq!(server.next_secrets(), None);
+}
+
+#[test]
+fn fragmented_handshake() {
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    let mut server = Session::new_server(server_config(), vec![2]);
+
+    while !(client.is_handshake_complete() && server.is_handshake_complete()) {
+        while let Some((level, data)) = client.write_handshake() {
+            for b in data.chunks(1) {
+                server.read_handshake(level, b).unwrap();
+            }
+        }
+        while let Some((level, data)) = server.write_handshake() {
+            for b in data.chunks(7) {
+                client.read_handshake(level, b).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn tampered_finished() {
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    let mut server = Session::new_server(server_config(), vec![2]);
+    transfer(&mut client, &mut server).unwrap();
+
+    let mut messages = std::iter::from_fn(|| server.write_handshake()).collect::<Vec<_>>();
+    let (_, finished) = messages.last_mut().unwrap();
+    *finished.last_mut().unwrap() ^= 1;
+    let result = messages
+        .into_iter()
+        .try_for_each(|(level, data)| client.read_handshake(level, &data));
+    let error = result.unwrap_err();
+    assert_eq!(error.description(), AlertDescription::DecryptError);
+    assert_eq!(error.transport_error_code(), 0x133);
+    assert!(!client.is_handshake_complete());
+    // the session stays closed
+    assert_eq!(client.read_handshake(Level::Handshake, &[]), Err(error));
+}
+
+#[test]
+fn unexpected_message() {
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    let mut server = Session::new_server(server_config(), vec![2]);
+    let (_, client_hello) = client.write_handshake().unwrap();
+
+    // ClientHello to the client
+    let error = client
+        .read_handshake(Level::Initial, &client_hello)
+        .unwrap_err();
+    assert_eq!(error.description(), AlertDescription::UnexpectedMessage);
+
+    // ClientHello in a wrong level
+    let error = server
+        .read_handshake(Level::Handshake, &client_hello)
+        .unwrap_err();
+    assert_eq!(error.description(), AlertDescription::UnexpectedMessage);
+}
+
+#[test]
+fn missing_transport_parameters() {
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    let (_, mut client_hello) = client.write_handshake().unwrap();
+    // rename quic_transport_parameters (0x39) to an unknown extension at the end
+    let position = client_hello.len() - 2 - 1 - 2;
+    assert_eq!(client_hello[position..position + 2], [0x00, 0x39]);
+    client_hello[position + 1] = 0x3a;
+
+    let mut server = Session::new_server(server_config(), vec![2]);
+    let error = server
+        .read_handshake(Level::Initial, &client_hello)
+        .unwrap_err();
+    assert_eq!(error.description(), AlertDescription::MissingExtension);
+}
+
+#[test]
+fn handshake_sha384() {
+    let config = ClientConfig {
+        cipher_suites: vec![crate::CipherSuite::TlsAes256GcmSha384],
+        ..client_config()
+    };
+    let mut client = Session::new_client(&config, "localhost", vec![1]);
+    let mut server = Session::new_server(server_config(), vec![2]);
+    transfer(&mut client, &mut server).unwrap();
+    transfer(&mut server, &mut client).unwrap();
+    transfer(&mut client, &mut server).unwrap();
+    assert!(client.is_handshake_complete() && server.is_handshake_complete());
+    assert_eq!(
+        client.cipher_suite(),
+        Some(crate::CipherSuite::TlsAes256GcmSha384)
+    );
+
+    let client_secrets = client.next_secrets().unwrap();
+    assert_eq!(client_secrets.client.len(), 48);
+    assert_eq!(client_secrets, server.next_secrets().unwrap());
+}
+
+#[test]
+fn handshake_secp256r1() {
+    let config = ClientConfig {
+        groups: vec![NamedCurve::Secp256r1],
+        ..client_config()
+    };
+    let mut client = Session::new_client(&config, "localhost", vec![1]);
+    let mut server = Session::new_server(server_config(), vec![2]);
+    transfer(&mut client, &mut server).unwrap();
+    transfer(&mut server, &mut client).unwrap();
+    transfer(&mut client, &mut server).unwrap();
+    assert!(client.is_handshake_complete() && server.is_handshake_complete());
+    assert_eq!(client.next_secrets(), server.next_secrets());
+}
+
+#[test]
+fn hello_retry_request() {
+    // the client sends an X25519 key share, but the server only takes secp256r1
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    let mut server_config = Arc::into_inner(server_config()).unwrap();
+    server_config.groups = vec![NamedCurve::Secp256r1];
+    let mut server = Session::new_server(Arc::new(server_config), vec![2]);
+
+    transfer(&mut client, &mut server).unwrap();
+    let (level, hello_retry_request) = server.write_handshake().unwrap();
+    assert_eq!(level, Level::Initial);
+    assert_eq!(hello_retry_request[6..38], HELLO_RETRY_REQUEST_RANDOM);
+    assert_eq!(server.write_handshake(), None);
+    client
+        .read_handshake(Level::Initial, &hello_retry_request)
+        .unwrap();
+    assert_eq!(client.next_secrets(), None);
+
+    transfer(&mut client, &mut server).unwrap();
+    transfer(&mut server, &mut client).unwrap();
+    transfer(&mut client, &mut server).unwrap();
+    assert!(client.is_handshake_complete() && server.is_handshake_complete());
+    for _ in 0..2 {
+        assert_eq!(client.next_secrets(), server.next_secrets());
+    }
+
+    // a second HelloRetryRequest is not allowed
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    client.write_handshake().unwrap();
+    client
+        .read_handshake(Level::Initial, &hello_retry_request)
+        .unwrap();
+    let error = client
+        .read_handshake(Level::Initial, &hello_retry_request)
+        .unwrap_err();
+    assert_eq!(error.description(), AlertDescription::UnexpectedMessage);
+}
+
+#[test]
+fn no_common_group() {
+    let config = ClientConfig {
+        groups: vec![NamedCurve::X25519],
+        ..client_config()
+    };
+    let mut client = Session::new_client(&config, "localhost", vec![1]);
+    let mut server_config = Arc::into_inner(server_config()).unwrap();
+    server_config.groups = vec![NamedCurve::Secp256r1];
+    let mut server = Session::new_server(Arc::new(server_config), vec![2]);
+    let error = transfer(&mut client, &mut server).unwrap_err();
+    assert_eq!(error.description(), AlertDescription::HandshakeFailure);
+}
+
+fn complete_handshake(server_config: ServerConfig) {
+    let mut client = Session::new_client(&client_config(), "localhost", vec![1]);
+    let mut server = Session::new_server(Arc::new(server_config), vec![2]);
+    transfer(&mut client, &mut server).unwrap();
+    transfer(&mut server, &mut client).unwrap();
+    transfer(&mut client, &mut server).unwrap();
+    assert!(client.is_handshake_complete() && server.is_handshake_complete());
+}
+
+#[test]
+fn ecdsa_certificate() {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    let config =
+        ServerConfig::load(cert.pem().as_bytes(), key_pair.serialize_pem().as_bytes()).unwrap();
+    complete_handshake(config);
+}
+
+#[test]
+fn rsa_pss_certificate() {
+    const RSA_2048_PEM: &str = include_str!("../pki/rsa_2048.pem");
+    // rcgen takes PKCS #8, while the key is in PKCS #1
+    let pkcs8 = rsa::RsaPrivateKey::from_pkcs1_pem(RSA_2048_PEM)
+        .unwrap()
+        .to_pkcs8_der()
+        .unwrap();
+    let key_pair = rcgen::KeyPair::try_from(pkcs8.as_bytes()).unwrap();
+    let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    let config = ServerConfig::load(cert.der(), RSA_2048_PEM.as_bytes()).unwrap();
+    complete_handshake(config);
+}
+
+/// Stand-in for a key kept outside the process, e.g. in an HSM.
+struct ExternalSigner {
+    key: Arc<dyn SigningKey>,
+    requests: Mutex<Vec<SignatureScheme>>,
+}
+
+impl SigningKey for ExternalSigner {
+    fn schemes(&self) -> &[SignatureScheme] {
+        self.key.schemes()
+    }
+
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, TlsError> {
+        self.requests.lock().unwrap().push(scheme);
+        self.key.sign(scheme, message)
+    }
+}
+
+#[test]
+fn external_signer() {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    let signer = Arc::new(ExternalSigner {
+        key: load_private_key(&key_pair.serialize_der()).unwrap(),
+        requests: Mutex::new(Vec::new()),
+    });
+    complete_handshake(ServerConfig::new(vec![cert.der().to_vec()], signer.clone()));
+    assert_eq!(
+        *signer.requests.lock().unwrap(),
+        [SignatureScheme::EcdsaSecp256r1Sha256]
+    );
+}
+
+struct TestCa {
+    cert: rcgen::Certificate,
+    key_pair: rcgen::KeyPair,
+}
+
+impl TestCa {
+    fn new(name: &str) -> Self {
+        let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        let mut params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, name);
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let cert = params.self_signed(&key_pair).unwrap();
+        Self { cert, key_pair }
+    }
+
+    fn der(&self) -> Vec<u8> {
+        self.cert.der().to_vec()
+    }
+
+    /// A certificate for the name and its key.
+    fn issue(&self, name: &str) -> ClientCertificate {
+        let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.to_string()])
+            .unwrap()
+            .signed_by(&key_pair, &self.cert, &self.key_pair)
+            .unwrap();
+        let signing_key = Ed25519SigningKey::from_pkcs8_der(&key_pair.serialize_der()).unwrap();
+        ClientCertificate {
+            certificate_chain: vec![cert.der().to_vec()],
+            signing_key: Arc::new(signing_key),
+        }
+    }
+
+    fn server_config(&self) -> ServerConfig {
+        let certificate = self.issue("localhost");
+        ServerConfig::new(certificate.certificate_chain, certificate.signing_key)
+    }
+}
+
+fn handshake_with(
+    client_config: &ClientConfig,
+    server_config: ServerConfig,
+) -> Result<(Session, Session), TlsError> {
+    connect(client_config, &Arc::new(server_config))
+}
+
+/// Run a handshake, and deliver the tickets the server sends after it.
+fn connect(
+    client_config: &ClientConfig,
+    server_config: &Arc<ServerConfig>,
+) -> Result<(Session, Session), TlsError> {
+    let mut client = Session::new_client(client_config, "localhost", vec![1]);
+    let mut server = Session::new_server(server_config.clone(), vec![2]);
+    // enough flights for a HelloRetryRequest
+    for _ in 0..3 {
+        transfer(&mut client, &mut server)?;
+        transfer(&mut server, &mut client)?;
+    }
+    assert!(client.is_handshake_complete() && server.is_handshake_complete());
+    Ok((client, server))
+}
+
+#[test]
+fn private_ca() {
+    let ca = TestCa::new("private CA");
+    let server_config = ca.server_config();
+    let chain = server_config.certificate_chain.clone();
+    let verifier = WebPkiVerifier::new(&[ca.der()]).unwrap();
+    let (client, _) =
+        handshake_with(&ClientConfig::new(Arc::new(verifier)), server_config).unwrap();
+    assert_eq!(client.peer_certificates(), chain);
+}
+
+#[test]
+fn unknown_ca() {
+    let server_config = TestCa::new("private CA").server_config();
+    let verifier = WebPkiVerifier::new(&[TestCa::new("other CA").der()]).unwrap();
+    let error = handshake_with(&ClientConfig::new(Arc::new(verifier)), server_config)
+        .err()
+        .unwrap();
+    assert_eq!(error.description(), AlertDescription::UnknownCa);
+}
+
+#[test]
+fn pinned_public_key() {
+    let ca = TestCa::new("private CA");
+    let server_config = ca.server_config();
+    let verifier = PinnedPublicKey::from_certificates(&server_config.certificate_chain).unwrap();
+    handshake_with(&ClientConfig::new(Arc::new(verifier)), server_config).unwrap();
+
+    let other = ca.issue("localhost");
+    let verifier = PinnedPublicKey::from_certificates(&other.certificate_chain).unwrap();
+    let error = handshake_with(&ClientConfig::new(Arc::new(verifier)), ca.server_config())
+        .err()
+        .unwrap();
+    assert_eq!(error.description(), AlertDescription::BadCertificate);
+}
+
+/// A client trusting the server CA, and a server requiring client certificates of the client CA.
+fn mutual_tls_configs(
+    server_ca: &TestCa,
+    client_ca: &TestCa,
+    client_auth: fn(Arc<dyn ClientCertVerifier>) -> ClientAuth,
+) -> (ClientConfig, ServerConfig) {
+    let client_config =
+        ClientConfig::new(Arc::new(WebPkiVerifier::new(&[server_ca.der()]).unwrap()));
+    let mut server_config = server_ca.server_config();
+    server_config.client_auth =
+        client_auth(Arc::new(WebPkiVerifier::new(&[client_ca.der()]).unwrap()));
+    (client_config, server_config)
+}
+
+#[test]
+fn mutual_tls() {
+    let server_ca = TestCa::new("server CA");
+    let client_ca = TestCa::new("client CA");
+    let (mut client_config, server_config) =
+        mutual_tls_configs(&server_ca, &client_ca, ClientAuth::Required);
+    let certificate = client_ca.issue("client.internal");
+    let chain = certificate.certificate_chain.clone();
+    client_config.client_certificate = Some(certificate);
+    let (_, server) = handshake_with(&client_config, server_config).unwrap();
+    assert_eq!(server.peer_certificates(), chain);
+}
+
+#[test]
+fn client_certificate_required() {
+    let server_ca = TestCa::new("server CA");
+    let client_ca = TestCa::new("client CA");
+    let (client_config, server_config) =
+        mutual_tls_configs(&server_ca, &client_ca, ClientAuth::Required);
+    let error = handshake_with(&client_config, server_config).err().unwrap();
+    assert_eq!(error.description(), AlertDescription::CertificateRequired);
+}
+
+#[test]
+fn client_certificate_optional() {
+    let server_ca = TestCa::new("server CA");
+    let client_ca = TestCa::new("client CA");
+    let (client_config, server_config) =
+        mutual_tls_configs(&server_ca, &client_ca, ClientAuth::Optional);
+    let (_, server) = handshake_with(&client_config, server_config).unwrap();
+    assert!(server.peer_certificates().is_empty());
+}
+
+#[test]
+fn untrusted_client_certificate() {
+    let server_ca = TestCa::new("server CA");
+    let client_ca = TestCa::new("client CA");
+    let (mut client_config, server_config) =
+        mutual_tls_configs(&server_ca, &client_ca, ClientAuth::Optional);
+    client_config.client_certificate = Some(server_ca.issue("client.internal"));
+    let error = handshake_with(&client_config, server_config).err().unwrap();
+    assert_eq!(error.description(), AlertDescription::UnknownCa);
+}
+
+fn server_config_with_tickets() -> ServerConfig {
+    let mut config = Arc::into_inner(server_config()).unwrap();
+    config.ticketer = Some(Arc::new(Ticketer::new(Duration::from_secs(3600))));
+    config
+}
+
+#[test]
+fn resumption() {
+    let client_config = client_config();
+    let server_config = Arc::new(server_config_with_tickets());
+    let (client, server) = connect(&client_config, &server_config).unwrap();
+    assert!(!client.is_resumed() && !server.is_resumed());
+    let server_certificates = client.peer_certificates().to_vec();
+
+    let (client, server) = connect(&client_config, &server_config).unwrap();
+    assert!(client.is_resumed() && server.is_resumed());
+    assert_eq!(client.peer_certificates(), server_certificates);
+
+    // the ticket of the second connection replaces the one used
+    let (client, _) = connect(&client_config, &server_config).unwrap();
+    assert!(client.is_resumed());
+}
+
+#[test]
+fn resumption_after_hello_retry_request() {
+    let client_config = client_config();
+    let server_config = server_config_with_tickets();
+    let ticketer = server_config.ticketer.clone();
+    connect(&client_config, &Arc::new(server_config)).unwrap();
+
+    let mut server_config = server_config_with_tickets();
+    server_config.ticketer = ticketer;
+    server_config.groups = vec![NamedCurve::Secp256r1];
+    let (client, server) = connect(&client_config, &Arc::new(server_config)).unwrap();
+    assert!(client.is_resumed() && server.is_resumed());
+}
+
+#[test]
+fn ticket_of_another_server() {
+    let client_config = client_config();
+    connect(&client_config, &Arc::new(server_config_with_tickets())).unwrap();
+
+    let (client, server) =
+        connect(&client_config, &Arc::new(server_config_with_tickets())).unwrap();
+    assert!(!client.is_resumed() && !server.is_resumed());
+}
+
+#[test]
+fn invalid_binder() {
+    let client_config = client_config();
+    let server_config = Arc::new(server_config_with_tickets());
+    connect(&client_config, &server_config).unwrap();
+
+    let mut client = Session::new_client(&client_config, "localhost", vec![1]);
+    let (_, mut client_hello) = client.write_handshake().unwrap();
+    // the binder is at the end
+    *client_hello.last_mut().unwrap() ^= 1;
+    let mut server = Session::new_server(server_config, vec![2]);
+    let error = server
+        .read_handshake(Level::Initial, &client_hello)
+        .unwrap_err();
+    assert_eq!(error.description(), AlertDescription::DecryptError);
+}
+
+#[test]
+fn resumption_keeps_client_identity() {
+    let server_ca = TestCa::new("server CA");
+    let client_ca = TestCa::new("client CA");
+    let (mut client_config, mut server_config) =
+        mutual_tls_configs(&server_ca, &client_ca, ClientAuth::Required);
+    let certificate = client_ca.issue("client.internal");
+    let chain = certificate.certificate_chain.clone();
+    client_config.client_certificate = Some(certificate);
+    server_config.ticketer = Some(Arc::new(Ticketer::new(Duration::from_secs(3600))));
+    let server_config = Arc::new(server_config);
+    connect(&client_config, &server_config).unwrap();
+
+    let (_, server) = connect(&client_config, &server_config).unwrap();
+    assert!(server.is_resumed());
+    assert_eq!(server.peer_certificates(), chain);
+}
+
+fn early_data_configs(anti_replay: Arc<dyn AntiReplay>) -> (ClientConfig, Arc<ServerConfig>) {
+    let mut client_config = client_config();
+    client_config.early_data = true;
+    let mut server_config = server_config_with_tickets();
+    server_config.early_data = Some(anti_replay);
+    (client_config, Arc::new(server_config))
+}
+
+fn zero_rtt_secrets(session: &mut Session) -> Option<Secrets> {
+    std::iter::from_fn(|| session.next_secrets()).find(|s| s.level == Level::ZeroRtt)
+}
+
+#[test]
+fn early_data() {
+    let (client_config, server_config) =
+        early_data_configs(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    let (client, _) = connect(&client_config, &server_config).unwrap();
+    assert_eq!(client.early_data(), EarlyData::NotOffered);
+
+    let mut client = Session::new_client(&client_config, "localhost", vec![1]);
+    assert_eq!(client.early_data(), EarlyData::Offered);
+    // the transport parameters of the server in the first connection
+    assert_eq!(client.remembered_transport_parameters(), Some(&[2][..]));
+    let client_secrets = zero_rtt_secrets(&mut client).unwrap();
+    let mut server = Session::new_server(server_config, vec![2]);
+    transfer(&mut client, &mut server).unwrap();
+    assert_eq!(server.early_data(), EarlyData::Accepted);
+    assert_eq!(zero_rtt_secrets(&mut server).unwrap(), client_secrets);
+    transfer(&mut server, &mut client).unwrap();
+    transfer(&mut client, &mut server).unwrap();
+    assert!(client.is_handshake_complete() && server.is_handshake_complete());
+    assert_eq!(client.early_data(), EarlyData::Accepted);
+}
+
+struct RejectEarlyData;
+
+impl AntiReplay for RejectEarlyData {
+    fn accept_early_data(&self, _: &[u8], _: Duration, _: Duration, _: SystemTime) -> bool {
+        false
+    }
+}
+
+#[test]
+fn early_data_rejected() {
+    let (client_config, server_config) = early_data_configs(Arc::new(RejectEarlyData));
+    connect(&client_config, &server_config).unwrap();
+
+    let (client, mut server) = connect(&client_config, &server_config).unwrap();
+    assert!(client.is_resumed() && server.is_resumed());
+    assert_eq!(client.early_data(), EarlyData::Rejected);
+    assert_eq!(server.early_data(), EarlyData::Rejected);
+    assert!(zero_rtt_secrets(&mut server).is_none());
+}
+
+#[test]
+fn replayed_early_data() {
+    let (client_config, server_config) =
+        early_data_configs(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    connect(&client_config, &server_config).unwrap();
+
+    let mut client = Session::new_client(&client_config, "localhost", vec![1]);
+    let (level, client_hello) = client.write_handshake().unwrap();
+    let mut server = Session::new_server(server_config.clone(), vec![2]);
+    server.read_handshake(level, &client_hello).unwrap();
+    assert_eq!(server.early_data(), EarlyData::Accepted);
+    let mut server = Session::new_server(server_config, vec![2]);
+    server.read_handshake(level, &client_hello).unwrap();
+    assert_eq!(server.early_data(), EarlyData::Rejected);
+}
+
+#[test]
+fn early_data_context_changed() {
+    let (client_config, server_config) =
+        early_data_configs(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    connect(&client_config, &server_config).unwrap();
+
+    let mut client = Session::new_client(&client_config, "localhost", vec![1]);
+    let mut server = Session::new_server(server_config, vec![2]);
+    server.set_early_data_context(vec![3]);
+    transfer(&mut client, &mut server).unwrap();
+    transfer(&mut server, &mut client).unwrap();
+    assert!(server.is_resumed());
+    assert_eq!(client.early_data(), EarlyData::Rejected);
+}
+
+fn alpn_configs(client: &[&[u8]], server: &[&[u8]]) -> (ClientConfig, ServerConfig) {
+    let mut client_config = client_config();
+    client_config.alpn_protocols = client.iter().map(|p| p.to_vec()).collect();
+    let mut server_config = Arc::into_inner(server_config()).unwrap();
+    server_config.alpn_protocols = server.iter().map(|p| p.to_vec()).collect();
+    (client_config, server_config)
+}
+
+#[test]
+fn alpn() {
+    // the preference of the server wins
+    let (client_config, server_config) = alpn_configs(&[b"simple", b"h3"], &[b"h3", b"simple"]);
+    let (client, server) = handshake_with(&client_config, server_config).unwrap();
+    assert_eq!(client.alpn_protocol(), Some(&b"h3"[..]));
+    assert_eq!(server.alpn_protocol(), Some(&b"h3"[..]));
+
+    let (client_config, server_config) = alpn_configs(&[], &[]);
+    let (client, server) = handshake_with(&client_config, server_config).unwrap();
+    assert_eq!(client.alpn_protocol(), None);
+    assert_eq!(server.alpn_protocol(), None);
+}
+
+#[test]
+fn no_application_protocol() {
+    for client_protocols in [&[&b"simple"[..]][..], &[]] {
+        let (client_config, server_config) = alpn_configs(client_protocols, &[b"h3"]);
+        let error = handshake_with(&client_config, server_config).err().unwrap();
+        assert_eq!(error.description(), AlertDescription::NoApplicationProtocol);
+    }
+
+    // the server ignores ALPN
+    let (client_config, server_config) = alpn_configs(&[b"h3"], &[]);
+    let error = handshake_with(&client_config, server_config).err().unwrap();
+    assert_eq!(error.description(), AlertDescription::NoApplicationProtocol);
+}
+
+#[test]
+fn early_data_in_another_protocol() {
+    let (mut client_config, mut server_config) = alpn_configs(&[b"h3", b"simple"], &[b"h3"]);
+    client_config.early_data = true;
+    let ticketer = Arc::new(Ticketer::new(Duration::from_secs(3600)));
+    server_config.ticketer = Some(ticketer.clone());
+    server_config.early_data = Some(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    connect(&client_config, &Arc::new(server_config)).unwrap();
+
+    let (_, mut server_config) = alpn_configs(&[], &[b"simple"]);
+    server_config.ticketer = Some(ticketer);
+    server_config.early_data = Some(Arc::new(ReplayWindow::new(Duration::from_secs(10))));
+    let (client, server) = connect(&client_config, &Arc::new(server_config)).unwrap();
+    assert!(server.is_resumed());
+    assert_eq!(client.alpn_protocol(), Some(&b"simple"[..]));
+    assert_eq!(client.early_data(), EarlyData::Rejected);
+}
@@ -0,0 +1,236 @@
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+use p256::ecdsa::signature::{Signer, Verifier};
+use rand::rngs::OsRng;
+use rsa::{pkcs1::DecodeRsaPrivateKey, pkcs1::DecodeRsaPublicKey, Pss};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use x509_cert::{der::Decode, spki::ObjectIdentifier, Certificate};
+
+use crate::{pki::LoadError, AlertDescription, SignatureScheme, TlsError};
+
+/// id-Ed25519 in https://www.rfc-editor.org/rfc/rfc8410.html#section-3
+const ID_ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+/// id-ecPublicKey in https://www.rfc-editor.org/rfc/rfc5480.html#section-2.1.1
+const ID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+/// secp256r1 in https://www.rfc-editor.org/rfc/rfc5480.html#section-2.1.1.1
+const SECP256R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+/// rsaEncryption in https://www.rfc-editor.org/rfc/rfc8017.html#appendix-C
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+
+/// Schemes a signature of the peer can be verified with, in order of preference.
+pub(crate) const SUPPORTED_SIGNATURE_SCHEMES: &[SignatureScheme] = &[
+    SignatureScheme::EcdsaSecp256r1Sha256,
+    SignatureScheme::Ed25519,
+    SignatureScheme::RsaPssRsaeSha256,
+    SignatureScheme::RsaPssRsaeSha384,
+    SignatureScheme::RsaPssRsaeSha512,
+];
+
+/// Private key to sign CertificateVerify with.
+/// It is a trait so that the key can live outside the process, e.g. in an HSM.
+pub trait SigningKey: Send + Sync {
+    /// Schemes the key signs with, in order of preference.
+    fn schemes(&self) -> &[SignatureScheme];
+
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, TlsError>;
+
+    /// The first scheme of the key the peer offered in signature_algorithms.
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<SignatureScheme> {
+        self.schemes().iter().find(|s| offered.contains(s)).copied()
+    }
+}
+
+fn unsupported_scheme(scheme: SignatureScheme) -> TlsError {
+    TlsError::new(
+        AlertDescription::InternalError,
+        format!("the key does not sign with {scheme:?}"),
+    )
+}
+
+pub struct Ed25519SigningKey(ed25519_dalek::SigningKey);
+
+impl Ed25519SigningKey {
+    /// Load a PKCS #8 private key in DER.
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, LoadError> {
+        ed25519_dalek::SigningKey::from_pkcs8_der(der)
+            .map(Self)
+            .map_err(|e| LoadError::InvalidPrivateKey(e.to_string()))
+    }
+}
+
+impl SigningKey for Ed25519SigningKey {
+    fn schemes(&self) -> &[SignatureScheme] {
+        &[SignatureScheme::Ed25519]
+    }
+
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, TlsError> {
+        if scheme != SignatureScheme::Ed25519 {
+            return Err(unsupported_scheme(scheme));
+        }
+        Ok(self.0.sign(message).to_bytes().to_vec())
+    }
+}
+
+pub struct EcdsaP256SigningKey(p256::ecdsa::SigningKey);
+
+impl EcdsaP256SigningKey {
+    /// Load a PKCS #8 private key in DER.
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, LoadError> {
+        p256::ecdsa::SigningKey::from_pkcs8_der(der)
+            .map(Self)
+            .map_err(|e| LoadError::InvalidPrivateKey(e.to_string()))
+    }
+
+    /// Load a SEC1 private key in DER.
+    pub fn from_sec1_der(der: &[u8]) -> Result<Self, LoadError> {
+        p256::SecretKey::from_sec1_der(der)
+            .map(|key| Self(key.into()))
+            .map_err(|e| LoadError::InvalidPrivateKey(e.to_string()))
+    }
+}
+
+impl SigningKey for EcdsaP256SigningKey {
+    fn schemes(&self) -> &[SignatureScheme] {
+        &[SignatureScheme::EcdsaSecp256r1Sha256]
+    }
+
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, TlsError> {
+        if scheme != SignatureScheme::EcdsaSecp256r1Sha256 {
+            return Err(unsupported_scheme(scheme));
+        }
+        let signature: p256::ecdsa::DerSignature = self.0.sign(message);
+        Ok(signature.as_bytes().to_vec())
+    }
+}
+
+/// RSA key signing with RSASSA-PSS, since TLS 1.3 does not allow PKCS #1 v1.5 in CertificateVerify.
+pub struct RsaPssSigningKey(rsa::RsaPrivateKey);
+
+impl RsaPssSigningKey {
+    /// Load a PKCS #8 private key in DER.
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, LoadError> {
+        rsa::RsaPrivateKey::from_pkcs8_der(der)
+            .map(Self)
+            .map_err(|e| LoadError::InvalidPrivateKey(e.to_string()))
+    }
+
+    /// Load a PKCS #1 private key in DER.
+    pub fn from_pkcs1_der(der: &[u8]) -> Result<Self, LoadError> {
+        rsa::RsaPrivateKey::from_pkcs1_der(der)
+            .map(Self)
+            .map_err(|e| LoadError::InvalidPrivateKey(e.to_string()))
+    }
+}
+
+impl SigningKey for RsaPssSigningKey {
+    fn schemes(&self) -> &[SignatureScheme] {
+        &[
+            SignatureScheme::RsaPssRsaeSha256,
+            SignatureScheme::RsaPssRsaeSha384,
+            SignatureScheme::RsaPssRsaeSha512,
+        ]
+    }
+
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, TlsError> {
+        let (padding, hashed) = rsa_pss(scheme, message).ok_or(unsupported_scheme(scheme))?;
+        self.0
+            .sign_with_rng(&mut OsRng, padding, &hashed)
+            .map_err(|e| TlsError::new(AlertDescription::InternalError, e.to_string()))
+    }
+}
+
+/// RSASSA-PSS padding with a salt as long as the hash, and the hashed message.
+fn rsa_pss(scheme: SignatureScheme, message: &[u8]) -> Option<(Pss, Vec<u8>)> {
+    Some(match scheme {
+        SignatureScheme::RsaPssRsaeSha256 => {
+            (Pss::new::<Sha256>(), Sha256::digest(message).to_vec())
+        }
+        SignatureScheme::RsaPssRsaeSha384 => {
+            (Pss::new::<Sha384>(), Sha384::digest(message).to_vec())
+        }
+        SignatureScheme::RsaPssRsaeSha512 => {
+            (Pss::new::<Sha512>(), Sha512::digest(message).to_vec())
+        }
+        _ => return None,
+    })
+}
+
+/// The content covered by the signature in CertificateVerify.
+/// https://www.rfc-editor.org/rfc/rfc8446.html#section-4.4.3
+pub(crate) fn certificate_verify_message(is_server: bool, transcript_hash: &[u8]) -> Vec<u8> {
+    let context: &[u8] = if is_server {
+        b"TLS 1.3, server CertificateVerify"
+    } else {
+        b"TLS 1.3, client CertificateVerify"
+    };
+    [&[0x20; 64][..], context, &[0], transcript_hash].concat()
+}
+
+/// Verify a signature with the public key of the end-entity certificate.
+pub(crate) fn verify_signature(
+    scheme: SignatureScheme,
+    cert_der: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), TlsError> {
+    let cert = Certificate::from_der(cert_der)
+        .map_err(|e| TlsError::new(AlertDescription::BadCertificate, e.to_string()))?;
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    let public_key = spki.subject_public_key.raw_bytes();
+    let bad_key = |e: String| TlsError::new(AlertDescription::BadCertificate, e);
+    let curve = spki
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|p| p.decode_as::<ObjectIdentifier>().ok());
+    let verified = match scheme {
+        SignatureScheme::Ed25519 if spki.algorithm.oid == ID_ED25519 => {
+            let public_key = public_key
+                .try_into()
+                .map_err(|_| bad_key("invalid Ed25519 key".to_string()))?;
+            let Ok(signature) = ed25519_dalek::Signature::from_slice(signature) else {
+                return Err(invalid_signature());
+            };
+            ed25519_dalek::VerifyingKey::from_bytes(public_key)
+                .and_then(|key| key.verify(message, &signature))
+                .is_ok()
+        }
+        SignatureScheme::EcdsaSecp256r1Sha256
+            if spki.algorithm.oid == ID_EC_PUBLIC_KEY && curve == Some(SECP256R1) =>
+        {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                .map_err(|e| bad_key(e.to_string()))?;
+            let Ok(signature) = p256::ecdsa::DerSignature::from_bytes(signature) else {
+                return Err(invalid_signature());
+            };
+            key.verify(message, &signature).is_ok()
+        }
+        SignatureScheme::RsaPssRsaeSha256
+        | SignatureScheme::RsaPssRsaeSha384
+        | SignatureScheme::RsaPssRsaeSha512
+            if spki.algorithm.oid == RSA_ENCRYPTION =>
+        {
+            let key = rsa::RsaPublicKey::from_pkcs1_der(public_key)
+                .map_err(|e| bad_key(e.to_string()))?;
+            let (padding, hashed) = rsa_pss(scheme, message).expect("the scheme is RSASSA-PSS");
+            key.verify(padding, &hashed, signature).is_ok()
+        }
+        _ => {
+            return Err(TlsError::new(
+                AlertDescription::IllegalParameter,
+                format!("signature scheme {scheme:?} does not match the certificate"),
+            ))
+        }
+    };
+    if verified {
+        Ok(())
+    } else {
+        Err(invalid_signature())
+    }
+}
+
+fn invalid_signature() -> TlsError {
+    TlsError::new(
+        AlertDescription::DecryptError,
+        "invalid CertificateVerify signature",
+    )
+}
@@ -0,0 +1,216 @@
+//! Verification of the certificate chain the peer sends.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use webpki::types::{CertificateDer, ServerName, TrustAnchor, UnixTime};
+use x509_cert::der::{Decode, Encode};
+
+use crate::{
+    pki::{load_certificate_chain, LoadError},
+    AlertDescription, TlsError,
+};
+
+/// Decides whether the server is trusted.
+/// It is a trait so that applications can bring their own policy.
+pub trait ServerCertVerifier: Send + Sync {
+    /// `end_entity` and `intermediates` are in DER, in the order the server sent them.
+    fn verify_server_cert(
+        &self,
+        end_entity: &[u8],
+        intermediates: &[Vec<u8>],
+        server_name: &str,
+        now: SystemTime,
+    ) -> Result<(), TlsError>;
+}
+
+/// Decides whether a client is trusted, when the server asks for a certificate.
+pub trait ClientCertVerifier: Send + Sync {
+    /// `end_entity` and `intermediates` are in DER, in the order the client sent them.
+    fn verify_client_cert(
+        &self,
+        end_entity: &[u8],
+        intermediates: &[Vec<u8>],
+        now: SystemTime,
+    ) -> Result<(), TlsError>;
+}
+
+/// Builds a path from the end-entity certificate to one of the roots,
+/// and checks the validity periods and, for a server, its name.
+pub struct WebPkiVerifier {
+    roots: Vec<TrustAnchor<'static>>,
+}
+
+impl WebPkiVerifier {
+    /// Trust the root certificates in DER, e.g. those of a private CA.
+    pub fn new(root_certificates: &[Vec<u8>]) -> Result<Self, LoadError> {
+        let roots = root_certificates
+            .iter()
+            .map(|cert| {
+                webpki::anchor_from_trusted_cert(&CertificateDer::from(cert.as_slice()))
+                    .map(|anchor| anchor.to_owned())
+                    .map_err(|e| LoadError::InvalidCertificate(e.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { roots })
+    }
+
+    /// Trust the root certificates of a PEM bundle, or a single one in DER.
+    pub fn load(root_certificates: &[u8]) -> Result<Self, LoadError> {
+        Self::new(&load_certificate_chain(root_certificates)?)
+    }
+
+    /// Build a path to one of the roots for the extended key usage.
+    fn verify_path(
+        &self,
+        end_entity: &webpki::EndEntityCert,
+        intermediates: &[Vec<u8>],
+        now: SystemTime,
+        usage: webpki::KeyUsage,
+    ) -> Result<(), TlsError> {
+        let intermediates: Vec<_> = intermediates
+            .iter()
+            .map(|c| CertificateDer::from(c.as_slice()))
+            .collect();
+        let now = UnixTime::since_unix_epoch(now.duration_since(UNIX_EPOCH).unwrap_or_default());
+        end_entity
+            .verify_for_usage(
+                webpki::ALL_VERIFICATION_ALGS,
+                &self.roots,
+                &intermediates,
+                now,
+                usage,
+                None,
+                None,
+            )
+            .map(|_| ())
+            .map_err(certificate_error)
+    }
+}
+
+impl ServerCertVerifier for WebPkiVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &[u8],
+        intermediates: &[Vec<u8>],
+        server_name: &str,
+        now: SystemTime,
+    ) -> Result<(), TlsError> {
+        let end_entity = CertificateDer::from(end_entity);
+        let cert = webpki::EndEntityCert::try_from(&end_entity).map_err(certificate_error)?;
+        self.verify_path(&cert, intermediates, now, webpki::KeyUsage::server_auth())?;
+        let server_name = ServerName::try_from(server_name).map_err(|_| {
+            TlsError::new(
+                AlertDescription::BadCertificate,
+                format!("invalid server name {server_name:?}"),
+            )
+        })?;
+        cert.verify_is_valid_for_subject_name(&server_name)
+            .map_err(certificate_error)
+    }
+}
+
+impl ClientCertVerifier for WebPkiVerifier {
+    fn verify_client_cert(
+        &self,
+        end_entity: &[u8],
+        intermediates: &[Vec<u8>],
+        now: SystemTime,
+    ) -> Result<(), TlsError> {
+        let end_entity = CertificateDer::from(end_entity);
+        let cert = webpki::EndEntityCert::try_from(&end_entity).map_err(certificate_error)?;
+        self.verify_path(&cert, intermediates, now, webpki::KeyUsage::client_auth())
+    }
+}
+
+fn certificate_error(error: webpki::Error) -> TlsError {
+    let description = match error {
+        webpki::Error::UnknownIssuer => AlertDescription::UnknownCa,
+        webpki::Error::CertExpired | webpki::Error::CertNotValidYet => {
+            AlertDescription::CertificateExpired
+        }
+        _ => AlertDescription::BadCertificate,
+    };
+    TlsError::new(description, error.to_string())
+}
+
+/// Accepts any certificate, which is only safe in tests.
+pub struct AcceptAnyServerCert;
+
+impl ServerCertVerifier for AcceptAnyServerCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &[u8],
+        _intermediates: &[Vec<u8>],
+        _server_name: &str,
+        _now: SystemTime,
+    ) -> Result<(), TlsError> {
+        Ok(())
+    }
+}
+
+/// Accepts an end-entity certificate only if its public key is one of the pinned ones,
+/// whatever the issuer, the validity period or the names are.
+pub struct PinnedPublicKey {
+    /// SHA-256 of the DER of SubjectPublicKeyInfo.
+    spki_sha256: Vec<[u8; 32]>,
+}
+
+impl PinnedPublicKey {
+    pub fn new(spki_sha256: Vec<[u8; 32]>) -> Self {
+        Self { spki_sha256 }
+    }
+
+    /// Pin the public keys of the certificates in DER.
+    pub fn from_certificates(certificates: &[Vec<u8>]) -> Result<Self, LoadError> {
+        certificates
+            .iter()
+            .map(|cert| spki_sha256(cert).map_err(|e| LoadError::InvalidCertificate(e.to_string())))
+            .collect::<Result<_, _>>()
+            .map(Self::new)
+    }
+
+    fn verify(&self, end_entity: &[u8]) -> Result<(), TlsError> {
+        let hash = spki_sha256(end_entity)
+            .map_err(|e| TlsError::new(AlertDescription::BadCertificate, e.to_string()))?;
+        if !self.spki_sha256.contains(&hash) {
+            return Err(TlsError::new(
+                AlertDescription::BadCertificate,
+                "public key is not pinned",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl ServerCertVerifier for PinnedPublicKey {
+    fn verify_server_cert(
+        &self,
+        end_entity: &[u8],
+        _intermediates: &[Vec<u8>],
+        _server_name: &str,
+        _now: SystemTime,
+    ) -> Result<(), TlsError> {
+        self.verify(end_entity)
+    }
+}
+
+impl ClientCertVerifier for PinnedPublicKey {
+    fn verify_client_cert(
+        &self,
+        end_entity: &[u8],
+        _intermediates: &[Vec<u8>],
+        _now: SystemTime,
+    ) -> Result<(), TlsError> {
+        self.verify(end_entity)
+    }
+}
+
+fn spki_sha256(cert_der: &[u8]) -> Result<[u8; 32], x509_cert::der::Error> {
+    let cert = x509_cert::Certificate::from_der(cert_der)?;
+    let spki = cert.tbs_certificate.subject_public_key_info.to_der()?;
+    Ok(Sha256::digest(spki).into())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,146 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::AlertDescription;
+
+use super::{AcceptAnyServerCert, PinnedPublicKey, ServerCertVerifier, WebPkiVerifier};
+
+/// 2024-01-01T00:00:00Z
+const NOW: Duration = Duration::from_secs(1_704_067_200);
+
+struct Issuer {
+    cert: rcgen::Certificate,
+    key_pair: rcgen::KeyPair,
+}
+
+fn new_ca(name: &str) -> Issuer {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let mut params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, name);
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let cert = params.self_signed(&key_pair).unwrap();
+    Issuer { cert, key_pair }
+}
+
+fn intermediate(issuer: &Issuer) -> Issuer {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let mut params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "intermediate");
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let cert = params
+        .signed_by(&key_pair, &issuer.cert, &issuer.key_pair)
+        .unwrap();
+    Issuer { cert, key_pair }
+}
+
+fn end_entity(issuer: &Issuer, name: &str) -> Vec<u8> {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    rcgen::CertificateParams::new(vec![name.to_string()])
+        .unwrap()
+        .signed_by(&key_pair, &issuer.cert, &issuer.key_pair)
+        .unwrap()
+        .der()
+        .to_vec()
+}
+
+fn verify(
+    verifier: &dyn ServerCertVerifier,
+    end_entity: &[u8],
+    intermediates: &[Vec<u8>],
+    server_name: &str,
+) -> Result<(), AlertDescription> {
+    verifier
+        .verify_server_cert(end_entity, intermediates, server_name, UNIX_EPOCH + NOW)
+        .map_err(|e| e.description())
+}
+
+#[test]
+fn private_ca() {
+    let ca = new_ca("private CA");
+    let verifier = WebPkiVerifier::load(ca.cert.pem().as_bytes()).unwrap();
+    let cert = end_entity(&ca, "service.internal");
+
+    assert_eq!(verify(&verifier, &cert, &[], "service.internal"), Ok(()));
+    assert_eq!(
+        verify(&verifier, &cert, &[], "other.internal"),
+        Err(AlertDescription::BadCertificate)
+    );
+
+    let other_ca = new_ca("other CA");
+    let cert = end_entity(&other_ca, "service.internal");
+    assert_eq!(
+        verify(&verifier, &cert, &[], "service.internal"),
+        Err(AlertDescription::UnknownCa)
+    );
+}
+
+#[test]
+fn intermediate_certificate() {
+    let ca = new_ca("private CA");
+    let verifier = WebPkiVerifier::new(&[ca.cert.der().to_vec()]).unwrap();
+    let intermediate = intermediate(&ca);
+    let cert = end_entity(&intermediate, "service.internal");
+
+    let intermediates = [intermediate.cert.der().to_vec()];
+    assert_eq!(
+        verify(&verifier, &cert, &intermediates, "service.internal"),
+        Ok(())
+    );
+    assert_eq!(
+        verify(&verifier, &cert, &[], "service.internal"),
+        Err(AlertDescription::UnknownCa)
+    );
+}
+
+#[test]
+fn validity_period() {
+    let ca = new_ca("private CA");
+    let verifier = WebPkiVerifier::new(&[ca.cert.der().to_vec()]).unwrap();
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let mut params = rcgen::CertificateParams::new(vec!["service.internal".to_string()]).unwrap();
+    params.not_before = rcgen::date_time_ymd(2023, 1, 1);
+    params.not_after = rcgen::date_time_ymd(2023, 12, 31);
+    let cert = params.signed_by(&key_pair, &ca.cert, &ca.key_pair).unwrap();
+
+    let at = |secs| {
+        verifier
+            .verify_server_cert(
+                cert.der(),
+                &[],
+                "service.internal",
+                UNIX_EPOCH + Duration::from_secs(secs),
+            )
+            .map_err(|e| e.description())
+    };
+    // 2023-06-01
+    assert_eq!(at(1_685_577_600), Ok(()));
+    // 2022-06-01
+    assert_eq!(at(1_654_041_600), Err(AlertDescription::CertificateExpired));
+    assert_eq!(at(NOW.as_secs()), Err(AlertDescription::CertificateExpired));
+}
+
+#[test]
+fn pinned_public_key() {
+    let ca = new_ca("private CA");
+    let cert = end_entity(&ca, "service.internal");
+    let verifier = PinnedPublicKey::from_certificates(std::slice::from_ref(&cert)).unwrap();
+
+    // neither the issuer nor the name matter
+    assert_eq!(verify(&verifier, &cert, &[], "other.internal"), Ok(()));
+    let other = end_entity(&ca, "service.internal");
+    assert_eq!(
+        verify(&verifier, &other, &[], "service.internal"),
+        Err(AlertDescription::BadCertificate)
+    );
+}
+
+#[test]
+fn accept_any() {
+    assert_eq!(
+        AcceptAnyServerCert.verify_server_cert(b"garbage", &[], "", SystemTime::now()),
+        Ok(())
+    );
+}
@@ -6,10 +6,11 @@ use tokio::net::UdpSocket;
 mod tokio_stream;
 
 pub mod error;
+pub mod router;
 pub mod server;
 pub mod simple_app;
 
-pub use self::{error::RuzzicError, error::RuzzicResult, simple_app::SimpleApp};
+pub use self::{error::RuzzicError, error::RuzzicResult, router::AppRouter, simple_app::SimpleApp};
 
 pub struct Ruzzic<App>
 where
@@ -47,6 +48,10 @@ where
 pub trait AppLayer {
     type Message: AppMessage<Self::Error>;
     type Error: AppError;
+
+    /// Protocol IDs the app is negotiated with in ALPN, in the order of preference.
+    /// https://www.rfc-editor.org/rfc/rfc9001.html#section-8.1
+    const ALPN_PROTOCOLS: &'static [&'static [u8]];
 }
 
 #[async_trait::async_trait]
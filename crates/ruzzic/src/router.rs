@@ -0,0 +1,110 @@
+//! Serve several apps on one port by the protocol negotiated with ALPN.
+
+use crate::AppLayer;
+
+/// Maps the protocol IDs of each app to what handles its connections.
+pub struct AppRouter<T> {
+    routes: Vec<(&'static [u8], T)>,
+}
+
+impl<T> AppRouter<T> {
+    pub fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// Route the connections of `App` to `handler`.
+    /// Apps added first are preferred, and a protocol already routed is kept.
+    pub fn route<App: AppLayer>(mut self, handler: T) -> Self
+    where
+        T: Clone,
+    {
+        for &protocol in App::ALPN_PROTOCOLS {
+            if self.select(protocol).is_none() {
+                self.routes.push((protocol, handler.clone()));
+            }
+        }
+        self
+    }
+
+    /// The list the server negotiates with, in the order of preference.
+    pub fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        self.routes
+            .iter()
+            .map(|(protocol, _)| protocol.to_vec())
+            .collect()
+    }
+
+    /// The handler of the protocol the handshake selected.
+    pub fn select(&self, protocol: &[u8]) -> Option<&T> {
+        self.routes
+            .iter()
+            .find(|(routed, _)| *routed == protocol)
+            .map(|(_, handler)| handler)
+    }
+}
+
+impl<T> Default for AppRouter<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple_app::{RuzzicSimpleAppError, SimpleAppMessage};
+    use crate::SimpleApp;
+
+    struct Http3;
+
+    impl AppLayer for Http3 {
+        type Message = SimpleAppMessage;
+        type Error = RuzzicSimpleAppError;
+
+        const ALPN_PROTOCOLS: &'static [&'static [u8]] = &[b"h3", b"h3-29"];
+    }
+
+    struct LegacyHttp3;
+
+    impl AppLayer for LegacyHttp3 {
+        type Message = SimpleAppMessage;
+        type Error = RuzzicSimpleAppError;
+
+        const ALPN_PROTOCOLS: &'static [&'static [u8]] = &[b"h3-29", b"h3-27"];
+    }
+
+    #[test]
+    fn preference_order() {
+        let router = AppRouter::new()
+            .route::<Http3>("http3")
+            .route::<SimpleApp>("simple");
+        assert_eq!(
+            router.alpn_protocols(),
+            vec![b"h3".to_vec(), b"h3-29".to_vec(), b"ruzzic-simple".to_vec()]
+        );
+        assert_eq!(router.select(b"h3"), Some(&"http3"));
+        assert_eq!(router.select(b"ruzzic-simple"), Some(&"simple"));
+    }
+
+    #[test]
+    fn protocol_routed_twice() {
+        let router = AppRouter::new()
+            .route::<Http3>("http3")
+            .route::<LegacyHttp3>("legacy");
+        // the app added first keeps the protocol
+        assert_eq!(router.select(b"h3-29"), Some(&"http3"));
+        assert_eq!(router.select(b"h3-27"), Some(&"legacy"));
+        assert_eq!(
+            router.alpn_protocols(),
+            vec![b"h3".to_vec(), b"h3-29".to_vec(), b"h3-27".to_vec()]
+        );
+    }
+
+    #[test]
+    fn unknown_protocol() {
+        let router = AppRouter::new().route::<SimpleApp>("simple");
+        assert_eq!(router.select(b"h3"), None);
+        assert_eq!(AppRouter::<&str>::default().select(b"ruzzic-simple"), None);
+        assert!(AppRouter::<&str>::new().alpn_protocols().is_empty());
+    }
+}
@@ -9,6 +9,8 @@ pub struct SimpleApp;
 impl AppLayer for SimpleApp {
     type Message = SimpleAppMessage;
     type Error = RuzzicSimpleAppError;
+
+    const ALPN_PROTOCOLS: &'static [&'static [u8]] = &[b"ruzzic-simple"];
 }
 
 #[derive(Debug)]
@@ -64,8 +64,9 @@ impl Decoder for RuzzicTokioCodec {
         };
         src.advance(packet.raw_length());
 
-        match packet.version().to_u32() {
-            0x1 => {
+        // short header packets belong to connections whose version is already negotiated
+        match packet.version().map(|v| v.to_u32()) {
+            Some(0x1) => {
                 if self.support_versions.contains(&QuicVersion::Rfc9000) {
                     QuicVersion::Rfc9000
                 } else {